@@ -0,0 +1,62 @@
+//! One large file among many small ones, timed with each `--schedule` mode.
+//!
+//! Run with `cargo bench --bench schedule`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const LARGE_FILE_BYTES: usize = 256 * 1024 * 1024;
+const SMALL_FILES: usize = 2000;
+const RUNS: usize = 5;
+
+fn fixture(dir: &tempfile::TempDir) -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(SMALL_FILES + 1);
+    let small = "the quick brown fox jumps over the lazy dog\n".repeat(2000);
+    for i in 0..SMALL_FILES {
+        let path = dir.path().join(format!("small_{:04}.txt", i));
+        fs::write(&path, &small).unwrap();
+        paths.push(path);
+    }
+
+    let line = "lorem ipsum dolor sit amet consectetur adipiscing elit\n";
+    let large = line.repeat(LARGE_FILE_BYTES / line.len());
+    let path = dir.path().join("zz_large.txt");
+    fs::write(&path, large).unwrap();
+    paths.push(path);
+    paths
+}
+
+fn time_schedule(paths: &[PathBuf], schedule: &str) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let status = Command::new(env!("CARGO_BIN_EXE_kz"))
+            .arg("--total-only")
+            .arg("--schedule")
+            .arg(schedule)
+            .args(paths)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = fixture(&dir);
+
+    for schedule in ["input", "size"] {
+        let best = time_schedule(&paths, schedule);
+        println!(
+            "schedule={:<6} best of {}: {:.1}ms",
+            schedule,
+            RUNS,
+            best.as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Many small files through the buffered read, decode and `--code` filter
+//! path, where per-file buffers are reused instead of reallocated.
+//!
+//! Run with `cargo bench --bench small_files`.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const SMALL_FILES: usize = 100_000;
+const RUNS: usize = 5;
+
+fn fixture(dir: &Path) {
+    let source = "let total = count(words); // running sum\n".repeat(40);
+    for i in 0..SMALL_FILES {
+        fs::write(dir.join(format!("small_{:06}.rs", i)), &source).unwrap();
+    }
+}
+
+fn time_run(dir: &Path, args: &[&str]) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let status = Command::new(env!("CARGO_BIN_EXE_kz"))
+            .arg("--total-only")
+            .arg("-r")
+            .args(args)
+            .arg(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    fixture(dir.path());
+
+    for args in [&["-lwc"][..], &["-lwc", "--code"][..]] {
+        let best = time_run(dir.path(), args);
+        println!(
+            "{:<14} best of {}: {:.1}ms",
+            args.join(" "),
+            RUNS,
+            best.as_secs_f64() * 1000.0
+        );
+    }
+}
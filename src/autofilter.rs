@@ -0,0 +1,72 @@
+use crate::config::Args;
+use std::path::Path;
+
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+/// Source files whose comments `--code` understands: `//`, `/* */`, `#`,
+/// `--` and Python docstrings.
+const SOURCE_EXTENSIONS: [&str; 43] = [
+    "c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx", "cs", "java", "kt", "kts", "scala", "go",
+    "rs", "swift", "dart", "js", "jsx", "mjs", "cjs", "ts", "tsx", "php", "py", "pyw", "pyi", "rb",
+    "pl", "pm", "sh", "bash", "zsh", "fish", "r", "jl", "lua", "sql", "hs", "elm", "zig", "nim",
+    "ex",
+];
+
+/// The filter `--auto-filter` picks for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Code,
+    Markdown,
+}
+
+impl Filter {
+    /// The filter for `path` from its extension, ignoring case; None leaves
+    /// the file unfiltered.
+    pub fn for_path(path: &Path) -> Option<Filter> {
+        let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        if MARKDOWN_EXTENSIONS.contains(&ext.as_str()) {
+            Some(Filter::Markdown)
+        } else if SOURCE_EXTENSIONS.contains(&ext.as_str()) {
+            Some(Filter::Code)
+        } else {
+            None
+        }
+    }
+
+    /// The name recorded in the JSON `filter` field.
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Code => "code",
+            Filter::Markdown => "markdown",
+        }
+    }
+
+    /// `args` with this filter's flag turned on.
+    pub fn apply(self, args: &Args) -> Args {
+        let mut args = args.clone();
+        match self {
+            Filter::Code => args.code = true,
+            Filter::Markdown => args.markdown = true,
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_path() {
+        assert_eq!(
+            Filter::for_path(Path::new("docs/README.MD")),
+            Some(Filter::Markdown)
+        );
+        assert_eq!(
+            Filter::for_path(Path::new("src/main.rs")),
+            Some(Filter::Code)
+        );
+        assert_eq!(Filter::for_path(Path::new("notes.txt")), None);
+        assert_eq!(Filter::for_path(Path::new("Makefile")), None);
+    }
+}
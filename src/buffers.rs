@@ -0,0 +1,141 @@
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
+use std::thread::LocalKey;
+
+/// Buffers that grew past this are freed rather than kept for the next file,
+/// so one large input does not pin its size on every worker.
+const POOLED_CAPACITY_LIMIT: usize = 8 * 1024 * 1024;
+
+thread_local! {
+    /// File contents on the buffered (non-mmap) path.
+    pub static READ: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+    /// Output of `count::decode_to_utf8`.
+    pub static DECODED: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+    /// Gzip input after decompression.
+    pub static INFLATED: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+    /// Output of `--code` / `--markdown` filtering.
+    pub static FILTERED: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// An empty buffer taken from a per-thread slot and handed back on drop, so
+/// each rayon worker reuses its allocations across files. A worker that
+/// picks up another file while waiting inside this one finds the slot empty
+/// and allocates, rather than sharing.
+pub struct Pooled {
+    slot: &'static LocalKey<Cell<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl Pooled {
+    pub fn take(slot: &'static LocalKey<Cell<Vec<u8>>>) -> Self {
+        let mut buffer = slot.take();
+        buffer.clear();
+        Pooled { slot, buffer }
+    }
+}
+
+impl Deref for Pooled {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for Pooled {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        if self.buffer.capacity() <= POOLED_CAPACITY_LIMIT {
+            let buffer = std::mem::take(&mut self.buffer);
+            // The slot is gone while its thread shuts down; nothing to keep.
+            let _ = self.slot.try_with(|slot| slot.set(buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count;
+    use std::alloc::{GlobalAlloc, Layout};
+
+    /// Allocations at least this big are counted; per-line scratch strings
+    /// stay below it.
+    const LARGE: usize = 4096;
+
+    thread_local! {
+        static LARGE_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if layout.size() >= LARGE {
+                let _ = LARGE_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            }
+            unsafe { mimalloc::MiMalloc.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { mimalloc::MiMalloc.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            if new_size >= LARGE {
+                let _ = LARGE_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            }
+            unsafe { mimalloc::MiMalloc.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+
+    fn large_allocations(run: impl FnOnce()) -> usize {
+        let before = LARGE_ALLOCATIONS.with(Cell::get);
+        run();
+        LARGE_ALLOCATIONS.with(Cell::get) - before
+    }
+
+    fn decode_and_filter(data: &[u8]) -> usize {
+        let mut decoded = Pooled::take(&DECODED);
+        let mut filtered = Pooled::take(&FILTERED);
+        let (text, _) = count::decode_to_utf8(data, Some("windows-1252"), false, &mut decoded);
+        count::filter_code_comments(text, &mut filtered);
+        filtered.len()
+    }
+
+    #[test]
+    fn test_second_file_reuses_buffers() {
+        let data = b"int caf\xe9 = 1; // comment\n".repeat(1000);
+        let mut first = 0;
+        assert!(large_allocations(|| first = decode_and_filter(&data)) > 0);
+        let mut second = 0;
+        assert_eq!(large_allocations(|| second = decode_and_filter(&data)), 0);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_nested_take_gets_its_own_buffer() {
+        let mut outer = Pooled::take(&READ);
+        outer.extend_from_slice(b"outer");
+        let inner = Pooled::take(&READ);
+        assert!(inner.is_empty());
+        drop(inner);
+        assert_eq!(&outer[..], b"outer");
+    }
+
+    #[test]
+    fn test_oversized_buffer_is_not_kept() {
+        let mut buffer = Pooled::take(&FILTERED);
+        buffer.reserve(POOLED_CAPACITY_LIMIT + 1);
+        drop(buffer);
+        assert_eq!(Pooled::take(&FILTERED).capacity(), 0);
+    }
+}
@@ -0,0 +1,152 @@
+use serde_json::Value;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use std::io::{self, Write};
+
+/// Decimal places kept for floating point values in `--deterministic` output
+/// unless `--precision` says otherwise.
+pub const DETERMINISTIC_PRECISION: usize = 6;
+
+/// `value` rounded to `decimals` places, fixed-point; text output and
+/// `FixedFloats` both go through here so they agree digit for digit.
+pub fn fixed(value: f64, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    // Rounding can leave "-0.000000"; print it as plain zero.
+    match text
+        .strip_prefix('-')
+        .filter(|digits| digits.bytes().all(|b| b == b'0' || b == b'.'))
+    {
+        Some(zero) => zero.to_string(),
+        None => text,
+    }
+}
+
+/// JSON whose floats are written with a fixed number of decimals and never
+/// in scientific notation, so last-bit differences from summation order
+/// cannot change the output. Layout is left to the wrapped formatter.
+struct FixedFloats<F> {
+    inner: F,
+    decimals: usize,
+}
+
+impl<F: Formatter> Formatter for FixedFloats<F> {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        writer.write_all(fixed(value, self.decimals).as_bytes())
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+/// Pretty-prints a report. Object keys are always sorted (`serde_json::Map`
+/// is ordered); with `decimals`, floats are also written fixed-point.
+pub fn to_string_pretty(value: &Value, decimals: Option<usize>) -> serde_json::Result<String> {
+    let Some(decimals) = decimals else {
+        return serde_json::to_string_pretty(value);
+    };
+    let mut out = Vec::new();
+    let formatter = FixedFloats {
+        inner: PrettyFormatter::new(),
+        decimals,
+    };
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    serde::Serialize::serialize(value, &mut serializer)?;
+    Ok(String::from_utf8(out).expect("serde_json writes UTF-8"))
+}
+
+/// Single-line variant of `to_string_pretty`, for `--ndjson` records.
+pub fn to_string_compact(value: &Value, decimals: Option<usize>) -> serde_json::Result<String> {
+    let Some(decimals) = decimals else {
+        return serde_json::to_string(value);
+    };
+    let mut out = Vec::new();
+    let formatter = FixedFloats {
+        inner: CompactFormatter,
+        decimals,
+    };
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    serde::Serialize::serialize(value, &mut serializer)?;
+    Ok(String::from_utf8(out).expect("serde_json writes UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_floats() {
+        let value = serde_json::json!({"b": 1e-7, "a": [2.5, 1e21, -1e-9], "n": 3});
+        let json = to_string_pretty(&value, Some(DETERMINISTIC_PRECISION)).unwrap();
+        let compact: String = json.split_whitespace().collect();
+        assert_eq!(
+            compact,
+            r#"{"a":[2.500000,1000000000000000000000.000000,0.000000],"b":0.000000,"n":3}"#
+        );
+        assert_eq!(
+            to_string_compact(&value, Some(DETERMINISTIC_PRECISION)).unwrap(),
+            compact
+        );
+        assert_eq!(
+            to_string_pretty(&value, None).unwrap(),
+            serde_json::to_string_pretty(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fixed_rounding() {
+        assert_eq!(fixed(12.340000000000002, 2), "12.34");
+        assert_eq!(fixed(2.675, 0), "3");
+        assert_eq!(fixed(0.125, 1), "0.1");
+        assert_eq!(fixed(-0.0004, 3), "0.000");
+        assert_eq!(fixed(-1.5, 1), "-1.5");
+        let value = serde_json::json!({"mean": 12.340000000000002, "n": 7});
+        assert_eq!(
+            to_string_compact(&value, Some(1)).unwrap(),
+            r#"{"mean":12.3,"n":7}"#
+        );
+        assert_eq!(
+            to_string_compact(&value, Some(0)).unwrap(),
+            r#"{"mean":12,"n":7}"#
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::io;
+
+const DEFAULT_TEST_PATTERNS: [&str; 10] = [
+    "**/tests/**",
+    "**/test/**",
+    "**/*_test.rs",
+    "**/*_test.go",
+    "**/test_*.py",
+    "**/*_test.py",
+    "**/*.spec.ts",
+    "**/*.test.ts",
+    "**/*.spec.js",
+    "**/*.test.js",
+];
+
+pub struct TestClassifier {
+    set: GlobSet,
+}
+
+impl TestClassifier {
+    pub fn new(extra: &[String]) -> io::Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in DEFAULT_TEST_PATTERNS
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+        {
+            let glob =
+                Glob::new(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            builder.add(glob);
+        }
+        let set = builder
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self { set })
+    }
+
+    pub fn is_test(&self, path: &str) -> bool {
+        self.set.is_match(path.replace('\\', "/"))
+    }
+
+    pub fn category(&self, path: &str) -> &'static str {
+        if self.is_test(path) { "test" } else { "source" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_conventions() {
+        let classifier = TestClassifier::new(&[]).unwrap();
+        assert!(classifier.is_test("tests/integration.rs"));
+        assert!(classifier.is_test("crate/src/parser_test.rs"));
+        assert!(classifier.is_test("pkg/test_utils.py"));
+        assert!(classifier.is_test("web/app.spec.ts"));
+        assert!(!classifier.is_test("src/main.rs"));
+        assert!(!classifier.is_test("src/contest.py"));
+    }
+
+    #[test]
+    fn test_extra_patterns() {
+        let classifier = TestClassifier::new(&["**/fixtures/**".to_string()]).unwrap();
+        assert!(classifier.is_test("data/fixtures/a.json"));
+        assert_eq!(classifier.category("src/lib.rs"), "source");
+    }
+}
@@ -0,0 +1,266 @@
+const SCAN_LINES: usize = 10;
+const MARKER: &str = "kz:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MaxWords,
+    MaxLines,
+    MinWords,
+    MaxLineLength,
+}
+
+impl Limit {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "max-words" => Some(Limit::MaxWords),
+            "max-lines" => Some(Limit::MaxLines),
+            "min-words" => Some(Limit::MinWords),
+            "max-line-length" => Some(Limit::MaxLineLength),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Limit::MaxWords => "max-words",
+            Limit::MaxLines => "max-lines",
+            Limit::MinWords => "min-words",
+            Limit::MaxLineLength => "max-line-length",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Directive {
+    pub limit: Limit,
+    pub value: usize,
+}
+
+impl Directive {
+    pub fn is_satisfied(&self, actual: usize) -> bool {
+        match self.limit {
+            Limit::MinWords => actual >= self.value,
+            _ => actual <= self.value,
+        }
+    }
+
+    pub fn expectation(&self) -> String {
+        let op = match self.limit {
+            Limit::MinWords => ">=",
+            _ => "<=",
+        };
+        format!("{} {}", op, self.value)
+    }
+}
+
+/// The `kz:` directives of the first and last ten lines of an input, e.g.
+/// `<!-- kz: max-words=500 max-lines=40 -->`, kept line by line so the
+/// blocks of a streamed file can be joined with `extend`.
+#[derive(Debug, Default, Clone)]
+pub struct Scan {
+    head: Vec<Line>,
+    /// The last lines not already in `head`.
+    tail: Vec<Line>,
+    lines: usize,
+}
+
+/// What one line holds after its `kz:` marker.
+#[derive(Debug, Default, Clone)]
+struct Line {
+    directives: Vec<Directive>,
+    /// `key=value` tokens with an unknown key or a value that is not a
+    /// number, e.g. a mistyped `max-word=1`.
+    unrecognised: Vec<String>,
+}
+
+impl Scan {
+    /// Reads only the lines it keeps, plus a newline count.
+    pub fn new(data: &[u8]) -> Scan {
+        if data.is_empty() {
+            return Scan::default();
+        }
+        let body = data.strip_suffix(b"\n").unwrap_or(data);
+        let lines = memchr::memchr_iter(b'\n', body).count() + 1;
+        let directives = |line: &[u8]| parse_line(&String::from_utf8_lossy(line));
+        let head: Vec<_> = body
+            .split(|&b| b == b'\n')
+            .take(SCAN_LINES)
+            .map(directives)
+            .collect();
+        let mut tail: Vec<_> = body
+            .rsplit(|&b| b == b'\n')
+            .take(SCAN_LINES.min(lines - head.len()))
+            .map(directives)
+            .collect();
+        tail.reverse();
+        Scan { head, tail, lines }
+    }
+
+    fn has_gap(&self) -> bool {
+        self.lines > self.head.len() + self.tail.len()
+    }
+
+    /// Appends the scan of the input that follows this one.
+    pub fn extend(&mut self, later: &Scan) {
+        let lines = self.lines + later.lines;
+        let known: Vec<Option<&Line>> = [&*self, later]
+            .into_iter()
+            .flat_map(|scan| {
+                scan.head
+                    .iter()
+                    .map(Some)
+                    .chain(scan.has_gap().then_some(None))
+                    .chain(scan.tail.iter().map(Some))
+            })
+            .collect();
+        let head: Vec<Line> = known
+            .iter()
+            .take(SCAN_LINES)
+            .map_while(|line| line.cloned())
+            .collect();
+        let tail_len = SCAN_LINES.min(lines - head.len());
+        let tail = known[known.len() - tail_len..]
+            .iter()
+            .flatten()
+            .map(|line| (*line).clone())
+            .collect();
+        *self = Scan { head, tail, lines };
+    }
+
+    pub fn directives(&self) -> Vec<Directive> {
+        self.head
+            .iter()
+            .chain(&self.tail)
+            .flat_map(|line| line.directives.iter().copied())
+            .collect()
+    }
+
+    /// The `key=value` tokens after a `kz:` marker that are not directives.
+    pub fn unrecognised(&self) -> Vec<&str> {
+        self.head
+            .iter()
+            .chain(&self.tail)
+            .flat_map(|line| line.unrecognised.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+fn parse_line(line: &str) -> Line {
+    let mut parsed = Line::default();
+    let Some(start) = line.find(MARKER) else {
+        return parsed;
+    };
+    for token in line[start + MARKER.len()..].split_whitespace() {
+        let Some((key, value)) = token.split_once('=') else {
+            continue;
+        };
+        let value = value.trim_end_matches(|c: char| !c.is_ascii_digit());
+        match (Limit::parse(key), value.parse()) {
+            (Some(limit), Ok(value)) => parsed.directives.push(Directive { limit, value }),
+            _ => parsed.unrecognised.push(token.to_string()),
+        }
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &[u8]) -> Vec<Directive> {
+        Scan::new(data).directives()
+    }
+
+    #[test]
+    fn test_parse_html_comment() {
+        let directives = parse(b"<!-- kz: max-words=500 min-words=10 -->\n# Title\n");
+        assert_eq!(
+            directives,
+            vec![
+                Directive {
+                    limit: Limit::MaxWords,
+                    value: 500
+                },
+                Directive {
+                    limit: Limit::MinWords,
+                    value: 10
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_scans_head_and_tail_only() {
+        let mut text = String::from("first\n");
+        text.push_str(&"body\n".repeat(30));
+        text.push_str("// kz: max-lines=40\n");
+        assert_eq!(parse(text.as_bytes()).len(), 1);
+
+        let mut middle = "body\n".repeat(15);
+        middle.push_str("kz: max-lines=1\n");
+        middle.push_str(&"body\n".repeat(15));
+        assert!(parse(middle.as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_extend_matches_whole_input() {
+        let mut text = String::new();
+        for i in 0..45 {
+            text.push_str(&format!("line {} kz: max-lines={}\n", i, i));
+        }
+        let whole = parse(text.as_bytes());
+        assert_eq!(whole.len(), 20);
+        let bytes = text.as_bytes();
+        let splits: Vec<usize> = bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .map(|(i, _)| i + 1)
+            .collect();
+        for &split in &splits {
+            let mut scan = Scan::new(&bytes[..split]);
+            scan.extend(&Scan::new(&bytes[split..]));
+            assert_eq!(scan.directives(), whole, "split at {}", split);
+        }
+        let short = b"kz: max-lines=1\nkz: max-lines=2\n";
+        let mut scan = Scan::new(&short[..16]);
+        scan.extend(&Scan::new(&short[16..]));
+        assert_eq!(scan.directives(), parse(short));
+    }
+
+    #[test]
+    fn test_parse_sets_aside_unknown_and_malformed() {
+        let scan = Scan::new(b"/* kz: max-line-length=100*/ kz: colour=red max-lines=x\n");
+        assert_eq!(
+            scan.directives(),
+            vec![Directive {
+                limit: Limit::MaxLineLength,
+                value: 100
+            }]
+        );
+        assert_eq!(scan.unrecognised(), vec!["colour=red", "max-lines=x"]);
+    }
+
+    #[test]
+    fn test_mistyped_key_is_unrecognised() {
+        let scan = Scan::new(b"<!-- kz: max-word=1 -->\none two three\n");
+        assert!(scan.directives().is_empty());
+        assert_eq!(scan.unrecognised(), vec!["max-word=1"]);
+    }
+
+    #[test]
+    fn test_is_satisfied() {
+        let max = Directive {
+            limit: Limit::MaxWords,
+            value: 5,
+        };
+        assert!(max.is_satisfied(5));
+        assert!(!max.is_satisfied(6));
+        let min = Directive {
+            limit: Limit::MinWords,
+            value: 5,
+        };
+        assert!(min.is_satisfied(5));
+        assert!(!min.is_satisfied(4));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::config::Args;
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// ANSI styling for text rows: bold file names, the first counter turned
+/// yellow or red past `--color-warn` / `--color-error`, and a cyan total.
+/// Every method returns its input unchanged when color is off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorConfig {
+    pub enabled: bool,
+    pub warn: Option<usize>,
+    pub error: Option<usize>,
+}
+
+impl ColorConfig {
+    /// `args.color` is already resolved by `Args::normalize`.
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            enabled: args.color,
+            warn: args.color_warn,
+            error: args.color_error,
+        }
+    }
+
+    fn paint(&self, style: &str, s: &str) -> String {
+        if !self.enabled || s.is_empty() {
+            return s.to_string();
+        }
+        format!("{}{}{}", style, s, RESET)
+    }
+
+    pub fn colorize_filename(&self, s: &str) -> String {
+        self.paint(BOLD, s)
+    }
+
+    /// The value of counter `metric_idx` in display order. Only the primary
+    /// (first) counter is checked against the thresholds.
+    pub fn colorize_value(&self, v: usize, metric_idx: usize) -> String {
+        let text = v.to_string();
+        if metric_idx != 0 {
+            return text;
+        }
+        if self.error.is_some_and(|limit| v > limit) {
+            self.paint(RED, &text)
+        } else if self.warn.is_some_and(|limit| v > limit) {
+            self.paint(YELLOW, &text)
+        } else {
+            text
+        }
+    }
+
+    pub fn colorize_total(&self, row: &str) -> String {
+        self.paint(CYAN, row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON: ColorConfig = ColorConfig {
+        enabled: true,
+        warn: Some(100),
+        error: Some(1000),
+    };
+
+    #[test]
+    fn test_thresholds_apply_to_primary_metric() {
+        assert_eq!(ON.colorize_value(100, 0), "100");
+        assert_eq!(ON.colorize_value(101, 0), "\x1b[33m101\x1b[0m");
+        assert_eq!(ON.colorize_value(1001, 0), "\x1b[31m1001\x1b[0m");
+        assert_eq!(ON.colorize_value(5000, 1), "5000");
+    }
+
+    #[test]
+    fn test_disabled_is_plain() {
+        let off = ColorConfig {
+            enabled: false,
+            ..ON
+        };
+        assert_eq!(off.colorize_value(5000, 0), "5000");
+        assert_eq!(off.colorize_filename("a.txt"), "a.txt");
+        assert_eq!(off.colorize_total("9 total"), "9 total");
+        assert_eq!(ON.colorize_filename(""), "");
+    }
+}
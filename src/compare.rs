@@ -0,0 +1,333 @@
+use crate::config::CompareArgs;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+
+type Report = BTreeMap<String, BTreeMap<String, usize>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delta {
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    pub delta: i64,
+    /// Change relative to the old value; `None` when there was no old value
+    /// or it was zero.
+    pub relative: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub file: String,
+    pub status: Status,
+    pub deltas: BTreeMap<String, Delta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub counter: String,
+    pub relative: bool,
+}
+
+impl SortKey {
+    /// Parses `delta-<counter>` (absolute change) or `rel-<counter>`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (relative, counter) = if let Some(counter) = s.strip_prefix("delta-") {
+            (false, counter)
+        } else if let Some(counter) = s.strip_prefix("rel-") {
+            (true, counter)
+        } else {
+            return Err(format!(
+                "invalid sort key '{}' (expected delta-<counter> or rel-<counter>)",
+                s
+            ));
+        };
+        if counter.is_empty() {
+            return Err(format!("invalid sort key '{}': missing counter name", s));
+        }
+        Ok(Self {
+            counter: counter.to_string(),
+            relative,
+        })
+    }
+}
+
+/// Reads a `kz --json` report into per-file counters, skipping the total
+/// row and any non-numeric fields.
+pub fn parse_report(content: &str) -> Result<Report, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| format!("invalid JSON report: {}", e))?;
+    let rows = value
+        .as_array()
+        .ok_or_else(|| "invalid report: expected a JSON array".to_string())?;
+
+    let mut report = Report::new();
+    for row in rows {
+        let Some(file) = row.get("file").and_then(|f| f.as_str()) else {
+            continue;
+        };
+        if file == "total" {
+            continue;
+        }
+        let Some(counts) = row.get("counts").and_then(|c| c.as_object()) else {
+            continue;
+        };
+        let counts = counts
+            .get("filtered")
+            .and_then(|f| f.as_object())
+            .unwrap_or(counts);
+        let counters = counts
+            .iter()
+            .filter_map(|(name, v)| v.as_u64().map(|n| (name.clone(), n as usize)))
+            .collect();
+        report.insert(file.to_string(), counters);
+    }
+    Ok(report)
+}
+
+fn delta(old: Option<usize>, new: Option<usize>) -> Delta {
+    let before = old.unwrap_or(0) as i64;
+    let after = new.unwrap_or(0) as i64;
+    let relative = match old {
+        Some(o) if o > 0 => Some((after - before) as f64 / o as f64),
+        _ => None,
+    };
+    Delta {
+        old,
+        new,
+        delta: after - before,
+        relative,
+    }
+}
+
+/// Pairs up files from both reports. Files whose counters are all unchanged
+/// are dropped.
+pub fn diff(old: &Report, new: &Report) -> Vec<Entry> {
+    let files: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let empty = BTreeMap::new();
+
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let before = old.get(file);
+            let after = new.get(file);
+            let status = match (before, after) {
+                (None, Some(_)) => Status::Added,
+                (Some(_), None) => Status::Removed,
+                _ => Status::Changed,
+            };
+            let before_counts = before.unwrap_or(&empty);
+            let after_counts = after.unwrap_or(&empty);
+            let counters: BTreeSet<&String> =
+                before_counts.keys().chain(after_counts.keys()).collect();
+            let deltas: BTreeMap<String, Delta> = counters
+                .into_iter()
+                .map(|name| {
+                    let o = before.map(|c| c.get(name).copied().unwrap_or(0));
+                    let n = after.map(|c| c.get(name).copied().unwrap_or(0));
+                    (name.clone(), delta(o, n))
+                })
+                .collect();
+            if status == Status::Changed && deltas.values().all(|d| d.delta == 0) {
+                return None;
+            }
+            Some(Entry {
+                file: file.clone(),
+                status,
+                deltas,
+            })
+        })
+        .collect()
+}
+
+fn sort_value(entry: &Entry, key: &SortKey) -> f64 {
+    let Some(d) = entry.deltas.get(&key.counter) else {
+        return 0.0;
+    };
+    if key.relative {
+        match d.relative {
+            Some(r) => r.abs(),
+            None if d.delta != 0 => f64::INFINITY,
+            None => 0.0,
+        }
+    } else {
+        d.delta.unsigned_abs() as f64
+    }
+}
+
+/// Orders entries by the largest change in the sort counter first,
+/// breaking ties by file name, and keeps the first `top`.
+pub fn rank(mut entries: Vec<Entry>, key: &SortKey, top: usize) -> Vec<Entry> {
+    entries.sort_by(|a, b| {
+        sort_value(b, key)
+            .total_cmp(&sort_value(a, key))
+            .then_with(|| a.file.cmp(&b.file))
+    });
+    entries.truncate(top);
+    entries
+}
+
+fn fmt_opt(value: Option<usize>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+fn fmt_relative(relative: Option<f64>) -> String {
+    relative.map_or_else(|| "-".to_string(), |r| format!("{:+.1}%", r * 100.0))
+}
+
+pub fn render(entries: &[Entry], key: &SortKey) -> String {
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            let d = entry.deltas.get(&key.counter);
+            let status = match entry.status {
+                Status::Added => "added",
+                Status::Removed => "removed",
+                Status::Changed => "changed",
+            };
+            [
+                status.to_string(),
+                fmt_opt(d.and_then(|d| d.old)),
+                fmt_opt(d.and_then(|d| d.new)),
+                d.map_or_else(|| "-".to_string(), |d| format!("{:+}", d.delta)),
+                fmt_relative(d.and_then(|d| d.relative)),
+                entry.file.clone(),
+            ]
+        })
+        .collect();
+
+    let header = [
+        "status".to_string(),
+        "old".to_string(),
+        "new".to_string(),
+        "delta".to_string(),
+        "rel".to_string(),
+        "file".to_string(),
+    ];
+    let mut widths = [0usize; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (i, width) in widths.iter_mut().enumerate() {
+            *width = (*width).max(row[i].len());
+        }
+    }
+
+    let mut out = format!(
+        "{} ({})\n",
+        key.counter,
+        if key.relative { "relative" } else { "absolute" }
+    );
+    for row in std::iter::once(&header).chain(&rows) {
+        out.push_str(&format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {:>w4$}  {}\n",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            row[5],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        ));
+    }
+    out
+}
+
+fn load(path: &str) -> io::Result<Report> {
+    let content = fs::read_to_string(path)?;
+    parse_report(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+pub fn run(args: &CompareArgs) -> io::Result<()> {
+    let key =
+        SortKey::parse(&args.sort).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let old = load(&args.old)?;
+    let new = load(&args.new)?;
+    let entries = rank(diff(&old, &new), &key, args.top);
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&entries).map_err(io::Error::other)?;
+        println!("{}", json);
+    } else {
+        print!("{}", render(&entries, &key));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"[
+        {"file": "a.rs", "counts": {"lines": 100, "words": 500}},
+        {"file": "b.rs", "counts": {"lines": 10, "words": 40}},
+        {"file": "gone.rs", "counts": {"lines": 30, "words": 90}},
+        {"file": "same.rs", "counts": {"lines": 5, "words": 5}},
+        {"file": "total", "counts": {"lines": 145, "words": 635}}
+    ]"#;
+
+    const NEW: &str = r#"[
+        {"file": "a.rs", "counts": {"lines": 110, "words": 520}},
+        {"file": "b.rs", "counts": {"lines": 40, "words": 150}},
+        {"file": "new.rs", "counts": {"lines": 60, "words": 200}},
+        {"file": "same.rs", "counts": {"lines": 5, "words": 5}}
+    ]"#;
+
+    fn entries() -> Vec<Entry> {
+        diff(&parse_report(OLD).unwrap(), &parse_report(NEW).unwrap())
+    }
+
+    #[test]
+    fn test_parse_report_skips_total() {
+        let report = parse_report(OLD).unwrap();
+        assert_eq!(report.len(), 4);
+        assert_eq!(report["a.rs"]["lines"], 100);
+        assert!(parse_report("{}").is_err());
+    }
+
+    #[test]
+    fn test_diff_marks_added_removed_changed() {
+        let entries = entries();
+        let status: BTreeMap<&str, Status> = entries
+            .iter()
+            .map(|e| (e.file.as_str(), e.status))
+            .collect();
+        assert_eq!(status.len(), 4);
+        assert_eq!(status["new.rs"], Status::Added);
+        assert_eq!(status["gone.rs"], Status::Removed);
+        assert_eq!(status["b.rs"], Status::Changed);
+
+        let added = entries.iter().find(|e| e.file == "new.rs").unwrap();
+        assert_eq!(added.deltas["lines"].delta, 60);
+        assert_eq!(added.deltas["lines"].relative, None);
+    }
+
+    #[test]
+    fn test_rank_absolute_and_relative() {
+        let by_delta = rank(entries(), &SortKey::parse("delta-lines").unwrap(), 2);
+        let files: Vec<&str> = by_delta.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, vec!["new.rs", "b.rs"]);
+
+        let by_rel = rank(entries(), &SortKey::parse("rel-lines").unwrap(), 10);
+        let files: Vec<&str> = by_rel.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, vec!["new.rs", "b.rs", "gone.rs", "a.rs"]);
+    }
+
+    #[test]
+    fn test_sort_key_validation() {
+        assert!(SortKey::parse("lines").is_err());
+        assert!(SortKey::parse("delta-").is_err());
+        assert!(SortKey::parse("rel-words").unwrap().relative);
+    }
+}
@@ -1,9 +1,106 @@
-use clap::Parser;
+use crate::count::{LinePrefix, MIN_CHUNK_SIZE, Tokenizer, TuningParams};
+use crate::sniff::SniffType;
+use crate::timestamp::DateFormat;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io::IsTerminal;
 
-#[derive(Parser, Debug)]
-#[command(version, about = "Fast wc replacement", long_about = None)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Start the largest files first
+    Size,
+    /// Process files in the order given
+    Input,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlineStyle {
+    /// Unix line feeds
+    Lf,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSeparator {
+    /// The platform's own separator (backslash on Windows)
+    Auto,
+    /// Forward slashes
+    Slash,
+    /// Backslashes
+    Backslash,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnlySection {
+    /// Just the --stats block
+    Stats,
+    /// Just the --histogram chart
+    Histogram,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Lines,
+    Words,
+    Bytes,
+    Chars,
+    #[value(name = "max_line_length", alias = "max-line-length")]
+    MaxLineLength,
+    #[value(name = "blank_lines", alias = "blank-lines")]
+    BlankLines,
+    #[value(name = "unique_words", alias = "unique-words")]
+    UniqueWords,
+    Pattern,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotalOf {
+    /// Every file row
+    All,
+    /// Files whose content was counted (not detected as binary)
+    Counted,
+    /// Files with at least one --pattern match
+    Matching,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Compare two `kz --json` reports and list the files that changed most
+    Compare(CompareArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompareArgs {
+    #[arg(help = "Earlier JSON report")]
+    pub old: String,
+
+    #[arg(help = "Later JSON report")]
+    pub new: String,
+
+    #[arg(long = "top", default_value_t = 10, help = "Number of files to list")]
+    pub top: usize,
+
+    #[arg(
+        long = "sort",
+        value_name = "KEY",
+        default_value = "delta-lines",
+        help = "delta-<counter> for absolute change, rel-<counter> for relative change"
+    )]
+    pub sort: String,
+
+    #[arg(long = "json", help = "Output the deltas as JSON")]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(name = "kz", version, about = "Fast wc replacement", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(help = "Files to process (reads from stdin if not provided)")]
     pub files: Vec<String>,
 
@@ -26,8 +123,405 @@ pub struct Args {
     )]
     pub max_line_length: bool,
 
-    #[arg(long = "pattern", help = "Count occurrences of a specific pattern")]
-    pub pattern: Option<String>,
+    #[arg(
+        long = "max-line-width",
+        help = "Print width of the longest line in terminal columns (tabs stop every 8)"
+    )]
+    pub max_line_width: bool,
+
+    #[arg(
+        long = "max-word-length",
+        help = "Print length of the longest whitespace-separated word, in characters"
+    )]
+    pub max_word_length: bool,
+
+    #[arg(
+        long = "pattern",
+        help = "Count occurrences of a specific pattern (repeat for one column per pattern)"
+    )]
+    pub pattern: Vec<String>,
+
+    #[arg(
+        long = "pattern-glob",
+        help = "Treat --pattern as a whole-line wildcard (* and ?) and count matching lines"
+    )]
+    pub pattern_glob: bool,
+
+    #[arg(
+        long = "pattern-lines",
+        requires = "pattern",
+        conflicts_with = "pattern_glob",
+        help = "Count lines containing --pattern at least once (like grep -c) instead of occurrences"
+    )]
+    pub pattern_lines: bool,
+
+    #[arg(
+        long = "count-matching-lines",
+        requires = "pattern",
+        help = "Also count lines containing any --pattern at least once, in a matching_lines column"
+    )]
+    pub count_matching_lines: bool,
+
+    #[arg(
+        long = "regex",
+        requires = "pattern",
+        conflicts_with = "pattern_glob",
+        help = "Treat --pattern as a regular expression and count its matches"
+    )]
+    pub regex: bool,
+
+    #[arg(
+        short = 'i',
+        long = "ignore-case",
+        requires = "pattern",
+        conflicts_with = "pattern_glob",
+        help = "Match --pattern (or --regex) case-insensitively"
+    )]
+    pub ignore_case: bool,
+
+    /// The compiled `--pattern`s when `--regex` is set, filled in by
+    /// `normalize()`.
+    #[arg(skip)]
+    pub pattern_regex: Vec<regex::bytes::Regex>,
+
+    #[arg(
+        long = "repeated-lines",
+        help = "Count lines identical to the line before them"
+    )]
+    pub repeated_lines: bool,
+
+    #[arg(
+        long = "unique-lines",
+        help = "Count distinct lines (like sort -u | wc -l); the total counts lines distinct across all files"
+    )]
+    pub unique_lines: bool,
+
+    #[arg(
+        long = "duplicate-lines",
+        help = "Count lines that occur more than once anywhere in the file, and their extra occurrences"
+    )]
+    pub duplicate_lines: bool,
+
+    #[arg(
+        long = "line-length-cardinality",
+        help = "Count distinct line lengths, listing them when there are at most 5"
+    )]
+    pub line_length_cardinality: bool,
+
+    #[arg(
+        long = "expect-width",
+        value_name = "N",
+        help = "Report lines whose length is not N bytes and exit nonzero if any exist"
+    )]
+    pub expect_width: Option<usize>,
+
+    #[arg(
+        long = "feff-count",
+        help = "Count U+FEFF (zero-width no-break space) after the start of the input, as left by concatenating BOM files"
+    )]
+    pub feff_count: bool,
+
+    #[arg(
+        long = "strip-feff",
+        help = "Remove every U+FEFF, including a leading BOM, before counting"
+    )]
+    pub strip_feff: bool,
+
+    #[arg(
+        long = "graphemes",
+        help = "Count grapheme clusters (user-perceived characters; a ZWJ emoji sequence is one)"
+    )]
+    pub graphemes: bool,
+
+    #[arg(
+        long = "require-complete",
+        help = "Fail before printing totals if any file could not be read"
+    )]
+    pub require_complete: bool,
+
+    #[arg(
+        long = "check",
+        help = "Check files against their embedded kz: directives (max-words, max-lines, min-words, max-line-length)"
+    )]
+    pub check: bool,
+
+    #[arg(
+        long = "diff-input",
+        help = "Read unified diffs (stdin or files) and count added/removed lines per file and extension"
+    )]
+    pub diff_input: bool,
+
+    #[arg(
+        long = "detect-encodings",
+        help = "Report the probable encoding of each file (from its first 64 KB) instead of counting"
+    )]
+    pub detect_encodings: bool,
+
+    #[arg(
+        long = "check-require",
+        requires = "check",
+        help = "With --check, fail files that have no kz: directives or an unrecognised one"
+    )]
+    pub check_require: bool,
+
+    #[arg(
+        long = "sample-files",
+        value_name = "RATE",
+        value_parser = parse_rate,
+        conflicts_with = "sample_files_count",
+        help = "Count a random fraction RATE (0-1] of the files and report estimated totals"
+    )]
+    pub sample_files: Option<f64>,
+
+    #[arg(
+        long = "sample-files-count",
+        value_name = "N",
+        help = "Count N randomly chosen files and report estimated totals"
+    )]
+    pub sample_files_count: Option<usize>,
+
+    #[arg(
+        long = "seed",
+        value_name = "N",
+        default_value_t = 0,
+        help = "Seed for --sample-files selection (same seed, same files)"
+    )]
+    pub seed: u64,
+
+    #[arg(
+        long = "threads",
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Worker threads for counting (default: one per CPU)"
+    )]
+    pub threads: Option<usize>,
+
+    #[arg(
+        long = "schedule",
+        value_enum,
+        default_value = "size",
+        help = "Order in which files are handed to worker threads"
+    )]
+    pub schedule: Schedule,
+
+    #[arg(
+        long = "value-only",
+        conflicts_with_all = [
+            "json", "ndjson", "csv", "stats", "histogram", "summary", "summary_only", "timing",
+            "check", "split_tests", "interval", "interval_lines", "serve", "serve_socket",
+        ],
+        help = "Print only the bare number (one counter, one input)"
+    )]
+    pub value_only: bool,
+
+    #[arg(
+        long = "prose-words",
+        help = "Count words containing at least one letter (skips numbers and punctuation)"
+    )]
+    pub prose_words: bool,
+
+    #[arg(
+        long = "sentences",
+        help = "Count sentences (ended by . ! ? or an ellipsis; abbreviations and decimals don't end one)"
+    )]
+    pub sentences: bool,
+
+    #[arg(
+        long = "paragraphs",
+        help = "Count paragraphs (runs of non-blank lines separated by blank lines)"
+    )]
+    pub paragraphs: bool,
+
+    #[arg(
+        long = "lang-detect",
+        help = "Guess each file's language from its character trigrams (files under 200 letters report unknown)"
+    )]
+    pub lang_detect: bool,
+
+    #[arg(
+        long = "reading-time",
+        help = "Estimate silent reading time at 238 words per minute"
+    )]
+    pub reading_time: bool,
+
+    #[arg(long = "ttr", help = "Type-token ratio: unique words divided by words")]
+    pub ttr: bool,
+
+    #[arg(
+        long = "with-raw",
+        conflicts_with = "value_only",
+        help = "With --code, --markdown or --html, also show counts for the unfiltered input"
+    )]
+    pub with_raw: bool,
+
+    #[arg(
+        long = "trend",
+        conflicts_with = "value_only",
+        help = "Show each total's change since the previous run over the same inputs and flags"
+    )]
+    pub trend: bool,
+
+    #[arg(
+        long = "tokenizer",
+        alias = "word-boundary",
+        value_name = "MODE",
+        default_value = "whitespace",
+        value_parser = clap::value_parser!(Tokenizer),
+        help = "How words are delimited for -w and --unique: whitespace, unicode, identifiers or kmer:N"
+    )]
+    pub tokenizer: Tokenizer,
+
+    #[arg(
+        long = "unique-exact-spill",
+        value_name = "DIR",
+        requires = "unique",
+        help = "For --unique, spill words to temporary files in DIR when over --spill-budget"
+    )]
+    pub unique_exact_spill: Option<String>,
+
+    #[arg(
+        long = "spill-budget",
+        value_name = "SIZE",
+        default_value = "256M",
+        value_parser = parse_size,
+        help = "Memory budget for --unique-exact-spill before words go to disk"
+    )]
+    pub spill_budget: usize,
+
+    #[arg(
+        long = "head",
+        value_name = "N",
+        conflicts_with_all = ["tail", "head_bytes"],
+        help = "Count only the first N lines of each file"
+    )]
+    pub head: Option<usize>,
+
+    #[arg(
+        long = "tail",
+        value_name = "N",
+        conflicts_with = "head_bytes",
+        help = "Count only the last N lines of each file"
+    )]
+    pub tail: Option<usize>,
+
+    #[arg(
+        long = "head-bytes",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Count only the first SIZE bytes of each file (e.g. 1M)"
+    )]
+    pub head_bytes: Option<usize>,
+
+    #[arg(
+        long = "dump-unique-words",
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-",
+        help = "Also write the sorted unique words: merged to stdout, or one .words file per input under PATH (keeps every word in memory)"
+    )]
+    pub dump_unique_words: Option<String>,
+
+    #[arg(
+        long = "verify",
+        help = "Count every file twice, chunked and serial, and report any difference as an internal error (exit 3; doubles runtime)"
+    )]
+    pub verify: bool,
+
+    #[arg(
+        long = "line-endings",
+        help = "Report LF, CRLF and lone CR line endings per file, and whether they are mixed"
+    )]
+    pub line_endings: bool,
+
+    #[arg(
+        long = "normalize-check",
+        value_enum,
+        value_name = "STYLE",
+        help = "Report line endings that would change (and bytes saved) when normalizing to STYLE; nothing is modified"
+    )]
+    pub normalize_check: Option<NewlineStyle>,
+
+    #[arg(
+        long = "fail-if-normalize-needed",
+        requires = "normalize_check",
+        help = "With --normalize-check, exit 1 if any file would change"
+    )]
+    pub fail_if_normalize_needed: bool,
+
+    #[arg(
+        long = "fs-meta",
+        help = "Add owner, permission bits and mtime per file (JSON) and a summary of owners and world-writable files (Unix only)"
+    )]
+    pub fs_meta: bool,
+
+    #[arg(
+        long = "no-filename",
+        conflicts_with = "filename_first",
+        help = "Omit file names (and the total label) from text output"
+    )]
+    pub no_filename: bool,
+
+    #[arg(
+        long = "filename-first",
+        help = "Print the file name before the counts, padded so the counts line up"
+    )]
+    pub filename_first: bool,
+
+    #[arg(
+        long = "top-lines",
+        value_name = "N",
+        help = "List the N most frequent lines (merged across files with --total-only)"
+    )]
+    pub top_lines: Option<usize>,
+
+    #[arg(
+        long = "skip-fields",
+        value_name = "N",
+        default_value_t = 0,
+        help = "For --top-lines, ignore the first N whitespace-separated fields (e.g. timestamps)"
+    )]
+    pub skip_fields: usize,
+
+    #[arg(
+        long = "top-words",
+        value_name = "N",
+        help = "List the N most frequent words (split like --unique) under each file and the total"
+    )]
+    pub top_words: Option<usize>,
+
+    #[arg(
+        long = "line-prefix-groups",
+        value_name = "N",
+        conflicts_with = "line_prefix_fields",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Group lines by their first N characters and list the largest groups"
+    )]
+    pub line_prefix_groups: Option<usize>,
+
+    #[arg(
+        long = "line-prefix-fields",
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Group lines by their first N whitespace-separated fields (e.g. the log level)"
+    )]
+    pub line_prefix_fields: Option<usize>,
+
+    #[arg(
+        long = "group-by-timestamp",
+        value_name = "FORMAT",
+        value_parser = DateFormat::parse,
+        help = "Lines and bytes per date at the start of each line: auto (ISO 8601) or a pattern like %d/%b/%Y"
+    )]
+    pub group_by_timestamp: Option<DateFormat>,
+
+    #[arg(
+        long = "top-lines-width",
+        value_name = "CHARS",
+        default_value_t = 120,
+        help = "Truncate --top-lines output lines to CHARS characters"
+    )]
+    pub top_lines_width: usize,
 
     #[arg(
         long = "files0-from",
@@ -46,6 +540,20 @@ pub struct Args {
     #[arg(long = "json", help = "Output results as JSON")]
     pub json: bool,
 
+    #[arg(
+        long = "ndjson",
+        conflicts_with = "json",
+        help = "Output one JSON object per line as each file finishes, then the total"
+    )]
+    pub ndjson: bool,
+
+    #[arg(
+        long = "csv",
+        conflicts_with_all = ["json", "ndjson", "stats", "only"],
+        help = "Output results as CSV (header row, one row per file, total row)"
+    )]
+    pub csv: bool,
+
     #[arg(long = "stats", help = "Show detailed statistics")]
     pub stats: bool,
 
@@ -65,14 +573,162 @@ pub struct Args {
     )]
     pub exclude: Vec<String>,
 
-    #[arg(long = "fast", help = "Skip UTF-8 validation for faster processing")]
+    #[arg(
+        long = "include",
+        value_name = "PATTERN",
+        help = "When recursing, count only files matching pattern (can be used multiple times; --exclude wins)"
+    )]
+    pub include: Vec<String>,
+
+    #[arg(
+        long = "ext",
+        value_name = "EXTENSION",
+        help = "When recursing, count only files with this extension, ignoring case (can be used multiple times)"
+    )]
+    pub extensions: Vec<String>,
+
+    #[arg(
+        long = "hidden",
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set,
+        help = "Scan hidden files and directories when recursing (--hidden=false to skip)"
+    )]
+    pub hidden: bool,
+
+    #[arg(
+        long = "strict-walk",
+        help = "Fail if any directory or file cannot be read while recursing"
+    )]
+    pub strict_walk: bool,
+
+    #[arg(
+        long = "include-vcs-dirs",
+        help = "Recurse into .git, .hg, .svn and .jj directories"
+    )]
+    pub include_vcs_dirs: bool,
+
+    #[arg(
+        long = "git-ignore",
+        overrides_with = "no_git_ignore",
+        help = "When recursing, skip what git ignores (.gitignore, .git/info/exclude, global excludes)"
+    )]
+    pub git_ignore: bool,
+
+    #[arg(
+        long = "no-git-ignore",
+        overrides_with = "git_ignore",
+        help = "Recurse without applying git's ignore rules (the default)"
+    )]
+    pub no_git_ignore: bool,
+
+    #[arg(
+        long = "no-kzignore",
+        help = "Do not read .kzignore files from the scanned directories"
+    )]
+    pub no_kzignore: bool,
+
+    #[arg(
+        long = "split-tests",
+        help = "Report separate subtotals for source and test files"
+    )]
+    pub split_tests: bool,
+
+    #[arg(
+        long = "test-pattern",
+        value_name = "GLOB",
+        help = "Additional glob marking files as tests (can be used multiple times)"
+    )]
+    pub test_pattern: Vec<String>,
+
+    #[arg(
+        long = "tests-only",
+        conflicts_with = "no_tests",
+        help = "Count only test files"
+    )]
+    pub tests_only: bool,
+
+    #[arg(long = "no-tests", help = "Skip test files")]
+    pub no_tests: bool,
+
+    #[arg(
+        long = "total-of",
+        value_name = "ROWS",
+        value_enum,
+        default_value = "all",
+        help = "Which file rows the total adds up"
+    )]
+    pub total_of: TotalOf,
+
+    #[arg(
+        long = "exclude-from-total",
+        value_name = "GLOB",
+        help = "Show files matching GLOB but leave them out of the total (can be used multiple times)"
+    )]
+    pub exclude_from_total: Vec<String>,
+
+    #[arg(
+        long = "sniff-type",
+        value_name = "TYPE",
+        value_enum,
+        help = "Keep only files whose content looks like TYPE (costs one 512-byte read per file before counting)"
+    )]
+    pub sniff_type: Option<SniffType>,
+
+    #[arg(
+        long = "fast",
+        conflicts_with = "encoding",
+        help = "Skip UTF-8 validation for faster processing"
+    )]
     pub fast: bool,
 
     #[arg(long = "histogram", help = "Show line length histogram")]
     pub histogram: bool,
 
+    #[arg(
+        long = "indent-stats",
+        help = "Show how lines are indented (tabs, spaces, mixed) and the deepest indentation"
+    )]
+    pub indent_stats: bool,
+
+    #[arg(
+        long = "tab-width",
+        value_name = "N",
+        default_value_t = 4,
+        requires = "indent_stats",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Columns per tab stop for --indent-stats"
+    )]
+    pub tab_width: usize,
+
+    #[arg(
+        long = "only",
+        value_name = "SECTION",
+        help = "Print only the stats or histogram section, without the counter row"
+    )]
+    pub only: Option<OnlySection>,
+
+    #[arg(
+        long = "sort",
+        value_name = "FIELD",
+        conflicts_with = "ndjson",
+        help = "Sort file rows by FIELD, largest first; the total row stays last"
+    )]
+    pub sort_field: Option<SortField>,
+
+    #[arg(
+        long = "sort-asc",
+        requires = "sort_field",
+        help = "With --sort, put the smallest values first"
+    )]
+    pub sort_asc: bool,
+
     #[arg(
         long = "code",
+        conflicts_with = "markdown",
         help = "Count only code (skip comments and blank lines)"
     )]
     pub code: bool,
@@ -80,18 +736,119 @@ pub struct Args {
     #[arg(long = "markdown", help = "Count markdown text (skip code blocks)")]
     pub markdown: bool,
 
+    #[arg(
+        long = "html",
+        conflicts_with_all = ["code", "markdown"],
+        help = "Count the text of HTML/XML (skip tags, attributes and comments; decode common entities)"
+    )]
+    pub html: bool,
+
+    #[arg(
+        long = "cloc",
+        conflicts_with_all = ["code", "markdown", "html"],
+        help = "Print code, comment and blank line counts, with the comment rules of --code"
+    )]
+    pub cloc: bool,
+
+    #[arg(
+        long = "auto-filter",
+        conflicts_with = "cloc",
+        help = "Pick the filter per file from its extension: --markdown for .md, --code for source files (--code, --markdown or --html override)"
+    )]
+    pub auto_filter: bool,
+
+    #[arg(
+        long = "join-continuations",
+        help = "Treat a trailing backslash as joining the next line for -l, -L, --blank-lines, --stats and --histogram"
+    )]
+    pub join_continuations: bool,
+
     #[arg(short = 'v', long = "verbose", help = "Show warnings and errors")]
     pub verbose: bool,
 
+    #[arg(
+        long = "path-separator",
+        value_name = "STYLE",
+        default_value = "auto",
+        help = "Separator for printed paths on Windows: auto, slash or backslash"
+    )]
+    pub path_separator: PathSeparator,
+
+    #[arg(
+        long = "html-report",
+        value_name = "PATH",
+        help = "Also write a self-contained HTML report (table, histogram, extensions) to PATH"
+    )]
+    pub html_report: Option<String>,
+
+    #[arg(
+        long = "html-report-rows",
+        value_name = "N",
+        default_value_t = 1000,
+        help = "Per-file rows kept in the --html-report table"
+    )]
+    pub html_report_rows: usize,
+
     #[arg(long = "timing", help = "Show processing time for each file")]
     pub timing: bool,
 
+    #[arg(
+        long = "deterministic",
+        help = "Byte-identical output for identical inputs: no timing, files sorted by path, fixed float precision"
+    )]
+    pub deterministic: bool,
+
+    #[arg(
+        long = "precision",
+        value_name = "N",
+        help = "Decimal places for floating-point values in text and JSON (text keeps its own defaults, JSON full precision, --deterministic 6)"
+    )]
+    pub precision: Option<usize>,
+
+    #[arg(
+        long = "disk-usage",
+        help = "Print allocated bytes on disk (sparse files, block padding) next to the byte count"
+    )]
+    pub disk_usage: bool,
+
     #[arg(short = 'b', long = "blank-lines", help = "Print blank line counts")]
     pub blank_lines: bool,
 
+    #[arg(
+        long = "empty-lines",
+        help = "Print counts of empty (zero-length) lines"
+    )]
+    pub empty_lines: bool,
+
+    #[arg(
+        long = "whitespace-only-lines",
+        help = "Print counts of lines holding only spaces or tabs"
+    )]
+    pub whitespace_only_lines: bool,
+
+    #[arg(
+        long = "trailing-whitespace",
+        help = "Print counts of lines ending in a space or tab (before any \\r)"
+    )]
+    pub trailing_whitespace: bool,
+
     #[arg(long = "total-only", help = "Only show total, skip per-file output")]
     pub total_only: bool,
 
+    #[arg(
+        long = "decompress",
+        overrides_with = "no_decompress",
+        help = "Decompress gzip input whatever its name (.gz files are decompressed by default)"
+    )]
+    pub decompress: bool,
+
+    #[arg(
+        long = "no-decompress",
+        overrides_with = "decompress",
+        help = "Count .gz files as they are on disk"
+    )]
+    pub no_decompress: bool,
+
     #[arg(
         long = "encoding",
         value_name = "ENCODING",
@@ -99,26 +856,528 @@ pub struct Args {
     )]
     pub encoding: Option<String>,
 
+    #[arg(
+        long = "trust-detection",
+        help = "Use the auto-detected encoding even when the input is almost entirely valid UTF-8"
+    )]
+    pub trust_detection: bool,
+
     #[arg(long = "progress", help = "Show progress while processing files")]
     pub progress: bool,
+
+    #[arg(
+        long = "progress-json",
+        conflicts_with = "progress",
+        help = "Write NDJSON progress events (start, progress, skip, error, done) to stderr"
+    )]
+    pub progress_json: bool,
+
+    #[arg(
+        long = "progress-file",
+        value_name = "PATH",
+        conflicts_with = "progress",
+        help = "Write --progress-json events to PATH instead of stderr"
+    )]
+    pub progress_file: Option<String>,
+
+    #[arg(
+        long = "file-timeout",
+        value_name = "SECS",
+        help = "Skip files that take longer than SECS seconds to process"
+    )]
+    pub file_timeout: Option<f64>,
+
+    #[arg(
+        long = "summary",
+        help = "Print a human-friendly summary panel after the output"
+    )]
+    pub summary: bool,
+
+    #[arg(
+        long = "summary-only",
+        help = "Print only the summary panel, skipping per-file and total rows"
+    )]
+    pub summary_only: bool,
+
+    #[arg(
+        long = "ascii",
+        help = "Use plain ASCII instead of box-drawing characters"
+    )]
+    pub ascii: bool,
+
+    #[arg(
+        long = "tree",
+        conflicts_with_all = ["ndjson", "csv"],
+        help = "Show counts rolled up per directory as a tree, biggest first"
+    )]
+    pub tree: bool,
+
+    #[arg(
+        long = "tree-depth",
+        value_name = "N",
+        requires = "tree",
+        help = "With --tree, show only N levels below the top (deeper counts still roll up)"
+    )]
+    pub tree_depth: Option<usize>,
+
+    #[arg(
+        long = "color",
+        overrides_with = "no_color",
+        help = "Color text output even when stdout is not a terminal or NO_COLOR is set"
+    )]
+    pub color: bool,
+
+    #[arg(
+        long = "no-color",
+        overrides_with = "color",
+        help = "Never color output"
+    )]
+    pub no_color: bool,
+
+    #[arg(
+        long = "color-warn",
+        value_name = "N",
+        help = "Show the first counter in yellow when it exceeds N"
+    )]
+    pub color_warn: Option<usize>,
+
+    #[arg(
+        long = "color-error",
+        value_name = "N",
+        help = "Show the first counter in red when it exceeds N"
+    )]
+    pub color_error: Option<usize>,
+
+    #[arg(
+        long = "serve",
+        help = "Stay resident and answer newline-delimited JSON requests on stdin"
+    )]
+    pub serve: bool,
+
+    #[arg(
+        long = "serve-socket",
+        value_name = "PATH",
+        help = "Like --serve, but listen on a Unix socket at PATH"
+    )]
+    pub serve_socket: Option<String>,
+
+    #[arg(
+        long = "max-download",
+        value_name = "SIZE",
+        default_value = "100M",
+        value_parser = parse_size,
+        help = "Maximum size of a URL download (e.g. 512K, 100M, 1G)"
+    )]
+    pub max_download: usize,
+
+    #[arg(
+        long = "chunk-size",
+        value_name = "SIZE",
+        value_parser = parse_chunk_size,
+        help = "Bytes per parallel work chunk (default 1M, minimum 64K)"
+    )]
+    pub chunk_size: Option<usize>,
+
+    #[arg(
+        long = "debug-internals",
+        help = "Print how each input was read, decoded, filtered and split into chunks to stderr, for bug reports"
+    )]
+    pub debug_internals: bool,
+
+    #[arg(
+        long = "parallel-threshold",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Inputs smaller than SIZE are counted sequentially (default 512K)"
+    )]
+    pub parallel_threshold: Option<usize>,
+
+    #[arg(
+        long = "interval",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "With stdin, print counts every DURATION (e.g. 5s, 500ms, 1m)"
+    )]
+    pub interval: Option<std::time::Duration>,
+
+    #[arg(
+        long = "interval-lines",
+        value_name = "N",
+        help = "With stdin, print counts every N lines"
+    )]
+    pub interval_lines: Option<usize>,
+
+    #[arg(
+        long = "tee",
+        conflicts_with_all = [
+            "interval", "interval_lines", "serve", "serve_socket", "value_only", "diff_input",
+            "files0_from",
+        ],
+        help = "With stdin, pass the input through to stdout and print the counts to stderr"
+    )]
+    pub tee: bool,
+
+    #[arg(
+        long = "tee-report",
+        value_name = "PATH",
+        requires = "tee",
+        help = "With --tee, write the counts to PATH instead of stderr"
+    )]
+    pub tee_report: Option<String>,
+
+    #[arg(
+        long = "cumulative",
+        conflicts_with = "delta_only",
+        help = "In interval mode, print only the running totals"
+    )]
+    pub cumulative: bool,
+
+    #[arg(
+        long = "delta-only",
+        help = "In interval mode, print only the counts since the previous record"
+    )]
+    pub delta_only: bool,
+
+    /// Counters turned on by the options `counter::COUNTERS` declares.
+    #[arg(skip)]
+    pub registered: BTreeSet<&'static str>,
+}
+
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let secs = match unit {
+        "" | "s" => value,
+        "ms" => value / 1000.0,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("invalid duration unit in '{}'", s)),
+    };
+    if secs <= 0.0 {
+        return Err(format!("duration '{}' must be positive", s));
+    }
+    Ok(std::time::Duration::from_secs_f64(secs))
+}
+
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, suffix) = s.split_at(split);
+    let value: usize = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    let multiplier: usize = match suffix.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid size suffix in '{}'", s)),
+    };
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate '{}'", s))?;
+    if !(rate > 0.0 && rate <= 1.0) {
+        return Err(format!("rate must be in (0, 1], got '{}'", s));
+    }
+    Ok(rate)
+}
+
+fn parse_chunk_size(s: &str) -> Result<usize, String> {
+    let size = parse_size(s)?;
+    if size < MIN_CHUNK_SIZE {
+        return Err(format!("chunk size must be at least 64K, got '{}'", s));
+    }
+    Ok(size)
 }
 
 impl Args {
+    /// The command line, with the options counters in `counter::COUNTERS`
+    /// declare themselves.
+    pub fn command() -> clap::Command {
+        crate::counter::command(<Args as CommandFactory>::command())
+    }
+
+    pub fn parse() -> Args {
+        Self::parse_from(std::env::args_os())
+    }
+
+    pub fn parse_from<I, T>(itr: I) -> Args
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Self::command();
+        let matches = command.clone().get_matches_from(itr);
+        let mut args =
+            Args::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut command).exit());
+        args.registered = crate::counter::flags_given(&matches);
+        args
+    }
+
+    /// Rejects flag combinations whose output would silently drop one of the
+    /// flags, and returns warnings for combinations that are merely redundant.
+    /// Conflicts clap can express directly are declared on the fields instead.
+    pub fn validate(&self) -> Result<Vec<String>, clap::Error> {
+        if self.sort_field == Some(SortField::Pattern) && self.pattern.is_empty() {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--sort pattern needs --pattern",
+            ));
+        }
+        if self.total_of == TotalOf::Matching && self.pattern.is_empty() {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--total-of matching needs --pattern",
+            ));
+        }
+        if let Some((glob, e)) = self
+            .exclude_from_total
+            .iter()
+            .find_map(|glob| globset::Glob::new(glob).err().map(|e| (glob, e)))
+        {
+            return Err(Self::command().error(
+                ErrorKind::ValueValidation,
+                format!("invalid --exclude-from-total glob '{}': {}", glob, e),
+            ));
+        }
+        if self.regex
+            && let Some((pattern, e)) = self.pattern.iter().find_map(|pattern| {
+                crate::count::compile_regex(pattern, self.ignore_case)
+                    .err()
+                    .map(|e| (pattern, e))
+            })
+        {
+            return Err(Self::command().error(
+                ErrorKind::ValueValidation,
+                format!("invalid --pattern regex '{}': {}", pattern, e),
+            ));
+        }
+        if self.summary_only && self.structured_output() {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--summary-only prints nothing with --json, --ndjson or --csv; drop one of them",
+            ));
+        }
+
+        if self.value_only && (self.files.len() > 1 || self.files0_from.is_some() || self.recursive)
+        {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--value-only needs a single file or stdin",
+            ));
+        }
+
+        if self.detect_encodings && self.files.is_empty() && self.files0_from.is_none() {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--detect-encodings needs file or directory arguments",
+            ));
+        }
+
+        if self.structured_output() && self.dump_unique_words.as_deref() == Some("-") {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--dump-unique-words writes to stdout, which --json, --ndjson and --csv need; give it a PATH",
+            ));
+        }
+
+        if self.tee && !self.files.is_empty() {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--tee only passes stdin through; drop the file arguments",
+            ));
+        }
+        if self.tee && self.dump_unique_words.as_deref() == Some("-") {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--dump-unique-words writes to stdout, which --tee needs; give it a PATH",
+            ));
+        }
+
+        if self.with_raw && !self.code && !self.markdown && !self.html && !self.auto_filter {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "--with-raw needs a filter (--code, --markdown, --html or --auto-filter)",
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        if self.fast && !self.chars {
+            warnings.push("--fast only affects character counting (-m)".to_string());
+        }
+        if self.html_report.is_some() && self.files.is_empty() && self.files0_from.is_none() {
+            warnings.push("--html-report is only written for file inputs, not stdin".to_string());
+        }
+        if self.progress && !atty::is(atty::Stream::Stderr) {
+            warnings.push(
+                "--progress redraws a terminal line but stderr is not a terminal".to_string(),
+            );
+        }
+        Ok(warnings)
+    }
+
+    /// The line prefix that `--line-prefix-groups` or `--line-prefix-fields`
+    /// groups by, if either is given.
+    pub fn line_prefix(&self) -> Option<LinePrefix> {
+        self.line_prefix_groups
+            .map(LinePrefix::Chars)
+            .or(self.line_prefix_fields.map(LinePrefix::Fields))
+    }
+
+    /// Whether stdout carries a machine-readable format (`--json`,
+    /// `--ndjson`, `--csv`) that extra text lines would corrupt.
+    pub fn structured_output(&self) -> bool {
+        self.json || self.ndjson || self.csv
+    }
+
+    /// Whether an option other than the counters in `counter::COUNTERS`
+    /// needs the input decoded to UTF-8.
+    pub fn decodes(&self) -> bool {
+        self.encoding.is_some()
+            || self.reading_time
+            || self.ttr
+            || self.lang_detect
+            || self.stats
+            || self.summary
+            || self.top_lines.is_some()
+            || self.line_prefix().is_some()
+            || self.top_words.is_some()
+            || self.code
+            || self.markdown
+            || self.html
+    }
+
+    /// Whether an option other than the counters reads the input at all,
+    /// so a byte count cannot come from the file size alone. Counters say
+    /// so themselves through `Counter::reads_content`.
+    pub fn reads_content(&self) -> bool {
+        self.decodes()
+            || self.strip_feff
+            || self.check
+            || self.group_by_timestamp.is_some()
+            || !self.pattern.is_empty()
+            || self.histogram
+            || self.indent_stats
+            || self.html_report.is_some()
+            || self.debug_internals
+            || self.head.is_some()
+            || self.tail.is_some()
+            || self.head_bytes.is_some()
+            || self.normalize_check.is_some()
+            || self.line_endings
+    }
+
+    /// Whether raw counts are computed next to the filtered ones.
+    pub fn shows_raw(&self) -> bool {
+        self.with_raw && (self.code || self.markdown || self.html || self.auto_filter)
+    }
+
+    /// Decimal places for floats in JSON output; None writes them in full.
+    pub fn json_precision(&self) -> Option<usize> {
+        self.precision.or(self
+            .deterministic
+            .then_some(crate::canonical::DETERMINISTIC_PRECISION))
+    }
+
+    pub fn tuning(&self) -> TuningParams {
+        let defaults = TuningParams::default();
+        TuningParams {
+            chunk_size: self.chunk_size.unwrap_or(defaults.chunk_size),
+            parallel_threshold: self
+                .parallel_threshold
+                .unwrap_or(defaults.parallel_threshold),
+            deadline: None,
+        }
+    }
+
     pub fn normalize(&mut self) {
-        if !self.lines
-            && !self.bytes
-            && !self.chars
-            && !self.words
-            && !self.max_line_length
-            && self.pattern.is_none()
+        // From here on `color` means "color is on": --no-color wins, then
+        // --color, then NO_COLOR and whether stdout is a terminal.
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.color = !self.no_color
+            && !self.structured_output()
+            && !self.value_only
+            && (self.color || (!no_color_env && std::io::stdout().is_terminal()))
+            && crate::platform::enable_ansi_stdout();
+
+        if self.summary_only {
+            self.summary = true;
+        }
+
+        // A repeated pattern would only repeat its column.
+        let mut seen = std::collections::HashSet::new();
+        self.pattern.retain(|pattern| seen.insert(pattern.clone()));
+        if self.regex {
+            self.pattern_regex = self
+                .pattern
+                .iter()
+                .filter_map(|pattern| crate::count::compile_regex(pattern, self.ignore_case).ok())
+                .collect();
+        }
+
+        if self.deterministic {
+            self.timing = false;
+        }
+
+        match self.only {
+            Some(OnlySection::Stats) => self.stats = true,
+            Some(OnlySection::Histogram) => self.histogram = true,
+            None => {}
+        }
+
+        // A filter given explicitly applies to every file.
+        if self.code || self.markdown || self.html {
+            self.auto_filter = false;
+        }
+
+        if self.check {
+            self.lines = true;
+            self.words = true;
+            self.max_line_length = true;
+        }
+
+        // --disk-usage adds its column to the default counters.
+        let counter_selected = crate::counter::COUNTERS
+            .iter()
+            .any(|counter| counter.name != "disk_bytes" && counter.enabled(self));
+        if !counter_selected
+            && self.pattern.is_empty()
             && !self.stats
-            && !self.unique
             && !self.histogram
-            && !self.blank_lines
+            && !self.indent_stats
         {
             self.lines = true;
             self.bytes = true;
             self.words = true;
         }
+        // After the defaults: the dump alone still prints the usual counts.
+        if self.dump_unique_words.is_some() {
+            self.unique = true;
+        }
+
+        // Rows are sorted by a visible column, so turn on the one asked for.
+        match self.sort_field {
+            Some(SortField::Lines) => self.lines = true,
+            Some(SortField::Words) => self.words = true,
+            Some(SortField::Bytes) => self.bytes = true,
+            Some(SortField::Chars) => self.chars = true,
+            Some(SortField::MaxLineLength) => self.max_line_length = true,
+            Some(SortField::BlankLines) => self.blank_lines = true,
+            Some(SortField::UniqueWords) => self.unique = true,
+            Some(SortField::Pattern) | None => {}
+        }
     }
 }
@@ -1,56 +1,227 @@
+use crate::timestamp::{Date, DateFormat};
+use aho_corasick::AhoCorasick;
 use memchr::memmem::Finder;
 use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
 
 const CHUNK_SIZE: usize = 1024 * 1024;
 const PARALLEL_THRESHOLD: usize = 512 * 1024;
+pub const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Controls how the counters split their input: buffers shorter than
+/// `parallel_threshold` are counted sequentially, larger ones in chunks of
+/// roughly `chunk_size` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TuningParams {
+    pub chunk_size: usize,
+    pub parallel_threshold: usize,
+    /// Once past it the chunked paths skip their remaining chunks, leaving
+    /// a partial count for the caller to throw away.
+    pub deadline: Option<Instant>,
+}
+
+impl TuningParams {
+    /// Never takes the chunked parallel path, whatever the input size.
+    pub fn serial() -> Self {
+        Self {
+            parallel_threshold: usize::MAX,
+            ..Self::default()
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+impl Default for TuningParams {
+    fn default() -> Self {
+        Self {
+            chunk_size: CHUNK_SIZE,
+            parallel_threshold: PARALLEL_THRESHOLD,
+            deadline: None,
+        }
+    }
+}
 
-pub fn count_lines(data: &[u8]) -> usize {
-    if data.len() < PARALLEL_THRESHOLD {
+pub fn count_lines(data: &[u8], tuning: TuningParams) -> usize {
+    if data.len() < tuning.parallel_threshold {
         return memchr::memchr_iter(b'\n', data).count();
     }
 
-    data.par_chunks(CHUNK_SIZE)
+    data.par_chunks(tuning.chunk_size)
+        .filter(|_| !tuning.expired())
         .map(|chunk| memchr::memchr_iter(b'\n', chunk).count())
         .sum()
 }
 
-pub fn count_blank_lines(data: &[u8]) -> usize {
+/// Blank lines split by kind: `empty` lines have nothing but an optional
+/// `\r`, `whitespace_only` lines hold spaces or tabs and nothing else.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlankLines {
+    pub empty: usize,
+    pub whitespace_only: usize,
+}
+
+impl BlankLines {
+    /// Every line that is empty or whitespace-only (what `-b` reports).
+    pub fn total(&self) -> usize {
+        self.empty + self.whitespace_only
+    }
+
+    fn add(self, other: BlankLines) -> BlankLines {
+        BlankLines {
+            empty: self.empty + other.empty,
+            whitespace_only: self.whitespace_only + other.whitespace_only,
+        }
+    }
+}
+
+pub fn count_blank_lines(data: &[u8], tuning: TuningParams) -> BlankLines {
     if data.is_empty() {
-        return 0;
+        return BlankLines::default();
     }
 
-    if data.len() < PARALLEL_THRESHOLD {
+    if data.len() < tuning.parallel_threshold {
         return count_blank_lines_chunk(data);
     }
 
-    let boundaries = find_line_boundaries(data, CHUNK_SIZE);
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
     boundaries
         .par_windows(2)
+        .filter(|_| !tuning.expired())
         .map(|w| count_blank_lines_chunk(&data[w[0]..w[1]]))
-        .sum()
+        .reduce(BlankLines::default, BlankLines::add)
 }
 
-fn count_blank_lines_chunk(data: &[u8]) -> usize {
-    let mut count = 0;
-    let mut line_start = 0;
+/// Paragraph starts in a chunk, plus whether its first and last lines are
+/// non-blank so a paragraph cut by a chunk boundary is counted once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ParagraphTally {
+    starts: usize,
+    first_nonblank: bool,
+    last_nonblank: bool,
+}
 
+impl ParagraphTally {
+    /// Combines adjacent chunks, `self` first.
+    fn join(self, next: Self) -> Self {
+        let continued = self.last_nonblank && next.first_nonblank;
+        Self {
+            starts: self.starts + next.starts - usize::from(continued),
+            first_nonblank: self.first_nonblank,
+            last_nonblank: next.last_nonblank,
+        }
+    }
+}
+
+fn count_paragraphs_chunk(data: &[u8]) -> ParagraphTally {
+    let mut tally = ParagraphTally {
+        starts: 0,
+        first_nonblank: false,
+        last_nonblank: false,
+    };
+    let mut first = true;
+    let mut line_start = 0;
+    let mut visit = |line: &[u8]| {
+        let nonblank = !is_blank_line(line);
+        if nonblank && (first || !tally.last_nonblank) {
+            tally.starts += 1;
+        }
+        if first {
+            tally.first_nonblank = nonblank;
+            first = false;
+        }
+        tally.last_nonblank = nonblank;
+    };
     for pos in memchr::memchr_iter(b'\n', data) {
-        if data[line_start..pos]
-            .iter()
-            .all(|&b| b.is_ascii_whitespace())
-        {
-            count += 1;
+        visit(&data[line_start..pos]);
+        line_start = pos + 1;
+    }
+    if line_start < data.len() {
+        visit(&data[line_start..]);
+    }
+    tally
+}
+
+/// Counts paragraphs: runs of non-blank lines separated by one or more
+/// blank lines (blank as in `count_blank_lines`). Leading and trailing blank
+/// lines add nothing, and a last paragraph without a newline still counts.
+pub fn count_paragraphs(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_paragraphs_chunk(data).starts;
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_paragraphs_chunk(&data[w[0]..w[1]]))
+        .reduce_with(ParagraphTally::join)
+        .map_or(0, |tally| tally.starts)
+}
+
+/// A line without its `\r` terminator, the content length used by both the
+/// blank line counters and `--stats`.
+fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Whether a line (without its `\n`) is blank: empty, or only ASCII
+/// whitespace. Shared by the blank line and paragraph counters.
+fn is_blank_line(line: &[u8]) -> bool {
+    trim_cr(line).iter().all(u8::is_ascii_whitespace)
+}
+
+fn count_blank_lines_chunk(data: &[u8]) -> BlankLines {
+    let mut blanks = BlankLines::default();
+    let mut tally = |line: &[u8]| {
+        if trim_cr(line).is_empty() {
+            blanks.empty += 1;
+        } else if is_blank_line(line) {
+            blanks.whitespace_only += 1;
         }
+    };
+
+    let mut line_start = 0;
+    for pos in memchr::memchr_iter(b'\n', data) {
+        tally(&data[line_start..pos]);
         line_start = pos + 1;
     }
+    if line_start < data.len() {
+        tally(&data[line_start..]);
+    }
 
-    if line_start < data.len() && data[line_start..].iter().all(|&b| b.is_ascii_whitespace()) {
-        count += 1;
+    blanks
+}
+
+/// Counts lines ending in a space or tab before their `\r\n` or `\n`, the
+/// trailing whitespace linters flag. An unterminated last line counts too.
+pub fn count_trailing_whitespace_lines(data: &[u8], tuning: TuningParams) -> usize {
+    if data.len() < tuning.parallel_threshold {
+        return count_trailing_whitespace_chunk(data);
     }
 
-    count
+    find_line_boundaries(data, tuning.chunk_size)
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_trailing_whitespace_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+fn count_trailing_whitespace_chunk(data: &[u8]) -> usize {
+    data.split(|&b| b == b'\n')
+        .filter(|line| matches!(trim_cr(line).last(), Some(b' ' | b'\t')))
+        .count()
 }
 
 fn find_line_boundaries(data: &[u8], chunk_size: usize) -> Vec<usize> {
@@ -74,18 +245,131 @@ fn find_line_boundaries(data: &[u8], chunk_size: usize) -> Vec<usize> {
     boundaries
 }
 
-pub fn count_all_words(data: &[u8]) -> usize {
+/// Splits text into the tokens the word-based counters (`-w`, `--unique`)
+/// see.
+///
+/// Boundary contract: implementations keep no state between calls and never
+/// produce a token that spans a newline. Parallel counting splits the input
+/// at line boundaries and tokenizes each chunk on its own, so a tokenizer
+/// that honours this gives the same tokens chunked as it does serially.
+pub trait Tokenize {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+}
+
+/// The built-in tokenizers selected with `--tokenizer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tokenizer {
+    /// Runs of non-whitespace, like wc.
+    #[default]
+    Whitespace,
+    /// UAX #29 word segmentation, keeping segments with letters or digits.
+    Unicode,
+    /// Runs of `[A-Za-z0-9_]`, as in most programming languages.
+    Identifiers,
+    /// Overlapping windows of N bytes within each whitespace-separated run,
+    /// e.g. k-mers of a DNA sequence.
+    Kmer(usize),
+}
+
+impl Tokenize for Tokenizer {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        match *self {
+            Tokenizer::Whitespace => Box::new(text.split_whitespace()),
+            Tokenizer::Unicode => Box::new(text.unicode_words()),
+            Tokenizer::Identifiers => Box::new(
+                text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .filter(|token| !token.is_empty()),
+            ),
+            Tokenizer::Kmer(n) => Box::new(text.split_whitespace().flat_map(move |run| {
+                // Windows that would cut a multi-byte character are skipped.
+                (0..=run.len().saturating_sub(n)).filter_map(move |i| run.get(i..i + n))
+            })),
+        }
+    }
+}
+
+impl std::str::FromStr for Tokenizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitespace" => Ok(Tokenizer::Whitespace),
+            "unicode" => Ok(Tokenizer::Unicode),
+            "identifiers" => Ok(Tokenizer::Identifiers),
+            _ => {
+                let n = s
+                    .strip_prefix("kmer:")
+                    .ok_or_else(|| {
+                        format!(
+                            "unknown tokenizer '{}' (expected whitespace, unicode, identifiers or kmer:N)",
+                            s
+                        )
+                    })?
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid k-mer length in '{}'", s))?;
+                if n == 0 {
+                    return Err("k-mer length must be at least 1".to_string());
+                }
+                Ok(Tokenizer::Kmer(n))
+            }
+        }
+    }
+}
+
+/// Counts the tokens `tokenizer` produces. Chunks split on newlines, which
+/// end every token under the `Tokenize` contract, so no boundary correction
+/// is needed.
+pub fn count_tokens(data: &[u8], tuning: TuningParams, tokenizer: Tokenizer) -> usize {
     if data.is_empty() {
         return 0;
     }
 
-    if data.len() < PARALLEL_THRESHOLD {
-        return count_words_in_chunk(data);
+    let count_chunk = |chunk: &[u8]| tokenizer.tokens(&String::from_utf8_lossy(chunk)).count();
+
+    if data.len() < tuning.parallel_threshold {
+        return count_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+pub fn count_all_words(data: &[u8], tuning: TuningParams) -> usize {
+    count_all_words_traced(data, tuning).0
+}
+
+/// How a chunked count was put back together, for `--debug-internals`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BoundaryTrace {
+    /// Chunks counted; 1 on the serial path.
+    pub chunks: usize,
+    /// Words cut in two by a chunk boundary (subtracted), or pattern
+    /// matches spanning one (added).
+    pub correction: usize,
+}
+
+/// `count_all_words`, also reporting the boundary correction it applied.
+pub fn count_all_words_traced(data: &[u8], tuning: TuningParams) -> (usize, BoundaryTrace) {
+    if data.is_empty() {
+        return (0, BoundaryTrace::default());
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        let trace = BoundaryTrace {
+            chunks: 1,
+            correction: 0,
+        };
+        return (count_words_in_chunk(data), trace);
     }
 
-    let chunk_boundaries = find_utf8_chunk_boundaries(data, CHUNK_SIZE);
+    let chunk_boundaries = find_utf8_chunk_boundaries(data, tuning.chunk_size);
     let count: usize = chunk_boundaries
         .par_windows(2)
+        .filter(|_| !tuning.expired())
         .map(|window| {
             let chunk = &data[window[0]..window[1]];
             count_words_in_chunk(chunk)
@@ -104,7 +388,353 @@ pub fn count_all_words(data: &[u8]) -> usize {
         }
     }
 
-    count.saturating_sub(overcounted)
+    let trace = BoundaryTrace {
+        chunks: chunk_boundaries.len() - 1,
+        correction: overcounted,
+    };
+    (count.saturating_sub(overcounted), trace)
+}
+
+/// Length in characters of the longest whitespace-separated word, split the
+/// same way as `count_all_words`. Invalid UTF-8 is measured in bytes.
+pub fn max_word_length(data: &[u8], tuning: TuningParams) -> usize {
+    if data.len() < tuning.parallel_threshold {
+        return WordRun::of_chunk(data).longest;
+    }
+
+    let chunk_boundaries = find_utf8_chunk_boundaries(data, tuning.chunk_size);
+    chunk_boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|window| WordRun::of_chunk(&data[window[0]..window[1]]))
+        .reduce(WordRun::empty, WordRun::then)
+        .longest
+}
+
+/// Word lengths seen in one chunk. Merging chunks in order joins the word
+/// cut at each boundary, so straddling words are measured whole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WordRun {
+    /// Length of the word touching the chunk start.
+    leading: usize,
+    /// Length of the word touching the chunk end.
+    trailing: usize,
+    longest: usize,
+    /// No whitespace at all, so `leading` and `trailing` are the same word.
+    unbroken: bool,
+}
+
+impl WordRun {
+    fn empty() -> Self {
+        Self {
+            leading: 0,
+            trailing: 0,
+            longest: 0,
+            unbroken: true,
+        }
+    }
+
+    fn of_chunk(chunk: &[u8]) -> Self {
+        match std::str::from_utf8(chunk) {
+            Ok(text) => Self::scan(text.chars().map(char::is_whitespace)),
+            Err(_) => Self::scan(chunk.iter().map(u8::is_ascii_whitespace)),
+        }
+    }
+
+    fn scan(is_space: impl Iterator<Item = bool>) -> Self {
+        let mut run = Self::empty();
+        let mut current = 0;
+        for space in is_space {
+            if space {
+                if run.unbroken {
+                    run.leading = current;
+                    run.unbroken = false;
+                }
+                current = 0;
+            } else {
+                current += 1;
+                run.longest = run.longest.max(current);
+            }
+        }
+        if run.unbroken {
+            run.leading = current;
+        }
+        run.trailing = current;
+        run
+    }
+
+    fn then(self, next: Self) -> Self {
+        let joined = self.trailing + next.leading;
+        Self {
+            leading: if self.unbroken { joined } else { self.leading },
+            trailing: if next.unbroken { joined } else { next.trailing },
+            longest: self.longest.max(next.longest).max(joined),
+            unbroken: self.unbroken && next.unbroken,
+        }
+    }
+}
+
+/// Character trigram counts for `--lang-detect`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Trigrams {
+    pub counts: HashMap<[char; 3], usize>,
+    /// Letters seen, to tell whether there is enough text to judge.
+    pub letters: usize,
+}
+
+/// Lowercased letter trigrams, each word padded with a space on both
+/// sides (`" th"`, `"the"`, `"he "`). Han ideographs stand alone as words,
+/// since Chinese and Japanese do not separate words with spaces. Chunks end
+/// at newlines, which never fall inside a word.
+pub fn char_trigrams(data: &[u8], tuning: TuningParams) -> Trigrams {
+    if data.len() < tuning.parallel_threshold {
+        return char_trigrams_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| char_trigrams_chunk(&data[w[0]..w[1]]))
+        .reduce(Trigrams::default, |mut merged, chunk| {
+            for (trigram, count) in chunk.counts {
+                *merged.counts.entry(trigram).or_insert(0) += count;
+            }
+            merged.letters += chunk.letters;
+            merged
+        })
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}')
+}
+
+fn char_trigrams_chunk(chunk: &[u8]) -> Trigrams {
+    /// Counts the trigrams of the padded word and starts the next one.
+    fn flush(word: &mut Vec<char>, trigrams: &mut Trigrams) {
+        if word.len() > 1 {
+            word.push(' ');
+            for trigram in word.windows(3) {
+                *trigrams
+                    .counts
+                    .entry([trigram[0], trigram[1], trigram[2]])
+                    .or_insert(0) += 1;
+            }
+        }
+        word.truncate(1);
+    }
+
+    let mut trigrams = Trigrams::default();
+    let mut word = vec![' '];
+    for c in String::from_utf8_lossy(chunk).chars() {
+        if is_han(c) {
+            flush(&mut word, &mut trigrams);
+            word.push(c);
+            flush(&mut word, &mut trigrams);
+            trigrams.letters += 1;
+        } else if c.is_alphabetic() {
+            word.extend(c.to_lowercase());
+            trigrams.letters += 1;
+        } else {
+            flush(&mut word, &mut trigrams);
+        }
+    }
+    flush(&mut word, &mut trigrams);
+    trigrams
+}
+
+/// Abbreviations whose trailing period does not end a sentence, compared
+/// lowercased and without the period.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "cf", "al", "fig", "no", "approx",
+    "etc", "inc", "ltd",
+];
+
+fn is_sentence_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '\u{2026}')
+}
+
+/// Closing punctuation that may sit between a terminator and the following
+/// whitespace, as in `"Stop."` or `(see above.)`.
+fn is_closer(c: char) -> bool {
+    matches!(
+        c,
+        '"' | '\'' | ')' | ']' | '}' | '\u{2019}' | '\u{201D}' | '\u{00BB}'
+    )
+}
+
+/// Whether the word before a lone `.` is an abbreviation: a listed title or
+/// Latin short form, a single-letter initial, or a dotted form like `e.g`.
+fn is_abbreviation(word: &str) -> bool {
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    let mut letters = word.chars();
+    if letters.next().is_some_and(char::is_alphabetic) && letters.next().is_none() {
+        return true;
+    }
+    if word.contains('.') && word.split('.').all(|part| part.chars().count() == 1) {
+        return true;
+    }
+    ABBREVIATIONS
+        .iter()
+        .any(|abbreviation| word.eq_ignore_ascii_case(abbreviation))
+}
+
+/// Sentence ends in a chunk, and whether text follows the last of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SentenceTally {
+    ends: usize,
+    open_tail: bool,
+}
+
+fn count_sentences_chunk(data: &[u8]) -> SentenceTally {
+    let text = String::from_utf8_lossy(data);
+    let chars: Vec<char> = text.chars().collect();
+    let mut tally = SentenceTally {
+        ends: 0,
+        open_tail: false,
+    };
+    let mut word_start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            word_start = i + 1;
+            i += 1;
+            continue;
+        }
+        if !is_sentence_terminator(c) {
+            tally.open_tail = true;
+            i += 1;
+            continue;
+        }
+        let run_start = i;
+        while i < chars.len() && is_sentence_terminator(chars[i]) {
+            i += 1;
+        }
+        let run_end = i;
+        while i < chars.len() && is_closer(chars[i]) {
+            i += 1;
+        }
+        if i < chars.len() && !chars[i].is_whitespace() {
+            // "3.14", "example.com", "?!x": not the end of anything.
+            tally.open_tail = true;
+            continue;
+        }
+        let lone_period = run_end - run_start == 1 && chars[run_start] == '.';
+        let word: String = chars[word_start..run_start].iter().collect();
+        if word.is_empty() && !tally.open_tail {
+            // Stray punctuation with no sentence before it.
+            continue;
+        }
+        if lone_period && is_abbreviation(&word) {
+            tally.open_tail = true;
+            continue;
+        }
+        tally.ends += 1;
+        tally.open_tail = false;
+    }
+    tally
+}
+
+/// Counts sentences: runs of `.`, `!`, `?` or `…` followed by whitespace or
+/// the end of input, skipping abbreviations (`e.g.`, `Mr.`, initials) and
+/// decimals (`3.14`). Trailing text without a terminator counts as one more
+/// sentence. Chunks split on newlines; a sentence that continues across a
+/// chunk boundary is only counted once, when it ends.
+pub fn count_sentences(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    let tallies = if data.len() < tuning.parallel_threshold {
+        vec![count_sentences_chunk(data)]
+    } else {
+        let boundaries = find_line_boundaries(data, tuning.chunk_size);
+        boundaries
+            .par_windows(2)
+            .filter(|_| !tuning.expired())
+            .map(|w| count_sentences_chunk(&data[w[0]..w[1]]))
+            .collect()
+    };
+
+    let mut ends = 0;
+    let mut open_tail = false;
+    for tally in tallies {
+        ends += tally.ends;
+        open_tail = if tally.ends > 0 {
+            tally.open_tail
+        } else {
+            open_tail || tally.open_tail
+        };
+    }
+    ends + usize::from(open_tail)
+}
+
+/// Counts whitespace-separated tokens that contain at least one alphabetic
+/// character, so standalone numbers, dates and runs like `---` or `=>` are
+/// left out. Chunks split on newlines so no token straddles two chunks.
+pub fn count_prose_words(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_prose_words_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_prose_words_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+fn count_prose_words_chunk(chunk: &[u8]) -> usize {
+    String::from_utf8_lossy(chunk)
+        .split_whitespace()
+        .filter(|token| token.chars().any(char::is_alphabetic))
+        .count()
+}
+
+/// Chunk sizes under one of the splitting schemes, for `--debug-internals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLayout {
+    pub chunks: usize,
+    pub smallest: usize,
+    pub largest: usize,
+}
+
+impl ChunkLayout {
+    fn of(boundaries: &[usize]) -> ChunkLayout {
+        let sizes = || boundaries.windows(2).map(|w| w[1] - w[0]);
+        ChunkLayout {
+            chunks: boundaries.len().saturating_sub(1),
+            smallest: sizes().min().unwrap_or(0),
+            largest: sizes().max().unwrap_or(0),
+        }
+    }
+}
+
+/// How the parallel path would split `data`: fixed-size chunks (lines,
+/// bytes, literal patterns), chunks ending after a newline (line-based
+/// counters) and chunks ending on a UTF-8 character boundary (words, chars).
+pub fn chunk_layouts(data: &[u8], tuning: TuningParams) -> [(&'static str, ChunkLayout); 3] {
+    let fixed: Vec<usize> = (0..data.len())
+        .step_by(tuning.chunk_size)
+        .chain([data.len()])
+        .collect();
+    [
+        ("fixed", ChunkLayout::of(&fixed)),
+        (
+            "line-aligned",
+            ChunkLayout::of(&find_line_boundaries(data, tuning.chunk_size)),
+        ),
+        (
+            "UTF-8-aligned",
+            ChunkLayout::of(&find_utf8_chunk_boundaries(data, tuning.chunk_size)),
+        ),
+    ]
 }
 
 fn find_utf8_chunk_boundaries(data: &[u8], chunk_size: usize) -> Vec<usize> {
@@ -169,92 +799,530 @@ fn count_words_in_chunk(chunk: &[u8]) -> usize {
     }
 }
 
-pub fn count_pattern(data: &[u8], pattern: &[u8]) -> usize {
-    if data.is_empty() || pattern.is_empty() {
-        return 0;
-    }
-
-    let finder = Finder::new(pattern);
-
-    if data.len() < PARALLEL_THRESHOLD {
-        return finder.find_iter(data).count();
+/// The first match of the ASCII `pattern` at or after `from`, ignoring
+/// ASCII case: candidates are found by the pattern's first byte in either
+/// case, then compared case-folded in place.
+fn find_caseless(haystack: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    let (lower, upper) = (
+        pattern[0].to_ascii_lowercase(),
+        pattern[0].to_ascii_uppercase(),
+    );
+    let mut pos = from;
+    while pos + pattern.len() <= haystack.len() {
+        let candidates = &haystack[pos..=haystack.len() - pattern.len()];
+        let start = pos + memchr::memchr2(lower, upper, candidates)?;
+        if haystack[start..start + pattern.len()].eq_ignore_ascii_case(pattern) {
+            return Some(start);
+        }
+        pos = start + 1;
     }
+    None
+}
 
-    let num_chunks = data.len().div_ceil(CHUNK_SIZE);
-    let count: usize = (0..num_chunks)
-        .into_par_iter()
-        .map(|i| {
-            let start = i * CHUNK_SIZE;
-            let end = ((i + 1) * CHUNK_SIZE).min(data.len());
-            let chunk = &data[start..end];
-            finder.find_iter(chunk).count()
-        })
-        .sum();
-
-    let mut boundary_matches = 0;
-    for i in 1..num_chunks {
-        let boundary = i * CHUNK_SIZE;
-        let search_start = boundary.saturating_sub(pattern.len() - 1);
-        let search_end = (boundary + pattern.len() - 1).min(data.len());
-
-        if search_start >= search_end {
-            continue;
-        }
+/// A literal `--pattern`, matched byte for byte or ignoring ASCII case.
+enum Literal<'p> {
+    Exact(Box<Finder<'p>>),
+    AsciiCaseless(&'p [u8]),
+}
 
-        let region = &data[search_start..search_end];
-        for pos in finder.find_iter(region) {
-            let abs_start = search_start + pos;
-            if abs_start < boundary && abs_start + pattern.len() > boundary {
-                boundary_matches += 1;
+impl Literal<'_> {
+    /// Calls `on_match` with the start of every non-overlapping match, left
+    /// to right, like `Finder::find_iter`.
+    fn for_each_match(&self, haystack: &[u8], mut on_match: impl FnMut(usize)) {
+        match self {
+            Literal::Exact(finder) => finder.find_iter(haystack).for_each(on_match),
+            Literal::AsciiCaseless(pattern) => {
+                let mut pos = 0;
+                while let Some(start) = find_caseless(haystack, pattern, pos) {
+                    on_match(start);
+                    pos = start + pattern.len();
+                }
             }
         }
     }
 
-    count + boundary_matches
-}
-
-pub fn count_chars(data: &[u8]) -> usize {
-    if data.is_empty() {
-        return 0;
+    fn count(&self, haystack: &[u8]) -> usize {
+        let mut count = 0;
+        self.for_each_match(haystack, |_| count += 1);
+        count
     }
 
-    if data.len() < PARALLEL_THRESHOLD {
-        return std::str::from_utf8(data)
-            .map(|s| s.chars().count())
-            .unwrap_or(data.len());
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        match self {
+            Literal::Exact(finder) => finder.find(haystack).is_some(),
+            Literal::AsciiCaseless(pattern) => find_caseless(haystack, pattern, 0).is_some(),
+        }
     }
+}
 
-    let chunk_boundaries = find_utf8_chunk_boundaries(data, CHUNK_SIZE);
-    chunk_boundaries
-        .par_windows(2)
-        .map(|window| {
-            let chunk = &data[window[0]..window[1]];
-            std::str::from_utf8(chunk)
-                .map(|s| s.chars().count())
-                .unwrap_or(chunk.len())
-        })
-        .sum()
+/// A case-insensitive regex matching `pattern` literally, for patterns
+/// whose case folding is not ASCII-only.
+fn caseless_regex(pattern: &[u8]) -> Option<Regex> {
+    let escaped = regex::escape(&String::from_utf8_lossy(pattern));
+    RegexBuilder::new(&escaped)
+        .case_insensitive(true)
+        .build()
+        .ok()
 }
 
-pub fn max_line_length(data: &[u8]) -> usize {
-    if data.is_empty() {
+pub fn count_pattern(data: &[u8], pattern: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() || pattern.is_empty() {
         return 0;
     }
+    count_literal(
+        data,
+        &Literal::Exact(Box::new(Finder::new(pattern))),
+        pattern.len(),
+        tuning,
+    )
+    .0
+}
 
-    if data.len() < PARALLEL_THRESHOLD {
-        return max_line_length_chunk(data);
+/// `count_pattern`, or with `ignore_case` `count_pattern_ignore_case`, also
+/// reporting the matches across chunk boundaries it added. A non-ASCII
+/// caseless pattern is matched in one pass and reports a single chunk.
+pub fn count_pattern_traced(
+    data: &[u8],
+    pattern: &[u8],
+    ignore_case: bool,
+    tuning: TuningParams,
+) -> (usize, BoundaryTrace) {
+    if data.is_empty() || pattern.is_empty() {
+        return (0, BoundaryTrace::default());
     }
+    match (ignore_case, pattern.is_ascii()) {
+        (false, _) => count_literal(
+            data,
+            &Literal::Exact(Box::new(Finder::new(pattern))),
+            pattern.len(),
+            tuning,
+        ),
+        (true, true) => count_literal(
+            data,
+            &Literal::AsciiCaseless(pattern),
+            pattern.len(),
+            tuning,
+        ),
+        (true, false) => {
+            let count = count_pattern_ignore_case(data, pattern, tuning);
+            let trace = BoundaryTrace {
+                chunks: 1,
+                correction: 0,
+            };
+            (count, trace)
+        }
+    }
+}
 
-    let boundaries = find_line_boundaries(data, CHUNK_SIZE);
-    boundaries
+/// Like `count_pattern`, ignoring case. ASCII patterns compare each
+/// candidate window case-folded in place, so they share the chunked scan
+/// and its boundary correction. Other patterns need Unicode case folding,
+/// where a match may differ in length from the pattern (`ß` and `SS`), so
+/// they go through a case-insensitive regex in one pass.
+pub fn count_pattern_ignore_case(data: &[u8], pattern: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() || pattern.is_empty() {
+        return 0;
+    }
+    if pattern.is_ascii() {
+        return count_literal(
+            data,
+            &Literal::AsciiCaseless(pattern),
+            pattern.len(),
+            tuning,
+        )
+        .0;
+    }
+    caseless_regex(pattern).map_or(0, |regex| count_regex(data, &regex))
+}
+
+/// Counts lines for which `is_match` holds, given each line without its
+/// `\n`. Chunks split on newlines, so every line is tested exactly once.
+pub fn count_lines_matching(
+    data: &[u8],
+    tuning: TuningParams,
+    is_match: impl Fn(&[u8]) -> bool + Sync,
+) -> usize {
+    let count_chunk = |chunk: &[u8]| {
+        let mut count = 0;
+        let mut line_start = 0;
+        for pos in memchr::memchr_iter(b'\n', chunk) {
+            count += usize::from(is_match(&chunk[line_start..pos]));
+            line_start = pos + 1;
+        }
+        if line_start < chunk.len() {
+            count += usize::from(is_match(&chunk[line_start..]));
+        }
+        count
+    };
+
+    if data.len() < tuning.parallel_threshold {
+        return count_chunk(data);
+    }
+    find_line_boundaries(data, tuning.chunk_size)
         .par_windows(2)
-        .map(|w| max_line_length_chunk(&data[w[0]..w[1]]))
+        .filter(|_| !tuning.expired())
+        .map(|w| count_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+/// Counts lines holding `pattern` at least once, like `grep -c`, optionally
+/// ignoring case as `count_pattern_ignore_case` does.
+pub fn count_matching_lines(
+    data: &[u8],
+    pattern: &[u8],
+    ignore_case: bool,
+    tuning: TuningParams,
+) -> usize {
+    if data.is_empty() || pattern.is_empty() {
+        return 0;
+    }
+    if ignore_case && !pattern.is_ascii() {
+        return caseless_regex(pattern).map_or(0, |regex| {
+            count_lines_matching(data, tuning, |line| regex.is_match(line))
+        });
+    }
+    let literal = if ignore_case {
+        Literal::AsciiCaseless(pattern)
+    } else {
+        Literal::Exact(Box::new(Finder::new(pattern)))
+    };
+    count_lines_matching(data, tuning, |line| literal.is_match(line))
+}
+
+/// Counts lines holding at least one of `patterns`; a line with several
+/// matches counts once. Empty patterns never match.
+pub fn count_lines_matching_any(
+    data: &[u8],
+    patterns: &[&[u8]],
+    ignore_case: bool,
+    tuning: TuningParams,
+) -> usize {
+    let patterns: Vec<&[u8]> = patterns.iter().copied().filter(|p| !p.is_empty()).collect();
+    match patterns.as_slice() {
+        [] => 0,
+        [pattern] => count_matching_lines(data, pattern, ignore_case, tuning),
+        _ if ignore_case && patterns.iter().any(|p| !p.is_ascii()) => {
+            let alternation: Vec<String> = patterns
+                .iter()
+                .map(|p| regex::escape(&String::from_utf8_lossy(p)))
+                .collect();
+            RegexBuilder::new(&alternation.join("|"))
+                .case_insensitive(true)
+                .build()
+                .map_or(0, |regex| {
+                    count_lines_matching(data, tuning, |line| regex.is_match(line))
+                })
+        }
+        _ => {
+            let automaton = AhoCorasick::builder()
+                .ascii_case_insensitive(ignore_case)
+                .build(&patterns)
+                .expect("literal patterns always build");
+            count_lines_matching(data, tuning, |line| automaton.is_match(line))
+        }
+    }
+}
+
+fn count_literal(
+    data: &[u8],
+    literal: &Literal,
+    len: usize,
+    tuning: TuningParams,
+) -> (usize, BoundaryTrace) {
+    if data.len() < tuning.parallel_threshold {
+        let trace = BoundaryTrace {
+            chunks: 1,
+            correction: 0,
+        };
+        return (literal.count(data), trace);
+    }
+
+    let num_chunks = data.len().div_ceil(tuning.chunk_size);
+    let count: usize = (0..num_chunks)
+        .into_par_iter()
+        .filter(|_| !tuning.expired())
+        .map(|i| {
+            let start = i * tuning.chunk_size;
+            let end = ((i + 1) * tuning.chunk_size).min(data.len());
+            literal.count(&data[start..end])
+        })
+        .sum();
+
+    let mut boundary_matches = 0;
+    for i in 1..num_chunks {
+        let boundary = i * tuning.chunk_size;
+        let search_start = boundary.saturating_sub(len - 1);
+        let search_end = (boundary + len - 1).min(data.len());
+
+        if search_start >= search_end {
+            continue;
+        }
+
+        literal.for_each_match(&data[search_start..search_end], |pos| {
+            let abs_start = search_start + pos;
+            // A match spanning several small chunks is owned by the first boundary it crosses.
+            if abs_start < boundary
+                && abs_start + len > boundary
+                && abs_start + tuning.chunk_size >= boundary
+            {
+                boundary_matches += 1;
+            }
+        });
+    }
+
+    let trace = BoundaryTrace {
+        chunks: num_chunks,
+        correction: boundary_matches,
+    };
+    (count + boundary_matches, trace)
+}
+
+/// Counts several literal patterns in one pass over `data`, one count per
+/// pattern in the order given. Each pattern is counted as `count_pattern`
+/// (or, with `ignore_ascii_case`, `count_pattern_ignore_case`) would count it
+/// alone: its matches never overlap each other, though they may overlap
+/// another pattern's. Chunks are scanned in parallel, each reading up to the
+/// longest pattern past its end so that matches starting in the chunk are
+/// seen whole.
+pub fn count_patterns(
+    data: &[u8],
+    patterns: &[&[u8]],
+    ignore_ascii_case: bool,
+    tuning: TuningParams,
+) -> Vec<usize> {
+    // Empty patterns match nothing here, as in `count_pattern`.
+    let searched: Vec<usize> = (0..patterns.len())
+        .filter(|&i| !patterns[i].is_empty())
+        .collect();
+    let mut counts = vec![0; patterns.len()];
+    if data.is_empty() || searched.is_empty() {
+        return counts;
+    }
+    let automaton = AhoCorasick::builder()
+        .ascii_case_insensitive(ignore_ascii_case)
+        .build(searched.iter().map(|&i| patterns[i]))
+        .expect("literal patterns always build");
+    let overhang = searched
+        .iter()
+        .map(|&i| patterns[i].len())
         .max()
-        .unwrap_or(0)
+        .unwrap_or(1)
+        - 1;
+
+    let count_chunk = |start: usize, end: usize| {
+        let region = &data[start..(end + overhang).min(data.len())];
+        let mut counts = vec![0; searched.len()];
+        let mut free_from = vec![0; searched.len()];
+        // Overlapping search reports every match; keeping, per pattern,
+        // only those past its previous match leaves the leftmost
+        // non-overlapping ones that memmem would find.
+        for m in automaton.find_overlapping_iter(region) {
+            let pattern = m.pattern().as_usize();
+            if m.start() < end - start && m.start() >= free_from[pattern] {
+                counts[pattern] += 1;
+                free_from[pattern] = m.end();
+            }
+        }
+        counts
+    };
+
+    let found = if data.len() < tuning.parallel_threshold {
+        count_chunk(0, data.len())
+    } else {
+        let num_chunks = data.len().div_ceil(tuning.chunk_size);
+        (0..num_chunks)
+            .into_par_iter()
+            .filter(|_| !tuning.expired())
+            .map(|i| {
+                let start = i * tuning.chunk_size;
+                count_chunk(start, ((i + 1) * tuning.chunk_size).min(data.len()))
+            })
+            .reduce(
+                || vec![0; searched.len()],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            )
+    };
+    for (&i, count) in searched.iter().zip(found) {
+        counts[i] = count;
+    }
+    counts
+}
+
+/// Compiles a `--regex` pattern. `^` and `$` match at line starts and ends
+/// (LF or CRLF), as they do in grep.
+pub fn compile_regex(pattern: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .multi_line(true)
+        .crlf(true)
+        .case_insensitive(ignore_case)
+        .build()
+}
+
+/// Counts non-overlapping regex matches. Unlike `count_pattern` this is a
+/// single pass: a match has no bounded length (`foo\s+bar` may span any
+/// number of lines), so chunks cannot be stitched together the way fixed
+/// literals are, and the regex engine's own literal prefilters keep the
+/// scan fast.
+pub fn count_regex(data: &[u8], regex: &Regex) -> usize {
+    regex.find_iter(data).count()
+}
+
+enum GlobToken {
+    Literal(Vec<u8>),
+    AnyChar,
+}
+
+struct GlobSegment {
+    tokens: Vec<GlobToken>,
+    finder: Option<Finder<'static>>,
+}
+
+pub struct GlobPattern {
+    segments: Vec<GlobSegment>,
+    has_star: bool,
+}
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Self {
+        let has_star = pattern.contains('*');
+        let segments = pattern
+            .split('*')
+            .map(|part| {
+                let mut tokens = Vec::new();
+                let mut literal = Vec::new();
+                for c in part.chars() {
+                    if c == '?' {
+                        if !literal.is_empty() {
+                            tokens.push(GlobToken::Literal(std::mem::take(&mut literal)));
+                        }
+                        tokens.push(GlobToken::AnyChar);
+                    } else {
+                        let mut buf = [0; 4];
+                        literal.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                }
+                if !literal.is_empty() {
+                    tokens.push(GlobToken::Literal(literal));
+                }
+                let finder = match tokens.as_slice() {
+                    [GlobToken::Literal(lit)] => Some(Finder::new(lit).into_owned()),
+                    _ => None,
+                };
+                GlobSegment { tokens, finder }
+            })
+            .collect();
+
+        Self { segments, has_star }
+    }
+
+    fn match_at(segment: &GlobSegment, line: &[u8], mut pos: usize, utf8: bool) -> Option<usize> {
+        for token in &segment.tokens {
+            match token {
+                GlobToken::Literal(lit) => {
+                    if !line[pos..].starts_with(lit) {
+                        return None;
+                    }
+                    pos += lit.len();
+                }
+                GlobToken::AnyChar => {
+                    if pos >= line.len() {
+                        return None;
+                    }
+                    pos += if utf8 { utf8_char_width(line[pos]) } else { 1 };
+                }
+            }
+        }
+        Some(pos)
+    }
+
+    fn find_from(segment: &GlobSegment, line: &[u8], from: usize, utf8: bool) -> Option<usize> {
+        if let Some(finder) = &segment.finder {
+            return finder
+                .find(&line[from..])
+                .map(|i| from + i + finder.needle().len());
+        }
+        (from..=line.len())
+            .filter(|&p| !utf8 || p == line.len() || (line[p] & 0xC0) != 0x80)
+            .find_map(|p| Self::match_at(segment, line, p, utf8))
+    }
+
+    fn matches_at_end(segment: &GlobSegment, line: &[u8], from: usize, utf8: bool) -> bool {
+        if let Some(finder) = &segment.finder {
+            let needle = finder.needle();
+            return line.len() >= from + needle.len() && line.ends_with(needle);
+        }
+        (from..=line.len())
+            .filter(|&p| !utf8 || p == line.len() || (line[p] & 0xC0) != 0x80)
+            .any(|p| Self::match_at(segment, line, p, utf8) == Some(line.len()))
+    }
+
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        let utf8 = std::str::from_utf8(line).is_ok();
+
+        if !self.has_star {
+            return Self::match_at(&self.segments[0], line, 0, utf8) == Some(line.len());
+        }
+
+        let first = &self.segments[0];
+        let mut pos = match Self::match_at(first, line, 0, utf8) {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let last_idx = self.segments.len() - 1;
+        for segment in &self.segments[1..last_idx] {
+            if segment.tokens.is_empty() {
+                continue;
+            }
+            pos = match Self::find_from(segment, line, pos, utf8) {
+                Some(p) => p,
+                None => return false,
+            };
+        }
+
+        Self::matches_at_end(&self.segments[last_idx], line, pos, utf8)
+    }
+}
+
+fn utf8_char_width(first_byte: u8) -> usize {
+    match first_byte {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+pub fn count_glob_lines(data: &[u8], pattern: &GlobPattern, tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_glob_lines_chunk(data, pattern);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_glob_lines_chunk(&data[w[0]..w[1]], pattern))
+        .sum()
+}
+
+/// Counts lines matching at least one of `patterns`.
+pub fn count_glob_lines_any(data: &[u8], patterns: &[GlobPattern], tuning: TuningParams) -> usize {
+    count_lines_matching(data, tuning, |line| {
+        let line = trim_cr(line);
+        patterns.iter().any(|pattern| pattern.is_match(line))
+    })
 }
 
-fn max_line_length_chunk(data: &[u8]) -> usize {
-    let mut max_len = 0;
+fn count_glob_lines_chunk(data: &[u8], pattern: &GlobPattern) -> usize {
+    let mut count = 0;
     let mut prev = 0;
 
     for pos in memchr::memchr_iter(b'\n', data) {
@@ -262,7 +1330,9 @@ fn max_line_length_chunk(data: &[u8]) -> usize {
         if end > prev && data[end - 1] == b'\r' {
             end -= 1;
         }
-        max_len = max_len.max(end - prev);
+        if pattern.is_match(&data[prev..end]) {
+            count += 1;
+        }
         prev = pos + 1;
     }
 
@@ -271,181 +1341,1137 @@ fn max_line_length_chunk(data: &[u8]) -> usize {
         if end > prev && data[end - 1] == b'\r' {
             end -= 1;
         }
-        max_len = max_len.max(end - prev);
+        if pattern.is_match(&data[prev..end]) {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+pub fn count_chars(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return std::str::from_utf8(data)
+            .map(|s| s.chars().count())
+            .unwrap_or(data.len());
+    }
+
+    let chunk_boundaries = find_utf8_chunk_boundaries(data, tuning.chunk_size);
+    chunk_boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|window| {
+            let chunk = &data[window[0]..window[1]];
+            std::str::from_utf8(chunk)
+                .map(|s| s.chars().count())
+                .unwrap_or(chunk.len())
+        })
+        .sum()
+}
+
+fn count_non_ascii_chunk(chunk: &[u8]) -> usize {
+    chunk
+        .utf8_chunks()
+        .map(|c| c.valid().chars().filter(|ch| !ch.is_ascii()).count() + c.invalid().len())
+        .sum()
+}
+
+/// Counts characters above U+007F, such as smart quotes or homoglyphs.
+/// Bytes that are not valid UTF-8 count one each.
+pub fn count_non_ascii(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_ascii() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_non_ascii_chunk(data);
+    }
+
+    find_utf8_chunk_boundaries(data, tuning.chunk_size)
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_non_ascii_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+/// U+FEFF in UTF-8. At offset 0 it is a byte order mark; anywhere else it
+/// is an invisible zero-width no-break space, usually left behind by
+/// concatenating files that each started with a BOM.
+const FEFF: &[u8] = b"\xEF\xBB\xBF";
+
+/// The U+FEFFs after the start of `data`: how many, and the byte offset of
+/// the first.
+pub fn mid_file_feff(data: &[u8]) -> (usize, Option<usize>) {
+    let rest = data.get(1..).unwrap_or_default();
+    let mut offsets = memchr::memmem::find_iter(rest, FEFF).map(|i| i + 1);
+    match offsets.next() {
+        Some(first) => (1 + offsets.count(), Some(first)),
+        None => (0, None),
+    }
+}
+
+/// `data` without any U+FEFF, copied into `out` only when it has one. A
+/// leading BOM goes too: left in place it would still be counted as part of
+/// the first word.
+pub fn strip_feff<'a>(data: &'a [u8], out: &'a mut Vec<u8>) -> &'a [u8] {
+    let mut found = memchr::memmem::find_iter(data, FEFF).peekable();
+    if found.peek().is_none() {
+        return data;
+    }
+    out.clear();
+    out.reserve(data.len());
+    let mut kept = 0;
+    for i in found {
+        out.extend_from_slice(&data[kept..i]);
+        kept = i + FEFF.len();
+    }
+    out.extend_from_slice(&data[kept..]);
+    out
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const EMOJI_VARIATION_SELECTOR: char = '\u{FE0F}';
+const COMBINING_KEYCAP: char = '\u{20E3}';
+
+/// Characters that render as emoji without a variation selector.
+fn is_emoji_presentation(c: char) -> bool {
+    matches!(c,
+        '\u{231A}'..='\u{231B}'
+        | '\u{23E9}'..='\u{23EC}'
+        | '\u{23F0}'
+        | '\u{23F3}'
+        | '\u{25FD}'..='\u{25FE}'
+        | '\u{2614}'..='\u{2615}'
+        | '\u{2648}'..='\u{2653}'
+        | '\u{267F}'
+        | '\u{2693}'
+        | '\u{26A1}'
+        | '\u{26AA}'..='\u{26AB}'
+        | '\u{26BD}'..='\u{26BE}'
+        | '\u{26C4}'..='\u{26C5}'
+        | '\u{26CE}'
+        | '\u{26D4}'
+        | '\u{26EA}'
+        | '\u{26F2}'..='\u{26F3}'
+        | '\u{26F5}'
+        | '\u{26FA}'
+        | '\u{26FD}'
+        | '\u{2705}'
+        | '\u{270A}'..='\u{270B}'
+        | '\u{2728}'
+        | '\u{274C}'
+        | '\u{274E}'
+        | '\u{2753}'..='\u{2755}'
+        | '\u{2757}'
+        | '\u{2795}'..='\u{2797}'
+        | '\u{27B0}'
+        | '\u{27BF}'
+        | '\u{2B1B}'..='\u{2B1C}'
+        | '\u{2B50}'
+        | '\u{2B55}'
+        | '\u{1F004}'
+        | '\u{1F0CF}'
+        | '\u{1F18E}'
+        | '\u{1F191}'..='\u{1F19A}'
+        | '\u{1F1E6}'..='\u{1F1FF}'
+        | '\u{1F201}'
+        | '\u{1F21A}'
+        | '\u{1F22F}'
+        | '\u{1F232}'..='\u{1F236}'
+        | '\u{1F238}'..='\u{1F23A}'
+        | '\u{1F250}'..='\u{1F251}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F680}'..='\u{1F6FF}'
+        | '\u{1F7E0}'..='\u{1F7F0}'
+        | '\u{1F90C}'..='\u{1F9FF}'
+        | '\u{1FA70}'..='\u{1FAFF}'
+    )
+}
+
+/// A grapheme is an emoji if it starts with an emoji-presentation character,
+/// or if a text-default symbol is promoted by a variation selector, keycap,
+/// or ZWJ sequence. Plain symbols such as (c) and (tm) do not count.
+fn is_emoji_grapheme(grapheme: &str) -> bool {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    if is_emoji_presentation(first) {
+        return true;
+    }
+    if first.is_ascii() && !matches!(first, '#' | '*' | '0'..='9') {
+        return false;
+    }
+    chars.any(|c| {
+        matches!(
+            c,
+            EMOJI_VARIATION_SELECTOR | COMBINING_KEYCAP | ZERO_WIDTH_JOINER
+        )
+    })
+}
+
+fn count_emoji_chunk(chunk: &[u8]) -> usize {
+    String::from_utf8_lossy(chunk)
+        .graphemes(true)
+        .filter(|g| is_emoji_grapheme(g))
+        .count()
+}
+
+/// Counts emoji, treating each grapheme cluster (ZWJ sequences, flags,
+/// skin-tone modifiers) as a single emoji. Chunks split on newlines, which
+/// always end a grapheme, so no sequence straddles two chunks.
+pub fn count_emoji(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_emoji_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_emoji_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+fn count_graphemes_chunk(chunk: &[u8]) -> usize {
+    String::from_utf8_lossy(chunk).graphemes(true).count()
+}
+
+/// Counts extended grapheme clusters, the user-perceived characters: a ZWJ
+/// family or a Hangul syllable built from jamo is one. Chunks split after
+/// newlines, which end a cluster (`\r\n` included), so none is counted twice.
+pub fn count_graphemes(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return count_graphemes_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| count_graphemes_chunk(&data[w[0]..w[1]]))
+        .sum()
+}
+
+/// The longest line in bytes, without its line ending, and its 1-based
+/// line number; the first one wins a tie.
+pub fn longest_line(data: &[u8], tuning: TuningParams) -> LongestLine {
+    if data.len() < tuning.parallel_threshold {
+        return LongestLine::of_chunk(data);
+    }
+
+    find_line_boundaries(data, tuning.chunk_size)
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| LongestLine::of_chunk(&data[w[0]..w[1]]))
+        .reduce(LongestLine::default, LongestLine::then)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LongestLine {
+    pub length: usize,
+    /// 0 only for empty input.
+    pub line: usize,
+    /// Newlines in the chunk, to number the lines of the chunks after it.
+    newlines: usize,
+}
+
+impl LongestLine {
+    fn of_chunk(data: &[u8]) -> Self {
+        let mut longest = Self::default();
+        let mut prev = 0;
+        let measure = |longest: &mut Self, start: usize, mut end: usize| {
+            if end > start && data[end - 1] == b'\r' {
+                end -= 1;
+            }
+            if longest.line == 0 || end - start > longest.length {
+                longest.length = end - start;
+                longest.line = longest.newlines + 1;
+            }
+        };
+
+        for pos in memchr::memchr_iter(b'\n', data) {
+            measure(&mut longest, prev, pos);
+            longest.newlines += 1;
+            prev = pos + 1;
+        }
+        if prev < data.len() {
+            measure(&mut longest, prev, data.len());
+        }
+        longest
+    }
+
+    fn then(self, next: Self) -> Self {
+        let newlines = self.newlines + next.newlines;
+        if next.line > 0 && (self.line == 0 || next.length > self.length) {
+            Self {
+                length: next.length,
+                line: self.newlines + next.line,
+                newlines,
+            }
+        } else {
+            Self { newlines, ..self }
+        }
+    }
+}
+
+/// Columns between tab stops for `--max-line-width`.
+const TAB_STOP: usize = 8;
+
+/// The widest line in terminal columns: wide characters take two, combining
+/// marks none, and a tab moves to the next multiple of `TAB_STOP`. A line
+/// that is not valid UTF-8 counts its bytes instead.
+pub fn max_line_width(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    if data.len() < tuning.parallel_threshold {
+        return max_line_width_chunk(data);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| max_line_width_chunk(&data[w[0]..w[1]]))
+        .max()
+        .unwrap_or(0)
+}
+
+fn max_line_width_chunk(data: &[u8]) -> usize {
+    data.split(|&b| b == b'\n')
+        .map(|line| line_width(trim_cr(line)))
+        .max()
+        .unwrap_or(0)
+}
+
+fn line_width(line: &[u8]) -> usize {
+    let Ok(text) = std::str::from_utf8(line) else {
+        return line.len();
+    };
+    text.chars().fold(0, |column, c| {
+        if c == '\t' {
+            (column / TAB_STOP + 1) * TAB_STOP
+        } else {
+            column + c.width().unwrap_or(0)
+        }
+    })
+}
+
+pub fn count_repeated_lines(data: &[u8], tuning: TuningParams) -> usize {
+    if data.is_empty() {
+        return 0;
     }
 
-    max_len
+    if data.len() < tuning.parallel_threshold {
+        return repeated_lines_chunk(data).count;
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    let scans: Vec<RepeatScan> = boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| repeated_lines_chunk(&data[w[0]..w[1]]))
+        .collect();
+
+    let internal: usize = scans.iter().map(|s| s.count).sum();
+    let straddling = scans
+        .windows(2)
+        .filter(|pair| pair[0].last.is_some() && pair[0].last == pair[1].first)
+        .count();
+
+    internal + straddling
+}
+
+/// The lines of `chunk` without their line endings.
+fn chunk_lines(chunk: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut prev = 0;
+    for pos in memchr::memchr_iter(b'\n', chunk) {
+        lines.push(trim_cr(&chunk[prev..pos]));
+        prev = pos + 1;
+    }
+    if prev < chunk.len() {
+        lines.push(trim_cr(&chunk[prev..]));
+    }
+    lines
+}
+
+/// The distinct lines of `data`, without their line endings, borrowed
+/// from it. Chunks split on newlines and their sets are merged.
+pub fn unique_lines(data: &[u8], tuning: TuningParams) -> HashSet<&[u8]> {
+    if data.len() < tuning.parallel_threshold {
+        return chunk_lines(data).into_iter().collect();
+    }
+
+    find_line_boundaries(data, tuning.chunk_size)
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| {
+            chunk_lines(&data[w[0]..w[1]])
+                .into_iter()
+                .collect::<HashSet<&[u8]>>()
+        })
+        .reduce(HashSet::new, |mut merged, set| {
+            merged.extend(set);
+            merged
+        })
+}
+
+/// Lines occurring more than once anywhere in a file, not just in a row.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateLines {
+    /// Distinct lines seen at least twice.
+    pub distinct_duplicated: usize,
+    /// Occurrences past the first of each such line.
+    pub redundant_total: usize,
+}
+
+/// Counts lines that appear more than once in `data`, ignoring line endings.
+/// Per-chunk occurrence maps are merged before anything is counted, so a
+/// line repeated across chunks is caught too.
+pub fn count_duplicate_lines(data: &[u8], tuning: TuningParams) -> DuplicateLines {
+    fn occurrences(chunk: &[u8]) -> HashMap<&[u8], usize> {
+        let mut seen = HashMap::new();
+        for line in chunk_lines(chunk) {
+            *seen.entry(line).or_insert(0) += 1;
+        }
+        seen
+    }
+
+    let seen = if data.len() < tuning.parallel_threshold {
+        occurrences(data)
+    } else {
+        find_line_boundaries(data, tuning.chunk_size)
+            .par_windows(2)
+            .filter(|_| !tuning.expired())
+            .map(|w| occurrences(&data[w[0]..w[1]]))
+            .reduce(HashMap::new, |mut merged, chunk| {
+                for (line, n) in chunk {
+                    *merged.entry(line).or_insert(0) += n;
+                }
+                merged
+            })
+    };
+
+    seen.values()
+        .filter(|&&n| n > 1)
+        .fold(DuplicateLines::default(), |acc, &n| DuplicateLines {
+            distinct_duplicated: acc.distinct_duplicated + 1,
+            redundant_total: acc.redundant_total + n - 1,
+        })
+}
+
+struct RepeatScan<'a> {
+    count: usize,
+    first: Option<&'a [u8]>,
+    last: Option<&'a [u8]>,
+}
+
+fn repeated_lines_chunk(data: &[u8]) -> RepeatScan<'_> {
+    let mut lines = Vec::new();
+    let mut prev = 0;
+
+    for pos in memchr::memchr_iter(b'\n', data) {
+        let mut end = pos;
+        if end > prev && data[end - 1] == b'\r' {
+            end -= 1;
+        }
+        lines.push(&data[prev..end]);
+        prev = pos + 1;
+    }
+
+    if prev < data.len() {
+        let mut end = data.len();
+        if end > prev && data[end - 1] == b'\r' {
+            end -= 1;
+        }
+        lines.push(&data[prev..end]);
+    }
+
+    RepeatScan {
+        count: lines.windows(2).filter(|pair| pair[0] == pair[1]).count(),
+        first: lines.first().copied(),
+        last: lines.last().copied(),
+    }
+}
+
+pub fn is_binary(data: &[u8]) -> bool {
+    let sample_size = data.len().min(8192);
+    let sample = &data[..sample_size];
+    memchr::memchr(0, sample).is_some()
+}
+
+pub fn count_unique_words(data: &[u8], tuning: TuningParams, tokenizer: Tokenizer) -> usize {
+    unique_words(data, tuning, tokenizer).len()
+}
+
+/// The distinct words of `data`, borrowed from it.
+pub fn unique_words(data: &[u8], tuning: TuningParams, tokenizer: Tokenizer) -> HashSet<&str> {
+    let text = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return HashSet::new(),
+    };
+
+    if data.len() < tuning.parallel_threshold {
+        return tokenizer.tokens(text).collect();
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+
+    let local_sets: Vec<HashSet<&str>> = boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|window| {
+            let chunk = &data[window[0]..window[1]];
+            let chunk_text = std::str::from_utf8(chunk).unwrap_or("");
+            tokenizer.tokens(chunk_text).collect::<HashSet<&str>>()
+        })
+        .collect();
+
+    let mut final_set = HashSet::new();
+    for set in local_sets {
+        final_set.extend(set);
+    }
+
+    final_set
+}
+
+/// Leading whitespace of the non-blank lines, for `--indent-stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IndentStats {
+    pub tab_lines: usize,
+    pub space_lines: usize,
+    /// Lines whose indentation mixes tabs and spaces.
+    pub mixed_lines: usize,
+    /// Deepest indentation in columns, tabs advancing to the next stop.
+    pub max_depth: usize,
+}
+
+impl IndentStats {
+    pub fn merge(self, other: IndentStats) -> IndentStats {
+        IndentStats {
+            tab_lines: self.tab_lines + other.tab_lines,
+            space_lines: self.space_lines + other.space_lines,
+            mixed_lines: self.mixed_lines + other.mixed_lines,
+            max_depth: self.max_depth.max(other.max_depth),
+        }
+    }
+}
+
+fn indent_stats_chunk(data: &[u8], tab_width: usize) -> IndentStats {
+    let mut stats = IndentStats::default();
+    for line in data.split(|&b| b == b'\n') {
+        if is_blank_line(line) {
+            continue;
+        }
+        let indent = line.iter().take_while(|&&b| b == b' ' || b == b'\t').fold(
+            (0, false, false),
+            |(depth, tabs, spaces), &b| {
+                if b == b'\t' {
+                    ((depth / tab_width + 1) * tab_width, true, spaces)
+                } else {
+                    (depth + 1, tabs, true)
+                }
+            },
+        );
+        match indent {
+            (_, true, true) => stats.mixed_lines += 1,
+            (_, true, false) => stats.tab_lines += 1,
+            (_, false, true) => stats.space_lines += 1,
+            (_, false, false) => {}
+        }
+        stats.max_depth = stats.max_depth.max(indent.0);
+    }
+    stats
+}
+
+/// Classifies the indentation of every non-blank line as tabs, spaces or
+/// mixed, and finds the deepest in columns with `tab_width`-column tab
+/// stops. Chunks split on newlines.
+pub fn calculate_indent_stats(data: &[u8], tab_width: usize, tuning: TuningParams) -> IndentStats {
+    if data.len() < tuning.parallel_threshold {
+        return indent_stats_chunk(data, tab_width);
+    }
+
+    find_line_boundaries(data, tuning.chunk_size)
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| indent_stats_chunk(&data[w[0]..w[1]], tab_width))
+        .reduce(IndentStats::default, IndentStats::merge)
+}
+
+pub struct Statistics {
+    pub mean_line_length: f64,
+    pub median_line_length: usize,
+    pub std_dev: f64,
+    pub min_line_length: usize,
+    pub max_line_length: usize,
+    /// Lines with no content, the same definition as `BlankLines::empty`.
+    pub empty_lines: usize,
+}
+
+pub fn calculate_statistics(data: &[u8], tuning: TuningParams) -> Statistics {
+    if data.is_empty() {
+        return Statistics {
+            mean_line_length: 0.0,
+            median_line_length: 0,
+            std_dev: 0.0,
+            min_line_length: 0,
+            max_line_length: 0,
+            empty_lines: 0,
+        };
+    }
+
+    let line_lengths = if data.len() < tuning.parallel_threshold {
+        collect_line_lengths_chunk(data)
+    } else {
+        let boundaries = find_line_boundaries(data, tuning.chunk_size);
+
+        boundaries
+            .par_windows(2)
+            .filter(|_| !tuning.expired())
+            .flat_map(|w| collect_line_lengths_chunk(&data[w[0]..w[1]]))
+            .collect()
+    };
+
+    if line_lengths.is_empty() {
+        return Statistics {
+            mean_line_length: 0.0,
+            median_line_length: 0,
+            std_dev: 0.0,
+            min_line_length: 0,
+            max_line_length: 0,
+            empty_lines: 0,
+        };
+    }
+
+    let empty_lines = line_lengths.iter().filter(|&&l| l == 0).count();
+    let sum: usize = line_lengths.iter().sum();
+    let mean = sum as f64 / line_lengths.len() as f64;
+
+    let variance: f64 = if data.len() < tuning.parallel_threshold {
+        line_lengths
+            .iter()
+            .map(|&len| {
+                let diff = len as f64 - mean;
+                diff * diff
+            })
+            .sum::<f64>()
+    } else {
+        line_lengths
+            .par_iter()
+            .map(|&len| {
+                let diff = len as f64 - mean;
+                diff * diff
+            })
+            .sum::<f64>()
+    } / line_lengths.len() as f64;
+
+    let std_dev = variance.sqrt();
+
+    let mut sorted = line_lengths;
+    sorted.sort_unstable();
+
+    let median = if sorted.len() % 2 == 0 {
+        let mid = sorted.len() / 2;
+        (sorted[mid - 1] + sorted[mid]) / 2
+    } else {
+        sorted[sorted.len() / 2]
+    };
+
+    Statistics {
+        mean_line_length: mean,
+        median_line_length: median,
+        std_dev,
+        min_line_length: sorted[0],
+        max_line_length: sorted[sorted.len() - 1],
+        empty_lines,
+    }
+}
+
+/// Word lengths in characters for `--stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WordLengthStats {
+    pub mean: f64,
+    pub median: usize,
+    pub max: usize,
+}
+
+/// Length statistics over the words `count_all_words` counts. Chunks end at
+/// newlines, which are whitespace, so no word is cut.
+pub fn word_length_stats(data: &[u8], tuning: TuningParams) -> WordLengthStats {
+    let lengths = if data.len() < tuning.parallel_threshold {
+        word_lengths_chunk(data)
+    } else {
+        let boundaries = find_line_boundaries(data, tuning.chunk_size);
+        boundaries
+            .par_windows(2)
+            .filter(|_| !tuning.expired())
+            .map(|w| word_lengths_chunk(&data[w[0]..w[1]]))
+            .reduce(BTreeMap::new, |mut merged, lengths| {
+                for (len, count) in lengths {
+                    *merged.entry(len).or_insert(0) += count;
+                }
+                merged
+            })
+    };
+
+    let words: usize = lengths.values().sum();
+    if words == 0 {
+        return WordLengthStats::default();
+    }
+    let chars: usize = lengths.iter().map(|(len, count)| len * count).sum();
+    let nth = |index: usize| {
+        let mut seen = 0;
+        lengths
+            .iter()
+            .find(|(_, count)| {
+                seen += **count;
+                seen > index
+            })
+            .map_or(0, |(len, _)| *len)
+    };
+    let median = if words.is_multiple_of(2) {
+        (nth(words / 2 - 1) + nth(words / 2)) / 2
+    } else {
+        nth(words / 2)
+    };
+    WordLengthStats {
+        mean: chars as f64 / words as f64,
+        median,
+        max: lengths.keys().next_back().copied().unwrap_or(0),
+    }
+}
+
+/// How many words of each length a chunk holds.
+fn word_lengths_chunk(chunk: &[u8]) -> BTreeMap<usize, usize> {
+    fn tally(is_space: impl Iterator<Item = bool>) -> BTreeMap<usize, usize> {
+        let mut lengths = BTreeMap::new();
+        let mut current = 0;
+        for space in is_space.chain(std::iter::once(true)) {
+            if !space {
+                current += 1;
+            } else if current > 0 {
+                *lengths.entry(current).or_insert(0) += 1;
+                current = 0;
+            }
+        }
+        lengths
+    }
+    match std::str::from_utf8(chunk) {
+        Ok(text) => tally(text.chars().map(char::is_whitespace)),
+        Err(_) => tally(chunk.iter().map(u8::is_ascii_whitespace)),
+    }
+}
+
+fn collect_line_lengths_chunk(data: &[u8]) -> Vec<usize> {
+    let mut lengths = Vec::new();
+    let mut prev = 0;
+
+    for pos in memchr::memchr_iter(b'\n', data) {
+        lengths.push(trim_cr(&data[prev..pos]).len());
+        prev = pos + 1;
+    }
+
+    if prev < data.len() {
+        lengths.push(trim_cr(&data[prev..]).len());
+    }
+
+    lengths
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineTally {
+    pub count: usize,
+    /// The first line seen with this key, before fields were skipped.
+    pub exemplar: String,
+}
+
+/// Drops the first `skip_fields` whitespace-separated fields of a trimmed line.
+fn line_key(line: &str, skip_fields: usize) -> &str {
+    let mut rest = line.trim();
+    for _ in 0..skip_fields {
+        match rest.find(char::is_whitespace) {
+            Some(end) => rest = rest[end..].trim_start(),
+            None => return "",
+        }
+    }
+    rest
+}
+
+fn line_frequencies_chunk(data: &[u8], skip_fields: usize) -> HashMap<String, LineTally> {
+    let text = String::from_utf8_lossy(data);
+    let mut map: HashMap<String, LineTally> = HashMap::new();
+    for line in text.lines() {
+        let key = line_key(line, skip_fields);
+        if key.is_empty() {
+            continue;
+        }
+        match map.get_mut(key) {
+            Some(tally) => tally.count += 1,
+            None => {
+                map.insert(
+                    key.to_string(),
+                    LineTally {
+                        count: 1,
+                        exemplar: line.trim().to_string(),
+                    },
+                );
+            }
+        }
+    }
+    map
+}
+
+/// Merges `other` into `into`, keeping the earlier exemplar for each key.
+pub fn merge_line_frequencies(
+    into: &mut HashMap<String, LineTally>,
+    other: HashMap<String, LineTally>,
+) {
+    for (key, tally) in other {
+        into.entry(key)
+            .and_modify(|t| t.count += tally.count)
+            .or_insert(tally);
+    }
+}
+
+/// Counts how often each non-blank line occurs, keyed by the trimmed line
+/// with its first `skip_fields` fields removed (e.g. timestamps).
+pub fn line_frequencies(
+    data: &[u8],
+    skip_fields: usize,
+    tuning: TuningParams,
+) -> HashMap<String, LineTally> {
+    if data.len() < tuning.parallel_threshold {
+        return line_frequencies_chunk(data, skip_fields);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    let maps: Vec<HashMap<String, LineTally>> = boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| line_frequencies_chunk(&data[w[0]..w[1]], skip_fields))
+        .collect();
+
+    let mut merged = HashMap::new();
+    for map in maps {
+        merge_line_frequencies(&mut merged, map);
+    }
+    merged
+}
+
+/// The `n` most frequent lines as (count, exemplar), ties broken by text.
+pub fn top_lines(frequencies: &HashMap<String, LineTally>, n: usize) -> Vec<(usize, &str)> {
+    let mut ranked: Vec<(usize, &str)> = frequencies
+        .values()
+        .map(|t| (t.count, t.exemplar.as_str()))
+        .collect();
+    ranked.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    ranked.truncate(n);
+    ranked
+}
+
+/// How `--line-prefix-groups` and `--line-prefix-fields` key a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinePrefix {
+    /// The first N characters.
+    Chars(usize),
+    /// The first N whitespace-separated fields, with their separators.
+    Fields(usize),
+}
+
+/// Longest prefix kept for a group, so a field-based key on a line with
+/// no whitespace cannot turn into a whole-line key.
+const MAX_PREFIX_CHARS: usize = 64;
+
+fn line_prefix(line: &str, prefix: LinePrefix) -> &str {
+    let kept = match prefix {
+        LinePrefix::Chars(n) => truncate_chars(line, n),
+        LinePrefix::Fields(n) => {
+            let line = line.trim_start();
+            let mut end = 0;
+            for _ in 0..n {
+                let rest = &line[end..];
+                let start = end + (rest.len() - rest.trim_start().len());
+                end = line[start..]
+                    .find(char::is_whitespace)
+                    .map_or(line.len(), |i| start + i);
+            }
+            &line[..end]
+        }
+    };
+    truncate_chars(kept, MAX_PREFIX_CHARS)
+}
+
+fn truncate_chars(text: &str, max: usize) -> &str {
+    text.char_indices()
+        .nth(max)
+        .map_or(text, |(i, _)| &text[..i])
+}
+
+fn prefix_groups_chunk(data: &[u8], prefix: LinePrefix) -> HashMap<String, usize> {
+    let text = String::from_utf8_lossy(data);
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    for line in text.lines() {
+        let key = line_prefix(line, prefix);
+        if key.trim().is_empty() {
+            continue;
+        }
+        match groups.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                groups.insert(key.to_string(), 1);
+            }
+        }
+    }
+    groups
+}
+
+/// Adds the per-key counts of `other` into `into`.
+pub fn merge_key_counts(into: &mut HashMap<String, usize>, other: HashMap<String, usize>) {
+    for (key, count) in other {
+        *into.entry(key).or_insert(0) += count;
+    }
 }
 
-pub fn is_binary(data: &[u8]) -> bool {
-    let sample_size = data.len().min(8192);
-    let sample = &data[..sample_size];
-    memchr::memchr(0, sample).is_some()
+/// Counts non-blank lines per prefix, a histogram over message types when
+/// the prefix is a log level or module name.
+pub fn prefix_groups(
+    data: &[u8],
+    prefix: LinePrefix,
+    tuning: TuningParams,
+) -> HashMap<String, usize> {
+    if data.len() < tuning.parallel_threshold {
+        return prefix_groups_chunk(data, prefix);
+    }
+
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| prefix_groups_chunk(&data[w[0]..w[1]], prefix))
+        .reduce(HashMap::new, |mut a, b| {
+            merge_key_counts(&mut a, b);
+            a
+        })
 }
 
-pub fn count_unique_words(data: &[u8]) -> usize {
-    let text = match std::str::from_utf8(data) {
-        Ok(s) => s,
-        Err(_) => return 0,
-    };
+/// Lines and bytes under one `--group-by-timestamp` date.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DayCounts {
+    pub lines: usize,
+    /// Including line endings, so the buckets add up to the file size.
+    pub bytes: usize,
+}
 
-    if data.len() < PARALLEL_THRESHOLD {
-        let words: HashSet<&str> = text
-            .split(|c: char| c.is_whitespace())
-            .filter(|w| !w.is_empty())
-            .collect();
-        return words.len();
+fn date_buckets_chunk(data: &[u8], format: &DateFormat) -> HashMap<Option<Date>, DayCounts> {
+    let mut buckets: HashMap<Option<Date>, DayCounts> = HashMap::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = memchr::memchr(b'\n', &data[start..]).map_or(data.len(), |pos| start + pos + 1);
+        let day = buckets.entry(format.date(&data[start..end])).or_default();
+        day.lines += 1;
+        day.bytes += end - start;
+        start = end;
     }
+    buckets
+}
 
-    let boundaries = find_line_boundaries(data, CHUNK_SIZE);
+/// Lines and bytes per date at the start of each line; lines without one
+/// fall under `None`.
+pub fn date_buckets(
+    data: &[u8],
+    format: &DateFormat,
+    tuning: TuningParams,
+) -> HashMap<Option<Date>, DayCounts> {
+    if data.len() < tuning.parallel_threshold {
+        return date_buckets_chunk(data, format);
+    }
 
-    let local_sets: Vec<HashSet<&str>> = boundaries
+    find_line_boundaries(data, tuning.chunk_size)
         .par_windows(2)
-        .map(|window| {
-            let chunk = &data[window[0]..window[1]];
-            let chunk_text = std::str::from_utf8(chunk).unwrap_or("");
-            let mut local_set = HashSet::new();
-            for word in chunk_text.split(|c: char| c.is_whitespace()) {
-                if !word.is_empty() {
-                    local_set.insert(word);
-                }
-            }
-            local_set
+        .filter(|_| !tuning.expired())
+        .map(|w| date_buckets_chunk(&data[w[0]..w[1]], format))
+        .reduce(HashMap::new, |mut a, b| {
+            merge_date_buckets(&mut a, b);
+            a
         })
-        .collect();
+}
 
-    let mut final_set = HashSet::new();
-    for set in local_sets {
-        final_set.extend(set);
+pub fn merge_date_buckets(
+    into: &mut HashMap<Option<Date>, DayCounts>,
+    from: HashMap<Option<Date>, DayCounts>,
+) {
+    for (date, counts) in from {
+        let day = into.entry(date).or_default();
+        day.lines += counts.lines;
+        day.bytes += counts.bytes;
     }
-
-    final_set.len()
 }
 
-pub struct Statistics {
-    pub mean_line_length: f64,
-    pub median_line_length: usize,
-    pub std_dev: f64,
-    pub min_line_length: usize,
-    pub max_line_length: usize,
-    pub empty_lines: usize,
+/// Keyed counts (prefix groups, word frequencies) as (count, key), largest
+/// first, ties broken alphabetically.
+pub fn rank_key_counts(counts: &HashMap<String, usize>) -> Vec<(usize, &str)> {
+    let mut ranked: Vec<(usize, &str)> = counts
+        .iter()
+        .map(|(key, count)| (*count, key.as_str()))
+        .collect();
+    ranked.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    ranked
 }
 
-pub fn calculate_statistics(data: &[u8]) -> Statistics {
-    if data.is_empty() {
-        return Statistics {
-            mean_line_length: 0.0,
-            median_line_length: 0,
-            std_dev: 0.0,
-            min_line_length: 0,
-            max_line_length: 0,
-            empty_lines: 0,
-        };
+/// Tallies a chunk against borrowed words and only copies each distinct
+/// word once at the end, rather than allocating per occurrence.
+fn word_frequencies_chunk(data: &[u8], tokenizer: Tokenizer) -> HashMap<String, usize> {
+    let text = String::from_utf8_lossy(data);
+    let mut frequencies: HashMap<&str, usize> = HashMap::new();
+    for word in tokenizer.tokens(&text) {
+        *frequencies.entry(word).or_insert(0) += 1;
     }
+    frequencies
+        .into_iter()
+        .map(|(word, count)| (word.to_string(), count))
+        .collect()
+}
 
-    let line_lengths = if data.len() < PARALLEL_THRESHOLD {
-        collect_line_lengths_chunk(data)
-    } else {
-        let boundaries = find_line_boundaries(data, CHUNK_SIZE);
-
-        boundaries
-            .par_windows(2)
-            .flat_map(|w| collect_line_lengths_chunk(&data[w[0]..w[1]]))
-            .collect()
-    };
-
-    if line_lengths.is_empty() {
-        return Statistics {
-            mean_line_length: 0.0,
-            median_line_length: 0,
-            std_dev: 0.0,
-            min_line_length: 0,
-            max_line_length: 0,
-            empty_lines: 0,
-        };
+/// How often each word occurs, split the same way as `unique_words`.
+pub fn word_frequencies(
+    data: &[u8],
+    tuning: TuningParams,
+    tokenizer: Tokenizer,
+) -> HashMap<String, usize> {
+    if data.len() < tuning.parallel_threshold {
+        return word_frequencies_chunk(data, tokenizer);
     }
 
-    let empty_lines = line_lengths.iter().filter(|&&l| l == 0).count();
-    let sum: usize = line_lengths.iter().sum();
-    let mean = sum as f64 / line_lengths.len() as f64;
-
-    let variance: f64 = if data.len() < PARALLEL_THRESHOLD {
-        line_lengths
-            .iter()
-            .map(|&len| {
-                let diff = len as f64 - mean;
-                diff * diff
-            })
-            .sum::<f64>()
-    } else {
-        line_lengths
-            .par_iter()
-            .map(|&len| {
-                let diff = len as f64 - mean;
-                diff * diff
-            })
-            .sum::<f64>()
-    } / line_lengths.len() as f64;
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    let maps: Vec<HashMap<String, usize>> = boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| word_frequencies_chunk(&data[w[0]..w[1]], tokenizer))
+        .collect();
 
-    let std_dev = variance.sqrt();
+    let mut merged = HashMap::new();
+    for map in maps {
+        merge_key_counts(&mut merged, map);
+    }
+    merged
+}
 
-    let mut sorted = line_lengths;
-    sorted.sort_unstable();
+pub fn line_length_counts(data: &[u8], tuning: TuningParams) -> HashMap<usize, usize> {
+    if data.is_empty() {
+        return HashMap::new();
+    }
 
-    let median = if sorted.len() % 2 == 0 {
-        let mid = sorted.len() / 2;
-        (sorted[mid - 1] + sorted[mid]) / 2
-    } else {
-        sorted[sorted.len() / 2]
+    let tally = |chunk: &[u8]| {
+        let mut map = HashMap::new();
+        for len in collect_line_lengths_chunk(chunk) {
+            *map.entry(len).or_insert(0) += 1;
+        }
+        map
     };
 
-    Statistics {
-        mean_line_length: mean,
-        median_line_length: median,
-        std_dev,
-        min_line_length: sorted[0],
-        max_line_length: sorted[sorted.len() - 1],
-        empty_lines,
+    if data.len() < tuning.parallel_threshold {
+        return tally(data);
     }
-}
 
-fn collect_line_lengths_chunk(data: &[u8]) -> Vec<usize> {
-    let mut lengths = Vec::new();
-    let mut prev = 0;
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    let maps: Vec<HashMap<usize, usize>> = boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|w| tally(&data[w[0]..w[1]]))
+        .collect();
 
-    for pos in memchr::memchr_iter(b'\n', data) {
-        let mut end = pos;
-        if end > prev && data[end - 1] == b'\r' {
-            end -= 1;
+    let mut merged = HashMap::new();
+    for map in maps {
+        for (len, count) in map {
+            *merged.entry(len).or_insert(0) += count;
         }
-        lengths.push(end - prev);
-        prev = pos + 1;
     }
+    merged
+}
 
-    if prev < data.len() {
-        let mut end = data.len();
-        if end > prev && data[end - 1] == b'\r' {
-            end -= 1;
+pub struct WidthViolations {
+    pub count: usize,
+    pub first_lines: Vec<usize>,
+}
+
+/// Finds lines whose length differs from `width`, reporting 1-based line
+/// numbers for at most `limit` of them.
+pub fn find_width_violations(
+    data: &[u8],
+    width: usize,
+    limit: usize,
+    tuning: TuningParams,
+) -> WidthViolations {
+    let scan = |chunk: &[u8]| {
+        let lengths = collect_line_lengths_chunk(chunk);
+        let bad: Vec<usize> = lengths
+            .iter()
+            .enumerate()
+            .filter(|(_, len)| **len != width)
+            .map(|(i, _)| i)
+            .collect();
+        (lengths.len(), bad)
+    };
+
+    let chunks: Vec<(usize, Vec<usize>)> = if data.len() < tuning.parallel_threshold {
+        vec![scan(data)]
+    } else {
+        let boundaries = find_line_boundaries(data, tuning.chunk_size);
+        boundaries
+            .par_windows(2)
+            .filter(|_| !tuning.expired())
+            .map(|w| scan(&data[w[0]..w[1]]))
+            .collect()
+    };
+
+    let mut result = WidthViolations {
+        count: 0,
+        first_lines: Vec::new(),
+    };
+    let mut offset = 0;
+    for (line_count, bad) in chunks {
+        result.count += bad.len();
+        for idx in bad {
+            if result.first_lines.len() >= limit {
+                break;
+            }
+            result.first_lines.push(offset + idx + 1);
         }
-        lengths.push(end - prev);
+        offset += line_count;
     }
-
-    lengths
+    result
 }
 
-pub fn generate_histogram(data: &[u8]) -> HashMap<usize, usize> {
+pub fn generate_histogram(data: &[u8], tuning: TuningParams) -> HashMap<usize, usize> {
     if data.is_empty() {
         return HashMap::new();
     }
 
-    if data.len() < PARALLEL_THRESHOLD {
+    if data.len() < tuning.parallel_threshold {
         return generate_histogram_chunk(data);
     }
 
-    let boundaries = find_line_boundaries(data, CHUNK_SIZE);
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
 
     let maps: Vec<HashMap<usize, usize>> = boundaries
         .par_windows(2)
+        .filter(|_| !tuning.expired())
         .map(|w| generate_histogram_chunk(&data[w[0]..w[1]]))
         .collect();
 
@@ -503,33 +2529,33 @@ fn find_comment_marker(s: &str, marker: &str, require_whitespace_before: bool) -
     None
 }
 
-pub fn filter_code_comments(data: &[u8]) -> Vec<u8> {
-    let text = match std::str::from_utf8(data) {
-        Ok(s) => s,
-        Err(_) => return data.to_vec(),
-    };
-
-    let mut result = Vec::new();
-    let mut in_multiline_c_comment = false;
-    let mut in_python_docstring = false;
-    let mut docstring_marker: &str = "";
+/// Comment state carried from one line of source to the next, shared by
+/// `--code` and `--cloc`.
+#[derive(Default)]
+struct CommentScanner {
+    in_multiline_c_comment: bool,
+    in_python_docstring: bool,
+    docstring_marker: &'static str,
+}
 
-    for line in text.lines() {
+impl CommentScanner {
+    /// The parts of `line` outside comments and docstrings.
+    fn code(&mut self, line: &str) -> String {
         let mut current = line;
         let mut line_output = String::new();
 
         while !current.is_empty() {
-            if in_multiline_c_comment {
+            if self.in_multiline_c_comment {
                 if let Some(pos) = current.find("*/") {
-                    in_multiline_c_comment = false;
+                    self.in_multiline_c_comment = false;
                     current = &current[pos + 2..];
                 } else {
                     break;
                 }
-            } else if in_python_docstring {
-                if let Some(pos) = current.find(docstring_marker) {
-                    in_python_docstring = false;
-                    current = &current[pos + docstring_marker.len()..];
+            } else if self.in_python_docstring {
+                if let Some(pos) = current.find(self.docstring_marker) {
+                    self.in_python_docstring = false;
+                    current = &current[pos + self.docstring_marker.len()..];
                 } else {
                     break;
                 }
@@ -560,7 +2586,7 @@ pub fn filter_code_comments(data: &[u8]) -> Vec<u8> {
                             if let Some(end_pos) = after.find("*/") {
                                 current = &after[end_pos + 2..];
                             } else {
-                                in_multiline_c_comment = true;
+                                self.in_multiline_c_comment = true;
                                 break;
                             }
                         }
@@ -569,8 +2595,8 @@ pub fn filter_code_comments(data: &[u8]) -> Vec<u8> {
                             if let Some(end_pos) = after.find("\"\"\"") {
                                 current = &after[end_pos + 3..];
                             } else {
-                                docstring_marker = "\"\"\"";
-                                in_python_docstring = true;
+                                self.docstring_marker = "\"\"\"";
+                                self.in_python_docstring = true;
                                 break;
                             }
                         }
@@ -579,8 +2605,8 @@ pub fn filter_code_comments(data: &[u8]) -> Vec<u8> {
                             if let Some(end_pos) = after.find("'''") {
                                 current = &after[end_pos + 3..];
                             } else {
-                                docstring_marker = "'''";
-                                in_python_docstring = true;
+                                self.docstring_marker = "'''";
+                                self.in_python_docstring = true;
                                 break;
                             }
                         }
@@ -592,24 +2618,68 @@ pub fn filter_code_comments(data: &[u8]) -> Vec<u8> {
                 }
             }
         }
+        line_output
+    }
+}
+
+/// Writes `data` without comments or blank lines into `result`, replacing
+/// what it held. Data that is not UTF-8 is copied unchanged.
+pub fn filter_code_comments(data: &[u8], result: &mut Vec<u8>) {
+    result.clear();
+    let text = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return result.extend_from_slice(data),
+    };
 
+    let mut scanner = CommentScanner::default();
+    for line in text.lines() {
+        let line_output = scanner.code(line);
         let trimmed = line_output.trim_end();
         if !trimmed.trim_start().is_empty() {
             result.extend_from_slice(trimmed.as_bytes());
             result.push(b'\n');
         }
     }
+}
 
-    result
+/// `--cloc`: physical lines by kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CodeBreakdown {
+    pub code: usize,
+    pub comment: usize,
+    pub blank: usize,
 }
 
-pub fn filter_markdown_code(data: &[u8]) -> Vec<u8> {
+/// Sorts each line into code, comment or blank with the comment rules of
+/// `filter_code_comments`. A line with any code outside comments is code,
+/// so `x = 1; // note` is code and a line inside `/* */` or a docstring is
+/// a comment. Serial: whether a line is in a comment depends on the lines
+/// before it.
+pub fn code_breakdown(data: &[u8]) -> CodeBreakdown {
+    let text = String::from_utf8_lossy(data);
+    let mut scanner = CommentScanner::default();
+    let mut breakdown = CodeBreakdown::default();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            breakdown.blank += 1;
+        } else if scanner.code(line).trim().is_empty() {
+            breakdown.comment += 1;
+        } else {
+            breakdown.code += 1;
+        }
+    }
+    breakdown
+}
+
+/// Writes `data` without fenced or inline code into `result`, replacing what
+/// it held. Data that is not UTF-8 is copied unchanged.
+pub fn filter_markdown_code(data: &[u8], result: &mut Vec<u8>) {
+    result.clear();
     let text = match std::str::from_utf8(data) {
         Ok(s) => s,
-        Err(_) => return data.to_vec(),
+        Err(_) => return result.extend_from_slice(data),
     };
 
-    let mut result = Vec::new();
     let mut in_code_block = false;
 
     for line in text.lines() {
@@ -628,8 +2698,205 @@ pub fn filter_markdown_code(data: &[u8]) -> Vec<u8> {
         result.extend_from_slice(filtered_line.as_bytes());
         result.push(b'\n');
     }
+}
 
-    result
+const HTML_ENTITIES: [(&[u8], &[u8]); 6] = [
+    (b"&amp;", b"&"),
+    (b"&lt;", b"<"),
+    (b"&gt;", b">"),
+    (b"&quot;", b"\""),
+    (b"&apos;", b"'"),
+    (b"&nbsp;", b" "),
+];
+
+/// Writes the text of an HTML or XML document into `result`, replacing what
+/// it held. Tags and comments become a space, so `<li>a</li><li>b</li>`
+/// stays two words, but newlines inside them are kept so line counts match
+/// the source. The common named entities are decoded; others are left as is.
+/// A `<` not followed by a letter, `/`, `!` or `?` is text.
+pub fn filter_html_tags(data: &[u8], result: &mut Vec<u8>) {
+    enum State {
+        Text,
+        Tag { quote: Option<u8> },
+        Comment,
+    }
+
+    result.clear();
+    let mut state = State::Text;
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        match state {
+            State::Text if b == b'<' => {
+                let rest = &data[i + 1..];
+                if rest.starts_with(b"!--") {
+                    state = State::Comment;
+                    i += 4;
+                    continue;
+                }
+                if rest
+                    .first()
+                    .is_some_and(|&c| c.is_ascii_alphabetic() || matches!(c, b'/' | b'!' | b'?'))
+                {
+                    state = State::Tag { quote: None };
+                } else {
+                    result.push(b);
+                }
+            }
+            State::Text if b == b'&' => {
+                let entity = HTML_ENTITIES
+                    .iter()
+                    .find(|(name, _)| data[i..].starts_with(name));
+                if let Some((name, text)) = entity {
+                    result.extend_from_slice(text);
+                    i += name.len();
+                    continue;
+                }
+                result.push(b);
+            }
+            State::Text => result.push(b),
+            State::Tag { quote: Some(q) } if b == q => state = State::Tag { quote: None },
+            State::Tag { quote: None } if matches!(b, b'"' | b'\'') => {
+                state = State::Tag { quote: Some(b) }
+            }
+            State::Tag { quote: None } if b == b'>' => {
+                state = State::Text;
+                if !result.last().is_none_or(|c| c.is_ascii_whitespace()) {
+                    result.push(b' ');
+                }
+            }
+            State::Comment if data[i..].starts_with(b"-->") => {
+                state = State::Text;
+                i += 3;
+                if !result.last().is_none_or(|c| c.is_ascii_whitespace()) {
+                    result.push(b' ');
+                }
+                continue;
+            }
+            State::Tag { .. } | State::Comment => {
+                if b == b'\n' {
+                    result.push(b);
+                }
+            }
+        }
+        i += 1;
+    }
+}
+
+/// The first `n` lines of `data`, including the last one's newline.
+pub fn head_lines(data: &[u8], n: usize) -> &[u8] {
+    if n == 0 {
+        return &data[..0];
+    }
+    match memchr::memchr_iter(b'\n', data).nth(n - 1) {
+        Some(end) => &data[..end + 1],
+        None => data,
+    }
+}
+
+/// The last `n` lines of `data`. A final newline ends the last line rather
+/// than starting an empty one, matching `tail -n`.
+pub fn tail_lines(data: &[u8], n: usize) -> &[u8] {
+    if n == 0 {
+        return &data[data.len()..];
+    }
+    let body = data.strip_suffix(b"\n").unwrap_or(data);
+    match memchr::memrchr_iter(b'\n', body).nth(n - 1) {
+        Some(start) => &data[start + 1..],
+        None => data,
+    }
+}
+
+/// Line terminators by style. A `\r\n` pair counts once, as CRLF.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndings {
+    pub lf: usize,
+    pub crlf: usize,
+    pub cr: usize,
+}
+
+fn line_endings_chunk(data: &[u8]) -> LineEndings {
+    let mut endings = LineEndings::default();
+    for pos in memchr::memchr2_iter(b'\r', b'\n', data) {
+        if data[pos] == b'\n' {
+            if pos > 0 && data[pos - 1] == b'\r' {
+                endings.crlf += 1;
+            } else {
+                endings.lf += 1;
+            }
+        } else if data.get(pos + 1) != Some(&b'\n') {
+            endings.cr += 1;
+        }
+    }
+    endings
+}
+
+impl LineEndings {
+    /// Whether more than one terminator style is present.
+    pub fn is_mixed(&self) -> bool {
+        [self.lf, self.crlf, self.cr]
+            .iter()
+            .filter(|&&n| n > 0)
+            .count()
+            > 1
+    }
+}
+
+pub fn count_line_endings(data: &[u8], tuning: TuningParams) -> LineEndings {
+    if data.len() < tuning.parallel_threshold {
+        return line_endings_chunk(data);
+    }
+
+    // Boundaries fall right after a `\n`, so a CRLF pair is never split.
+    let boundaries = find_line_boundaries(data, tuning.chunk_size);
+    boundaries
+        .par_windows(2)
+        .filter(|_| !tuning.expired())
+        .map(|window| line_endings_chunk(&data[window[0]..window[1]]))
+        .reduce(LineEndings::default, |a, b| LineEndings {
+            lf: a.lf + b.lf,
+            crlf: a.crlf + b.crlf,
+            cr: a.cr + b.cr,
+        })
+}
+
+/// Joins backslash-continued lines into one logical line by removing each
+/// `\\`-newline pair (CRLF included). A line ending in an even run of
+/// backslashes is an escaped backslash, not a continuation, and a trailing
+/// continuation at the end of the buffer is kept since there is nothing to join.
+pub fn join_continuations(data: &[u8]) -> Cow<'_, [u8]> {
+    let mut result: Option<Vec<u8>> = None;
+    let mut copied = 0;
+
+    for newline in memchr::memchr_iter(b'\n', data) {
+        if newline + 1 == data.len() {
+            break;
+        }
+        let line_end = if newline > 0 && data[newline - 1] == b'\r' {
+            newline - 1
+        } else {
+            newline
+        };
+        let backslashes = data[..line_end]
+            .iter()
+            .rev()
+            .take_while(|&&b| b == b'\\')
+            .count();
+        if backslashes % 2 == 0 {
+            continue;
+        }
+        let joined = result.get_or_insert_with(|| Vec::with_capacity(data.len()));
+        joined.extend_from_slice(&data[copied..line_end - 1]);
+        copied = newline + 1;
+    }
+
+    match result {
+        None => Cow::Borrowed(data),
+        Some(mut joined) => {
+            joined.extend_from_slice(&data[copied..]);
+            Cow::Owned(joined)
+        }
+    }
 }
 
 fn filter_inline_code(line: &str) -> String {
@@ -647,123 +2914,326 @@ fn filter_inline_code(line: &str) -> String {
     result
 }
 
-pub fn decode_to_utf8<'a>(data: &'a [u8], encoding_name: Option<&str>) -> Cow<'a, [u8]> {
+/// Guesses the encoding of `data`. A byte order mark wins, valid UTF-8
+/// (including plain ASCII) is UTF-8, and anything else goes to chardetng.
+/// Pass `complete = false` when `data` is only the start of a file, so a
+/// multi-byte character cut off at the end does not count against UTF-8.
+/// The second value is whether the encoding came from a BOM.
+pub fn detect_encoding(data: &[u8], complete: bool) -> (&'static encoding_rs::Encoding, bool) {
     use chardetng::EncodingDetector;
+
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(data) {
+        return (encoding, true);
+    }
+    match std::str::from_utf8(data) {
+        Ok(_) => return (encoding_rs::UTF_8, false),
+        Err(e) if !complete && e.error_len().is_none() => return (encoding_rs::UTF_8, false),
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(data, complete);
+    (detector.guess(None, true), false)
+}
+
+/// Share of valid UTF-8 at or above which a non-UTF-8 guess from chardetng
+/// is overruled: a handful of stray bytes rarely means a legacy encoding.
+pub const UTF8_PREFERENCE_RATIO: f64 = 0.99;
+
+/// How `decode_to_utf8` settled on an encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoding {
+    /// Named with `--encoding`.
+    Declared(&'static encoding_rs::Encoding),
+    /// Chosen by `detect_encoding` and used as is.
+    Detected(&'static encoding_rs::Encoding),
+    /// chardetng guessed `detected`, but the data was mostly valid UTF-8, so
+    /// it was decoded as UTF-8 with the invalid bytes replaced.
+    PreferredUtf8 {
+        detected: &'static encoding_rs::Encoding,
+    },
+}
+
+/// Fraction of `data` made up of valid UTF-8 sequences.
+pub fn utf8_validity(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 1.0;
+    }
+    let valid: usize = data.utf8_chunks().map(|chunk| chunk.valid().len()).sum();
+    valid as f64 / data.len() as f64
+}
+
+/// Decodes `data` to UTF-8 from the named encoding, or an auto-detected one.
+/// Unless `trust_detection` is set, a non-UTF-8 guess is overruled when at
+/// least `UTF8_PREFERENCE_RATIO` of the bytes are valid UTF-8, since
+/// chardetng tends to pick windows-1252 for UTF-8 with a few bad bytes.
+///
+/// UTF-8 input is returned as is; anything else is decoded into `decoded`,
+/// replacing what it held, so callers can reuse one buffer across files.
+pub fn decode_to_utf8<'a>(
+    data: &'a [u8],
+    encoding_name: Option<&str>,
+    trust_detection: bool,
+    decoded: &'a mut Vec<u8>,
+) -> (&'a [u8], Decoding) {
     use encoding_rs::Encoding;
 
-    let encoding = if let Some(name) = encoding_name {
-        Encoding::for_label(name.as_bytes()).unwrap_or(encoding_rs::UTF_8)
+    let decoding = if let Some(name) = encoding_name {
+        Decoding::Declared(Encoding::for_label(name.as_bytes()).unwrap_or(encoding_rs::UTF_8))
     } else {
-        let mut detector = EncodingDetector::new();
-        detector.feed(data, true);
-        detector.guess(None, true)
+        match detect_encoding(data, true) {
+            (encoding, false)
+                if encoding != encoding_rs::UTF_8
+                    && !trust_detection
+                    && utf8_validity(data) >= UTF8_PREFERENCE_RATIO =>
+            {
+                Decoding::PreferredUtf8 { detected: encoding }
+            }
+            (encoding, _) => Decoding::Detected(encoding),
+        }
+    };
+
+    let encoding = match decoding {
+        Decoding::Declared(encoding) | Decoding::Detected(encoding) => encoding,
+        Decoding::PreferredUtf8 { .. } => {
+            decoded.clear();
+            for chunk in data.utf8_chunks() {
+                decoded.extend_from_slice(chunk.valid().as_bytes());
+                if !chunk.invalid().is_empty() {
+                    decoded.extend_from_slice("\u{FFFD}".as_bytes());
+                }
+            }
+            return (decoded, decoding);
+        }
     };
 
     if encoding == encoding_rs::UTF_8 {
-        return Cow::Borrowed(data);
+        return (data, decoding);
+    }
+
+    // Same BOM sniffing and replacement as `Encoding::decode`, minus the
+    // fresh allocation.
+    let mut decoder = encoding.new_decoder();
+    let capacity = decoder
+        .max_utf8_buffer_length(data.len())
+        .unwrap_or(usize::MAX);
+    decoded.clear();
+    decoded.resize(capacity, 0);
+    let (_, _, written, _) = decoder.decode_to_utf8(data, decoded, true);
+    decoded.truncate(written);
+    (decoded, decoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_line_endings() {
+        let data = b"unix\nwindows\r\nmac\rmixed\r\r\nend";
+        let expected = LineEndings {
+            lf: 1,
+            crlf: 2,
+            cr: 2,
+        };
+        assert_eq!(count_line_endings(data, TuningParams::default()), expected);
+
+        let big = data.repeat(20_000);
+        let parallel = count_line_endings(
+            &big,
+            TuningParams {
+                chunk_size: MIN_CHUNK_SIZE,
+                parallel_threshold: 0,
+                deadline: None,
+            },
+        );
+        assert_eq!(parallel.crlf, 40_000);
+        assert_eq!(parallel, count_line_endings(&big, TuningParams::serial()));
+        assert!(parallel.is_mixed());
+
+        // Chunks of exactly "a\r\n" would split the pair if cut at a fixed size.
+        let crlf = b"a\r\n".repeat(MIN_CHUNK_SIZE);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let endings = count_line_endings(&crlf, tiny);
+        assert_eq!(
+            (endings.lf, endings.crlf, endings.cr),
+            (0, MIN_CHUNK_SIZE, 0)
+        );
+        assert!(!endings.is_mixed());
+    }
+
+    #[test]
+    fn test_head_and_tail_lines() {
+        let data = b"one\ntwo\nthree\n";
+        assert_eq!(head_lines(data, 2), b"one\ntwo\n");
+        assert_eq!(head_lines(data, 10), data);
+        assert_eq!(head_lines(data, 0), b"");
+        assert_eq!(tail_lines(data, 2), b"two\nthree\n");
+        assert_eq!(tail_lines(data, 3), data);
+        assert_eq!(tail_lines(data, 10), data);
+        assert_eq!(tail_lines(b"one\ntwo", 1), b"two");
+        assert_eq!(tail_lines(data, 0), b"");
     }
 
-    let (decoded, _, _) = encoding.decode(data);
-    Cow::Owned(decoded.into_owned().into_bytes())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_join_continuations() {
+        let makefile = b"all: \\\n\tfoo \\\r\n\tbar\nclean:\n\trm -f \\\\\nlast \\\n";
+        let joined = join_continuations(makefile);
+        assert_eq!(
+            &joined[..],
+            b"all: \tfoo \tbar\nclean:\n\trm -f \\\\\nlast \\\n"
+        );
+        assert_eq!(count_lines(&joined, TuningParams::default()), 4);
+
+        assert!(matches!(join_continuations(b"plain\n"), Cow::Borrowed(_)));
+        assert_eq!(&join_continuations(b"eof \\")[..], b"eof \\");
+    }
 
     #[test]
     fn test_count_lines_empty() {
-        assert_eq!(count_lines(b""), 0);
+        assert_eq!(count_lines(b"", TuningParams::default()), 0);
     }
 
     #[test]
     fn test_count_lines_single() {
-        assert_eq!(count_lines(b"hello\n"), 1);
+        assert_eq!(count_lines(b"hello\n", TuningParams::default()), 1);
     }
 
     #[test]
     fn test_count_lines_multiple() {
-        assert_eq!(count_lines(b"line1\nline2\nline3\n"), 3);
+        assert_eq!(
+            count_lines(b"line1\nline2\nline3\n", TuningParams::default()),
+            3
+        );
     }
 
     #[test]
     fn test_count_lines_no_trailing_newline() {
-        assert_eq!(count_lines(b"line1\nline2"), 1);
+        assert_eq!(count_lines(b"line1\nline2", TuningParams::default()), 1);
+    }
+
+    #[test]
+    fn test_pass_stops_at_deadline() {
+        // Each chunk is one slow line; a pass that outlives the deadline
+        // skips the chunks it has not started.
+        let line = [b"x".repeat(MIN_CHUNK_SIZE - 1), b"\n".to_vec()].concat();
+        let data = line.repeat(256);
+        let tuning = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: Some(Instant::now() + std::time::Duration::from_millis(5)),
+        };
+        let matched = count_lines_matching(&data, tuning, |_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            true
+        });
+        assert!(tuning.expired());
+        assert!(matched < 256, "counted {} lines", matched);
     }
 
     #[test]
     fn test_count_words_empty() {
-        assert_eq!(count_all_words(b""), 0);
+        assert_eq!(count_all_words(b"", TuningParams::default()), 0);
     }
 
     #[test]
     fn test_count_words_single() {
-        assert_eq!(count_all_words(b"hello"), 1);
+        assert_eq!(count_all_words(b"hello", TuningParams::default()), 1);
     }
 
     #[test]
     fn test_count_words_multiple() {
-        assert_eq!(count_all_words(b"hello world foo bar"), 4);
+        assert_eq!(
+            count_all_words(b"hello world foo bar", TuningParams::default()),
+            4
+        );
     }
 
     #[test]
     fn test_count_words_multiple_spaces() {
-        assert_eq!(count_all_words(b"hello    world"), 2);
+        assert_eq!(
+            count_all_words(b"hello    world", TuningParams::default()),
+            2
+        );
     }
 
     #[test]
     fn test_count_words_newlines() {
-        assert_eq!(count_all_words(b"hello\nworld\nfoo"), 3);
+        assert_eq!(
+            count_all_words(b"hello\nworld\nfoo", TuningParams::default()),
+            3
+        );
     }
 
     #[test]
     fn test_count_words_mixed_whitespace() {
-        assert_eq!(count_all_words(b"hello\t\nworld  \r\nfoo"), 3);
+        assert_eq!(
+            count_all_words(b"hello\t\nworld  \r\nfoo", TuningParams::default()),
+            3
+        );
     }
 
     #[test]
     fn test_count_words_unicode_whitespace() {
         let text = "hello\u{00A0}world";
-        assert_eq!(count_all_words(text.as_bytes()), 2);
+        assert_eq!(count_all_words(text.as_bytes(), TuningParams::default()), 2);
 
         let text2 = "hello\u{2003}world";
-        assert_eq!(count_all_words(text2.as_bytes()), 2);
+        assert_eq!(
+            count_all_words(text2.as_bytes(), TuningParams::default()),
+            2
+        );
     }
 
     #[test]
     fn test_count_pattern_empty_data() {
-        assert_eq!(count_pattern(b"", b"test"), 0);
+        assert_eq!(count_pattern(b"", b"test", TuningParams::default()), 0);
     }
 
     #[test]
     fn test_count_pattern_empty_pattern() {
-        assert_eq!(count_pattern(b"test", b""), 0);
+        assert_eq!(count_pattern(b"test", b"", TuningParams::default()), 0);
     }
 
     #[test]
     fn test_count_pattern_single_occurrence() {
-        assert_eq!(count_pattern(b"hello world", b"world"), 1);
+        assert_eq!(
+            count_pattern(b"hello world", b"world", TuningParams::default()),
+            1
+        );
     }
 
     #[test]
     fn test_count_pattern_multiple_occurrences() {
-        assert_eq!(count_pattern(b"foo bar foo baz foo", b"foo"), 3);
+        assert_eq!(
+            count_pattern(b"foo bar foo baz foo", b"foo", TuningParams::default()),
+            3
+        );
     }
 
     #[test]
     fn test_count_pattern_non_overlapping() {
-        assert_eq!(count_pattern(b"aaa", b"aa"), 1);
-        assert_eq!(count_pattern(b"aaaa", b"aa"), 2);
+        assert_eq!(count_pattern(b"aaa", b"aa", TuningParams::default()), 1);
+        assert_eq!(count_pattern(b"aaaa", b"aa", TuningParams::default()), 2);
     }
 
     #[test]
     fn test_count_pattern_no_match() {
-        assert_eq!(count_pattern(b"hello world", b"xyz"), 0);
+        assert_eq!(
+            count_pattern(b"hello world", b"xyz", TuningParams::default()),
+            0
+        );
     }
 
     #[test]
     fn test_count_pattern_byte_pattern() {
-        assert_eq!(count_pattern(b"a\nb\nc\n", b"\n"), 3);
+        assert_eq!(
+            count_pattern(b"a\nb\nc\n", b"\n", TuningParams::default()),
+            3
+        );
     }
 
     #[test]
@@ -771,10 +3241,10 @@ mod tests {
         let large_text = "word ".repeat(200_000);
         let bytes = large_text.as_bytes();
 
-        assert_eq!(count_all_words(bytes), 200_000);
+        assert_eq!(count_all_words(bytes, TuningParams::default()), 200_000);
 
         let large_lines = b"line\n".repeat(200_000);
-        assert_eq!(count_lines(&large_lines), 200_000);
+        assert_eq!(count_lines(&large_lines, TuningParams::default()), 200_000);
     }
 
     #[test]
@@ -784,10 +3254,10 @@ mod tests {
         data.push(b'b');
         data.push(b'c');
 
-        assert_eq!(count_all_words(&data), 1);
+        assert_eq!(count_all_words(&data, TuningParams::default()), 1);
 
         data[chunk_size - 1] = b' ';
-        assert_eq!(count_all_words(&data), 2);
+        assert_eq!(count_all_words(&data, TuningParams::default()), 2);
     }
 
     #[test]
@@ -798,114 +3268,512 @@ mod tests {
         data.extend_from_slice(pattern);
         data.extend_from_slice(b"yyyyyy");
 
-        assert_eq!(count_pattern(&data, pattern), 1);
+        assert_eq!(count_pattern(&data, pattern, TuningParams::default()), 1);
     }
 
     #[test]
     fn test_count_chars_empty() {
-        assert_eq!(count_chars(b""), 0);
+        assert_eq!(count_chars(b"", TuningParams::default()), 0);
     }
 
     #[test]
     fn test_count_chars_ascii() {
-        assert_eq!(count_chars(b"hello world"), 11);
+        assert_eq!(count_chars(b"hello world", TuningParams::default()), 11);
     }
 
     #[test]
     fn test_count_chars_utf8() {
-        assert_eq!(count_chars("hello 世界".as_bytes()), 8);
-        assert_eq!(count_chars("🦀 Rust".as_bytes()), 6);
+        assert_eq!(
+            count_chars("hello 世界".as_bytes(), TuningParams::default()),
+            8
+        );
+        assert_eq!(
+            count_chars("🦀 Rust".as_bytes(), TuningParams::default()),
+            6
+        );
     }
 
     #[test]
     fn test_count_chars_vs_bytes() {
         let text = "café";
-        assert_eq!(count_chars(text.as_bytes()), 4);
-        assert_eq!(text.as_bytes().len(), 5);
+        assert_eq!(count_chars(text.as_bytes(), TuningParams::default()), 4);
+        assert_eq!(text.len(), 5);
     }
 
     #[test]
     fn test_max_line_length_empty() {
-        assert_eq!(max_line_length(b""), 0);
+        assert_eq!(longest_line(b"", TuningParams::default()).length, 0);
     }
 
     #[test]
     fn test_max_line_length_single_line() {
-        assert_eq!(max_line_length(b"hello"), 5);
+        assert_eq!(longest_line(b"hello", TuningParams::default()).length, 5);
     }
 
     #[test]
     fn test_max_line_length_multiple_lines() {
-        assert_eq!(max_line_length(b"hi\nhello\nbye"), 5);
+        assert_eq!(
+            longest_line(b"hi\nhello\nbye", TuningParams::default()).length,
+            5
+        );
     }
 
     #[test]
     fn test_max_line_length_trailing_newline() {
-        assert_eq!(max_line_length(b"hello\nworld\n"), 5);
+        assert_eq!(
+            longest_line(b"hello\nworld\n", TuningParams::default()).length,
+            5
+        );
+    }
+
+    #[test]
+    fn test_max_line_width() {
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let cjk = "日本語\nab\r\n";
+        assert_eq!(
+            longest_line(cjk.as_bytes(), TuningParams::serial()).length,
+            9
+        );
+        assert_eq!(max_line_width(cjk.as_bytes(), TuningParams::serial()), 6);
+        assert_eq!(max_line_width("cafe\u{301}".as_bytes(), tiny), 4);
+        assert_eq!(max_line_width(b"a\tb\n\t\tx", tiny), 17);
+        assert_eq!(max_line_width(b"\xff\xfe\xfd\nab", tiny), 3);
+        assert_eq!(max_line_width(b"", tiny), 0);
+
+        let data = "x\t\u{4e2d}\u{6587}\n".repeat(2000) + "wide \u{4e2d}\u{4e2d}\u{4e2d}";
+        assert_eq!(
+            max_line_width(data.as_bytes(), tiny),
+            max_line_width(data.as_bytes(), TuningParams::serial())
+        );
+        assert_eq!(max_line_width(data.as_bytes(), tiny), 12);
+    }
+
+    #[test]
+    fn test_blank_lines_split_by_kind() {
+        let data = b"code\n\n   \n\t\r\n\r\nmore\n \t \n\nend";
+        let blanks = count_blank_lines(data, TuningParams::default());
+        assert_eq!(
+            blanks,
+            BlankLines {
+                empty: 3,
+                whitespace_only: 3,
+            }
+        );
+        assert_eq!(blanks.total(), 6);
+        assert_eq!(
+            calculate_statistics(data, TuningParams::default()).empty_lines,
+            blanks.empty
+        );
     }
 
     #[test]
     fn test_max_line_length_empty_lines() {
-        assert_eq!(max_line_length(b"\n\nhello\n\n"), 5);
+        assert_eq!(
+            longest_line(b"\n\nhello\n\n", TuningParams::default()).length,
+            5
+        );
     }
 
     #[test]
     fn test_max_line_length_crlf() {
-        assert_eq!(max_line_length(b"hello\r\nworld\r\n"), 5);
-        assert_eq!(max_line_length(b"hi\r\nhello\r\nbye\r\n"), 5);
+        assert_eq!(
+            longest_line(b"hello\r\nworld\r\n", TuningParams::default()).length,
+            5
+        );
+        assert_eq!(
+            longest_line(b"hi\r\nhello\r\nbye\r\n", TuningParams::default()).length,
+            5
+        );
     }
 
     #[test]
     fn test_max_line_length_mixed_endings() {
-        assert_eq!(max_line_length(b"hello\nworld\r\nfoo\n"), 5);
+        assert_eq!(
+            longest_line(b"hello\nworld\r\nfoo\n", TuningParams::default()).length,
+            5
+        );
+    }
+
+    #[test]
+    fn test_longest_line_number() {
+        assert_eq!(longest_line(b"", TuningParams::serial()).line, 0);
+        assert_eq!(longest_line(b"\n\n", TuningParams::serial()).line, 1);
+        assert_eq!(
+            longest_line(b"hi\nhello\nworld\n", TuningParams::serial()).line,
+            2
+        );
+
+        // The longest line sits in a later chunk, and a tie in the one after.
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let mut text = "short\n".repeat(30_000);
+        text.push_str(&"x".repeat(100));
+        text.push('\n');
+        text.push_str(&"short\n".repeat(30_000));
+        text.push_str(&"y".repeat(100));
+        let parallel = longest_line(text.as_bytes(), tiny);
+        assert_eq!((parallel.length, parallel.line), (100, 30_001));
+        assert_eq!(
+            parallel,
+            longest_line(text.as_bytes(), TuningParams::serial())
+        );
+    }
+
+    #[test]
+    fn test_max_word_length_counts_chars() {
+        let serial = TuningParams::serial();
+        assert_eq!(max_word_length(b"", serial), 0);
+        assert_eq!(max_word_length(b"  \n\t ", serial), 0);
+        assert_eq!(max_word_length(b"a bb\tccc\n", serial), 3);
+        assert_eq!(
+            max_word_length("caf\u{e9} na\u{ef}ve".as_bytes(), serial),
+            5
+        );
+        assert_eq!(max_word_length("a\u{3000}bb".as_bytes(), serial), 2);
+        assert_eq!(max_word_length(b"\xFF\xFE ab", serial), 2);
+    }
+
+    #[test]
+    fn test_max_word_length_joins_words_across_chunks() {
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        // One word spanning several whole chunks.
+        let long = "x".repeat(3 * MIN_CHUNK_SIZE + 5);
+        let text = format!("short {} \u{e9}t\u{e9}\n", long);
+        assert_eq!(max_word_length(text.as_bytes(), tiny), long.len());
+        // Words of growing length, most of them cut by a boundary.
+        let text: String = (1..1200).map(|n| "\u{e9}".repeat(n) + " ").collect();
+        assert_eq!(max_word_length(text.as_bytes(), tiny), 1199);
+        assert_eq!(
+            max_word_length(text.as_bytes(), tiny),
+            max_word_length(text.as_bytes(), TuningParams::serial())
+        );
+    }
+
+    #[test]
+    fn test_word_length_stats() {
+        let stats = word_length_stats("a na\u{ef}ve cat\n\tsee".as_bytes(), TuningParams::serial());
+        assert_eq!(
+            stats,
+            WordLengthStats {
+                mean: 3.0,
+                median: 3,
+                max: 5,
+            }
+        );
+        assert_eq!(
+            word_length_stats(b" \n ", TuningParams::serial()),
+            WordLengthStats::default()
+        );
+        assert_eq!(
+            word_length_stats(b"ab abcd", TuningParams::serial()).median,
+            3
+        );
+    }
+
+    #[test]
+    fn test_word_length_stats_parallel_matches_serial() {
+        let text = "one three\tfifteen  a\n\u{e9}t\u{e9}\n\n".repeat(20_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let serial = word_length_stats(text.as_bytes(), TuningParams::serial());
+        assert_eq!(serial.max, 7);
+        assert_eq!(word_length_stats(text.as_bytes(), tiny), serial);
+    }
+
+    #[test]
+    fn test_char_trigrams() {
+        let trigrams = char_trigrams(
+            "The cat, THE\n\u{4E2D}\u{6587}\u{306E}".as_bytes(),
+            TuningParams::serial(),
+        );
+        let count = |t: &str| {
+            let chars: Vec<char> = t.chars().collect();
+            trigrams
+                .counts
+                .get(&[chars[0], chars[1], chars[2]])
+                .copied()
+                .unwrap_or(0)
+        };
+        assert_eq!(count(" th"), 2);
+        assert_eq!(count("he "), 2);
+        assert_eq!(count("e c"), 0);
+        assert_eq!(count(" \u{4E2D} "), 1);
+        assert_eq!(count(" \u{306E} "), 1);
+        assert_eq!(trigrams.letters, 12);
+    }
+
+    #[test]
+    fn test_char_trigrams_parallel_matches_serial() {
+        let text = "Die Straße, the road; \u{753A}\u{306E}\u{6B74}\u{53F2}\n".repeat(10_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(
+            char_trigrams(text.as_bytes(), tiny),
+            char_trigrams(text.as_bytes(), TuningParams::serial())
+        );
+    }
+
+    #[test]
+    fn test_unique_lines() {
+        let serial = TuningParams::serial();
+        assert!(unique_lines(b"", serial).is_empty());
+        assert_eq!(unique_lines(b"same\nsame\nsame", serial).len(), 1);
+        assert_eq!(unique_lines(b"a\r\nb\na\n\n\nb", serial).len(), 3);
+
+        let text: String = (0..60_000).map(|i| format!("line {}\n", i % 997)).collect();
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(unique_lines(text.as_bytes(), tiny).len(), 997);
+        assert_eq!(unique_lines(text.as_bytes(), serial).len(), 997);
+    }
+
+    #[test]
+    fn test_date_buckets() {
+        let log = b"2024-03-01 start\n  at frame\n2024-03-02 next\r\n2024-03-01 late\nend";
+        let buckets = date_buckets(log, &DateFormat::Iso8601, TuningParams::serial());
+        let day = |d: &str| buckets[&DateFormat::Iso8601.date(d.as_bytes())];
+        assert_eq!(
+            day("2024-03-01"),
+            DayCounts {
+                lines: 2,
+                bytes: 33
+            }
+        );
+        assert_eq!(
+            day("2024-03-02"),
+            DayCounts {
+                lines: 1,
+                bytes: 17
+            }
+        );
+        assert_eq!(
+            buckets[&None],
+            DayCounts {
+                lines: 2,
+                bytes: 14
+            }
+        );
+
+        let text: String = (0..30_000)
+            .map(|i| format!("2024-01-{:02} event {}\n", i % 28 + 1, i))
+            .collect();
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let parallel = date_buckets(text.as_bytes(), &DateFormat::Iso8601, tiny);
+        let serial = date_buckets(
+            text.as_bytes(),
+            &DateFormat::Iso8601,
+            TuningParams::serial(),
+        );
+        assert_eq!(parallel, serial);
+        assert_eq!(parallel.len(), 28);
+        let bytes: usize = parallel.values().map(|d| d.bytes).sum();
+        assert_eq!(bytes, text.len());
+    }
+
+    #[test]
+    fn test_trailing_whitespace_lines() {
+        let serial = TuningParams::serial();
+        let text = b"clean\nspace \ntab\t\r\ncrlf \r\nmid dle\r\n   \n\nlast  ";
+        assert_eq!(count_trailing_whitespace_lines(text, serial), 5);
+        assert_eq!(count_trailing_whitespace_lines(b"a\r\n\r", serial), 0);
+        assert_eq!(count_trailing_whitespace_lines(b"", serial), 0);
+
+        let repeated = text.repeat(5_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(
+            count_trailing_whitespace_lines(&repeated, tiny),
+            count_trailing_whitespace_lines(&repeated, serial)
+        );
+    }
+
+    #[test]
+    fn test_mid_file_feff() {
+        let data = b"\xEF\xBB\xBFone\n\xEF\xBB\xBFtwo\xEF\xBB\xBF\n";
+        assert_eq!(mid_file_feff(data), (2, Some(7)));
+        let mut out = Vec::new();
+        assert_eq!(strip_feff(data, &mut out), b"one\ntwo\n");
+
+        assert_eq!(mid_file_feff(b"\xEF\xBB\xBFplain"), (0, None));
+        assert_eq!(mid_file_feff(b""), (0, None));
+        assert_eq!(strip_feff(b"x\xEF\xBB\xBF", &mut out), b"x");
+        let mut untouched = Vec::new();
+        assert_eq!(strip_feff(b"plain", &mut untouched), b"plain");
+        assert_eq!(untouched.capacity(), 0);
+    }
+
+    #[test]
+    fn test_indent_stats() {
+        let code =
+            b"fn main() {\n\tlet a = 1;\n    let b = 2;\n\t  mixed();\n  \t\tdeep\n\n \t \n}\n";
+        let stats = calculate_indent_stats(code, 4, TuningParams::serial());
+        assert_eq!(
+            stats,
+            IndentStats {
+                tab_lines: 1,
+                space_lines: 1,
+                mixed_lines: 2,
+                max_depth: 8,
+            }
+        );
+        assert_eq!(
+            calculate_indent_stats(b"\t\tx\n", 8, TuningParams::serial()).max_depth,
+            16
+        );
+        assert_eq!(
+            calculate_indent_stats(b"", 4, TuningParams::serial()),
+            IndentStats::default()
+        );
+
+        let text = code.repeat(5_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(
+            calculate_indent_stats(&text, 4, tiny),
+            calculate_indent_stats(&text, 4, TuningParams::serial())
+        );
+    }
+
+    #[test]
+    fn test_duplicate_lines() {
+        let serial = TuningParams::serial();
+        let counts = |text: &[u8]| {
+            let d = count_duplicate_lines(text, serial);
+            (d.distinct_duplicated, d.redundant_total)
+        };
+        assert_eq!(counts(b""), (0, 0));
+        assert_eq!(counts(b"a\na\nb\n"), (1, 1));
+        assert_eq!(counts(b"x\r\ny\nx\nx\ny"), (2, 3));
+        assert_eq!(counts(b"one\ntwo\n"), (0, 0));
+
+        let text: String = (0..60_000).map(|i| format!("line {}\n", i % 997)).collect();
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let expected = DuplicateLines {
+            distinct_duplicated: 997,
+            redundant_total: 60_000 - 997,
+        };
+        assert_eq!(count_duplicate_lines(text.as_bytes(), tiny), expected);
+        assert_eq!(count_duplicate_lines(text.as_bytes(), serial), expected);
+    }
+
+    #[test]
+    fn test_word_run_merge() {
+        let run = |text: &str| WordRun::of_chunk(text.as_bytes());
+        assert_eq!(run("ab").then(run("cd ef")).then(run("g")).longest, 4);
+        assert_eq!(run("ab ").then(run("cd")).longest, 2);
+        assert_eq!(run("").then(run("abc")), run("abc"));
+        assert_eq!(run("a").then(run("b")).then(run("c")).leading, 3);
     }
 
     #[test]
     fn test_filter_code_c_style_single_line() {
         let input = b"// this is a comment\nint x = 5;\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"int x = 5;\n");
     }
 
     #[test]
     fn test_filter_code_c_style_multiline() {
         let input = b"/* multiline\ncomment */\nint x = 5;\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"int x = 5;\n");
     }
 
     #[test]
     fn test_filter_code_hash_comments() {
         let input = b"# Python comment\nprint('hello')\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"print('hello')\n");
     }
 
     #[test]
     fn test_filter_code_sql_comments() {
         let input = b"-- SQL comment\nSELECT * FROM users;\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"SELECT * FROM users;\n");
     }
 
     #[test]
     fn test_filter_code_python_docstring() {
         let input = b"\"\"\"\nThis is a docstring\n\"\"\"\ndef foo():\n    pass\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"def foo():\n    pass\n");
     }
 
+    #[test]
+    fn test_code_breakdown() {
+        let input = b"// header\nint x = 1; // trailing\n\n/* block\n\n   still */\nint y;\r\n  \n";
+        assert_eq!(
+            code_breakdown(input),
+            CodeBreakdown {
+                code: 2,
+                comment: 3,
+                blank: 3,
+            }
+        );
+        let python = b"def f():\n    \"\"\"Doc\n    more\"\"\"\n    return 1  # why\n";
+        assert_eq!(
+            code_breakdown(python),
+            CodeBreakdown {
+                code: 2,
+                comment: 2,
+                blank: 0,
+            }
+        );
+    }
+
     #[test]
     fn test_filter_code_empty_lines() {
         let input = b"int x = 5;\n\nint y = 10;\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert_eq!(output, b"int x = 5;\nint y = 10;\n");
     }
 
     #[test]
     fn test_filter_code_preserves_urls_and_colors() {
         let input = b"url = \"https://example.com#anchor\"\ncolor = \"#fff\"\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert!(String::from_utf8_lossy(&output).contains("#anchor"));
         assert!(String::from_utf8_lossy(&output).contains("#fff"));
     }
@@ -913,14 +3781,16 @@ mod tests {
     #[test]
     fn test_filter_code_preserves_sql_operators() {
         let input = b"SELECT * FROM foo--bar WHERE x = 1\n";
-        let output = filter_code_comments(input);
+        let mut output = Vec::new();
+        filter_code_comments(input, &mut output);
         assert!(String::from_utf8_lossy(&output).contains("foo--bar"));
     }
 
     #[test]
     fn test_filter_markdown_code_block() {
         let input = b"Some text\n```rust\nlet x = 5;\n```\nMore text\n";
-        let output = filter_markdown_code(input);
+        let mut output = Vec::new();
+        filter_markdown_code(input, &mut output);
         assert!(String::from_utf8_lossy(&output).contains("Some text"));
         assert!(String::from_utf8_lossy(&output).contains("More text"));
         assert!(!String::from_utf8_lossy(&output).contains("let x = 5"));
@@ -929,17 +3799,39 @@ mod tests {
     #[test]
     fn test_filter_markdown_inline_code() {
         let input = b"Use the `println!` macro\n";
-        let output = filter_markdown_code(input);
+        let mut output = Vec::new();
+        filter_markdown_code(input, &mut output);
         let output_str = String::from_utf8_lossy(&output);
         assert!(output_str.contains("Use the"));
         assert!(output_str.contains("macro"));
         assert!(!output_str.contains("println!"));
     }
 
+    #[test]
+    fn test_filter_html_tags() {
+        let html = |input: &[u8]| {
+            let mut output = Vec::new();
+            filter_html_tags(input, &mut output);
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(html(b"<p>Hello <b>world</b></p>"), "Hello world ");
+        assert_eq!(html(b"<ul><li>one</li><li>two</li></ul>"), "one two ");
+        assert_eq!(html(b"<a href=\"x.html\" title='a > b'>link</a>"), "link ");
+        assert_eq!(html(b"a<!-- <p>hidden</p> -->b"), "a b");
+        assert_eq!(html(b"<br/>Tom &amp; Jerry&nbsp;&lt;3"), "Tom & Jerry <3");
+        assert_eq!(html(b"&quot;hi&apos; &copy;"), "\"hi' &copy;");
+        assert_eq!(html(b"1 < 2 and 3 <= 4"), "1 < 2 and 3 <= 4");
+        assert_eq!(
+            html(b"<div\n  class=\"x\">\ntext\n<!-- a\nb -->\n"),
+            "\n\ntext\n\n\n"
+        );
+    }
+
     #[test]
     fn test_filter_markdown_multiple_blocks() {
         let input = b"Intro\n```\ncode1\n```\nMiddle\n```\ncode2\n```\nEnd\n";
-        let output = filter_markdown_code(input);
+        let mut output = Vec::new();
+        filter_markdown_code(input, &mut output);
         let output_str = String::from_utf8_lossy(&output);
         assert!(output_str.contains("Intro"));
         assert!(output_str.contains("Middle"));
@@ -951,18 +3843,27 @@ mod tests {
     #[test]
     fn test_unique_words_basic() {
         let input = b"hello world hello foo world bar";
-        assert_eq!(count_unique_words(input), 4);
+        assert_eq!(
+            count_unique_words(input, TuningParams::default(), Tokenizer::Whitespace),
+            4
+        );
     }
 
     #[test]
     fn test_unique_words_empty() {
-        assert_eq!(count_unique_words(b""), 0);
+        assert_eq!(
+            count_unique_words(b"", TuningParams::default(), Tokenizer::Whitespace),
+            0
+        );
     }
 
     #[test]
     fn test_unique_words_all_same() {
         let input = b"word word word word word";
-        assert_eq!(count_unique_words(input), 1);
+        assert_eq!(
+            count_unique_words(input, TuningParams::default(), Tokenizer::Whitespace),
+            1
+        );
     }
 
     #[test]
@@ -977,14 +3878,863 @@ mod tests {
     #[test]
     fn test_decode_utf8_passthrough() {
         let input = "hello world".as_bytes();
-        let output = decode_to_utf8(input, Some("utf-8"));
+        let mut decoded = Vec::new();
+        let (output, decoding) = decode_to_utf8(input, Some("utf-8"), false, &mut decoded);
         assert_eq!(output, input);
+        assert_eq!(decoding, Decoding::Declared(encoding_rs::UTF_8));
     }
 
     #[test]
     fn test_decode_autodetect_utf8() {
         let input = "hello 世界".as_bytes();
-        let output = decode_to_utf8(input, None);
+        let mut decoded = Vec::new();
+        let (output, decoding) = decode_to_utf8(input, None, false, &mut decoded);
         assert_eq!(output, input);
+        assert_eq!(decoding, Decoding::Detected(encoding_rs::UTF_8));
+    }
+
+    /// UTF-8 prose with one stray Latin-1 byte, which chardetng reads as
+    /// windows-1252.
+    fn mostly_utf8() -> Vec<u8> {
+        let mut data = "Grüße aus Köln, schöne Äpfel und Öl für Straßen.\n"
+            .repeat(4)
+            .into_bytes();
+        data.extend_from_slice(b"caf\xe9\n");
+        data
+    }
+
+    #[test]
+    fn test_decode_prefers_mostly_valid_utf8() {
+        let data = mostly_utf8();
+        let (encoding, bom) = detect_encoding(&data, true);
+        assert!(!bom);
+        assert_ne!(encoding, encoding_rs::UTF_8);
+        assert!(utf8_validity(&data) >= UTF8_PREFERENCE_RATIO);
+
+        let mut decoded = Vec::new();
+        let (output, decoding) = decode_to_utf8(&data, None, false, &mut decoded);
+        assert_eq!(decoding, Decoding::PreferredUtf8 { detected: encoding });
+        let text = std::str::from_utf8(output).unwrap();
+        assert!(text.starts_with("Grüße"));
+        assert!(text.ends_with("caf\u{FFFD}\n"));
+
+        let (output, decoding) = decode_to_utf8(&data, None, true, &mut decoded);
+        assert_eq!(decoding, Decoding::Detected(encoding));
+        assert!(std::str::from_utf8(output).unwrap().starts_with("GrÃ¼Ã"));
+    }
+
+    #[test]
+    fn test_decode_keeps_guess_for_legacy_text() {
+        let data = b"Gr\xfc\xdfe aus K\xf6ln, sch\xf6ne \xc4pfel\n";
+        assert!(utf8_validity(data) < UTF8_PREFERENCE_RATIO);
+        let mut decoded = Vec::new();
+        let (output, decoding) = decode_to_utf8(data, None, false, &mut decoded);
+        assert!(matches!(decoding, Decoding::Detected(e) if e != encoding_rs::UTF_8));
+        assert!(std::str::from_utf8(output).unwrap().starts_with("Grüße"));
+    }
+
+    fn naive_glob(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|i| naive_glob(rest, &text[i..])),
+            Some(('?', rest)) => !text.is_empty() && naive_glob(rest, &text[1..]),
+            Some((c, rest)) => text.first() == Some(c) && naive_glob(rest, &text[1..]),
+        }
+    }
+
+    #[test]
+    fn test_glob_basic() {
+        let glob = GlobPattern::new("ERROR*timeout");
+        assert!(glob.is_match(b"ERROR: connection timeout"));
+        assert!(!glob.is_match(b"WARN: connection timeout"));
+        assert!(!glob.is_match(b"ERROR: timeout reached"));
+    }
+
+    #[test]
+    fn test_glob_edge_stars() {
+        assert!(GlobPattern::new("*").is_match(b""));
+        assert!(GlobPattern::new("**fail**").is_match(b"it will fail now"));
+        assert!(GlobPattern::new("*end").is_match(b"the end"));
+        assert!(GlobPattern::new("start*").is_match(b"start here"));
+        assert!(!GlobPattern::new("start*").is_match(b"restart"));
+    }
+
+    #[test]
+    fn test_glob_question_mark_multibyte() {
+        let glob = GlobPattern::new("caf?");
+        assert!(glob.is_match("café".as_bytes()));
+        assert!(glob.is_match(b"cafe"));
+        assert!(!glob.is_match(b"caf"));
+    }
+
+    #[test]
+    fn test_glob_matches_reference() {
+        let alphabet = ['a', 'b', 'é'];
+        let patterns = ["a*b", "?b*", "*a?a*", "a**b?", "*é*", "??", "*b"];
+        let mut seed: u32 = 12345;
+        for _ in 0..500 {
+            let mut line = String::new();
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let len = (seed >> 16) % 7;
+            for _ in 0..len {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                line.push(alphabet[((seed >> 16) % 3) as usize]);
+            }
+            let text: Vec<char> = line.chars().collect();
+            for pattern in patterns {
+                let chars: Vec<char> = pattern.chars().collect();
+                assert_eq!(
+                    GlobPattern::new(pattern).is_match(line.as_bytes()),
+                    naive_glob(&chars, &text),
+                    "pattern {:?} line {:?}",
+                    pattern,
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_pattern_ignore_case() {
+        let tuning = TuningParams::default();
+        assert_eq!(count_pattern_ignore_case(b"foo Foo fOo", b"FOO", tuning), 3);
+        assert_eq!(count_pattern(b"foo Foo fOo", b"FOO", tuning), 0);
+        assert_eq!(count_pattern_ignore_case(b"aAaA", b"aa", tuning), 2);
+        assert_eq!(count_pattern_ignore_case(b"f\xc3\xb6o F", b"F", tuning), 2);
+        let unicode = "Stra\u{df}e STRASSE stra\u{df}e \u{c4}rger \u{e4}rger";
+        assert_eq!(
+            count_pattern_ignore_case(unicode.as_bytes(), "STRA\u{df}E".as_bytes(), tuning),
+            2
+        );
+        assert_eq!(
+            count_pattern_ignore_case(unicode.as_bytes(), "\u{e4}RGER".as_bytes(), tuning),
+            2
+        );
+    }
+
+    #[test]
+    fn test_count_pattern_ignore_case_parallel_matches_serial() {
+        let text = "Error: x\nERROR y\nno eRRor\nerro\nr\n".repeat(20_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let serial = count_pattern_ignore_case(text.as_bytes(), b"error", TuningParams::serial());
+        assert_eq!(serial, 60_000);
+        assert_eq!(
+            count_pattern_ignore_case(text.as_bytes(), b"error", tiny),
+            serial
+        );
+        // Matches straddling every chunk boundary.
+        let dense = "Ab".repeat(200_000);
+        assert_eq!(
+            count_pattern_ignore_case(dense.as_bytes(), b"bA", tiny),
+            199_999
+        );
+        assert_eq!(
+            count_pattern_ignore_case(dense.as_bytes(), b"bA", TuningParams::serial()),
+            199_999
+        );
+    }
+
+    #[test]
+    fn test_count_patterns_matches_separate_scans() {
+        let text = "TODO: fix FIXME todo aaaa TODOTODO xx\n".repeat(30_000);
+        let patterns: [&[u8]; 5] = [b"TODO", b"FIXME", b"aa", b"", b"ODOT"];
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        for tuning in [TuningParams::serial(), tiny] {
+            let expected: Vec<usize> = patterns
+                .iter()
+                .map(|p| count_pattern(text.as_bytes(), p, tuning))
+                .collect();
+            assert_eq!(expected, [90_000, 30_000, 60_000, 0, 30_000]);
+            assert_eq!(
+                count_patterns(text.as_bytes(), &patterns, false, tuning),
+                expected
+            );
+
+            let caseless = count_patterns(text.as_bytes(), &[b"todo", b"fixme"], true, tuning);
+            assert_eq!(
+                caseless,
+                [
+                    count_pattern_ignore_case(text.as_bytes(), b"todo", tuning),
+                    count_pattern_ignore_case(text.as_bytes(), b"fixme", tuning)
+                ]
+            );
+            assert_eq!(caseless, [120_000, 30_000]);
+        }
+    }
+
+    #[test]
+    fn test_count_matching_lines() {
+        let tuning = TuningParams::default();
+        let text = b"foo foo\nfoo\nbar\n";
+        assert_eq!(count_matching_lines(text, b"foo", false, tuning), 2);
+        assert_eq!(count_pattern(text, b"foo", tuning), 3);
+        assert_eq!(
+            count_matching_lines(b"FOO\nfoo\nFo", b"foo", true, tuning),
+            2
+        );
+        assert_eq!(
+            count_matching_lines(
+                "\u{c4}\n\u{e4}\n".as_bytes(),
+                "\u{e4}".as_bytes(),
+                true,
+                tuning
+            ),
+            2
+        );
+        assert_eq!(
+            count_matching_lines(b"no newline foo", b"foo", false, tuning),
+            1
+        );
+        assert_eq!(count_matching_lines(b"", b"foo", false, tuning), 0);
+
+        let long = "a foo and foo\nnothing\n".repeat(50_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(
+            count_matching_lines(long.as_bytes(), b"foo", false, tiny),
+            50_000
+        );
+        assert_eq!(
+            count_matching_lines(long.as_bytes(), b"foo", false, TuningParams::serial()),
+            50_000
+        );
+    }
+
+    #[test]
+    fn test_count_lines_matching_any() {
+        let tuning = TuningParams::default();
+        let text = b"TODO and FIXME
+TODO
+FIXME
+none
+todo
+";
+        assert_eq!(
+            count_lines_matching_any(text, &[b"TODO", b"FIXME"], false, tuning),
+            3
+        );
+        assert_eq!(
+            count_lines_matching_any(text, &[b"TODO", b"FIXME"], true, tuning),
+            4
+        );
+        assert_eq!(
+            count_lines_matching_any(text, &[b"TODO", b""], false, tuning),
+            2
+        );
+        assert_eq!(count_lines_matching_any(text, &[], false, tuning), 0);
+        assert_eq!(
+            count_lines_matching_any(
+                "\u{c4}x\ny\n\u{e4}\nz".as_bytes(),
+                &["\u{e4}".as_bytes(), b"Y"],
+                true,
+                tuning
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn test_count_glob_lines_any() {
+        let globs = [GlobPattern::new("fn *"), GlobPattern::new("*TODO*")];
+        let text = b"fn main() {
+    // TODO
+}
+fn x() // TODO
+";
+        assert_eq!(
+            count_glob_lines_any(text, &globs, TuningParams::default()),
+            3
+        );
+    }
+
+    #[test]
+    fn test_count_regex() {
+        let count = |pattern: &str, text: &str| {
+            count_regex(text.as_bytes(), &compile_regex(pattern, false).unwrap())
+        };
+        assert_eq!(count(r"\d+", "a1 b22 c333"), 3);
+        assert_eq!(count("^ERROR", "ERROR x\nan ERROR\r\nERROR y\n"), 2);
+        assert_eq!(count(r"\.$", "end.\r\nmid. dle\nlast."), 2);
+        assert_eq!(count(r"foo\s+bar", "foo\n\n  bar foobar"), 1);
+        assert!(compile_regex("(unclosed", false).is_err());
+    }
+
+    #[test]
+    fn test_count_glob_lines() {
+        let glob = GlobPattern::new("ERROR*timeout");
+        let data = b"ERROR a timeout\r\nINFO ok\nERROR b timeout\nERROR x";
+        assert_eq!(count_glob_lines(data, &glob, TuningParams::default()), 2);
+
+        let large = b"ERROR db timeout\nINFO fine\n".repeat(50_000);
+        assert_eq!(
+            count_glob_lines(&large, &glob, TuningParams::default()),
+            50_000
+        );
+    }
+
+    #[test]
+    fn test_repeated_lines_basic() {
+        assert_eq!(count_repeated_lines(b"", TuningParams::default()), 0);
+        assert_eq!(
+            count_repeated_lines(b"a\na\nb\na\n", TuningParams::default()),
+            1
+        );
+        assert_eq!(
+            count_repeated_lines(b"x\r\nx\nx", TuningParams::default()),
+            2
+        );
+    }
+
+    #[test]
+    fn test_repeated_lines_straddle_chunk_boundary() {
+        let mut data = vec![b'x'; CHUNK_SIZE - 2];
+        data.push(b'\n');
+        data.extend_from_slice(b"stuck\nstuck\n");
+        for i in 0..1000 {
+            data.extend_from_slice(format!("line {}\n", i).as_bytes());
+        }
+
+        let boundaries = find_line_boundaries(&data, CHUNK_SIZE);
+        assert_eq!(boundaries[1], CHUNK_SIZE + 5);
+        assert_eq!(count_repeated_lines(&data, TuningParams::default()), 1);
+        assert_eq!(repeated_lines_chunk(&data).count, 1);
+    }
+
+    #[test]
+    fn test_line_length_counts() {
+        let counts = line_length_counts(b"abc\ndef\r\nghij\n", TuningParams::default());
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&3], 2);
+        assert_eq!(counts[&4], 1);
+    }
+
+    #[test]
+    fn test_width_violations_line_numbers() {
+        let mut data = b"12345\n".repeat(100_000);
+        data.extend_from_slice(b"123\n");
+        data.extend_from_slice(&b"12345\n".repeat(100_000));
+        data.extend_from_slice(b"1234567\n");
+
+        let violations = find_width_violations(&data, 5, 5, TuningParams::default());
+        assert_eq!(violations.count, 2);
+        assert_eq!(violations.first_lines, vec![100_001, 200_002]);
+
+        let limited = find_width_violations(b"1\n22\n333\n", 5, 2, TuningParams::default());
+        assert_eq!(limited.count, 3);
+        assert_eq!(limited.first_lines, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_count_emoji_sequences() {
+        assert_eq!(
+            count_emoji(
+                "thumbs \u{1F44D}\u{1F3FD} up".as_bytes(),
+                TuningParams::default()
+            ),
+            1
+        );
+        assert_eq!(
+            count_emoji(
+                "\u{1F1EF}\u{1F1F5}\u{1F1EB}\u{1F1F7}".as_bytes(),
+                TuningParams::default()
+            ),
+            2
+        );
+        assert_eq!(
+            count_emoji(
+                "family \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}".as_bytes(),
+                TuningParams::default()
+            ),
+            1
+        );
+        assert_eq!(
+            count_emoji(
+                "keycap 1\u{FE0F}\u{20E3} heart \u{2764}\u{FE0F}".as_bytes(),
+                TuningParams::default()
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn test_count_non_ascii() {
+        let serial = TuningParams::serial();
+        assert_eq!(count_non_ascii(b"plain ascii\n", serial), 0);
+        assert_eq!(count_non_ascii("caf\u{e9}".as_bytes(), serial), 1);
+        assert_eq!(
+            count_non_ascii("\u{201C}quoted\u{201D} \u{1F600}".as_bytes(), serial),
+            3
+        );
+        assert_eq!(count_non_ascii(b"bad \xff\xfe bytes", serial), 2);
+
+        let data = "p\u{430}ypal \u{2014} ok\n".repeat(20_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(count_non_ascii(data.as_bytes(), tiny), 40_000);
+        assert_eq!(count_non_ascii(data.as_bytes(), serial), 40_000);
+    }
+
+    #[test]
+    fn test_count_emoji_ignores_plain_symbols() {
+        assert_eq!(
+            count_emoji(
+                "\u{00A9} 2024 \u{2122} \u{2764} #1".as_bytes(),
+                TuningParams::default()
+            ),
+            0
+        );
+        assert_eq!(count_emoji(b"plain ascii text", TuningParams::default()), 0);
+    }
+
+    #[test]
+    fn test_count_emoji_parallel() {
+        let line = "a \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} b \u{1F600}\n";
+        let data = line.repeat(20_000);
+        assert!(data.len() > PARALLEL_THRESHOLD);
+        assert_eq!(
+            count_emoji(data.as_bytes(), TuningParams::default()),
+            40_000
+        );
+    }
+
+    #[test]
+    fn test_count_paragraphs() {
+        let count = |text: &str| count_paragraphs(text.as_bytes(), TuningParams::default());
+        assert_eq!(count("one\ntwo\n\nthree\n"), 2);
+        assert_eq!(count("\n\n  \none\n\n\n\t\ntwo\n\n"), 2);
+        assert_eq!(count("one\r\ntwo\r\n\r\nthree"), 2);
+        assert_eq!(count("no newline at all"), 1);
+        assert_eq!(count(" \n\t\n\r\n"), 0);
+        assert_eq!(count(""), 0);
+        // Whatever -b counts as blank separates paragraphs.
+        let text = "a\n \t\nb\n\x0c\r\nc\n\x0b\nd\n";
+        assert_eq!(
+            count_blank_lines(text.as_bytes(), TuningParams::default()).total(),
+            2
+        );
+        assert_eq!(count(text), 3);
+    }
+
+    #[test]
+    fn test_count_paragraphs_parallel_matches_serial() {
+        let text = "first line\nsecond line\nthird line\n\n\nshort\n  \r\n".repeat(100_000);
+        assert!(text.len() > 4 * MIN_CHUNK_SIZE);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let serial = count_paragraphs(text.as_bytes(), TuningParams::serial());
+        assert_eq!(serial, 200_000);
+        assert_eq!(count_paragraphs(text.as_bytes(), tiny), serial);
+    }
+
+    #[test]
+    fn test_count_sentences() {
+        let count = |text: &str| count_sentences(text.as_bytes(), TuningParams::default());
+        assert_eq!(count("One. Two! Three?"), 3);
+        assert_eq!(count("Wait\u{2026} what?! Fine."), 3);
+        assert_eq!(count("Mr. Smith paid 3.14 dollars, e.g. for tea."), 1);
+        assert_eq!(count("J. R. R. Tolkien wrote it. See Fig. 2."), 2);
+        assert_eq!(
+            count("Acme Inc. and Foo Ltd. sell pens, ink etc. at Dr. Who's."),
+            1
+        );
+        assert_eq!(count("Sr. vs. Jr. i.e. Mrs. Jones left."), 1);
+        assert_eq!(count("He said \"Stop.\" Then he left."), 2);
+        assert_eq!(count("No terminator here"), 1);
+        assert_eq!(count("Visit example.com today. Done"), 2);
+        assert_eq!(count("...\n\n   "), 0);
+        assert_eq!(count(""), 0);
+    }
+
+    #[test]
+    fn test_count_sentences_across_chunks() {
+        // Each sentence spans several lines, so chunk boundaries fall
+        // mid-sentence.
+        let text = "This sentence\nkeeps going\nover lines. Short one!\n".repeat(30_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        assert_eq!(count_sentences(text.as_bytes(), tiny), 60_000);
+        let open = format!("{}and then it trails off", text);
+        assert_eq!(count_sentences(open.as_bytes(), tiny), 60_001);
+        assert_eq!(
+            count_sentences(open.as_bytes(), TuningParams::serial()),
+            60_001
+        );
+    }
+
+    #[test]
+    fn test_count_graphemes() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        assert_eq!(family.len(), 25);
+        assert_eq!(count_chars(family.as_bytes(), TuningParams::default()), 7);
+        assert_eq!(
+            count_graphemes(family.as_bytes(), TuningParams::default()),
+            1
+        );
+        // Conjoining jamo (HAN) and a combining accent form one cluster each.
+        assert_eq!(
+            count_graphemes(
+                "\u{1112}\u{1161}\u{11AB} e\u{301}\r\n".as_bytes(),
+                TuningParams::default()
+            ),
+            4
+        );
+    }
+
+    #[test]
+    fn test_count_graphemes_parallel() {
+        let line = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466} ok\n";
+        let data = line.repeat(20_000);
+        assert!(data.len() > PARALLEL_THRESHOLD);
+        assert_eq!(
+            count_graphemes(data.as_bytes(), TuningParams::default()),
+            100_000
+        );
+    }
+
+    #[test]
+    fn test_tiny_chunks_match_default_counts() {
+        let mut text = String::new();
+        for i in 0..400 {
+            text.push_str(&format!("line {} caf\u{e9} \u{1F600} foo\r\n", i));
+            if i % 7 == 0 {
+                text.push_str("\nfoo foo dup\ndup\ndup\n");
+            }
+        }
+        let data = text.as_bytes();
+        let defaults = TuningParams::default();
+        let tiny = TuningParams {
+            chunk_size: 7,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+
+        assert_eq!(count_lines(data, tiny), count_lines(data, defaults));
+        assert_eq!(
+            count_blank_lines(data, tiny),
+            count_blank_lines(data, defaults)
+        );
+        assert_eq!(count_all_words(data, tiny), count_all_words(data, defaults));
+        assert_eq!(count_chars(data, tiny), count_chars(data, defaults));
+        assert_eq!(count_emoji(data, tiny), count_emoji(data, defaults));
+        assert_eq!(
+            count_paragraphs(data, tiny),
+            count_paragraphs(data, defaults)
+        );
+        assert_eq!(count_graphemes(data, tiny), count_graphemes(data, defaults));
+        assert_eq!(
+            longest_line(data, tiny).length,
+            longest_line(data, defaults).length
+        );
+        assert_eq!(
+            count_unique_words(data, tiny, Tokenizer::Whitespace),
+            count_unique_words(data, defaults, Tokenizer::Whitespace)
+        );
+        assert_eq!(
+            count_repeated_lines(data, tiny),
+            count_repeated_lines(data, defaults)
+        );
+        assert_eq!(
+            count_pattern(data, b"foo foo", tiny),
+            count_pattern(data, b"foo foo", defaults)
+        );
+        assert_eq!(
+            generate_histogram(data, tiny),
+            generate_histogram(data, defaults)
+        );
+    }
+
+    #[test]
+    fn test_count_prose_words_skips_numbers_and_punctuation() {
+        let table =
+            "| Name | Qty | Date |\n|------|-----|------|\n| caf\u{e9} | 42 | 2024-01-05 |\n";
+        assert_eq!(
+            count_prose_words(table.as_bytes(), TuningParams::default()),
+            4
+        );
+        assert_eq!(
+            count_all_words(table.as_bytes(), TuningParams::default()),
+            15
+        );
+        assert_eq!(
+            count_prose_words(b"x => 3.14, v2 -- 1,000", TuningParams::default()),
+            2
+        );
+        assert_eq!(
+            count_prose_words("\u{65e5}\u{672c} 123".as_bytes(), TuningParams::default()),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unicode_word_boundaries() {
+        let tuning = TuningParams::default();
+        let text = "The state-of-the-art model can't fail.";
+        assert_eq!(count_all_words(text.as_bytes(), tuning), 5);
+        assert_eq!(count_tokens(text.as_bytes(), tuning, Tokenizer::Unicode), 8);
+        assert_eq!(
+            count_tokens(
+                "l'\u{e9}tat \u{2014} c'est moi !".as_bytes(),
+                tuning,
+                Tokenizer::Unicode
+            ),
+            3
+        );
+        assert_eq!(
+            count_tokens(b"Price: $3.50 -- 20% off", tuning, Tokenizer::Unicode),
+            4
+        );
+    }
+
+    #[test]
+    fn test_unicode_words_parallel_and_unique() {
+        let data = "Hello, world! hello-world\n".repeat(40_000);
+        let tuning = TuningParams::default();
+        assert_eq!(
+            count_tokens(data.as_bytes(), tuning, Tokenizer::Unicode),
+            160_000
+        );
+        assert_eq!(
+            count_unique_words(data.as_bytes(), tuning, Tokenizer::Unicode),
+            3
+        );
+        assert_eq!(
+            count_unique_words(data.as_bytes(), tuning, Tokenizer::Whitespace),
+            3
+        );
+    }
+
+    #[test]
+    fn test_identifier_tokens() {
+        let tokens: Vec<&str> = Tokenizer::Identifiers
+            .tokens("let foo_bar = baz2(x->y);")
+            .collect();
+        assert_eq!(tokens, ["let", "foo_bar", "baz2", "x", "y"]);
+    }
+
+    #[test]
+    fn test_kmer_uniqueness_on_dna() {
+        // 3-mers of ACGTACGT: ACG CGT GTA TAC ACG CGT, four distinct.
+        let data = b"ACGTACGT\n";
+        let tuning = TuningParams::default();
+        assert_eq!(count_tokens(data, tuning, Tokenizer::Kmer(3)), 6);
+        assert_eq!(count_unique_words(data, tuning, Tokenizer::Kmer(3)), 4);
+        assert_eq!(count_tokens(b"AC GT\n", tuning, Tokenizer::Kmer(3)), 0);
+        assert_eq!(
+            count_tokens("\u{e9}AB".as_bytes(), tuning, Tokenizer::Kmer(2)),
+            2
+        );
+    }
+
+    #[test]
+    fn test_tokenizers_match_across_chunks() {
+        let data = "fn main() { let x_1 = ACGTTGCA; }\n".repeat(5_000);
+        let tiny = TuningParams {
+            chunk_size: 64,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        for tokenizer in [
+            Tokenizer::Unicode,
+            Tokenizer::Identifiers,
+            Tokenizer::Kmer(4),
+        ] {
+            assert_eq!(
+                count_tokens(data.as_bytes(), tiny, tokenizer),
+                count_tokens(data.as_bytes(), TuningParams::serial(), tokenizer),
+                "{:?}",
+                tokenizer
+            );
+            assert_eq!(
+                count_unique_words(data.as_bytes(), tiny, tokenizer),
+                count_unique_words(data.as_bytes(), TuningParams::serial(), tokenizer)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_tokenizer() {
+        assert_eq!("identifiers".parse(), Ok(Tokenizer::Identifiers));
+        assert_eq!("kmer:21".parse(), Ok(Tokenizer::Kmer(21)));
+        assert!("kmer:0".parse::<Tokenizer>().is_err());
+        assert!("kmer:x".parse::<Tokenizer>().is_err());
+        assert!("words".parse::<Tokenizer>().is_err());
+    }
+
+    #[test]
+    fn test_top_lines_with_skipped_fields() {
+        let mut log = String::new();
+        for i in 0..30 {
+            log.push_str(&format!("2024-01-01T00:00:{:02} ERROR disk full\n", i));
+            if i % 3 == 0 {
+                log.push_str(&format!("2024-01-01T00:01:{:02} WARN slow request\n", i));
+            }
+            if i % 10 == 0 {
+                log.push('\n');
+            }
+        }
+        log.push_str("2024-01-01T00:02:00 INFO started\n");
+
+        let plain = line_frequencies(log.as_bytes(), 0, TuningParams::default());
+        assert_eq!(top_lines(&plain, 1)[0].0, 1);
+
+        let freq = line_frequencies(log.as_bytes(), 1, TuningParams::default());
+        let top = top_lines(&freq, 2);
+        assert_eq!(top[0], (30, "2024-01-01T00:00:00 ERROR disk full"));
+        assert_eq!(top[1], (10, "2024-01-01T00:01:00 WARN slow request"));
+        assert_eq!(freq.len(), 3);
+    }
+
+    fn level_log() -> String {
+        let mut log = String::new();
+        for i in 0..100 {
+            let level = match i % 10 {
+                0 => "ERROR",
+                1..=3 => "WARN",
+                _ => "INFO",
+            };
+            log.push_str(&format!("{} [net] request {} done\n", level, i));
+        }
+        log.push('\n');
+        log
+    }
+
+    #[test]
+    fn test_prefix_groups_by_field_and_chars() {
+        let log = level_log();
+        let groups = prefix_groups(
+            log.as_bytes(),
+            LinePrefix::Fields(1),
+            TuningParams::default(),
+        );
+        assert_eq!(
+            rank_key_counts(&groups),
+            vec![(60, "INFO"), (30, "WARN"), (10, "ERROR")]
+        );
+
+        let groups = prefix_groups(
+            log.as_bytes(),
+            LinePrefix::Fields(2),
+            TuningParams::default(),
+        );
+        assert_eq!(rank_key_counts(&groups)[0], (60, "INFO [net]"));
+
+        let groups = prefix_groups(
+            log.as_bytes(),
+            LinePrefix::Chars(1),
+            TuningParams::default(),
+        );
+        assert_eq!(
+            rank_key_counts(&groups),
+            vec![(60, "I"), (30, "W"), (10, "E")]
+        );
+    }
+
+    #[test]
+    fn test_word_frequencies_rank_with_alphabetical_ties() {
+        let text = "the cat and the dog\nand the bird\n";
+        let frequencies = word_frequencies(
+            text.as_bytes(),
+            TuningParams::default(),
+            Tokenizer::Whitespace,
+        );
+        assert_eq!(
+            rank_key_counts(&frequencies)[..4],
+            [(3, "the"), (2, "and"), (1, "bird"), (1, "cat")]
+        );
+        assert_eq!(
+            frequencies.len(),
+            count_unique_words(
+                text.as_bytes(),
+                TuningParams::default(),
+                Tokenizer::Whitespace
+            )
+        );
+    }
+
+    #[test]
+    fn test_word_frequencies_parallel_merge() {
+        let text = "alpha beta alpha\ngamma alpha\n".repeat(40_000);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let parallel = word_frequencies(text.as_bytes(), tiny, Tokenizer::Whitespace);
+        assert_eq!(
+            parallel,
+            word_frequencies(
+                text.as_bytes(),
+                TuningParams::serial(),
+                Tokenizer::Whitespace
+            )
+        );
+        assert_eq!(parallel["alpha"], 120_000);
+    }
+
+    #[test]
+    fn test_prefix_groups_are_bounded() {
+        let line = "x".repeat(500);
+        assert_eq!(
+            line_prefix(&line, LinePrefix::Fields(1)).len(),
+            MAX_PREFIX_CHARS
+        );
+        assert_eq!(line_prefix("  héllo wörld", LinePrefix::Fields(1)), "héllo");
+        assert_eq!(line_prefix("héllo", LinePrefix::Chars(2)), "hé");
+        assert_eq!(line_prefix("ab", LinePrefix::Chars(5)), "ab");
+    }
+
+    #[test]
+    fn test_prefix_groups_parallel_merge() {
+        let log = level_log().repeat(500);
+        let tiny = TuningParams {
+            chunk_size: MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let parallel = prefix_groups(log.as_bytes(), LinePrefix::Fields(1), tiny);
+        assert_eq!(
+            parallel,
+            prefix_groups(
+                log.as_bytes(),
+                LinePrefix::Fields(1),
+                TuningParams::serial()
+            )
+        );
+        assert_eq!(parallel["ERROR"], 5_000);
+    }
+
+    #[test]
+    fn test_line_frequencies_parallel_merge() {
+        let data = "alpha\nbeta\nalpha\n".repeat(50_000);
+        let tiny = TuningParams {
+            chunk_size: 64 * 1024,
+            parallel_threshold: 0,
+            deadline: None,
+        };
+        let freq = line_frequencies(data.as_bytes(), 0, tiny);
+        assert_eq!(
+            top_lines(&freq, 5),
+            vec![(100_000, "alpha"), (50_000, "beta")]
+        );
     }
 }
@@ -0,0 +1,612 @@
+use crate::Counts;
+use crate::config::Args;
+use crate::count::{self, TuningParams};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// How a counter's per-file values combine into a total.
+#[derive(Clone, Copy)]
+pub enum Aggregate {
+    Sum,
+    Max,
+}
+
+/// How a counter is turned on.
+pub enum Flag {
+    /// A field of `Args`, for counters other options also read. `switch`
+    /// is None when the option takes a value, as `--expect-width` does.
+    Field {
+        enabled: fn(&Args) -> bool,
+        switch: Option<fn(&mut Args) -> &mut bool>,
+    },
+    /// `--<long>`, declared here; `command` adds it to the command line and
+    /// `Args::registered` records it.
+    Long {
+        long: &'static str,
+        help: &'static str,
+    },
+}
+
+/// Where a counter's value lives in `Counts`.
+pub enum Storage {
+    Field {
+        get: fn(&Counts) -> usize,
+        get_mut: fn(&mut Counts) -> &mut usize,
+        aggregate: Aggregate,
+    },
+    /// Computed from state that `Counts::add` merges itself, such as the
+    /// line hashes behind `unique_lines`.
+    Derived(fn(&Counts) -> usize),
+    /// `Counts::registered`, under the counter's name.
+    Registered(Aggregate),
+}
+
+/// What a counter's `count` function reads.
+pub struct Input<'a> {
+    /// The decoded and filtered input.
+    pub data: &'a [u8],
+    /// `data` with `--join-continuations` applied.
+    pub lines: &'a [u8],
+}
+
+/// One column of the table output. A counter with a `Long` flag,
+/// `Registered` storage and a `count` function needs nothing outside
+/// this table.
+pub struct Counter {
+    /// Column name, also the JSON key (`.` separating nested keys) and the
+    /// name `--serve` requests use.
+    pub name: &'static str,
+    pub flag: Flag,
+    pub storage: Storage,
+    /// Whether the counter reads the input decoded to UTF-8 rather than
+    /// raw bytes.
+    pub decodes: bool,
+    /// Computes the counter; None when `count_buffer` does, because it
+    /// shares a scan with other counters or other options need it too.
+    pub count: Option<fn(&Input, TuningParams) -> usize>,
+}
+
+impl Counter {
+    pub fn enabled(&self, args: &Args) -> bool {
+        match self.flag {
+            Flag::Field { enabled, .. } => enabled(args),
+            Flag::Long { .. } => args.registered.contains(self.name),
+        }
+    }
+
+    /// Turns the counter on or off; false when its option takes a value.
+    pub fn set(&self, args: &mut Args, on: bool) -> bool {
+        match self.flag {
+            Flag::Field {
+                switch: Some(switch),
+                ..
+            } => *switch(args) = on,
+            Flag::Field { switch: None, .. } => return false,
+            Flag::Long { .. } if on => {
+                args.registered.insert(self.name);
+            }
+            Flag::Long { .. } => {
+                args.registered.remove(self.name);
+            }
+        }
+        true
+    }
+
+    /// Whether the counter looks at the input at all; `bytes` and
+    /// `disk_bytes` come from the file size.
+    pub fn reads_content(&self) -> bool {
+        !matches!(self.name, "bytes" | "disk_bytes")
+    }
+
+    pub fn value(&self, counts: &Counts) -> usize {
+        match self.storage {
+            Storage::Field { get, .. } | Storage::Derived(get) => get(counts),
+            Storage::Registered(_) => counts.registered.get(self.name).copied().unwrap_or(0),
+        }
+    }
+
+    fn slot<'a>(&self, counts: &'a mut Counts) -> Option<(&'a mut usize, Aggregate)> {
+        match self.storage {
+            Storage::Field {
+                get_mut, aggregate, ..
+            } => Some((get_mut(counts), aggregate)),
+            Storage::Derived(_) => None,
+            Storage::Registered(aggregate) => {
+                Some((counts.registered.entry(self.name).or_insert(0), aggregate))
+            }
+        }
+    }
+
+    /// Stores what `count` returned.
+    pub fn store(&self, counts: &mut Counts, value: usize) {
+        if let Some((slot, _)) = self.slot(counts) {
+            *slot = value;
+        }
+    }
+
+    /// Folds `other`'s value into `total`; `Derived` counters are merged
+    /// by `Counts::add`.
+    pub fn add(&self, total: &mut Counts, other: &Counts) {
+        let value = self.value(other);
+        match self.slot(total) {
+            Some((slot, Aggregate::Sum)) => *slot += value,
+            Some((slot, Aggregate::Max)) => *slot = (*slot).max(value),
+            None => {}
+        }
+    }
+
+    /// Scales a summed counter for sampled estimates; maxima are left alone.
+    pub fn scale(&self, counts: &mut Counts, factor: f64) {
+        if let Some((slot, Aggregate::Sum)) = self.slot(counts) {
+            *slot = (*slot as f64 * factor).round() as usize;
+        }
+    }
+}
+
+/// Every fixed counter in display order. The `--pattern` columns and
+/// `matching_lines` follow them; their names come from the patterns
+/// rather than a flag.
+pub const COUNTERS: &[Counter] = &[
+    Counter {
+        name: "lines",
+        flag: Flag::Field {
+            enabled: |args| args.lines,
+            switch: Some(|args| &mut args.lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.lines,
+            get_mut: |counts| &mut counts.lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "words",
+        flag: Flag::Field {
+            enabled: |args| args.words,
+            switch: Some(|args| &mut args.words),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.words,
+            get_mut: |counts| &mut counts.words,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: None,
+    },
+    Counter {
+        name: "sentences",
+        flag: Flag::Field {
+            enabled: |args| args.sentences,
+            switch: Some(|args| &mut args.sentences),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.sentences,
+            get_mut: |counts| &mut counts.sentences,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: Some(|input, tuning| count::count_sentences(input.data, tuning)),
+    },
+    Counter {
+        name: "paragraphs",
+        flag: Flag::Field {
+            enabled: |args| args.paragraphs,
+            switch: Some(|args| &mut args.paragraphs),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.paragraphs,
+            get_mut: |counts| &mut counts.paragraphs,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: Some(|input, tuning| count::count_paragraphs(input.data, tuning)),
+    },
+    Counter {
+        name: "chars",
+        flag: Flag::Field {
+            enabled: |args| args.chars,
+            switch: Some(|args| &mut args.chars),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.chars,
+            get_mut: |counts| &mut counts.chars,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: None,
+    },
+    Counter {
+        name: "graphemes",
+        flag: Flag::Field {
+            enabled: |args| args.graphemes,
+            switch: Some(|args| &mut args.graphemes),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.graphemes,
+            get_mut: |counts| &mut counts.graphemes,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: Some(|input, tuning| count::count_graphemes(input.data, tuning)),
+    },
+    Counter {
+        name: "bytes",
+        flag: Flag::Field {
+            enabled: |args| args.bytes,
+            switch: Some(|args| &mut args.bytes),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.bytes,
+            get_mut: |counts| &mut counts.bytes,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "disk_bytes",
+        flag: Flag::Field {
+            enabled: |args| args.disk_usage,
+            switch: Some(|args| &mut args.disk_usage),
+        },
+        storage: Storage::Derived(|counts| counts.disk_bytes.unwrap_or(0)),
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "max_line_length",
+        flag: Flag::Field {
+            enabled: |args| args.max_line_length,
+            switch: Some(|args| &mut args.max_line_length),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.max_line_length,
+            get_mut: |counts| &mut counts.max_line_length,
+            aggregate: Aggregate::Max,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "max_line_width",
+        flag: Flag::Field {
+            enabled: |args| args.max_line_width,
+            switch: Some(|args| &mut args.max_line_width),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.max_line_width,
+            get_mut: |counts| &mut counts.max_line_width,
+            aggregate: Aggregate::Max,
+        },
+        decodes: true,
+        count: Some(|input, tuning| count::max_line_width(input.lines, tuning)),
+    },
+    Counter {
+        name: "max_word_length",
+        flag: Flag::Field {
+            enabled: |args| args.max_word_length,
+            switch: Some(|args| &mut args.max_word_length),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.max_word_length,
+            get_mut: |counts| &mut counts.max_word_length,
+            aggregate: Aggregate::Max,
+        },
+        decodes: true,
+        count: Some(|input, tuning| count::max_word_length(input.data, tuning)),
+    },
+    Counter {
+        name: "blank_lines",
+        flag: Flag::Field {
+            enabled: |args| args.blank_lines,
+            switch: Some(|args| &mut args.blank_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.blank_lines,
+            get_mut: |counts| &mut counts.blank_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "empty_lines",
+        flag: Flag::Field {
+            enabled: |args| args.empty_lines,
+            switch: Some(|args| &mut args.empty_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.empty_lines,
+            get_mut: |counts| &mut counts.empty_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "whitespace_only_lines",
+        flag: Flag::Field {
+            enabled: |args| args.whitespace_only_lines,
+            switch: Some(|args| &mut args.whitespace_only_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.whitespace_only_lines,
+            get_mut: |counts| &mut counts.whitespace_only_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "trailing_whitespace_lines",
+        flag: Flag::Field {
+            enabled: |args| args.trailing_whitespace,
+            switch: Some(|args| &mut args.trailing_whitespace),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.trailing_whitespace_lines,
+            get_mut: |counts| &mut counts.trailing_whitespace_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: Some(|input, tuning| count::count_trailing_whitespace_lines(input.data, tuning)),
+    },
+    Counter {
+        name: "unique_words",
+        flag: Flag::Field {
+            enabled: |args| args.unique,
+            switch: Some(|args| &mut args.unique),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.unique_words,
+            get_mut: |counts| &mut counts.unique_words,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: None,
+    },
+    Counter {
+        name: "repeated_lines",
+        flag: Flag::Field {
+            enabled: |args| args.repeated_lines,
+            switch: Some(|args| &mut args.repeated_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.repeated_lines,
+            get_mut: |counts| &mut counts.repeated_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: Some(|input, tuning| count::count_repeated_lines(input.data, tuning)),
+    },
+    Counter {
+        name: "unique_lines",
+        flag: Flag::Field {
+            enabled: |args| args.unique_lines,
+            switch: Some(|args| &mut args.unique_lines),
+        },
+        storage: Storage::Derived(|counts| counts.unique_lines),
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "duplicate_lines",
+        flag: Flag::Field {
+            enabled: |args| args.duplicate_lines,
+            switch: Some(|args| &mut args.duplicate_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.duplicate_lines,
+            get_mut: |counts| &mut counts.duplicate_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "redundant_lines",
+        flag: Flag::Field {
+            enabled: |args| args.duplicate_lines,
+            switch: Some(|args| &mut args.duplicate_lines),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.redundant_lines,
+            get_mut: |counts| &mut counts.redundant_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "line_length_cardinality",
+        flag: Flag::Field {
+            enabled: |args| args.line_length_cardinality,
+            switch: Some(|args| &mut args.line_length_cardinality),
+        },
+        storage: Storage::Derived(|counts| counts.line_length_cardinality),
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "nonconforming_lines",
+        flag: Flag::Field {
+            enabled: |args| args.expect_width.is_some(),
+            switch: None,
+        },
+        storage: Storage::Field {
+            get: |counts| counts.nonconforming_lines,
+            get_mut: |counts| &mut counts.nonconforming_lines,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "emoji",
+        flag: Flag::Long {
+            long: "emoji",
+            help: "Count emoji (ZWJ sequences, flags and modifiers count as one)",
+        },
+        storage: Storage::Registered(Aggregate::Sum),
+        decodes: true,
+        count: Some(|input, tuning| count::count_emoji(input.data, tuning)),
+    },
+    Counter {
+        name: "non_ascii",
+        flag: Flag::Long {
+            long: "non-ascii",
+            help: "Count characters outside ASCII (smart quotes, homoglyphs); invalid UTF-8 bytes count one each",
+        },
+        storage: Storage::Registered(Aggregate::Sum),
+        decodes: true,
+        count: Some(|input, tuning| count::count_non_ascii(input.data, tuning)),
+    },
+    Counter {
+        name: "feff",
+        flag: Flag::Field {
+            enabled: |args| args.feff_count,
+            switch: Some(|args| &mut args.feff_count),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.feff,
+            get_mut: |counts| &mut counts.feff,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: false,
+        count: None,
+    },
+    Counter {
+        name: "prose_words",
+        flag: Flag::Field {
+            enabled: |args| args.prose_words,
+            switch: Some(|args| &mut args.prose_words),
+        },
+        storage: Storage::Field {
+            get: |counts| counts.prose_words,
+            get_mut: |counts| &mut counts.prose_words,
+            aggregate: Aggregate::Sum,
+        },
+        decodes: true,
+        count: Some(|input, tuning| count::count_prose_words(input.data, tuning)),
+    },
+    Counter {
+        name: "code_breakdown.code",
+        flag: Flag::Field {
+            enabled: |args| args.cloc,
+            switch: Some(|args| &mut args.cloc),
+        },
+        storage: Storage::Derived(|counts| counts.code_breakdown.map_or(0, |lines| lines.code)),
+        decodes: true,
+        count: None,
+    },
+    Counter {
+        name: "code_breakdown.comment",
+        flag: Flag::Field {
+            enabled: |args| args.cloc,
+            switch: Some(|args| &mut args.cloc),
+        },
+        storage: Storage::Derived(|counts| counts.code_breakdown.map_or(0, |lines| lines.comment)),
+        decodes: true,
+        count: None,
+    },
+    Counter {
+        name: "code_breakdown.blank",
+        flag: Flag::Field {
+            enabled: |args| args.cloc,
+            switch: Some(|args| &mut args.cloc),
+        },
+        storage: Storage::Derived(|counts| counts.code_breakdown.map_or(0, |lines| lines.blank)),
+        decodes: true,
+        count: None,
+    },
+];
+
+/// The registered counter called `name`.
+pub fn find(name: &str) -> Option<&'static Counter> {
+    COUNTERS.iter().find(|counter| counter.name == name)
+}
+
+/// `cmd` with the options of counters that declare their own flag.
+pub fn command(cmd: Command) -> Command {
+    COUNTERS
+        .iter()
+        .fold(cmd, |cmd, counter| match counter.flag {
+            Flag::Long { long, help } => cmd.arg(
+                Arg::new(counter.name)
+                    .long(long)
+                    .help(help)
+                    .action(ArgAction::SetTrue),
+            ),
+            Flag::Field { .. } => cmd,
+        })
+}
+
+/// The counters whose own flag was given.
+pub fn flags_given(matches: &ArgMatches) -> BTreeSet<&'static str> {
+    COUNTERS
+        .iter()
+        .filter(|counter| matches!(counter.flag, Flag::Long { .. }))
+        .filter(|counter| matches.get_flag(counter.name))
+        .map(|counter| counter.name)
+        .collect()
+}
+
+/// The value of the counter called `name` in a JSON `counts` object.
+pub fn json_value<'a>(counts: &'a Value, name: &str) -> Option<&'a Value> {
+    name.split('.')
+        .try_fold(counts, |value, key| value.get(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_names_are_unique() {
+        let mut seen = HashSet::new();
+        assert!(COUNTERS.iter().all(|counter| seen.insert(counter.name)));
+        assert_eq!(find("max_line_length").unwrap().name, "max_line_length");
+        assert!(find("pattern").is_none());
+    }
+
+    #[test]
+    fn test_count_has_somewhere_to_go() {
+        for counter in COUNTERS {
+            let derived = matches!(counter.storage, Storage::Derived(_));
+            assert!(counter.count.is_none() || !derived, "{}", counter.name);
+        }
+    }
+
+    #[test]
+    fn test_declared_flags() {
+        let mut args = Args::parse_from(["kz", "--non-ascii"]);
+        args.normalize();
+        let non_ascii = find("non_ascii").unwrap();
+        assert!(non_ascii.enabled(&args));
+        assert!(!find("emoji").unwrap().enabled(&args));
+        // A declared counter alone replaces the default columns.
+        assert!(!args.lines);
+
+        let mut counts = Counts::new();
+        let input = Input {
+            data: "caf\u{e9}".as_bytes(),
+            lines: b"",
+        };
+        non_ascii.store(
+            &mut counts,
+            (non_ascii.count.unwrap())(&input, TuningParams::serial()),
+        );
+        let mut total = Counts::new();
+        non_ascii.add(&mut total, &counts);
+        non_ascii.add(&mut total, &counts);
+        assert_eq!(non_ascii.value(&total), 2);
+
+        assert!(non_ascii.set(&mut args, false));
+        assert!(!non_ascii.enabled(&args));
+        assert!(!find("nonconforming_lines").unwrap().set(&mut args, true));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::count::{BoundaryTrace, ChunkLayout, TuningParams};
+
+/// What `--debug-internals` reports about how one input was counted.
+#[derive(Debug, Default, Clone)]
+pub struct Diagnostics {
+    /// `mmap`, `buffered`, `stdin` or `http`.
+    pub read: Option<&'static str>,
+    /// Bytes the counters ran on, after truncation, decoding and any
+    /// filter; the size compared against the parallel threshold.
+    pub bytes: usize,
+    /// The encoding used and how it was chosen; None when nothing needed
+    /// the input decoded.
+    pub encoding: Option<String>,
+    /// The filter flag with the input size before and after it.
+    pub filter: Option<(&'static str, usize, usize)>,
+    /// Whether the counters took the parallel path, with the tuning used.
+    pub parallel: bool,
+    pub tuning: Option<TuningParams>,
+    pub chunks: Vec<(&'static str, ChunkLayout)>,
+    /// Counters that do not follow the shared serial/parallel rule.
+    pub exceptions: Vec<String>,
+    /// Boundary corrections by counter.
+    pub corrections: Vec<(String, BoundaryTrace)>,
+}
+
+impl Diagnostics {
+    /// One `kz: debug:` line per fact, for stderr.
+    pub fn render(&self, name: &str) -> String {
+        let mut lines = Vec::new();
+        if let Some(read) = self.read {
+            lines.push(format!("read: {}", read));
+        }
+        lines.push(format!(
+            "encoding: {}",
+            self.encoding.as_deref().unwrap_or("not decoded")
+        ));
+        if let Some((flag, before, after)) = self.filter {
+            lines.push(format!(
+                "filter: {} kept {} of {} bytes ({} removed)",
+                flag,
+                after,
+                before,
+                before as i64 - after as i64
+            ));
+        }
+        if let Some(tuning) = self.tuning {
+            let (path, relation) = if self.parallel {
+                ("parallel", ">=")
+            } else {
+                ("serial", "<")
+            };
+            lines.push(format!(
+                "path: {} ({} bytes {} threshold {}), chunk size {}",
+                path, self.bytes, relation, tuning.parallel_threshold, tuning.chunk_size
+            ));
+        }
+        for (scheme, layout) in &self.chunks {
+            lines.push(format!(
+                "chunks: {} {} ({}..{} bytes)",
+                layout.chunks, scheme, layout.smallest, layout.largest
+            ));
+        }
+        lines.extend(self.exceptions.iter().cloned());
+        for (counter, trace) in &self.corrections {
+            let fix = if counter == "words" {
+                format!("subtracted {} split at chunk boundaries", trace.correction)
+            } else {
+                format!("added {} spanning chunk boundaries", trace.correction)
+            };
+            lines.push(format!("{}: {} chunks, {}", counter, trace.chunks, fix));
+        }
+        lines
+            .into_iter()
+            .map(|line| format!("kz: debug: {}: {}\n", name, line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let diagnostics = Diagnostics {
+            read: Some("mmap"),
+            bytes: 250_000,
+            encoding: Some("UTF-8 (detected)".to_string()),
+            filter: Some(("--code", 300_000, 250_000)),
+            parallel: true,
+            tuning: Some(TuningParams {
+                chunk_size: 65_536,
+                parallel_threshold: 0,
+                deadline: None,
+            }),
+            chunks: Vec::new(),
+            exceptions: Vec::new(),
+            corrections: vec![(
+                "words".to_string(),
+                BoundaryTrace {
+                    chunks: 4,
+                    correction: 2,
+                },
+            )],
+        };
+        assert_eq!(
+            diagnostics.render("a.rs"),
+            "kz: debug: a.rs: read: mmap\n\
+             kz: debug: a.rs: encoding: UTF-8 (detected)\n\
+             kz: debug: a.rs: filter: --code kept 250000 of 300000 bytes (50000 removed)\n\
+             kz: debug: a.rs: path: parallel (250000 bytes >= threshold 0), chunk size 65536\n\
+             kz: debug: a.rs: words: 4 chunks, subtracted 2 split at chunk boundaries\n"
+        );
+    }
+}
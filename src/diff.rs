@@ -0,0 +1,404 @@
+use crate::config::Args;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+const DEV_NULL: &str = "/dev/null";
+const NO_EXTENSION: &str = "(none)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDiff {
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_file: Option<String>,
+    pub change: Change,
+    pub added: usize,
+    pub removed: usize,
+    pub binary: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub files: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub binary_files: usize,
+}
+
+impl Totals {
+    fn add(&mut self, file: &FileDiff) {
+        self.files += 1;
+        self.added += file.added;
+        self.removed += file.removed;
+        if file.binary {
+            self.binary_files += 1;
+        }
+    }
+}
+
+impl FileDiff {
+    fn new(file: String) -> Self {
+        Self {
+            file,
+            old_file: None,
+            change: Change::Modified,
+            added: 0,
+            removed: 0,
+            binary: false,
+        }
+    }
+
+    pub fn extension(&self) -> &str {
+        Path::new(&self.file)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or(NO_EXTENSION)
+    }
+}
+
+/// Drops the `a/` or `b/` prefix git puts on paths, and the timestamp that
+/// `diff -u` appends after a tab.
+fn header_path(raw: &str, prefix: &str) -> String {
+    let path = raw.split('\t').next().unwrap_or(raw).trim_end();
+    path.strip_prefix(prefix).unwrap_or(path).to_string()
+}
+
+/// Old and new line counts from a `@@ -a,b +c,d @@` hunk header.
+fn hunk_lengths(line: &str) -> Option<(usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split_whitespace();
+    let old = ranges.next()?.strip_prefix('-')?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    let length = |range: &str| match range.split_once(',') {
+        Some((_, len)) => len.parse().ok(),
+        None => Some(1),
+    };
+    Some((length(old)?, length(new)?))
+}
+
+struct Parser {
+    files: Vec<FileDiff>,
+    current: Option<FileDiff>,
+    saw_new_header: bool,
+    old_left: usize,
+    new_left: usize,
+}
+
+impl Parser {
+    fn finish_file(&mut self) {
+        if let Some(file) = self.current.take() {
+            self.files.push(file);
+        }
+        self.saw_new_header = false;
+    }
+
+    fn current(&mut self) -> &mut FileDiff {
+        self.current
+            .get_or_insert_with(|| FileDiff::new(String::new()))
+    }
+
+    fn line(&mut self, line: &str) {
+        if self.old_left > 0 || self.new_left > 0 {
+            let file = self.current();
+            match line.as_bytes().first() {
+                Some(b'+') => {
+                    file.added += 1;
+                    self.new_left = self.new_left.saturating_sub(1);
+                }
+                Some(b'-') => {
+                    file.removed += 1;
+                    self.old_left = self.old_left.saturating_sub(1);
+                }
+                Some(b'\\') => {}
+                _ => {
+                    self.old_left = self.old_left.saturating_sub(1);
+                    self.new_left = self.new_left.saturating_sub(1);
+                }
+            }
+            return;
+        }
+
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            self.finish_file();
+            let (old, new) = match paths.rfind(" b/") {
+                Some(split) => (&paths[..split], &paths[split + 1..]),
+                None => (paths, paths),
+            };
+            let mut file = FileDiff::new(header_path(new, "b/"));
+            let old = header_path(old, "a/");
+            if old != file.file {
+                file.old_file = Some(old);
+            }
+            self.current = Some(file);
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            if self.saw_new_header {
+                self.finish_file();
+            }
+            let old = header_path(path, "a/");
+            let file = self.current();
+            if old == DEV_NULL {
+                file.change = Change::Added;
+            } else if file.file.is_empty() {
+                file.file = old;
+            }
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            self.saw_new_header = true;
+            let new = header_path(path, "b/");
+            let file = self.current();
+            if new == DEV_NULL {
+                file.change = Change::Deleted;
+            } else {
+                file.file = new;
+            }
+        } else if line.starts_with("new file mode") {
+            self.current().change = Change::Added;
+        } else if line.starts_with("deleted file mode") {
+            self.current().change = Change::Deleted;
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            let file = self.current();
+            file.change = Change::Renamed;
+            file.old_file = Some(path.to_string());
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            let file = self.current();
+            file.change = Change::Renamed;
+            file.file = path.to_string();
+        } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+            self.current().binary = true;
+        } else if let Some((old, new)) = hunk_lengths(line) {
+            self.old_left = old;
+            self.new_left = new;
+        }
+    }
+}
+
+/// Parses a unified diff (git or `diff -u`) into per-file added and removed
+/// line counts. Headers, hunk markers and context lines are not counted.
+pub fn parse(text: &str) -> Vec<FileDiff> {
+    let mut parser = Parser {
+        files: Vec::new(),
+        current: None,
+        saw_new_header: false,
+        old_left: 0,
+        new_left: 0,
+    };
+    for line in text.lines() {
+        parser.line(line);
+    }
+    parser.finish_file();
+    for file in &mut parser.files {
+        if file.change == Change::Modified && file.old_file.is_some() {
+            file.change = Change::Renamed;
+        }
+    }
+    parser.files
+}
+
+pub fn by_extension(files: &[FileDiff]) -> BTreeMap<String, Totals> {
+    let mut extensions: BTreeMap<String, Totals> = BTreeMap::new();
+    for file in files {
+        extensions
+            .entry(file.extension().to_string())
+            .or_default()
+            .add(file);
+    }
+    extensions
+}
+
+pub fn total(files: &[FileDiff]) -> Totals {
+    let mut total = Totals::default();
+    for file in files {
+        total.add(file);
+    }
+    total
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn describe(file: &FileDiff) -> String {
+    let mut name = file.file.clone();
+    match file.change {
+        Change::Added => name.push_str(" (new)"),
+        Change::Deleted => name.push_str(" (deleted)"),
+        Change::Renamed => {
+            if let Some(ref old) = file.old_file {
+                name.push_str(&format!(" (renamed from {})", old));
+            }
+        }
+        Change::Modified => {}
+    }
+    if file.binary {
+        name.push_str(" (binary)");
+    }
+    name
+}
+
+pub fn render(files: &[FileDiff], total_only: bool) -> String {
+    let extensions = by_extension(files);
+    let total = total(files);
+
+    let mut rows: Vec<(String, String, String)> = Vec::new();
+    if !total_only {
+        for file in files {
+            let (added, removed) = if file.binary {
+                ("-".to_string(), "-".to_string())
+            } else {
+                (format!("+{}", file.added), format!("-{}", file.removed))
+            };
+            rows.push((added, removed, describe(file)));
+        }
+        rows.push(Default::default());
+        for (extension, totals) in &extensions {
+            let label = if extension == NO_EXTENSION {
+                extension.clone()
+            } else {
+                format!(".{}", extension)
+            };
+            rows.push((
+                format!("+{}", totals.added),
+                format!("-{}", totals.removed),
+                format!("{} ({})", label, plural(totals.files, "file")),
+            ));
+        }
+    }
+    rows.push((
+        format!("+{}", total.added),
+        format!("-{}", total.removed),
+        format!(
+            "total ({}, {} binary)",
+            plural(total.files, "file"),
+            total.binary_files
+        ),
+    ));
+
+    let added_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let removed_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (added, removed, label) in rows {
+        if label.is_empty() {
+            out.push('\n');
+            continue;
+        }
+        out.push_str(&format!(
+            "{:>added_width$} {:>removed_width$} {}\n",
+            added, removed, label
+        ));
+    }
+    out
+}
+
+/// Reads diffs from the given files (or stdin) and prints per-file and
+/// per-extension line changes. Returns the exit code.
+pub fn run(args: &Args) -> i32 {
+    let mut text = String::new();
+    if args.files.is_empty() {
+        let mut buffer = Vec::new();
+        if let Err(e) = io::stdin().read_to_end(&mut buffer) {
+            eprintln!("kz: stdin: {}", e);
+            return 1;
+        }
+        text.push_str(&String::from_utf8_lossy(&buffer));
+    } else {
+        for path in &args.files {
+            match fs::read(path) {
+                Ok(data) => text.push_str(&String::from_utf8_lossy(&data)),
+                Err(e) => {
+                    eprintln!("kz: {}: {}", path, e);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    let files = parse(&text);
+    if args.json {
+        let report = serde_json::json!({
+            "files": files,
+            "extensions": by_extension(&files),
+            "total": total(&files),
+        });
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("kz: JSON serialization error: {}", e);
+                return 1;
+            }
+        }
+    } else {
+        print!("{}", render(&files, args.total_only));
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &str = "--- old/a.txt\t2024-01-01 00:00:00\n\
++++ new/a.txt\t2024-01-02 00:00:00\n\
+@@ -1,2 +1,2 @@\n\
+-one\n\
++uno\n\
+ two\n\
+--- old/b.txt\n\
++++ new/b.txt\n\
+@@ -3 +3,2 @@\n\
+ three\n\
++four\n";
+
+    #[test]
+    fn test_hunk_lengths() {
+        assert_eq!(hunk_lengths("@@ -1,3 +1,4 @@"), Some((3, 4)));
+        assert_eq!(hunk_lengths("@@ -1 +0,0 @@ fn main()"), Some((1, 0)));
+        assert_eq!(hunk_lengths("@@ bogus"), None);
+    }
+
+    #[test]
+    fn test_parse_plain_unified_diff() {
+        let files = parse(PLAIN);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file, "new/a.txt");
+        assert_eq!((files[0].added, files[0].removed), (1, 1));
+        assert_eq!(files[1].file, "new/b.txt");
+        assert_eq!((files[1].added, files[1].removed), (1, 0));
+        assert_eq!(files[1].change, Change::Modified);
+    }
+
+    #[test]
+    fn test_header_like_content_inside_hunk() {
+        let diff = "diff --git a/q.sql b/q.sql\n\
+--- a/q.sql\n\
++++ b/q.sql\n\
+@@ -1,2 +1,2 @@\n\
+--- comment\n\
++++ counter\n\
+ select 1;\n";
+        let files = parse(diff);
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].added, files[0].removed), (1, 1));
+    }
+
+    #[test]
+    fn test_by_extension_groups_files() {
+        let files = parse(PLAIN);
+        let extensions = by_extension(&files);
+        assert_eq!(extensions["txt"].files, 2);
+        assert_eq!(extensions["txt"].added, 2);
+        assert_eq!(total(&files).removed, 1);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::config::Args;
+use crate::count;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Only the start of each file is sampled; that is enough for the detector
+/// and keeps the audit fast on large trees.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+const BINARY: &str = "binary";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Detection {
+    pub file: String,
+    pub encoding: String,
+    pub bom: bool,
+}
+
+impl Detection {
+    /// Grouping label; BOM-bearing files are kept apart from their
+    /// BOM-less counterparts since a migration usually treats them differently.
+    fn label(&self) -> String {
+        if self.bom {
+            format!("{} (BOM)", self.encoding)
+        } else {
+            self.encoding.clone()
+        }
+    }
+
+    fn is_utf8(&self) -> bool {
+        self.encoding == encoding_rs::UTF_8.name() && !self.bom
+    }
+}
+
+pub fn classify(file: &str, sample: &[u8], complete: bool) -> Detection {
+    let (encoding, bom) = count::detect_encoding(sample, complete);
+    let encoding = if !bom && count::is_binary(sample) {
+        BINARY.to_string()
+    } else {
+        encoding.name().to_string()
+    };
+    Detection {
+        file: file.to_string(),
+        encoding,
+        bom,
+    }
+}
+
+fn sample_file(path: &str) -> io::Result<Detection> {
+    let mut sample = Vec::new();
+    let file = File::open(path)?;
+    let read = file.take(SAMPLE_SIZE + 1).read_to_end(&mut sample)?;
+    let complete = read as u64 <= SAMPLE_SIZE;
+    sample.truncate(SAMPLE_SIZE as usize);
+    Ok(classify(path, &sample, complete))
+}
+
+/// Files per encoding label, most common first.
+pub fn group(detections: &[Detection]) -> Vec<(String, usize)> {
+    let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+    for detection in detections {
+        *groups.entry(detection.label()).or_default() += 1;
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    groups
+}
+
+pub fn render(detections: &[Detection], verbose: bool) -> String {
+    let groups = group(detections);
+    let width = groups
+        .iter()
+        .map(|(label, _)| label.len())
+        .chain(std::iter::once("encoding".len()))
+        .max()
+        .unwrap_or(0);
+    let mut out = format!("{:<width$}  files\n", "encoding");
+    for (label, files) in &groups {
+        out.push_str(&format!("{:<width$}  {:>5}\n", label, files));
+    }
+    if verbose {
+        let mut others: Vec<&Detection> = detections.iter().filter(|d| !d.is_utf8()).collect();
+        if !others.is_empty() {
+            others.sort_by(|a, b| a.label().cmp(&b.label()).then_with(|| a.file.cmp(&b.file)));
+            out.push('\n');
+            for detection in others {
+                out.push_str(&format!(
+                    "{:<width$}  {}\n",
+                    detection.label(),
+                    detection.file
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Samples every file and prints the encoding table. Returns the exit code.
+pub fn run(files: &[String], args: &Args) -> i32 {
+    let results: Vec<_> = files.par_iter().map(|path| sample_file(path)).collect();
+    let mut detections = Vec::with_capacity(results.len());
+    let mut failed = 0;
+    for (path, result) in files.iter().zip(results) {
+        match result {
+            Ok(detection) => detections.push(detection),
+            Err(e) => {
+                eprintln!("kz: {}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if args.json {
+        let encodings: Vec<_> = group(&detections)
+            .into_iter()
+            .map(|(encoding, files)| serde_json::json!({ "encoding": encoding, "files": files }))
+            .collect();
+        let report = serde_json::json!({ "encodings": encodings, "files": detections });
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("kz: JSON serialization error: {}", e);
+                return 1;
+            }
+        }
+    } else {
+        print!("{}", render(&detections, args.verbose));
+    }
+
+    if failed > 0 { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_bom_and_binary() {
+        let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0];
+        let detection = classify("a", &utf16, true);
+        assert_eq!(detection.encoding, "UTF-16LE");
+        assert!(detection.bom);
+        assert_eq!(detection.label(), "UTF-16LE (BOM)");
+
+        assert_eq!(classify("b", b"\x7fELF\0\0", true).encoding, BINARY);
+        assert_eq!(classify("c", b"plain ascii", true).encoding, "UTF-8");
+    }
+
+    #[test]
+    fn test_truncated_sample_stays_utf8() {
+        let text = "日本語".as_bytes();
+        assert_eq!(classify("a", &text[..4], false).encoding, "UTF-8");
+    }
+
+    #[test]
+    fn test_group_and_verbose_listing() {
+        let detections = vec![
+            classify("a.txt", b"one", true),
+            classify("b.txt", b"two", true),
+            classify(
+                "c.txt",
+                b"caf\xe9 na\xefve r\xe9sum\xe9 d\xe9j\xe0 vu",
+                true,
+            ),
+        ];
+        let groups = group(&detections);
+        assert_eq!(groups[0], ("UTF-8".to_string(), 2));
+        assert_eq!(groups[1].1, 1);
+
+        let verbose = render(&detections, true);
+        assert!(verbose.contains("c.txt"));
+        assert!(!verbose.contains("a.txt"));
+        assert!(!render(&detections, false).contains("c.txt"));
+    }
+}
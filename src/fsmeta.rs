@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::path::Path;
+
+/// Owner, permission bits and modification time of one file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileMeta {
+    pub uid: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Permission bits in octal, e.g. `"644"`.
+    pub mode: String,
+    /// Seconds since the Unix epoch.
+    pub mtime: i64,
+    pub world_writable: bool,
+}
+
+impl FileMeta {
+    /// The user name when it resolves, otherwise the numeric uid.
+    pub fn owner_label(&self) -> String {
+        self.owner.clone().unwrap_or_else(|| self.uid.to_string())
+    }
+}
+
+#[cfg(unix)]
+pub fn read(metadata: &Metadata) -> Option<FileMeta> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mode = metadata.mode() & 0o7777;
+    Some(FileMeta {
+        uid: metadata.uid(),
+        owner: user_name(metadata.uid()),
+        mode: format!("{:o}", mode),
+        mtime: metadata.mtime(),
+        world_writable: mode & 0o002 != 0,
+    })
+}
+
+/// Ownership and permission bits are not surfaced on this platform.
+#[cfg(not(unix))]
+pub fn read(_metadata: &Metadata) -> Option<FileMeta> {
+    None
+}
+
+/// Bytes actually allocated on disk, which is less than the apparent size
+/// for sparse files and more for small files padded to whole blocks.
+#[cfg(unix)]
+pub fn allocated_bytes(_path: &Path, metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    // st_blocks is always in 512-byte units, whatever the filesystem block size.
+    Some(metadata.blocks() * 512)
+}
+
+#[cfg(windows)]
+pub fn allocated_bytes(path: &Path, _metadata: &Metadata) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    unsafe extern "system" {
+        fn GetCompressedFileSizeW(name: *const u16, high: *mut u32) -> u32;
+    }
+    const INVALID_FILE_SIZE: u32 = u32::MAX;
+
+    let path = crate::platform::extended_length(path);
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut high = 0u32;
+    // SAFETY: `wide` is NUL-terminated and `high` outlives the call.
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    if low == INVALID_FILE_SIZE && std::io::Error::last_os_error().raw_os_error() != Some(0) {
+        return None;
+    }
+    Some(((high as u64) << 32) | low as u64)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn allocated_bytes(_path: &Path, _metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Parses `/etc/passwd` lines into a uid to user name map.
+#[cfg(unix)]
+fn parse_passwd(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    static USERS: std::sync::OnceLock<HashMap<u32, String>> = std::sync::OnceLock::new();
+    USERS
+        .get_or_init(|| {
+            std::fs::read_to_string("/etc/passwd")
+                .map(|content| parse_passwd(&content))
+                .unwrap_or_default()
+        })
+        .get(&uid)
+        .cloned()
+}
+
+/// Distinct owners (with file counts) and world-writable files across a run.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub owners: BTreeMap<String, usize>,
+    pub world_writable: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, meta: &FileMeta) {
+        *self.owners.entry(meta.owner_label()).or_default() += 1;
+        if meta.world_writable {
+            self.world_writable += 1;
+        }
+    }
+
+    /// One line such as `owners: 2 (alice 10, bob 3), world-writable: 1`,
+    /// owners ordered by file count.
+    pub fn render(&self) -> String {
+        let mut owners: Vec<(&String, &usize)> = self.owners.iter().collect();
+        owners.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let listed: Vec<String> = owners
+            .iter()
+            .map(|(owner, files)| format!("{} {}", owner, files))
+            .collect();
+        format!(
+            "owners: {} ({}), world-writable: {}",
+            self.owners.len(),
+            listed.join(", "),
+            self.world_writable
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allocated_bytes_of_sparse_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse");
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+        let metadata = file.metadata().unwrap();
+        let allocated = allocated_bytes(&path, &metadata).unwrap();
+        assert!(allocated < metadata.len(), "allocated {}", allocated);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_passwd() {
+        let users = parse_passwd(
+            "# comment\nroot:x:0:0:root:/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\nbroken\n",
+        );
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[&1000], "alice");
+    }
+
+    #[test]
+    fn test_summary_render() {
+        let meta = |owner: &str, world_writable| FileMeta {
+            uid: 0,
+            owner: Some(owner.to_string()),
+            mode: "644".to_string(),
+            mtime: 0,
+            world_writable,
+        };
+        let mut summary = Summary::default();
+        summary.add(&meta("bob", false));
+        summary.add(&meta("alice", true));
+        summary.add(&meta("alice", false));
+        assert_eq!(
+            summary.render(),
+            "owners: 2 (alice 2, bob 1), world-writable: 1"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::config::Args;
+use flate2::read::MultiGzDecoder;
+use std::io::{self, Read};
+
+/// Every gzip member starts with these two bytes.
+const MAGIC: &[u8] = b"\x1f\x8b";
+
+/// Whether `path` is read through gzip when its content is gzip: `.gz`
+/// files unless `--no-decompress`, any file with `--decompress`.
+pub fn wanted(path: &str, args: &Args) -> bool {
+    args.decompress || (!args.no_decompress && path.ends_with(".gz"))
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Decompresses `data` into `out`, following concatenated members the way
+/// `gzip -d` does.
+pub fn decompress(data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+    MultiGzDecoder::new(data).read_to_end(out).map(|_| ())
+}
+
+/// Decompresses `reader` as it is read, for files streamed in blocks.
+pub fn reader(reader: impl Read) -> impl Read {
+    MultiGzDecoder::new(io::BufReader::new(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress_concatenated_members() {
+        let mut data = gzip(b"one\n");
+        data.extend(gzip(b"two\n"));
+        assert!(is_gzip(&data));
+        let mut out = Vec::new();
+        decompress(&data, &mut out).unwrap();
+        assert_eq!(out, b"one\ntwo\n");
+    }
+
+    #[test]
+    fn test_truncated_stream_is_an_error() {
+        let data = gzip(&b"line\n".repeat(100));
+        let mut out = Vec::new();
+        assert!(decompress(&data[..data.len() / 2], &mut out).is_err());
+        assert!(!is_gzip(b"plain text"));
+    }
+}
@@ -0,0 +1,34 @@
+use std::io::{self, Read};
+use std::time::Duration;
+
+const MAX_REDIRECTS: u32 = 5;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn fetch(url: &str, max_bytes: usize) -> io::Result<Vec<u8>> {
+    let agent = ureq::AgentBuilder::new()
+        .redirects(MAX_REDIRECTS)
+        .timeout(TIMEOUT)
+        .build();
+
+    let response = agent.get(url).call().map_err(|e| match e {
+        ureq::Error::Status(code, response) => {
+            io::Error::other(format!("HTTP {} {}", code, response.status_text()))
+        }
+        ureq::Error::Transport(t) => io::Error::other(t.to_string()),
+    })?;
+
+    let mut buffer = Vec::new();
+    response
+        .into_reader()
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut buffer)?;
+
+    if buffer.len() > max_bytes {
+        return Err(io::Error::other(format!(
+            "download exceeds --max-download limit of {} bytes",
+            max_bytes
+        )));
+    }
+
+    Ok(buffer)
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit status after Ctrl-C, as shells report for SIGINT (128 + 2).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the Ctrl-C handler for a file run. The first interrupt only
+/// raises a flag, so files already being counted finish and get reported;
+/// a second one exits at once.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })
+}
+
+/// Whether Ctrl-C was pressed; no new files start once it was.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// The marker printed with partial results.
+pub fn marker(processed: usize, total: usize) -> String {
+    format!(
+        "INTERRUPTED \u{2014} {} of {} files processed",
+        processed, total
+    )
+}
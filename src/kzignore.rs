@@ -0,0 +1,115 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::io;
+use std::path::Path;
+
+pub const FILE_NAME: &str = ".kzignore";
+
+pub struct KzIgnore {
+    ignore: GlobSet,
+    reinclude: GlobSet,
+}
+
+impl KzIgnore {
+    pub fn load(root: &Path) -> io::Result<Option<KzIgnore>> {
+        let path = root.join(FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        Self::parse(&content).map(Some).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<KzIgnore, globset::Error> {
+        let mut ignore = GlobSetBuilder::new();
+        let mut reinclude = GlobSetBuilder::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (builder, pattern) = match line.strip_prefix('!') {
+                Some(rest) => (&mut reinclude, rest),
+                None => (&mut ignore, line),
+            };
+            let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+            if !pattern.is_empty() {
+                builder.add(
+                    GlobBuilder::new(&anchored(pattern))
+                        .literal_separator(true)
+                        .build()?,
+                );
+            }
+        }
+
+        Ok(KzIgnore {
+            ignore: ignore.build()?,
+            reinclude: reinclude.build()?,
+        })
+    }
+
+    /// `relative` is the path of a file relative to the directory holding the
+    /// `.kzignore`. A pattern matching any ancestor directory applies to
+    /// everything below it.
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        let matches = |set: &GlobSet| {
+            relative
+                .ancestors()
+                .any(|p| !p.as_os_str().is_empty() && set.is_match(p))
+        };
+        matches(&self.ignore) && !matches(&self.reinclude)
+    }
+}
+
+/// Anchors a pattern as gitignore does: one with a slash is relative to the
+/// `.kzignore` directory, one without matches a name at any depth.
+fn anchored(pattern: &str) -> String {
+    match pattern.strip_prefix('/') {
+        Some(rooted) => rooted.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let ignore = KzIgnore::parse("# generated\n\n*.log\n").unwrap();
+        assert!(ignore.is_ignored(Path::new("app.log")));
+        assert!(!ignore.is_ignored(Path::new("app.txt")));
+    }
+
+    #[test]
+    fn test_directory_pattern() {
+        let ignore = KzIgnore::parse("vendor/\n").unwrap();
+        assert!(ignore.is_ignored(Path::new("vendor/lib/a.rs")));
+        assert!(!ignore.is_ignored(Path::new("src/vendor.rs")));
+    }
+
+    #[test]
+    fn test_negation() {
+        let ignore = KzIgnore::parse("*.log\n!keep.log\n").unwrap();
+        assert!(ignore.is_ignored(Path::new("logs/a.log")));
+        assert!(ignore.is_ignored(Path::new("sub/deep/x.log")));
+        assert!(!ignore.is_ignored(Path::new("keep.log")));
+        assert!(!ignore.is_ignored(Path::new("sub/deep/keep.log")));
+    }
+
+    #[test]
+    fn test_anchored_patterns() {
+        let ignore = KzIgnore::parse("/top.txt\ndocs/*.md\n").unwrap();
+        assert!(ignore.is_ignored(Path::new("top.txt")));
+        assert!(!ignore.is_ignored(Path::new("sub/top.txt")));
+        assert!(ignore.is_ignored(Path::new("docs/a.md")));
+        assert!(!ignore.is_ignored(Path::new("docs/api/a.md")));
+        assert!(!ignore.is_ignored(Path::new("sub/docs/a.md")));
+    }
+}
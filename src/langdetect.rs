@@ -0,0 +1,388 @@
+use crate::count::Trigrams;
+use serde::Serialize;
+
+/// Reported when a file has too little text or matches no profile.
+pub const UNKNOWN: &str = "unknown";
+
+/// Fewer letters than this are not enough to tell languages apart.
+pub const MIN_LETTERS: usize = 200;
+
+/// Best scores below this match no profile in any useful way.
+const MIN_SIMILARITY: f64 = 0.05;
+
+/// The language a file's text looks most like.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Guess {
+    /// ISO 639-1 code, or `unknown`.
+    pub language: &'static str,
+    /// Cosine similarity between the file's trigrams and the language's
+    /// profile, from 0 to 1.
+    pub confidence: f64,
+}
+
+struct Profile {
+    code: &'static str,
+    /// The most common trigrams in a sample of the language, most frequent
+    /// first; weights fall linearly with rank.
+    trigrams: &'static [&'static str],
+}
+
+const PROFILES: &[Profile] = &[
+    Profile {
+        code: "en",
+        trigrams: &[
+            " th", "the", "he ", "and", " an", "nd ", " to", " of", " wh", "of ", "re ", "to ",
+            " is", "at ", "ere", "es ", "hat", "her", "is ", "ll ", " be", " ca", " in", "ay ",
+            "en ", "ey ", "hey", "own", "tha", "ts ", "wn ", " fr", " wi", "ed ", "ent", "er ",
+            "ing", "ive", "me ", "ng ", "ome", "rs ", "th ", "ver", "whe", " a ", " ch", " it",
+            " li", " ma", " pa", " se", " st", "ds ", "ear", "ell", "ew ", "fro", "hou", "in ",
+            "ith", "ld ", "om ", "par", "rie", "rom", "ry ", "tor", "ve ", "wit", " al", " ba",
+            " br", " bu", " ci", " co", " ev", " fo", " gr", " ha", " me", " ne", " on", " ov",
+            " pe", " pl", " pr", " re", " sm", " so", " wa", " wo", " yo", "ace", "ade", "all",
+            "ame", "ant", "are", "ave",
+        ],
+    },
+    Profile {
+        code: "de",
+        trigrams: &[
+            "en ", "er ", "ie ", "nd ", "und", " di", "die", " de", " un", " si", "der", "es ",
+            "ich", "nen", "ten", " ei", " zu", "cht", "men", "rt ", "sch", "sen", " be", " da",
+            " ge", " ih", " st", "an ", "che", "ein", "em ", "hen", "hre", "nde", "sie", "zu ",
+            " is", " wi", "auf", "ch ", "das", "ern", "gen", "hte", "ihr", "ind", "ine", "ist",
+            "it ", "mme", "re ", "st ", "sta", "te ", " au", " es", " im", " ka", " ki", " le",
+            " mi", " ne", " we", "adt", "am ", "as ", "aus", "dem", "den", "dt ", "eit", "esc",
+            "ges", "kom", "mit", "neu", "omm", "ort", "sic", "sse", "str", "tad", "ufe", "ute",
+            " al", " am", " an", " br", " er", " in", " ja", " la", " ma", " me", " or", " ta",
+            " ve", " vo", " wo", "ach",
+        ],
+    },
+    Profile {
+        code: "fr",
+        trigrams: &[
+            "es ", " le", " de", "nt ", "le ", "ent", " et", "et ", "de ", "les", " vi", " qu",
+            "des", "est", "ont", "ven", " la", " on", " se", "ill", "la ", "our", "rs ", " en",
+            " es", "ant", "lle", "nts", "re ", "st ", "ts ", " co", " pa", " pr", " re", " un",
+            "and", "arc", "con", "end", "eur", "ien", "leu", "ns ", "oir", "ouv", "res", "se ",
+            "ses", "ui ", "un ", "ur ", "urs", "uve", "vil", " au", " ch", " il", " ma", " po",
+            " to", " y ", " à ", "aie", "au ", "ce ", "cha", "eau", "ir ", "ise", "it ", "jou",
+            "ndr", "on ", "out", "par", "que", "qui", "ren", "sen", "tou", "ue ", "ux ", " an",
+            " ap", " av", " be", " d ", " da", " di", " hi", " no", " où", " so", " ve", "ans",
+            "app", "ave", "bea", "com",
+        ],
+    },
+    Profile {
+        code: "es",
+        trigrams: &[
+            "os ", " co", " la", "as ", "es ", "que", " de", " y ", "de ", "la ", " el", " qu",
+            "el ", "se ", "ue ", " se", "con", "los", " en", " lo", " pa", "en ", "nte", "on ",
+            "do ", "ent", "er ", " al", " ca", " pr", " su", "an ", "ant", "com", "les", "nde",
+            "pre", "sus", "tes", "us ", " ci", " es", " hi", " po", " un", " vi", "ad ", "cid",
+            "ciu", "dad", "eci", "ias", "iud", "las", "lle", "nue", "ori", "par", "pas", "por",
+            "res", "ria", "sta", "tra", "uda", "uev", "un ", " a ", " an", " di", " do", " ha",
+            " ll", " lu", " mu", " nu", " pe", " pu", " si", " to", " tr", " ve", "aba", "ado",
+            "alg", "all", "ar ", "ara", "asa", "cen", "da ", "der", "die", "don", "dos", "ell",
+            "end", "erc", "ero", "est",
+        ],
+    },
+    Profile {
+        code: "it",
+        trigrams: &[
+            " e ", "no ", "la ", " co", " di", "to ", " de", " la", "ti ", " ch", " vi", "ato",
+            "con", "le ", "re ", "tor", " ci", " i ", " il", " le", " pa", " pe", " è ", "ann",
+            "che", "di ", "he ", "ia ", "il ", "per", " se", " st", " un", "del", "el ", "ent",
+            "erc", "ese", "iva", "lla", "nno", "nte", "ono", "ori", "ove", "ro ", "se ", "sto",
+            "str", "te ", "un ", "ve ", " da", " ha", " lo", " ne", " po", " pr", " qu", " si",
+            " su", " ve", "ano", "are", "chi", "cit", "da ", "ei ", "er ", "ggi", "gli", "ie ",
+            "itt", "lor", "mer", "nti", "ont", "oro", "par", "pre", "ran", "res", "riv", "si ",
+            "so ", "son", "tat", "tra", "ttà", "tà ", "ven", " ar", " be", " ca", " do", " fi",
+            " in", " me", " mo", " nu",
+        ],
+    },
+    Profile {
+        code: "pt",
+        trigrams: &[
+            "as ", "os ", " se", "de ", " co", " e ", "es ", " de", "que", " pa", " pe", "am ",
+            "da ", " o ", " qu", "com", "nte", "ue ", " vi", "cid", "do ", "ida", "par", "res",
+            "ria", "se ", " a ", " da", " no", " pr", " um", "ant", "ar ", "ara", "con", "dos",
+            "em ", "ent", "er ", "est", "ia ", "las", "nde", "pel", "pre", "ram", "tes", " al",
+            " ao", " ca", " ci", " do", " es", " fi", " mu", " os", " to", " ve", "ade", "ado",
+            "dad", "ela", "eus", "ica", "mui", "nov", "ont", "ra ", "ro ", "seu", "sta", "str",
+            "tar", "tra", "uit", "um ", "us ", " an", " as", " be", " ch", " cr", " di", " hi",
+            " lu", " on", " po", " tr", " é ", "ada", "ao ", "ass", "cas", "che", "der", "eci",
+            "eia", "elo", "end", "ess",
+        ],
+    },
+    Profile {
+        code: "nl",
+        trigrams: &[
+            "en ", "de ", " de", " en", " he", "et ", "den", "nde", " me", " te", "aar", "an ",
+            "ar ", "der", "ede", "gen", "het", " be", " ee", " va", " ve", "een", "er ", "ere",
+            "is ", "len", "ren", "ver", " da", " in", " is", " st", "eid", "end", "ie ", "men",
+            "oor", "sta", "te ", "ten", "van", " di", " ha", " hu", " je", " om", " ui", " wa",
+            " wo", " ze", " zi", "ad ", "at ", "dat", "die", "ege", "euw", "ied", "ien", "in ",
+            "ind", "it ", "je ", "ken", "lle", "met", "om ", "oop", "tad", "ts ", "uit", "ze ",
+            " aa", " al", " do", " er", " gr", " ie", " ki", " ko", " kw", " lo", " ne", " ni",
+            " op", " we", "aan", "ag ", "and", "are", "del", "ek ", "eke", "ele", "elk", "ens",
+            "erk", "ers", "ete", "gge",
+        ],
+    },
+    Profile {
+        code: "sv",
+        trigrams: &[
+            "na ", "en ", " oc", "ch ", "och", " de", "om ", "är ", " si", "er ", "tt ", " at",
+            " fö", "att", "de ", "et ", "för", " st", "kom", "ns ", "rna", "som", "sta", "ör ",
+            " be", " dä", " en", " ko", " so", " vä", "ar ", "den", "det", "där", "ens", "ig ",
+            "ina", "or ", "sin", "ten", " bo", " ma", " me", " på", " är", "ade", "and", "ed ",
+            "ill", "kor", "la ", "med", "män", "nde", "på ", "sig", "tad", "ts ", "änn", "ätt",
+            " al", " ar", " av", " ba", " da", " fr", " ga", " ha", " kä", " la", " li", " mä",
+            " ny", " sä", " ti", " va", " vi", "ag ", "all", "an ", "are", "arn", "ats", "av ",
+            "bar", "ber", "bes", "dag", "dem", "els", "em ", "ern", "erä", "est", "frå", "fte",
+            "ga ", "gen", "ger", "id ",
+        ],
+    },
+    Profile {
+        code: "pl",
+        trigrams: &[
+            " i ", " mi", "dzi", "ch ", "prz", "sta", " je", " kt", " pr", " si", " w ", " z ",
+            "ię ", "któ", "rzy", "się", "tór", "zie", "ast", "est", "ia ", "mie", "rze", "wie",
+            " do", " dz", " wi", " za", "ego", "eni", "go ", "ie ", "jes", "nia", "st ", "ych",
+            "ym ", " sw", "ali", "ami", "ać ", "ce ", "dy ", "ejs", "esz", "ias", "iec", "iej",
+            "je ", "li ", "mi ", "mia", "na ", "now", "ost", "owi", "sto", "swo", "to ", "ze ",
+            "zen", "ów ", "ła ", "ści", " cz", " ka", " ko", " lu", " na", " ni", " no", " op",
+            " os", " po", " sp", " te", " to", " ty", " ze", " zo", " że", "ach", "acz", "ają",
+            "ary", "ałe", "by ", "cie", "cio", "cze", "do ", "eci", "edz", "ej ", "em ", "ich",
+            "idz", "ied", "iel", "ies",
+        ],
+    },
+    Profile {
+        code: "ru",
+        trigrams: &[
+            " и ", "то ", "ли ", "ми ", "тор", " го", " пр", "сто", "ото", "ся ", " ко", " чт",
+            "ест", "оро", "что", " в ", " до", " на", " по", "да ", "их ", "кот", "ода", "ост",
+            "род", "ть ", " ве", " вс", " де", " из", " ра", " ре", " с ", " св", " ст", " то",
+            " эт", "ать", "вои", "гор", "дет", "ды ", "енн", "или", "лен", "на ", "ое ", "ом ",
+            "при", "про", "рас", "сво", "стр", "ти ", "тся", "ые ", "ыми", " гд", " др", " жи",
+            " ис", " ка", " лю", " ме", " мо", " не", " но", " со", "ажд", "ам ", "ами", "ары",
+            "аст", "ах ", "ая ", "бы ", "все", "вст", "вят", "гда", "где", "го ", "гов", "год",
+            "де ", "дом", "дор", "дру", "еле", "етс", "жел", "жив", "зна", "из ", "ист", "ия ",
+            "каж", "ла ", "люд", "мес",
+        ],
+    },
+    Profile {
+        code: "ja",
+        trigrams: &[
+            " の ",
+            " 人 ",
+            " 来 ",
+            " 町 ",
+            "した ",
+            "ました",
+            " から",
+            " が ",
+            " や ",
+            " を ",
+            " 住 ",
+            " 分 ",
+            " 場 ",
+            " 所 ",
+            " 自 ",
+            "から ",
+            "たちは",
+            "ます ",
+            " い ",
+            " いつ",
+            " この",
+            " しい",
+            " たち",
+            " と ",
+            " ども",
+            " に ",
+            " は ",
+            " られ",
+            " り ",
+            " んで",
+            " 作 ",
+            " 切 ",
+            " 商 ",
+            " 子 ",
+            " 新 ",
+            " 業 ",
+            " 聞 ",
+            " 話 ",
+            " 通 ",
+            " 道 ",
+            "います",
+            "いる ",
+            "こに ",
+            "この ",
+            "しい ",
+            "ちは ",
+            "って ",
+            "です ",
+            "どもた",
+            "もたち",
+            "やって",
+            "られて",
+            "りまし",
+            "れてい",
+            "んでい",
+            " 々で",
+            " 々は",
+            " いて",
+            " いま",
+            " う ",
+            " がど",
+            " がや",
+            " くが",
+            " くと",
+            " こえ",
+            " ここ",
+            " さな",
+            " され",
+            " しく",
+            " して",
+            " そこ",
+            " そし",
+            " その",
+            " たい",
+            " たの",
+            " だれ",
+            " ち ",
+            " て ",
+            " であ",
+            " です",
+            " では",
+            " な ",
+            " なの",
+            " にな",
+            " には",
+            " のに",
+            " のほ",
+            " はに",
+            " ぶこ",
+            " まし",
+            " また",
+            " まり",
+            " むこ",
+            " もの",
+            " りを",
+            " るた",
+            " るも",
+            " れた",
+            " れな",
+            " レス",
+        ],
+    },
+    Profile {
+        code: "zh",
+        trigrams: &[
+            " 的 ", " 这 ", " 们 ", " 里 ", " 来 ", " 一 ", " 个 ", " 人 ", " 到 ", " 在 ", " 是 ",
+            " 他 ", " 以 ", " 和 ", " 上 ", " 了 ", " 可 ", " 听 ", " 地 ", " 城 ", " 市 ", " 中 ",
+            " 为 ", " 事 ", " 些 ", " 从 ", " 会 ", " 多 ", " 天 ", " 子 ", " 孩 ", " 工 ", " 己 ",
+            " 常 ", " 座 ", " 总 ", " 成 ", " 故 ", " 新 ", " 方 ", " 有 ", " 自 ", " 要 ", " 路 ",
+            " 闻 ", " 饭 ", " 馆 ", " 万 ", " 下 ", " 不 ", " 世 ", " 业 ", " 东 ", " 丽 ", " 乐 ",
+            " 九 ", " 也 ", " 于 ", " 今 ", " 传 ", " 住 ", " 你 ", " 修 ", " 候 ", " 做 ", " 全 ",
+            " 公 ", " 其 ", " 再 ", " 决 ", " 出 ", " 十 ", " 千 ", " 历 ", " 去 ", " 友 ", " 发 ",
+            " 受 ", " 古 ", " 史 ", " 各 ", " 名 ", " 味 ", " 咖 ", " 哪 ", " 售 ", " 商 ", " 啡 ",
+            " 因 ", " 园 ", " 国 ", " 堂 ", " 大 ", " 好 ", " 始 ", " 学 ", " 定 ", " 客 ", " 小 ",
+            " 居 ",
+        ],
+    },
+];
+
+/// Scores the trigrams against every built-in profile and keeps the best.
+pub fn detect(trigrams: &Trigrams) -> Guess {
+    let unknown = Guess {
+        language: UNKNOWN,
+        confidence: 0.0,
+    };
+    if trigrams.letters < MIN_LETTERS {
+        return unknown;
+    }
+    let norm = |values: &mut dyn Iterator<Item = f64>| values.map(|v| v * v).sum::<f64>().sqrt();
+    let text_norm = norm(&mut trigrams.counts.values().map(|&c| c as f64));
+    if text_norm == 0.0 {
+        return unknown;
+    }
+    PROFILES
+        .iter()
+        .map(|profile| {
+            let ranks = profile.trigrams.len();
+            let weight = |rank: usize| (ranks - rank) as f64;
+            let dot: f64 = profile
+                .trigrams
+                .iter()
+                .enumerate()
+                .map(|(rank, trigram)| {
+                    let mut chars = trigram.chars();
+                    let key = [(); 3].map(|_| chars.next().unwrap_or(' '));
+                    trigrams.counts.get(&key).copied().unwrap_or(0) as f64 * weight(rank)
+                })
+                .sum();
+            let profile_norm = norm(&mut (0..ranks).map(weight));
+            Guess {
+                language: profile.code,
+                confidence: dot / (text_norm * profile_norm),
+            }
+        })
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+        .filter(|best| best.confidence >= MIN_SIMILARITY)
+        .unwrap_or(unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::{TuningParams, char_trigrams};
+
+    fn guess(text: &str) -> &'static str {
+        detect(&char_trigrams(text.as_bytes(), TuningParams::serial())).language
+    }
+
+    #[test]
+    fn test_profiles_are_well_formed() {
+        for profile in PROFILES {
+            assert_eq!(profile.trigrams.len(), 100, "{}", profile.code);
+            assert!(profile.trigrams.iter().all(|t| t.chars().count() == 3));
+        }
+    }
+
+    #[test]
+    fn test_detects_english() {
+        let text = "Software engineers spend a large part of their time reading code that was written by other people. A clear name for a function or a variable can save hours of work, and a short comment that explains why something was done is often worth more than a long description of what the code does. When a project grows, the team has to agree on the way they write and review changes, so that everyone can understand the history of the work.";
+        assert_eq!(guess(text), "en");
+    }
+
+    #[test]
+    fn test_detects_german() {
+        let text = "Softwareentwickler verbringen einen großen Teil ihrer Zeit damit, Code zu lesen, den andere Menschen geschrieben haben. Ein klarer Name für eine Funktion oder eine Variable kann Stunden an Arbeit sparen, und ein kurzer Kommentar, der erklärt, warum etwas gemacht wurde, ist oft mehr wert als eine lange Beschreibung dessen, was der Code tut. Wenn ein Projekt wächst, muss sich das Team darauf einigen, wie es Änderungen schreibt und prüft.";
+        assert_eq!(guess(text), "de");
+    }
+
+    #[test]
+    fn test_detects_japanese() {
+        let text = "ソフトウェアの開発者は、ほかの人が書いたコードを読むことに多くの時間を使っています。関数や変数にわかりやすい名前をつけると、何時間もの作業を減らすことができます。また、なぜそのように書いたのかを説明する短いコメントは、コードが何をしているのかを長く説明するよりも役に立つことがよくあります。プロジェクトが大きくなると、チームは変更の書き方や確認の仕方について話し合って決める必要があります。そうすれば、新しく入った人もすぐに仕事を始めることができるでしょう。";
+        assert_eq!(guess(text), "ja");
+    }
+
+    #[test]
+    fn test_detects_french() {
+        let text = "Les développeurs passent une grande partie de leur temps à lire du code écrit par d'autres personnes. Un nom clair pour une fonction ou une variable peut faire gagner des heures de travail, et un court commentaire qui explique pourquoi une chose a été faite vaut souvent plus qu'une longue description de ce que fait le code. Quand un projet grandit, l'équipe doit se mettre d'accord sur la manière dont elle écrit et relit les changements.";
+        assert_eq!(guess(text), "fr");
+    }
+
+    #[test]
+    fn test_short_text_is_unknown() {
+        assert_eq!(
+            guess("The quick brown fox jumps over the lazy dog."),
+            UNKNOWN
+        );
+        let guessed = detect(&char_trigrams(b"", TuningParams::serial()));
+        assert_eq!(guessed.confidence, 0.0);
+    }
+
+    #[test]
+    fn test_text_without_letters_is_unknown() {
+        assert_eq!(guess(&"1234 5678 ;; {} ()\n".repeat(50)), UNKNOWN);
+    }
+}
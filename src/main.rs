@@ -1,26 +1,86 @@
+mod autofilter;
+mod buffers;
+mod canonical;
+mod category;
+mod check;
+mod color;
+mod compare;
 mod config;
 mod count;
-
+mod counter;
+mod diagnostics;
+mod diff;
+mod encodings;
+mod fsmeta;
+mod gzip;
+#[cfg(feature = "http")]
+mod http;
+mod interrupt;
+mod kzignore;
+mod langdetect;
+mod output;
+mod pathcase;
+mod platform;
+mod progress;
+mod report;
+mod sample;
+mod serve;
+mod sniff;
+mod spill;
+mod stream;
+mod summary;
+mod timestamp;
+mod tree;
+mod trend;
+mod verify;
+mod watch;
+
+// Unit tests install a counting wrapper around it instead; see `buffers`.
+#[cfg(not(test))]
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-use clap::{CommandFactory, Parser};
 use clap_complete::generate;
 use encoding_rs::Encoding;
-use globset::{Glob, GlobSetBuilder};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use memmap2::MmapOptions;
 use rayon::prelude::*;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
-use std::io::{self, Read, Write};
-use std::path::Path;
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use walkdir::WalkDir;
 
 const MAX_WALKDIR_DEPTH: usize = 100;
+const VCS_DIRS: [&str; 4] = [".git", ".hg", ".svn", ".jj"];
+const LINE_LENGTH_LISTING_LIMIT: usize = 5;
+const WIDTH_VIOLATION_LIMIT: usize = 5;
+/// Exit status when `--verify` finds the parallel and serial counts differ.
+const EXIT_VERIFY_MISMATCH: i32 = 3;
+
+/// Average adult silent reading speed, for `--reading-time`.
+const WORDS_READ_PER_MINUTE: f64 = 238.0;
+
+/// Unique words over words, 0 for a file without words.
+fn type_token_ratio(unique_words: usize, words: usize) -> f64 {
+    if words == 0 {
+        0.0
+    } else {
+        unique_words as f64 / words as f64
+    }
+}
+
+/// Fixed-key hasher for `--unique-lines`, so equal lines hash alike in
+/// every file.
+const LINE_HASHER: std::hash::BuildHasherDefault<std::hash::DefaultHasher> =
+    std::hash::BuildHasherDefault::new();
+
+/// Appended to file rows that `--total-of` or `--exclude-from-total` leave
+/// out of the total.
+const EXCLUDED_MARKER: &str = " (not in total)";
 
 #[derive(Serialize)]
 struct Counts {
@@ -29,13 +89,382 @@ struct Counts {
     bytes: usize,
     chars: usize,
     max_line_length: usize,
+    /// Line number of the first line `max_line_length` long, for the
+    /// `--summary` panel; 0 when not measured.
+    #[serde(skip)]
+    longest_line: usize,
+    /// `--max-line-width`: the longest line in display columns.
+    max_line_width: usize,
+    max_word_length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_bytes: Option<usize>,
+    /// Size on disk of gzip input; `bytes` counts it decompressed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compressed_bytes: Option<usize>,
     blank_lines: usize,
+    empty_lines: usize,
+    whitespace_only_lines: usize,
+    trailing_whitespace_lines: usize,
+    /// `--reading-time` estimate from the word count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reading_time_seconds: Option<f64>,
+    /// `--ttr`: unique words over words. Totals recompute it from the
+    /// summed counts rather than averaging per-file ratios.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    type_token_ratio: Option<f64>,
+    /// Best `--lang-detect` match, with its confidence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_confidence: Option<f64>,
+    /// Files per detected language, for the total.
+    #[serde(skip)]
+    languages: BTreeMap<&'static str, usize>,
+    /// Matches of `--pattern`, summed over all patterns when several are
+    /// given.
     pattern: usize,
+    /// Lines holding at least one `--pattern` match, with
+    /// `--count-matching-lines`.
+    matching_lines: usize,
+    /// Matches per pattern text, with more than one `--pattern`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    patterns: BTreeMap<String, usize>,
     unique_words: usize,
+    repeated_lines: usize,
+    unique_lines: usize,
+    /// Hashes of the distinct lines, so the total counts lines distinct
+    /// across files rather than summing per-file counts.
+    #[serde(skip)]
+    line_hashes: Option<HashSet<u64>>,
+    /// `--duplicate-lines`: distinct lines seen more than once, and the
+    /// occurrences past the first of each.
+    duplicate_lines: usize,
+    redundant_lines: usize,
+    line_length_cardinality: usize,
+    #[serde(skip_serializing_if = "is_unlisted_line_lengths")]
+    line_lengths: Option<BTreeMap<usize, usize>>,
+    nonconforming_lines: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nonconforming_line_numbers: Vec<usize>,
+    /// Counters kept by name, for those `counter::COUNTERS` stores itself.
+    #[serde(flatten)]
+    registered: BTreeMap<&'static str, usize>,
+    /// U+FEFF after the start of the input; removed first with `--strip-feff`.
+    feff: usize,
+    #[serde(skip)]
+    feff_offset: Option<usize>,
+    graphemes: usize,
+    prose_words: usize,
+    sentences: usize,
+    paragraphs: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     statistics: Option<Statistics>,
     #[serde(skip_serializing_if = "Option::is_none")]
     histogram: Option<HashMap<usize, usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    indentation: Option<Indentation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    top_lines: Vec<TopLine>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prefix_groups: Vec<PrefixGroup>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    top_words: Vec<TopWord>,
+    /// `--group-by-timestamp` buckets in date order, unparsed lines last.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dates: Vec<DateGroup>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<Truncation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding_fallback: Option<EncodingFallback>,
+    #[serde(flatten)]
+    normalize: Option<NormalizeImpact>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_endings: Option<LineEndingReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_breakdown: Option<CodeLines>,
+    #[serde(skip)]
+    line_frequencies: Option<HashMap<String, count::LineTally>>,
+    #[serde(skip)]
+    prefix_frequencies: Option<HashMap<String, usize>>,
+    /// `--debug-internals` report, printed once the input is counted.
+    #[serde(skip)]
+    diagnostics: Option<Box<diagnostics::Diagnostics>>,
+    #[serde(skip)]
+    date_buckets: Option<HashMap<Option<timestamp::Date>, count::DayCounts>>,
+    #[serde(skip)]
+    word_frequencies: Option<HashMap<String, usize>>,
+    #[serde(skip)]
+    vocabulary: Option<BTreeSet<String>>,
+    #[serde(skip)]
+    mismatches: Vec<verify::Mismatch>,
+    /// `--check` directives, read while the input is counted.
+    #[serde(skip)]
+    directives: Option<check::Scan>,
+    #[serde(skip)]
+    raw: Option<Box<Counts>>,
+}
+
+/// What `--normalize-check lf` would change: CRLF pairs and bare CRs both
+/// become LF, and each CRLF conversion drops one byte.
+#[derive(Serialize, Clone, Copy, Default)]
+struct NormalizeImpact {
+    crlf_to_convert: usize,
+    cr_to_convert: usize,
+    bytes_saved: usize,
+}
+
+impl NormalizeImpact {
+    fn to_lf(endings: count::LineEndings) -> Self {
+        Self {
+            crlf_to_convert: endings.crlf,
+            cr_to_convert: endings.cr,
+            bytes_saved: endings.crlf,
+        }
+    }
+
+    fn needed(&self) -> bool {
+        self.crlf_to_convert > 0 || self.cr_to_convert > 0
+    }
+}
+
+/// `--line-endings` counts by terminator style.
+#[derive(Serialize, Clone, Copy)]
+struct LineEndingReport {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+    mixed: bool,
+}
+
+impl From<count::LineEndings> for LineEndingReport {
+    fn from(endings: count::LineEndings) -> Self {
+        LineEndingReport {
+            lf: endings.lf,
+            crlf: endings.crlf,
+            cr: endings.cr,
+            mixed: endings.is_mixed(),
+        }
+    }
+}
+
+/// `--cloc` line counts by kind.
+#[derive(Serialize, Clone, Copy)]
+struct CodeLines {
+    code: usize,
+    comment: usize,
+    blank: usize,
+}
+
+impl From<count::CodeBreakdown> for CodeLines {
+    fn from(breakdown: count::CodeBreakdown) -> Self {
+        CodeLines {
+            code: breakdown.code,
+            comment: breakdown.comment,
+            blank: breakdown.blank,
+        }
+    }
+}
+
+/// Sampling details for `--sample-files`: how many files were counted and
+/// 95% intervals for the scaled line and byte totals.
+struct SampleReport {
+    files: usize,
+    population: usize,
+    seed: u64,
+    lines: Option<sample::Estimate>,
+    bytes: Option<sample::Estimate>,
+}
+
+impl SampleReport {
+    fn to_json(&self) -> serde_json::Value {
+        let interval = |e: &Option<sample::Estimate>| {
+            e.map(|e| serde_json::json!([e.low.round() as u64, e.high.round() as u64]))
+        };
+        let mut confidence = serde_json::Map::new();
+        if let Some(lines) = interval(&self.lines) {
+            confidence.insert("lines".to_string(), lines);
+        }
+        if let Some(bytes) = interval(&self.bytes) {
+            confidence.insert("bytes".to_string(), bytes);
+        }
+        serde_json::json!({
+            "files": self.files,
+            "population": self.population,
+            "fraction": self.files as f64 / self.population.max(1) as f64,
+            "seed": self.seed,
+            "confidence_95": confidence,
+        })
+    }
+
+    fn render(&self) -> String {
+        let intervals: Vec<String> = [("lines", self.lines), ("bytes", self.bytes)]
+            .into_iter()
+            .filter_map(|(name, estimate)| {
+                estimate.map(|e| format!("{} {:.0}-{:.0}", name, e.low, e.high))
+            })
+            .collect();
+        let mut out = format!(
+            "estimated from {} of {} files (seed {})",
+            self.files, self.population, self.seed
+        );
+        if !intervals.is_empty() {
+            out.push_str(&format!("; 95% CI {}", intervals.join(", ")));
+        }
+        out
+    }
+}
+
+/// How an input was cut down before counting, so partial counts are not
+/// mistaken for whole-file ones.
+#[derive(Serialize, Clone, Copy)]
+struct Truncation {
+    mode: &'static str,
+    amount: usize,
+}
+
+/// Applies `--head`, `--tail` or `--head-bytes`, returning the kept slice
+/// and a record of the cut when anything was actually dropped.
+fn truncate_input<'a>(data: &'a [u8], args: &config::Args) -> (&'a [u8], Option<Truncation>) {
+    let (kept, mode, amount) = if let Some(n) = args.head {
+        (count::head_lines(data, n), "head", n)
+    } else if let Some(n) = args.tail {
+        (count::tail_lines(data, n), "tail", n)
+    } else if let Some(n) = args.head_bytes {
+        (&data[..n.min(data.len())], "head-bytes", n)
+    } else {
+        return (data, None);
+    };
+    let truncation = (kept.len() < data.len()).then_some(Truncation { mode, amount });
+    (kept, truncation)
+}
+
+/// An auto-detected encoding that was overruled in favour of UTF-8 because
+/// the input was almost entirely valid UTF-8.
+#[derive(Serialize, Clone, Copy)]
+struct EncodingFallback {
+    detected: &'static str,
+    used: &'static str,
+}
+
+#[derive(Serialize)]
+struct TopLine {
+    count: usize,
+    line: String,
+}
+
+#[derive(Serialize)]
+struct TopWord {
+    word: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct PrefixGroup {
+    prefix: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct DateGroup {
+    date: String,
+    lines: usize,
+    bytes: usize,
+}
+
+/// `--group-by-timestamp` bucket for lines without a date.
+const UNPARSED_DATE: &str = "unparsed";
+
+/// Longest date range whose sparkline shows days without lines as gaps;
+/// wider ranges get one mark per date present.
+const SPARKLINE_MAX_DAYS: i64 = 366;
+
+/// Lines per day from the first date to the last, for the sparkline.
+fn daily_lines(buckets: &HashMap<Option<timestamp::Date>, count::DayCounts>) -> Vec<usize> {
+    let mut dated: Vec<(i64, usize)> = buckets
+        .iter()
+        .filter_map(|(date, day)| date.map(|d| (d.days(), day.lines)))
+        .collect();
+    dated.sort_unstable();
+    let (Some(&(first, _)), Some(&(last, _))) = (dated.first(), dated.last()) else {
+        return Vec::new();
+    };
+    if last - first >= SPARKLINE_MAX_DAYS {
+        return dated.into_iter().map(|(_, lines)| lines).collect();
+    }
+    let mut per_day = vec![0; (last - first + 1) as usize];
+    for (day, lines) in dated {
+        per_day[(day - first) as usize] = lines;
+    }
+    per_day
+}
+
+/// Prefix groups listed in text output; `--json` lists every group.
+const PREFIX_GROUPS_SHOWN: usize = 10;
+
+/// Places the file name (or `total` label) after the counts, before them
+/// with `--filename-first` (padded to `name_width`), or drops it with
+/// `--no-filename`.
+fn label_row(args: &config::Args, values: String, name: &str, name_width: usize) -> String {
+    if name.is_empty() || args.no_filename {
+        values
+    } else if args.filename_first {
+        format!("{:<name_width$} {}", name, values)
+    } else {
+        format!("{} {}", values, name)
+    }
+}
+
+/// Prints `--verify` mismatches as internal-error diagnostics and returns
+/// whether there were any.
+fn report_mismatches(name: &str, counts: &Counts) -> bool {
+    for mismatch in &counts.mismatches {
+        eprintln!(
+            "kz: internal error: {}: {} differs between parallel ({}) and serial ({}) counting; please report this",
+            name, mismatch.counter, mismatch.parallel, mismatch.serial
+        );
+    }
+    !counts.mismatches.is_empty()
+}
+
+/// Warns that `name` was counted as UTF-8 although detection guessed
+/// otherwise, since char and word counts depend on the choice.
+fn report_encoding_fallback(name: &str, counts: &Counts) {
+    if let Some(fallback) = counts.encoding_fallback {
+        eprintln!(
+            "kz: warning: {}: detected {} but the input is almost entirely valid UTF-8; counting as {} (--trust-detection keeps {})",
+            name, fallback.detected, fallback.used, fallback.detected
+        );
+    }
+}
+
+/// Under `--verbose`, points at the first U+FEFF found after the start of
+/// `name`; it is invisible in an editor and splits the word it precedes.
+fn report_feff(name: &str, counts: &Counts, args: &config::Args) {
+    if args.verbose
+        && let Some(offset) = counts.feff_offset
+    {
+        eprintln!(
+            "kz: warning: {}: {} U+FEFF after the start of the input, first at byte {}{}",
+            name,
+            counts.feff,
+            offset,
+            if args.strip_feff { " (stripped)" } else { "" }
+        );
+    }
+}
+
+fn truncate_line(line: &str, max_chars: usize) -> String {
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+    let kept: String = line.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
+
+fn is_unlisted_line_lengths(lengths: &Option<BTreeMap<usize, usize>>) -> bool {
+    lengths
+        .as_ref()
+        .is_none_or(|l| l.len() > LINE_LENGTH_LISTING_LIMIT)
 }
 
 #[derive(Serialize)]
@@ -46,6 +475,28 @@ struct Statistics {
     min_line_length: usize,
     max_line_length: usize,
     empty_lines: usize,
+    mean_word_length: f64,
+    median_word_length: usize,
+    max_word_length: usize,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct Indentation {
+    tab_lines: usize,
+    space_lines: usize,
+    mixed_lines: usize,
+    max_depth: usize,
+}
+
+impl From<count::IndentStats> for Indentation {
+    fn from(stats: count::IndentStats) -> Self {
+        Indentation {
+            tab_lines: stats.tab_lines,
+            space_lines: stats.space_lines,
+            mixed_lines: stats.mixed_lines,
+            max_depth: stats.max_depth,
+        }
+    }
 }
 
 impl Counts {
@@ -56,83 +507,605 @@ impl Counts {
             bytes: 0,
             chars: 0,
             max_line_length: 0,
+            longest_line: 0,
+            max_line_width: 0,
+            max_word_length: 0,
+            disk_bytes: None,
+            compressed_bytes: None,
+            reading_time_seconds: None,
+            type_token_ratio: None,
+            language: None,
+            language_confidence: None,
+            languages: BTreeMap::new(),
             blank_lines: 0,
+            empty_lines: 0,
+            whitespace_only_lines: 0,
+            trailing_whitespace_lines: 0,
             pattern: 0,
+            patterns: BTreeMap::new(),
+            matching_lines: 0,
             unique_words: 0,
+            repeated_lines: 0,
+            unique_lines: 0,
+            line_hashes: None,
+            duplicate_lines: 0,
+            redundant_lines: 0,
+            line_length_cardinality: 0,
+            line_lengths: None,
+            nonconforming_lines: 0,
+            nonconforming_line_numbers: Vec::new(),
+            registered: counter::COUNTERS
+                .iter()
+                .filter(|counter| matches!(counter.storage, counter::Storage::Registered(_)))
+                .map(|counter| (counter.name, 0))
+                .collect(),
+            feff: 0,
+            feff_offset: None,
+            graphemes: 0,
+            prose_words: 0,
+            sentences: 0,
+            paragraphs: 0,
             statistics: None,
+            indentation: None,
             histogram: None,
+            top_lines: Vec::new(),
+            prefix_groups: Vec::new(),
+            dates: Vec::new(),
+            top_words: Vec::new(),
+            truncated: None,
+            encoding_fallback: None,
+            normalize: None,
+            line_endings: None,
+            code_breakdown: None,
+            line_frequencies: None,
+            prefix_frequencies: None,
+            diagnostics: None,
+            date_buckets: None,
+            word_frequencies: None,
+            vocabulary: None,
+            mismatches: Vec::new(),
+            directives: None,
+            raw: None,
         }
     }
 
-    fn add(&mut self, other: &Counts) {
-        self.lines += other.lines;
-        self.words += other.words;
-        self.bytes += other.bytes;
-        self.chars += other.chars;
-        self.max_line_length = self.max_line_length.max(other.max_line_length);
-        self.blank_lines += other.blank_lines;
-        self.pattern += other.pattern;
-        self.unique_words += other.unique_words;
+    /// Scales the additive counters by `factor` for sampled estimates;
+    /// maxima such as the longest line are left alone.
+    fn scale(&mut self, factor: f64) {
+        for counter in counter::COUNTERS {
+            counter.scale(self, factor);
+        }
+        for value in [&mut self.pattern, &mut self.matching_lines] {
+            *value = (*value as f64 * factor).round() as usize;
+        }
+        for value in self.patterns.values_mut() {
+            *value = (*value as f64 * factor).round() as usize;
+        }
+        if let Some(ref mut disk_bytes) = self.disk_bytes {
+            *disk_bytes = (*disk_bytes as f64 * factor).round() as usize;
+        }
+        if let Some(ref mut seconds) = self.reading_time_seconds {
+            *seconds *= factor;
+        }
     }
 
-    fn get_values(&self, args: &config::Args) -> Vec<usize> {
-        let mut values = Vec::new();
-        if args.lines {
-            values.push(self.lines);
+    fn add(&mut self, other: &Counts) {
+        // Before `lines` grows: a later block numbers its lines after ours.
+        if other.longest_line > 0
+            && (self.longest_line == 0 || other.max_line_length > self.max_line_length)
+        {
+            self.longest_line = self.lines + other.longest_line;
         }
-        if args.words {
-            values.push(self.words);
+        for counter in counter::COUNTERS {
+            counter.add(self, other);
         }
-        if args.chars {
-            values.push(self.chars);
+        if let Some(seconds) = other.reading_time_seconds {
+            *self.reading_time_seconds.get_or_insert(0.0) += seconds;
         }
-        if args.bytes {
-            values.push(self.bytes);
+        for (language, files) in &other.languages {
+            *self.languages.entry(language).or_insert(0) += files;
         }
-        if args.max_line_length {
-            values.push(self.max_line_length);
+        if let Some(disk_bytes) = other.disk_bytes {
+            *self.disk_bytes.get_or_insert(0) += disk_bytes;
         }
-        if args.blank_lines {
-            values.push(self.blank_lines);
+        if let Some(compressed_bytes) = other.compressed_bytes {
+            *self.compressed_bytes.get_or_insert(0) += compressed_bytes;
         }
-        if args.unique {
-            values.push(self.unique_words);
+        self.pattern += other.pattern;
+        self.matching_lines += other.matching_lines;
+        for (pattern, count) in &other.patterns {
+            *self.patterns.entry(pattern.clone()).or_insert(0) += count;
         }
-        if args.pattern.is_some() {
-            values.push(self.pattern);
+        if self.type_token_ratio.is_some() || other.type_token_ratio.is_some() {
+            self.type_token_ratio = Some(type_token_ratio(self.unique_words, self.words));
         }
-        values
+        if let Some(ref other_hashes) = other.line_hashes {
+            let hashes = self.line_hashes.get_or_insert_with(HashSet::new);
+            hashes.extend(other_hashes);
+            self.unique_lines = hashes.len();
+        }
+        if let Some(ref other_lengths) = other.line_lengths {
+            let lengths = self.line_lengths.get_or_insert_with(BTreeMap::new);
+            for (len, count) in other_lengths {
+                *lengths.entry(*len).or_insert(0) += count;
+            }
+            self.line_length_cardinality = lengths.len();
+        }
+        if let Some(ref other_histogram) = other.histogram {
+            let histogram = self.histogram.get_or_insert_with(HashMap::new);
+            for (bucket, count) in other_histogram {
+                *histogram.entry(*bucket).or_insert(0) += count;
+            }
+        }
+        if let Some(other_indent) = other.indentation {
+            self.indentation = Some(match self.indentation {
+                Some(indent) => Indentation {
+                    tab_lines: indent.tab_lines + other_indent.tab_lines,
+                    space_lines: indent.space_lines + other_indent.space_lines,
+                    mixed_lines: indent.mixed_lines + other_indent.mixed_lines,
+                    max_depth: indent.max_depth.max(other_indent.max_depth),
+                },
+                None => other_indent,
+            });
+        }
+        if self.truncated.is_none() {
+            self.truncated = other.truncated;
+        }
+        if let Some(other_impact) = other.normalize {
+            let impact = self.normalize.get_or_insert_with(NormalizeImpact::default);
+            impact.crlf_to_convert += other_impact.crlf_to_convert;
+            impact.cr_to_convert += other_impact.cr_to_convert;
+            impact.bytes_saved += other_impact.bytes_saved;
+        }
+        if let Some(other_endings) = other.line_endings {
+            self.line_endings = Some(match self.line_endings {
+                Some(endings) => count::LineEndings {
+                    lf: endings.lf + other_endings.lf,
+                    crlf: endings.crlf + other_endings.crlf,
+                    cr: endings.cr + other_endings.cr,
+                }
+                .into(),
+                None => other_endings,
+            });
+        }
+        if let Some(other_lines) = other.code_breakdown {
+            self.code_breakdown = Some(match self.code_breakdown {
+                Some(lines) => CodeLines {
+                    code: lines.code + other_lines.code,
+                    comment: lines.comment + other_lines.comment,
+                    blank: lines.blank + other_lines.blank,
+                },
+                None => other_lines,
+            });
+        }
+        if let Some(ref other_vocabulary) = other.vocabulary {
+            self.vocabulary
+                .get_or_insert_with(BTreeSet::new)
+                .extend(other_vocabulary.iter().cloned());
+        }
+        if let Some(ref other_frequencies) = other.line_frequencies {
+            count::merge_line_frequencies(
+                self.line_frequencies.get_or_insert_with(HashMap::new),
+                other_frequencies.clone(),
+            );
+        }
+        if let Some(ref other_groups) = other.prefix_frequencies {
+            count::merge_key_counts(
+                self.prefix_frequencies.get_or_insert_with(HashMap::new),
+                other_groups.clone(),
+            );
+        }
+        if let Some(ref other_buckets) = other.date_buckets {
+            count::merge_date_buckets(
+                self.date_buckets.get_or_insert_with(HashMap::new),
+                other_buckets.clone(),
+            );
+        }
+        if let Some(ref other_words) = other.word_frequencies {
+            count::merge_key_counts(
+                self.word_frequencies.get_or_insert_with(HashMap::new),
+                other_words.clone(),
+            );
+        }
+        if let Some(ref other_scan) = other.directives {
+            match self.directives {
+                Some(ref mut scan) => scan.extend(other_scan),
+                None => self.directives = Some(other_scan.clone()),
+            }
+        }
+        if let Some(ref other_raw) = other.raw {
+            self.raw
+                .get_or_insert_with(|| Box::new(Counts::new()))
+                .add(other_raw);
+        }
+    }
+
+    /// Column values in display order. With `--with-raw` each counter gets a
+    /// raw column followed by the filtered one.
+    fn get_values(&self, args: &config::Args) -> Vec<usize> {
+        let values = self.counter_values(args);
+        if !args.shows_raw() {
+            return values;
+        }
+        let raw = self
+            .raw
+            .as_ref()
+            .map(|r| r.counter_values(args))
+            .unwrap_or_else(|| vec![0; values.len()]);
+        raw.into_iter()
+            .zip(values)
+            .flat_map(|(raw, filtered)| [raw, filtered])
+            .collect()
+    }
+
+    fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        let value = serde_json::to_value(self)?;
+        match self.raw {
+            Some(ref raw) => Ok(serde_json::json!({
+                "raw": serde_json::to_value(raw)?,
+                "filtered": value,
+            })),
+            None => Ok(value),
+        }
+    }
+
+    /// Enabled counters in display order, named as in the JSON output. With
+    /// several `--pattern`s each gets a `pattern:<text>` column.
+    fn counters(&self, args: &config::Args) -> Vec<(Cow<'static, str>, usize)> {
+        let mut counters: Vec<(Cow<'static, str>, usize)> = counter::COUNTERS
+            .iter()
+            .filter(|counter| counter.enabled(args))
+            .map(|counter| (Cow::Borrowed(counter.name), counter.value(self)))
+            .collect();
+        match args.pattern.as_slice() {
+            [] => {}
+            [_] => counters.push((Cow::Borrowed("pattern"), self.pattern)),
+            patterns => {
+                for pattern in patterns {
+                    counters.push((
+                        Cow::Owned(format!("pattern:{}", pattern)),
+                        self.patterns.get(pattern).copied().unwrap_or(0),
+                    ));
+                }
+            }
+        }
+        if args.count_matching_lines {
+            counters.push((Cow::Borrowed("matching_lines"), self.matching_lines));
+        }
+        counters
+    }
+
+    fn counter_values(&self, args: &config::Args) -> Vec<usize> {
+        self.counters(args)
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
     }
 
-    fn format(&self, args: &config::Args, name: &str, widths: &[usize]) -> String {
+    fn format(
+        &self,
+        args: &config::Args,
+        name: &str,
+        widths: &[usize],
+        name_width: usize,
+    ) -> String {
         let values = self.get_values(args);
+        let color = color::ColorConfig::from_args(args);
+        let total = name == "total";
 
+        // Pad before coloring; escape codes would throw off the widths.
         let formatted: Vec<String> = values
             .iter()
             .enumerate()
-            .map(|(i, v)| format!("{:>width$}", v, width = widths.get(i).copied().unwrap_or(1)))
+            .map(|(i, v)| {
+                let width = widths.get(i).copied().unwrap_or(1);
+                let padding = " ".repeat(width.saturating_sub(v.to_string().len()));
+                if total {
+                    format!("{}{}", padding, v)
+                } else {
+                    format!("{}{}", padding, color.colorize_value(*v, i))
+                }
+            })
+            .collect();
+
+        let label = if args.filename_first && !name.is_empty() {
+            format!("{:<name_width$}", name)
+        } else {
+            name.to_string()
+        };
+        if total {
+            color.colorize_total(&label_row(args, formatted.join(" "), &label, 0))
+        } else {
+            label_row(
+                args,
+                formatted.join(" "),
+                &color.colorize_filename(&label),
+                0,
+            )
+        }
+    }
+
+    /// Like `format`, with each value followed by its change since `previous`.
+    fn format_trend(
+        &self,
+        args: &config::Args,
+        name: &str,
+        name_width: usize,
+        previous: Option<&trend::RunRecord>,
+    ) -> String {
+        let values = self.get_values(args);
+        let previous = previous.filter(|p| p.totals.len() == values.len());
+        let Some(previous) = previous else {
+            let plain = values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            return format!(
+                "{} (no previous run)",
+                label_row(args, plain, name, name_width)
+            );
+        };
+        let formatted: Vec<String> = values
+            .iter()
+            .zip(&previous.totals)
+            .map(|(v, p)| format!("{} ({})", v, trend::format_delta(*v, *p)))
+            .collect();
+        label_row(args, formatted.join(" "), name, name_width)
+    }
+
+    /// Fills `top_lines` from the line frequency map, truncating long lines.
+    fn rank_top_lines(&mut self, args: &config::Args) {
+        let (Some(n), Some(frequencies)) = (args.top_lines, &self.line_frequencies) else {
+            return;
+        };
+        self.top_lines = count::top_lines(frequencies, n)
+            .into_iter()
+            .map(|(count, line)| TopLine {
+                count,
+                line: truncate_line(line, args.top_lines_width),
+            })
+            .collect();
+    }
+
+    /// Fills `top_words` with the `--top-words` most frequent words.
+    fn rank_top_words(&mut self, args: &config::Args) {
+        let (Some(n), Some(frequencies)) = (args.top_words, &self.word_frequencies) else {
+            return;
+        };
+        self.top_words = count::rank_key_counts(frequencies)
+            .into_iter()
+            .take(n)
+            .map(|(count, word)| TopWord {
+                word: word.to_string(),
+                count,
+            })
+            .collect();
+    }
+
+    /// Fills `prefix_groups` from the prefix frequency map, largest first.
+    fn rank_prefix_groups(&mut self) {
+        let Some(ref frequencies) = self.prefix_frequencies else {
+            return;
+        };
+        self.prefix_groups = count::rank_key_counts(frequencies)
+            .into_iter()
+            .map(|(count, prefix)| PrefixGroup {
+                prefix: prefix.to_string(),
+                count,
+            })
             .collect();
+    }
+
+    /// Fills `dates` from the date buckets, oldest first, unparsed last.
+    fn list_dates(&mut self) {
+        let Some(ref buckets) = self.date_buckets else {
+            return;
+        };
+        let mut dates: Vec<_> = buckets.iter().collect();
+        dates.sort_unstable_by_key(|(date, _)| (date.is_none(), **date));
+        self.dates = dates
+            .into_iter()
+            .map(|(date, day)| DateGroup {
+                date: date.map_or_else(|| UNPARSED_DATE.to_string(), |d| d.to_string()),
+                lines: day.lines,
+                bytes: day.bytes,
+            })
+            .collect();
+    }
+
+    fn format_details(&self, args: &config::Args) -> String {
+        let mut details = String::new();
+        if !self.top_lines.is_empty() {
+            let width = self.top_lines[0].count.to_string().len();
+            for top in &self.top_lines {
+                details.push_str(&format!("\n  {:>width$}  {}", top.count, top.line));
+            }
+        }
+        if !self.top_words.is_empty() {
+            let width = self.top_words[0].count.to_string().len();
+            for top in &self.top_words {
+                details.push_str(&format!("\n  {:>width$}  {}", top.count, top.word));
+            }
+        }
+        if !self.prefix_groups.is_empty() {
+            let width = self.prefix_groups[0].count.to_string().len();
+            for group in self.prefix_groups.iter().take(PREFIX_GROUPS_SHOWN) {
+                details.push_str(&format!("\n  {:>width$}  {}", group.count, group.prefix));
+            }
+            let hidden = self.prefix_groups.len().saturating_sub(PREFIX_GROUPS_SHOWN);
+            if hidden > 0 {
+                details.push_str(&format!("\n  ({} more groups)", hidden));
+            }
+        }
+        if !self.dates.is_empty() {
+            let per_day = self
+                .date_buckets
+                .as_ref()
+                .map(daily_lines)
+                .unwrap_or_default();
+            if !per_day.is_empty() {
+                details.push_str(&format!("\n  {}", output::sparkline(&per_day)));
+            }
+            let lines_width = self.dates.iter().map(|g| g.lines).max().unwrap_or(0);
+            let lines_width = lines_width.to_string().len();
+            let bytes_width = self.dates.iter().map(|g| g.bytes).max().unwrap_or(0);
+            let bytes_width = bytes_width.to_string().len();
+            for group in &self.dates {
+                details.push_str(&format!(
+                    "\n  {:<10}  {:>lines_width$} lines  {:>bytes_width$} bytes",
+                    group.date, group.lines, group.bytes
+                ));
+            }
+        }
+        if args.line_length_cardinality
+            && let Some(ref lengths) = self.line_lengths
+            && lengths.len() <= LINE_LENGTH_LISTING_LIMIT
+        {
+            let listed: Vec<String> = lengths
+                .iter()
+                .map(|(len, count)| format!("{} ({} lines)", len, count))
+                .collect();
+            details.push_str(&format!("\n  line lengths: {}", listed.join(", ")));
+        }
+        if let Some(endings) = self.line_endings {
+            details.push_str(&format!(
+                "\n  line endings: {} LF, {} CRLF, {} CR{}",
+                endings.lf,
+                endings.crlf,
+                endings.cr,
+                if endings.mixed { " (mixed)" } else { "" }
+            ));
+        }
+        if let Some(impact) = self.normalize
+            && impact.needed()
+        {
+            details.push_str(&format!(
+                "\n  normalize to LF: {} CRLF, {} bare CR, saves {} bytes",
+                impact.crlf_to_convert, impact.cr_to_convert, impact.bytes_saved
+            ));
+        }
+        if let Some(width) = args.expect_width
+            && !self.nonconforming_line_numbers.is_empty()
+        {
+            let numbers: Vec<String> = self
+                .nonconforming_line_numbers
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            details.push_str(&format!(
+                "\n  {} lines not {} bytes wide (first at lines {})",
+                self.nonconforming_lines,
+                width,
+                numbers.join(", ")
+            ));
+        }
+        details
+    }
+
+    /// The text output for one input: the counter row with its details,
+    /// followed by the `--stats`, `--histogram` and `--indent-stats` sections.
+    /// `--only` prints just that section, under a heading naming the input.
+    fn render_text(
+        &self,
+        args: &config::Args,
+        name: &str,
+        widths: &[usize],
+        name_width: usize,
+        duration: Option<std::time::Duration>,
+    ) -> String {
+        let duration_ms = duration.map(|d| d.as_secs_f64() * 1000.0);
+        let section = match args.only {
+            Some(config::OnlySection::Stats) => {
+                let mut stats = self.format_stats(args.precision);
+                if let Some(ms) = duration_ms {
+                    stats.push_str(&format!("\n  Duration: {:.3}ms", ms));
+                }
+                Some(stats)
+            }
+            Some(config::OnlySection::Histogram) => Some(self.format_histogram()),
+            None => None,
+        };
+        if let Some(section) = section {
+            return if name.is_empty() {
+                section
+            } else {
+                format!("\n{}\n{}", name, section)
+            };
+        }
+
+        let mut sections = Vec::new();
+        if args.stats {
+            sections.push(self.format_stats(args.precision));
+        }
+        if args.histogram {
+            sections.push(self.format_histogram().trim_end().to_string());
+        }
+        if args.indent_stats {
+            sections.push(self.format_indent_stats());
+        }
+        sections.retain(|s| !s.is_empty());
 
-        if name.is_empty() {
-            formatted.join(" ")
+        let mut row = if self.get_values(args).is_empty() {
+            if sections.is_empty() {
+                return String::new();
+            }
+            name.to_string()
         } else {
-            format!("{} {}", formatted.join(" "), name)
+            self.format(args, name, widths, name_width)
+        };
+        if let Some(seconds) = self.reading_time_seconds {
+            row.push_str(&format!(" [{} read]", output::reading_time(seconds)));
+        }
+        if let Some(ratio) = self.type_token_ratio {
+            let ratio = canonical::fixed(ratio, args.precision.unwrap_or(4));
+            row.push_str(&format!(" [ttr {}]", ratio));
+        }
+        if let (Some(language), Some(confidence)) = (self.language, self.language_confidence) {
+            let confidence = canonical::fixed(confidence, args.precision.unwrap_or(2));
+            row.push_str(&format!(" [{} {}]", language, confidence));
         }
+        if let Some(ms) = duration_ms {
+            row.push_str(&format!(" ({:.3}ms)", ms));
+        }
+        row.push_str(&self.format_details(args));
+        let row = row.strip_prefix('\n').unwrap_or(&row);
+        if !row.is_empty() {
+            sections.insert(0, row.to_string());
+        }
+        sections.join("\n")
     }
 
-    fn format_stats(&self) -> String {
+    /// The `--stats` block, with floats to `precision` places (2 by default).
+    fn format_stats(&self, precision: Option<usize>) -> String {
         if let Some(ref stats) = self.statistics {
+            let decimals = precision.unwrap_or(2);
             format!(
-                "Statistics:\n  Lines: {}\n  Words: {}\n  Bytes: {}\n  Mean line length: {:.2}\n  Median line length: {}\n  Std deviation: {:.2}\n  Min line length: {}\n  Max line length: {}\n  Empty lines: {}",
+                "Statistics:\n  Lines: {}\n  Words: {}\n  Bytes: {}\n  Mean line length: {}\n  Median line length: {}\n  Std deviation: {}\n  Min line length: {}\n  Max line length: {}\n  Empty lines: {}\n  Mean word length: {}\n  Median word length: {}\n  Longest word: {}",
                 self.lines,
                 self.words,
                 self.bytes,
-                stats.mean_line_length,
+                canonical::fixed(stats.mean_line_length, decimals),
                 stats.median_line_length,
-                stats.std_dev,
+                canonical::fixed(stats.std_dev, decimals),
                 stats.min_line_length,
                 stats.max_line_length,
-                stats.empty_lines
+                stats.empty_lines,
+                canonical::fixed(stats.mean_word_length, decimals),
+                stats.median_word_length,
+                stats.max_word_length
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    /// The `--indent-stats` block.
+    fn format_indent_stats(&self) -> String {
+        if let Some(indent) = self.indentation {
+            format!(
+                "Indentation:\n  Tab-indented lines: {}\n  Space-indented lines: {}\n  Mixed-indented lines: {}\n  Max depth: {} columns",
+                indent.tab_lines, indent.space_lines, indent.mixed_lines, indent.max_depth
             )
         } else {
             String::new()
@@ -167,18 +1140,113 @@ impl Counts {
     }
 }
 
-fn process_data(data: &[u8], args: &config::Args) -> Counts {
-    let mut counts = Counts::new();
+struct TimedOut;
+
+fn check_deadline(deadline: Option<Instant>) -> Result<(), TimedOut> {
+    match deadline {
+        Some(d) if Instant::now() >= d => Err(TimedOut),
+        _ => Ok(()),
+    }
+}
+
+/// Lines holding a match of any `--pattern`, in whichever mode
+/// (`--regex`, `--pattern-glob`, `--ignore-case`) the patterns use.
+fn count_lines_matching_any_pattern(
+    data: &[u8],
+    args: &config::Args,
+    tuning: count::TuningParams,
+) -> usize {
+    if args.regex {
+        return count::count_lines_matching(data, tuning, |line| {
+            args.pattern_regex.iter().any(|regex| regex.is_match(line))
+        });
+    }
+    if args.pattern_glob {
+        let globs: Vec<count::GlobPattern> = args
+            .pattern
+            .iter()
+            .map(|pattern| count::GlobPattern::new(pattern))
+            .collect();
+        return count::count_glob_lines_any(data, &globs, tuning);
+    }
+    let patterns: Vec<&[u8]> = args.pattern.iter().map(|p| p.as_bytes()).collect();
+    count::count_lines_matching_any(data, &patterns, args.ignore_case, tuning)
+}
+
+/// Matches of each `--pattern`, in the order given, or with
+/// `--pattern-lines` the lines holding one. Several literal patterns share
+/// one pass over the data; the other modes scan once per pattern.
+fn count_pattern_matches(
+    data: &[u8],
+    args: &config::Args,
+    tuning: count::TuningParams,
+) -> Vec<usize> {
+    if args.pattern_lines {
+        if args.regex {
+            return args
+                .pattern_regex
+                .iter()
+                .map(|regex| count::count_lines_matching(data, tuning, |line| regex.is_match(line)))
+                .collect();
+        }
+        return args
+            .pattern
+            .iter()
+            .map(|pattern| {
+                count::count_matching_lines(data, pattern.as_bytes(), args.ignore_case, tuning)
+            })
+            .collect();
+    }
+    if args.pattern_glob {
+        return args
+            .pattern
+            .iter()
+            .map(|pattern| count::count_glob_lines(data, &count::GlobPattern::new(pattern), tuning))
+            .collect();
+    }
+    if args.regex {
+        return args
+            .pattern_regex
+            .iter()
+            .map(|regex| count::count_regex(data, regex))
+            .collect();
+    }
+    match args.pattern.as_slice() {
+        [pattern] if args.ignore_case => {
+            vec![count::count_pattern_ignore_case(
+                data,
+                pattern.as_bytes(),
+                tuning,
+            )]
+        }
+        [pattern] => vec![count::count_pattern(data, pattern.as_bytes(), tuning)],
+        patterns if args.ignore_case && !patterns.iter().all(|p| p.is_ascii()) => patterns
+            .iter()
+            .map(|pattern| count::count_pattern_ignore_case(data, pattern.as_bytes(), tuning))
+            .collect(),
+        patterns => {
+            let literals: Vec<&[u8]> = patterns.iter().map(|p| p.as_bytes()).collect();
+            count::count_patterns(data, &literals, args.ignore_case, tuning)
+        }
+    }
+}
 
-    let needs_decoding = args.encoding.is_some()
-        || args.words
-        || args.chars
-        || args.unique
-        || args.stats
-        || args.code
-        || args.markdown;
+fn process_data(
+    data: &[u8],
+    args: &config::Args,
+    deadline: Option<Instant>,
+) -> Result<Counts, TimedOut> {
+    check_deadline(deadline)?;
+    let (data, truncated) = truncate_input(data, args);
 
-    let decoded_data;
+    let needs_decoding = args.decodes()
+        || counter::COUNTERS
+            .iter()
+            .any(|counter| counter.decodes && counter.enabled(args));
+
+    let mut decoded_data = buffers::Pooled::take(&buffers::DECODED);
+    let mut encoding_fallback = None;
+    let mut decoding_used = None;
     let data_after_encoding = if needs_decoding {
         let validated_encoding = args.encoding.as_deref().and_then(|name| {
             if Encoding::for_label(name.as_bytes()).is_some() {
@@ -193,53 +1261,323 @@ fn process_data(data: &[u8], args: &config::Args) -> Counts {
                 None
             }
         });
-        decoded_data = count::decode_to_utf8(data, validated_encoding);
-        &decoded_data[..]
+        let (decoded, decoding) = count::decode_to_utf8(
+            data,
+            validated_encoding,
+            args.trust_detection,
+            &mut decoded_data,
+        );
+        if let count::Decoding::PreferredUtf8 { detected } = decoding {
+            encoding_fallback = Some(EncodingFallback {
+                detected: detected.name(),
+                used: encoding_rs::UTF_8.name(),
+            });
+        }
+        decoding_used = Some(decoding);
+        decoded
     } else {
         data
     };
 
-    let filtered_data;
+    let (feff, feff_offset) = if args.feff_count || args.strip_feff || args.verbose {
+        count::mid_file_feff(data_after_encoding)
+    } else {
+        (0, None)
+    };
+    let mut feff_stripped = Vec::new();
+    let data_after_encoding = if args.strip_feff {
+        count::strip_feff(data_after_encoding, &mut feff_stripped)
+    } else {
+        data_after_encoding
+    };
+
+    let endings = (args.line_endings || args.normalize_check.is_some())
+        .then(|| count::count_line_endings(data_after_encoding, args.tuning()));
+    let normalize = args
+        .normalize_check
+        .zip(endings)
+        .map(|(style, endings)| match style {
+            config::NewlineStyle::Lf => NormalizeImpact::to_lf(endings),
+        });
+    let line_endings = endings
+        .filter(|_| args.line_endings)
+        .map(LineEndingReport::from);
+
+    let mut filtered_data = buffers::Pooled::take(&buffers::FILTERED);
     let data_to_process = if args.code {
-        filtered_data = count::filter_code_comments(data_after_encoding);
-        &filtered_data
+        count::filter_code_comments(data_after_encoding, &mut filtered_data);
+        &filtered_data[..]
     } else if args.markdown {
-        filtered_data = count::filter_markdown_code(data_after_encoding);
-        &filtered_data
+        count::filter_markdown_code(data_after_encoding, &mut filtered_data);
+        &filtered_data[..]
+    } else if args.html {
+        count::filter_html_tags(data_after_encoding, &mut filtered_data);
+        &filtered_data[..]
     } else {
         data_after_encoding
     };
+    check_deadline(deadline)?;
+
+    let tuning = count::TuningParams {
+        deadline,
+        ..args.tuning()
+    };
+    let mut counts = count_buffer(data_to_process, args, tuning, deadline)?;
+    if args.verify {
+        counts.mismatches = verify::check(tuning, |tuning| {
+            let counts = count_buffer(data_to_process, args, tuning, deadline)?;
+            Ok(counts.to_json().unwrap_or_default())
+        })?;
+    }
+    if args.shows_raw() {
+        counts.raw = Some(Box::new(count_buffer(
+            data_after_encoding,
+            args,
+            tuning,
+            deadline,
+        )?));
+    }
+    if args.debug_internals {
+        counts.diagnostics = Some(Box::new(diagnose(
+            data_after_encoding,
+            data_to_process,
+            decoding_used,
+            args,
+        )));
+    }
+    counts.truncated = truncated;
+    if args.check {
+        counts.directives = Some(check::Scan::new(data_after_encoding));
+    }
+    counts.encoding_fallback = encoding_fallback;
+    counts.normalize = normalize;
+    counts.line_endings = line_endings;
+    counts.feff = feff;
+    counts.feff_offset = feff_offset;
+    Ok(counts)
+}
+
+/// Assembles the `--debug-internals` report for one input from each stage
+/// of `process_data`. Boundary corrections are recounted with the traced
+/// variants of the counters, so they only cost anything under the flag.
+fn diagnose(
+    decoded: &[u8],
+    filtered: &[u8],
+    decoding: Option<count::Decoding>,
+    args: &config::Args,
+) -> diagnostics::Diagnostics {
+    let tuning = args.tuning();
+    let mut diagnostics = diagnostics::Diagnostics {
+        bytes: filtered.len(),
+        encoding: decoding.map(|decoding| match decoding {
+            count::Decoding::Declared(encoding) => format!("{} (--encoding)", encoding.name()),
+            count::Decoding::Detected(encoding) => format!("{} (detected)", encoding.name()),
+            count::Decoding::PreferredUtf8 { detected } => format!(
+                "UTF-8 (detected {}, but mostly valid UTF-8)",
+                detected.name()
+            ),
+        }),
+        parallel: filtered.len() >= tuning.parallel_threshold,
+        tuning: Some(tuning),
+        ..Default::default()
+    };
+    let filter = [
+        (args.code, "--code"),
+        (args.markdown, "--markdown"),
+        (args.html, "--html"),
+    ]
+    .into_iter()
+    .find_map(|(on, flag)| on.then_some(flag));
+    diagnostics.filter = filter.map(|flag| (flag, decoded.len(), filtered.len()));
+    if diagnostics.parallel {
+        diagnostics.chunks = count::chunk_layouts(filtered, tuning).to_vec();
+    }
+
+    if args.regex && !args.pattern.is_empty() {
+        diagnostics
+            .exceptions
+            .push("patterns: regex, one pass over the whole input".to_string());
+    } else if args.pattern.len() > 1 && !args.pattern_lines && !args.pattern_glob {
+        diagnostics.exceptions.push(
+            "patterns: searched together, each chunk read past its end (no correction)".to_string(),
+        );
+    }
+    if args.cloc {
+        diagnostics
+            .exceptions
+            .push("cloc: serial, comments can span lines".to_string());
+    }
+    if args.unique_exact_spill.is_some() && args.unique {
+        diagnostics
+            .exceptions
+            .push("unique words: counted through on-disk spill files".to_string());
+    }
+
+    let words_counted = args.words || args.stats || args.summary || args.reading_time || args.ttr;
+    if words_counted && args.tokenizer == count::Tokenizer::Whitespace {
+        let (_, trace) = count::count_all_words_traced(filtered, tuning);
+        diagnostics.corrections.push(("words".to_string(), trace));
+    }
+    if let [pattern] = args.pattern.as_slice()
+        && !args.regex
+        && !args.pattern_lines
+        && !args.pattern_glob
+    {
+        let (_, trace) =
+            count::count_pattern_traced(filtered, pattern.as_bytes(), args.ignore_case, tuning);
+        diagnostics
+            .corrections
+            .push((format!("pattern {:?}", pattern), trace));
+    }
+    diagnostics
+}
+
+/// Runs every enabled counter over an already decoded and filtered buffer.
+fn count_buffer(
+    data_to_process: &[u8],
+    args: &config::Args,
+    tuning: count::TuningParams,
+    deadline: Option<Instant>,
+) -> Result<Counts, TimedOut> {
+    let mut counts = Counts::new();
+    let logical_lines = if args.join_continuations {
+        count::join_continuations(data_to_process)
+    } else {
+        Cow::Borrowed(data_to_process)
+    };
 
-    if args.lines || args.stats {
-        counts.lines = count::count_lines(data_to_process);
+    if args.lines || args.stats || args.summary {
+        counts.lines = count::count_lines(&logical_lines, tuning);
     }
-    if args.words || args.stats {
-        counts.words = count::count_all_words(data_to_process);
+    if args.words || args.stats || args.summary || args.reading_time || args.ttr {
+        counts.words = match args.tokenizer {
+            count::Tokenizer::Whitespace => count::count_all_words(data_to_process, tuning),
+            tokenizer => count::count_tokens(data_to_process, tuning, tokenizer),
+        };
+        check_deadline(deadline)?;
     }
     if args.chars {
         if args.fast {
             counts.chars = data_to_process.len();
         } else {
-            counts.chars = count::count_chars(data_to_process);
+            counts.chars = count::count_chars(data_to_process, tuning);
         }
+        check_deadline(deadline)?;
     }
-    if args.bytes || args.stats {
+    if args.bytes || args.stats || args.summary {
         counts.bytes = data_to_process.len();
     }
-    if args.max_line_length {
-        counts.max_line_length = count::max_line_length(data_to_process);
+    if args.max_line_length || args.summary {
+        let longest = count::longest_line(&logical_lines, tuning);
+        counts.max_line_length = longest.length;
+        counts.longest_line = longest.line;
+        check_deadline(deadline)?;
+    }
+    let input = counter::Input {
+        data: data_to_process,
+        lines: &logical_lines,
+    };
+    for counter in counter::COUNTERS {
+        if let Some(count) = counter.count
+            && counter.enabled(args)
+        {
+            counter.store(&mut counts, count(&input, tuning));
+            check_deadline(deadline)?;
+        }
+    }
+    if args.reading_time {
+        counts.reading_time_seconds = Some(counts.words as f64 / WORDS_READ_PER_MINUTE * 60.0);
+    }
+    if args.lang_detect {
+        let guess = langdetect::detect(&count::char_trigrams(data_to_process, tuning));
+        counts.language = Some(guess.language);
+        counts.language_confidence = Some(guess.confidence);
+        counts.languages.insert(guess.language, 1);
+        check_deadline(deadline)?;
+    }
+    if args.blank_lines || args.empty_lines || args.whitespace_only_lines {
+        let blanks = count::count_blank_lines(&logical_lines, tuning);
+        counts.blank_lines = blanks.total();
+        counts.empty_lines = blanks.empty;
+        counts.whitespace_only_lines = blanks.whitespace_only;
+        check_deadline(deadline)?;
+    }
+    if args.unique && args.dump_unique_words.is_some() {
+        let vocabulary: BTreeSet<String> =
+            count::unique_words(data_to_process, tuning, args.tokenizer)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+        counts.unique_words = vocabulary.len();
+        counts.vocabulary = Some(vocabulary);
+        check_deadline(deadline)?;
+    } else if args.unique || args.ttr {
+        counts.unique_words = match args.unique_exact_spill {
+            Some(ref dir) => spill::count_unique_words(
+                data_to_process,
+                args.tokenizer,
+                Path::new(dir),
+                args.spill_budget,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "kz: warning: spilling unique words failed ({}), counting in memory",
+                    e
+                );
+                count::count_unique_words(data_to_process, tuning, args.tokenizer)
+            }),
+            None => count::count_unique_words(data_to_process, tuning, args.tokenizer),
+        };
+        check_deadline(deadline)?;
+    }
+    if args.ttr {
+        counts.type_token_ratio = Some(type_token_ratio(counts.unique_words, counts.words));
+    }
+    if args.duplicate_lines {
+        let duplicates = count::count_duplicate_lines(data_to_process, tuning);
+        counts.duplicate_lines = duplicates.distinct_duplicated;
+        counts.redundant_lines = duplicates.redundant_total;
+        check_deadline(deadline)?;
+    }
+    if args.unique_lines {
+        let lines = count::unique_lines(data_to_process, tuning);
+        counts.unique_lines = lines.len();
+        counts.line_hashes = Some(
+            lines
+                .into_iter()
+                .map(|line| std::hash::BuildHasher::hash_one(&LINE_HASHER, line))
+                .collect(),
+        );
+        check_deadline(deadline)?;
     }
-    if args.blank_lines {
-        counts.blank_lines = count::count_blank_lines(data_to_process);
+    if args.line_length_cardinality {
+        let lengths = count::line_length_counts(data_to_process, tuning);
+        counts.line_length_cardinality = lengths.len();
+        counts.line_lengths = Some(lengths.into_iter().collect());
+        check_deadline(deadline)?;
     }
-    if args.unique {
-        counts.unique_words = count::count_unique_words(data_to_process);
+    if let Some(width) = args.expect_width {
+        let violations =
+            count::find_width_violations(data_to_process, width, WIDTH_VIOLATION_LIMIT, tuning);
+        counts.nonconforming_lines = violations.count;
+        counts.nonconforming_line_numbers = violations.first_lines;
+        check_deadline(deadline)?;
     }
-    if let Some(pattern) = &args.pattern {
-        counts.pattern = count::count_pattern(data_to_process, pattern.as_bytes());
+    if !args.pattern.is_empty() {
+        let matches = count_pattern_matches(data_to_process, args, tuning);
+        counts.pattern = matches.iter().sum();
+        if args.pattern.len() > 1 {
+            counts.patterns = args.pattern.iter().cloned().zip(matches).collect();
+        }
+        check_deadline(deadline)?;
+    }
+    if args.count_matching_lines {
+        counts.matching_lines = count_lines_matching_any_pattern(data_to_process, args, tuning);
+        check_deadline(deadline)?;
     }
     if args.stats {
-        let stats = count::calculate_statistics(data_to_process);
+        let stats = count::calculate_statistics(&logical_lines, tuning);
+        let words = count::word_length_stats(data_to_process, tuning);
         counts.statistics = Some(Statistics {
             mean_line_length: stats.mean_line_length,
             median_line_length: stats.median_line_length,
@@ -247,96 +1585,376 @@ fn process_data(data: &[u8], args: &config::Args) -> Counts {
             min_line_length: stats.min_line_length,
             max_line_length: stats.max_line_length,
             empty_lines: stats.empty_lines,
+            mean_word_length: words.mean,
+            median_word_length: words.median,
+            max_word_length: words.max,
         });
     }
-    if args.histogram {
-        counts.histogram = Some(count::generate_histogram(data_to_process));
+    if args.cloc {
+        counts.code_breakdown = Some(count::code_breakdown(data_to_process).into());
+        check_deadline(deadline)?;
+    }
+    if args.indent_stats {
+        counts.indentation =
+            Some(count::calculate_indent_stats(data_to_process, args.tab_width, tuning).into());
+    }
+    if args.histogram || args.html_report.is_some() {
+        counts.histogram = Some(count::generate_histogram(&logical_lines, tuning));
     }
+    if args.top_lines.is_some() {
+        counts.line_frequencies = Some(count::line_frequencies(
+            data_to_process,
+            args.skip_fields,
+            tuning,
+        ));
+        counts.rank_top_lines(args);
+    }
+    if args.top_words.is_some() {
+        counts.word_frequencies = Some(count::word_frequencies(
+            data_to_process,
+            tuning,
+            args.tokenizer,
+        ));
+        counts.rank_top_words(args);
+        check_deadline(deadline)?;
+    }
+    if let Some(prefix) = args.line_prefix() {
+        counts.prefix_frequencies = Some(count::prefix_groups(data_to_process, prefix, tuning));
+        counts.rank_prefix_groups();
+    }
+    if let Some(ref format) = args.group_by_timestamp {
+        counts.date_buckets = Some(count::date_buckets(data_to_process, format, tuning));
+        counts.list_dates();
+    }
+    check_deadline(deadline)?;
 
-    counts
+    Ok(counts)
 }
 
 struct FileResult {
     counts: Counts,
     duration: Option<std::time::Duration>,
+    skipped: Option<&'static str>,
+    /// Detected as binary; shown with zero counts.
+    binary: bool,
+    meta: Option<fsmeta::FileMeta>,
+    /// The filter `--auto-filter` chose, or `none`.
+    filter: Option<&'static str>,
+}
+
+impl FileResult {
+    /// Attaches owner and permission details for `--fs-meta` and the
+    /// allocated size for `--disk-usage`.
+    fn with_meta(mut self, path: &str, metadata: &std::fs::Metadata, args: &config::Args) -> Self {
+        if args.fs_meta {
+            self.meta = fsmeta::read(metadata);
+        }
+        if args.disk_usage && self.skipped.is_none() {
+            self.counts.disk_bytes =
+                fsmeta::allocated_bytes(Path::new(path), metadata).map(|bytes| bytes as usize);
+        }
+        self
+    }
+
+    fn finish(result: Result<Counts, TimedOut>, start: Option<Instant>) -> FileResult {
+        match result {
+            Ok(counts) => FileResult {
+                counts,
+                duration: start.map(|s| s.elapsed()),
+                skipped: None,
+                binary: false,
+                meta: None,
+                filter: None,
+            },
+            Err(TimedOut) => FileResult {
+                counts: Counts::new(),
+                duration: start.map(|s| s.elapsed()),
+                skipped: Some("timeout"),
+                binary: false,
+                meta: None,
+                filter: None,
+            },
+        }
+    }
+
+    /// Prints the `--debug-internals` report for the input, noting how it
+    /// was read.
+    fn debug_report(mut self, name: &str, read: &'static str) -> Self {
+        if let Some(ref mut diagnostics) = self.counts.diagnostics {
+            diagnostics.read = Some(read);
+            eprint!("{}", diagnostics.render(name));
+        }
+        self
+    }
+
+    fn binary(start: Option<Instant>) -> FileResult {
+        FileResult {
+            binary: true,
+            ..FileResult::finish(Ok(Counts::new()), start)
+        }
+    }
+}
+
+/// The `--json` array entry (and `--ndjson` line) for one file.
+fn file_json(
+    path: &str,
+    file_result: &FileResult,
+    classifier: Option<&category::TestClassifier>,
+    excluded_from_total: bool,
+) -> serde_json::Value {
+    let mut json_obj = serde_json::Map::new();
+    json_obj.insert(
+        "file".to_string(),
+        serde_json::Value::String(path.to_string()),
+    );
+    if let Some(classifier) = classifier {
+        json_obj.insert(
+            "category".to_string(),
+            serde_json::Value::String(classifier.category(path).to_string()),
+        );
+    }
+    if let Some(filter) = file_result.filter {
+        json_obj.insert(
+            "filter".to_string(),
+            serde_json::Value::String(filter.to_string()),
+        );
+    }
+    if let Some(reason) = file_result.skipped {
+        json_obj.insert(
+            "skipped".to_string(),
+            serde_json::Value::String(reason.to_string()),
+        );
+    } else if let Ok(counts_value) = file_result.counts.to_json() {
+        json_obj.insert("counts".to_string(), counts_value);
+    }
+    if excluded_from_total {
+        json_obj.insert(
+            "excluded_from_total".to_string(),
+            serde_json::Value::Bool(true),
+        );
+    }
+    if let Some(ref meta) = file_result.meta {
+        json_obj.insert("fs".to_string(), serde_json::json!(meta));
+    }
+    if let Some(duration) = file_result.duration {
+        let ms = duration.as_secs_f64() * 1000.0;
+        if let Some(num) = serde_json::Number::from_f64(ms) {
+            json_obj.insert("duration_ms".to_string(), serde_json::Value::Number(num));
+        }
+    }
+    serde_json::Value::Object(json_obj)
+}
+
+fn file_deadline(args: &config::Args) -> Option<Instant> {
+    args.file_timeout
+        .map(|secs| Instant::now() + std::time::Duration::from_secs_f64(secs.max(0.0)))
 }
 
 fn process_file(path: &str, args: &config::Args) -> io::Result<FileResult> {
+    if !args.auto_filter {
+        return process_file_filtered(path, args);
+    }
+    let filter = autofilter::Filter::for_path(Path::new(path));
+    let mut result = match filter {
+        Some(filter) => process_file_filtered(path, &filter.apply(args))?,
+        None => process_file_filtered(path, args)?,
+    };
+    result.filter = Some(filter.map_or("none", autofilter::Filter::name));
+    Ok(result)
+}
+
+/// `process_file` once `--auto-filter` has settled the filter.
+fn process_file_filtered(path: &str, args: &config::Args) -> io::Result<FileResult> {
     let start = if args.timing {
         Some(Instant::now())
     } else {
         None
     };
+    let deadline = file_deadline(args);
+
+    if is_url(path) {
+        return process_url(path, args, start, deadline);
+    }
 
     let needs_only_bytes = args.bytes
-        && !args.lines
-        && !args.words
-        && !args.chars
-        && !args.max_line_length
-        && !args.blank_lines
-        && !args.unique
-        && args.pattern.is_none()
-        && !args.stats
-        && !args.histogram
-        && !args.code
-        && !args.markdown
-        && args.encoding.is_none();
+        && !args.reads_content()
+        && !counter::COUNTERS
+            .iter()
+            .any(|counter| counter.reads_content() && counter.enabled(args))
+        && !gzip::wanted(path, args);
 
     if needs_only_bytes {
         let metadata = std::fs::metadata(path)?;
         let mut counts = Counts::new();
         counts.bytes = metadata.len() as usize;
-        return Ok(FileResult {
-            counts,
-            duration: start.map(|s| s.elapsed()),
-        });
+        return Ok(FileResult::finish(Ok(counts), start).with_meta(path, &metadata, args));
     }
 
     let file = File::open(path)?;
     let metadata = file.metadata()?;
-    let file_size = metadata.len() as usize;
+    let result = process_opened_file(path, file, &metadata, args, start, deadline)?;
+    Ok(result.with_meta(path, &metadata, args))
+}
 
-    if file_size == 0 {
-        return Ok(FileResult {
-            counts: Counts::new(),
-            duration: start.map(|s| s.elapsed()),
-        });
+fn process_opened_file(
+    path: &str,
+    file: File,
+    metadata: &std::fs::Metadata,
+    args: &config::Args,
+    start: Option<Instant>,
+    deadline: Option<Instant>,
+) -> io::Result<FileResult> {
+    let size = metadata.len();
+
+    if size == 0 {
+        return Ok(FileResult::finish(Ok(Counts::new()), start));
     }
 
-    const MMAP_THRESHOLD: usize = 128 * 1024;
+    let mut file = file;
+    let mmap;
+    let mut buffer;
+    let (raw, read): (&[u8], _) =
+        match stream::ReadStrategy::for_file(size, metadata.is_file(), stream::STREAM_THRESHOLD) {
+            stream::ReadStrategy::Stream => {
+                let reason = format!(
+                    "on this platform for files over {}",
+                    summary::human_bytes(stream::STREAM_THRESHOLD as usize)
+                );
+                return stream_file(path, file, size, args, start, deadline, &reason);
+            }
+            stream::ReadStrategy::Mmap => match map_file(&file) {
+                Ok(map) => {
+                    mmap = map;
+                    (&mmap, "mmap")
+                }
+                Err(e) => {
+                    let reason = format!("for a file that could not be mapped ({})", e);
+                    return stream_file(path, file, size, args, start, deadline, &reason);
+                }
+            },
+            stream::ReadStrategy::Buffered => {
+                buffer = buffers::Pooled::take(&buffers::READ);
+                if buffer
+                    .try_reserve(usize::try_from(size).unwrap_or(usize::MAX))
+                    .is_err()
+                {
+                    let reason = "for a file too large to read into memory";
+                    return stream_file(path, file, size, args, start, deadline, reason);
+                }
+                file.read_to_end(&mut buffer)?;
+                (&buffer, "buffered")
+            }
+        };
+
+    let mut inflated;
+    let compressed = gzip::wanted(path, args) && gzip::is_gzip(raw);
+    let data = if compressed {
+        inflated = buffers::Pooled::take(&buffers::INFLATED);
+        gzip::decompress(raw, &mut inflated)?;
+        &inflated[..]
+    } else {
+        raw
+    };
 
-    let counts = if file_size >= MMAP_THRESHOLD && metadata.is_file() {
-        let mmap = unsafe { MmapOptions::new().map(&file)? };
+    if count::is_binary(data) {
+        eprintln!("kz: {}: binary file detected, skipping", path);
+        return Ok(FileResult::binary(start));
+    }
 
-        if count::is_binary(&mmap) {
-            eprintln!("kz: {}: binary file detected, skipping", path);
-            return Ok(FileResult {
-                counts: Counts::new(),
-                duration: start.map(|s| s.elapsed()),
-            });
-        }
+    let mut counts = process_data(data, args, deadline);
+    if compressed && let Ok(ref mut counts) = counts {
+        counts.compressed_bytes = Some(raw.len());
+    }
+    let read = match (read, compressed) {
+        ("mmap", true) => "mmap, gzip",
+        (_, true) => "buffered, gzip",
+        (read, false) => read,
+    };
+    Ok(FileResult::finish(counts, start).debug_report(path, read))
+}
 
-        process_data(&mmap, args)
-    } else {
-        let mut buffer = Vec::with_capacity(file_size);
-        let mut file = file;
-        file.read_to_end(&mut buffer)?;
+/// Maps `file` for reading. Debug builds fail here when `KZ_TEST_MMAP_ERROR`
+/// is set, so tests can reach the streaming fallback.
+fn map_file(file: &File) -> io::Result<memmap2::Mmap> {
+    #[cfg(debug_assertions)]
+    if std::env::var_os("KZ_TEST_MMAP_ERROR").is_some() {
+        return Err(io::Error::other("injected by KZ_TEST_MMAP_ERROR"));
+    }
+    unsafe { MmapOptions::new().map(file) }
+}
 
-        if count::is_binary(&buffer) {
-            eprintln!("kz: {}: binary file detected, skipping", path);
-            return Ok(FileResult {
-                counts: Counts::new(),
-                duration: start.map(|s| s.elapsed()),
-            });
-        }
+/// Counts a file that cannot be held in memory a block at a time, warning
+/// for each requested option that needs the whole file and was left out.
+fn stream_file(
+    path: &str,
+    mut file: File,
+    size: u64,
+    args: &config::Args,
+    start: Option<Instant>,
+    deadline: Option<Instant>,
+    reason: &str,
+) -> io::Result<FileResult> {
+    let (stream_args, unavailable) = stream::streamable(args);
+    let mut magic = [0u8; 2];
+    let compressed =
+        gzip::wanted(path, args) && file.read_exact(&mut magic).is_ok() && gzip::is_gzip(&magic);
+    file.rewind()?;
+    let streamed = if compressed {
+        stream::count_blocks(
+            gzip::reader(file),
+            stream::BLOCK_SIZE,
+            &stream_args,
+            deadline,
+        )?
+    } else {
+        stream::count_blocks(file, stream::BLOCK_SIZE, &stream_args, deadline)?
+    };
 
-        process_data(&buffer, args)
+    let Some(mut counts) = streamed else {
+        eprintln!("kz: {}: binary file detected, skipping", path);
+        return Ok(FileResult::binary(start));
     };
+    for option in unavailable {
+        eprintln!("kz: warning: {}: {} unavailable {}", path, option, reason);
+    }
+    if compressed && let Ok(ref mut counts) = counts {
+        counts.compressed_bytes = Some(usize::try_from(size).unwrap_or(usize::MAX));
+    }
+    Ok(FileResult::finish(counts, start))
+}
 
-    Ok(FileResult {
-        counts,
-        duration: start.map(|s| s.elapsed()),
-    })
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+#[cfg(feature = "http")]
+fn process_url(
+    url: &str,
+    args: &config::Args,
+    start: Option<Instant>,
+    deadline: Option<Instant>,
+) -> io::Result<FileResult> {
+    let buffer = http::fetch(url, args.max_download)?;
+
+    if count::is_binary(&buffer) {
+        eprintln!("kz: {}: binary data detected, skipping", url);
+        return Ok(FileResult::binary(start));
+    }
+
+    Ok(FileResult::finish(process_data(&buffer, args, deadline), start).debug_report(url, "http"))
+}
+
+#[cfg(not(feature = "http"))]
+fn process_url(
+    _url: &str,
+    _args: &config::Args,
+    _start: Option<Instant>,
+    _deadline: Option<Instant>,
+) -> io::Result<FileResult> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "URL inputs require kz built with the `http` feature",
+    ))
 }
 
 fn process_stdin(args: &config::Args) -> io::Result<FileResult> {
@@ -346,21 +1964,76 @@ fn process_stdin(args: &config::Args) -> io::Result<FileResult> {
         None
     };
 
-    let mut buffer = Vec::new();
-    io::stdin().read_to_end(&mut buffer)?;
+    let deadline = file_deadline(args);
 
-    if count::is_binary(&buffer) {
+    let buffer = read_stdin(args)?;
+    let mut inflated = Vec::new();
+    let compressed = args.decompress && gzip::is_gzip(&buffer);
+    let data = if compressed {
+        gzip::decompress(&buffer, &mut inflated)?;
+        &inflated[..]
+    } else {
+        &buffer[..]
+    };
+
+    if count::is_binary(data) {
         eprintln!("kz: stdin: binary data detected, skipping");
-        return Ok(FileResult {
-            counts: Counts::new(),
-            duration: start.map(|s| s.elapsed()),
-        });
+        return Ok(FileResult::binary(start));
     }
 
-    Ok(FileResult {
-        counts: process_data(&buffer, args),
-        duration: start.map(|s| s.elapsed()),
-    })
+    let mut counts = process_data(data, args, deadline);
+    if compressed && let Ok(ref mut counts) = counts {
+        counts.compressed_bytes = Some(buffer.len());
+    }
+    Ok(FileResult::finish(counts, start).debug_report("stdin", "stdin"))
+}
+
+/// Reads stdin to the end. With `--tee` every read is copied to stdout as it
+/// arrives; a reader that closes the pipe early only stops the copy, so the
+/// counts still cover the whole input.
+fn read_stdin(args: &config::Args) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    if !args.tee {
+        io::stdin().read_to_end(&mut buffer)?;
+        return Ok(buffer);
+    }
+
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut passing_through = true;
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let n = match stdin.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if passing_through {
+            match stdout.write_all(&chunk[..n]).and_then(|()| stdout.flush()) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                    passing_through = false;
+                    if args.verbose {
+                        eprintln!("kz: --tee: stdout closed early, counting the rest of stdin");
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+    Ok(buffer)
+}
+
+/// Writes the stdin result to stdout, or with `--tee` (where stdout carries
+/// the data) to the `--tee-report` file or stderr.
+fn write_stdin_report(args: &config::Args, report: &str) -> io::Result<()> {
+    match (&args.tee_report, args.tee) {
+        (Some(path), _) => std::fs::write(path, report),
+        (None, true) => io::stderr().write_all(report.as_bytes()),
+        (None, false) => io::stdout().write_all(report.as_bytes()),
+    }
 }
 
 fn read_files_from_file(path: &str) -> io::Result<Vec<String>> {
@@ -380,18 +2053,115 @@ fn read_files_from_file(path: &str) -> io::Result<Vec<String>> {
         .collect())
 }
 
-fn collect_files(args: &config::Args) -> io::Result<Vec<String>> {
-    let mut all_files = Vec::new();
+fn is_hidden_name(name: &std::ffi::OsStr) -> bool {
+    name.to_str().is_some_and(|n| n.starts_with('.'))
+}
+
+/// Prunes VCS metadata directories, and hidden entries when `--hidden=false`.
+#[derive(Clone, Copy)]
+struct WalkFilter {
+    include_vcs_dirs: bool,
+    hidden: bool,
+}
+
+impl WalkFilter {
+    fn new(args: &config::Args) -> Self {
+        WalkFilter {
+            include_vcs_dirs: args.include_vcs_dirs,
+            hidden: args.hidden,
+        }
+    }
+
+    fn skips(self, name: &std::ffi::OsStr, is_dir: bool) -> bool {
+        if !self.include_vcs_dirs && is_dir && VCS_DIRS.iter().any(|vcs| name == *vcs) {
+            return true;
+        }
+        !self.hidden && is_hidden_name(name)
+    }
+}
+
+/// The `--git-ignore` walk: `.gitignore` files (those above `root` too),
+/// `.git/info/exclude` and the global excludes file, applied only inside a
+/// git repository, as git does. Everything else is pruned as in the plain
+/// walk.
+fn git_ignore_walk(root: &Path, filter: WalkFilter) -> ignore::Walk {
+    let mut builder = ignore::WalkBuilder::new(root);
+    builder
+        .standard_filters(false)
+        .git_ignore(true)
+        .git_exclude(true)
+        .git_global(true)
+        .parents(true)
+        .follow_links(true)
+        .max_depth(Some(MAX_WALKDIR_DEPTH))
+        .filter_entry(move |e| {
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            e.depth() == 0 || !filter.skips(e.file_name(), is_dir)
+        });
+    builder.build()
+}
+
+/// Names each entry `--git-ignore` left out: the children of walked
+/// directories that the walk did not yield. An ignored directory is named
+/// once rather than file by file.
+fn report_git_ignored(walked: &BTreeSet<PathBuf>, filter: WalkFilter) {
+    for dir in walked.iter().filter(|path| path.is_dir()) {
+        let Ok(children) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut ignored: Vec<PathBuf> = children
+            .filter_map(Result::ok)
+            .filter(|child| {
+                let is_dir = child.file_type().is_ok_and(|t| t.is_dir());
+                !filter.skips(&child.file_name(), is_dir)
+            })
+            .map(|child| child.path())
+            .filter(|path| !walked.contains(path))
+            .collect();
+        ignored.sort();
+        for path in ignored {
+            eprintln!("kz: warning: {}: ignored by git", path.display());
+        }
+    }
+}
+
+/// Whether `path` ends in one of `extensions`, ignoring case and a leading
+/// `.` on either side.
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy();
+        extensions
+            .iter()
+            .any(|wanted| wanted.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+    })
+}
 
-    let mut exclude_builder = GlobSetBuilder::new();
-    for pattern in &args.exclude {
+fn build_glob_set(patterns: &[String]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
         let glob =
             Glob::new(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-        exclude_builder.add(glob);
+        builder.add(glob);
     }
-    let exclude_set = exclude_builder
+    builder
         .build()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+struct CollectedFiles {
+    files: Vec<String>,
+    walk_errors: Vec<String>,
+}
+
+fn collect_files(args: &config::Args) -> io::Result<CollectedFiles> {
+    let mut all_files = Vec::new();
+    let mut walk_errors = Vec::new();
+
+    let exclude_set = build_glob_set(&args.exclude)?;
+    let include_set = build_glob_set(&args.include)?;
+
+    let mut kzignored = 0;
+    let mut hidden_included = 0;
 
     if let Some(ref files0_path) = args.files0_from {
         let files = read_files_from_file(files0_path)?;
@@ -399,6 +2169,11 @@ fn collect_files(args: &config::Args) -> io::Result<Vec<String>> {
     }
 
     for path_str in &args.files {
+        if is_url(path_str) {
+            all_files.push(path_str.clone());
+            continue;
+        }
+
         let path = Path::new(path_str);
 
         if !path.exists() {
@@ -418,37 +2193,365 @@ fn collect_files(args: &config::Args) -> io::Result<Vec<String>> {
                 ));
             }
 
-            for entry in WalkDir::new(path)
-                .follow_links(true)
-                .max_depth(MAX_WALKDIR_DEPTH)
-            {
-                let entry = match entry {
-                    Ok(e) => e,
-                    Err(e) => {
-                        if args.verbose {
-                            eprintln!("kz: warning: {}", e);
-                        }
-                        continue;
-                    }
-                };
-                let entry_path = entry.path();
+            let kzignore = if args.no_kzignore {
+                None
+            } else {
+                kzignore::KzIgnore::load(path)?
+            };
+
+            let filter = WalkFilter::new(args);
+            let entries: Box<dyn Iterator<Item = Result<PathBuf, String>>> = if args.git_ignore {
+                Box::new(git_ignore_walk(path, filter).map(|entry| {
+                    entry
+                        .map(ignore::DirEntry::into_path)
+                        .map_err(|e| e.to_string())
+                }))
+            } else {
+                Box::new(
+                    WalkDir::new(path)
+                        .follow_links(true)
+                        .max_depth(MAX_WALKDIR_DEPTH)
+                        .into_iter()
+                        .filter_entry(move |e| {
+                            e.depth() == 0 || !filter.skips(e.file_name(), e.file_type().is_dir())
+                        })
+                        .map(|entry| {
+                            entry
+                                .map(walkdir::DirEntry::into_path)
+                                .map_err(|e| e.to_string())
+                        }),
+                )
+            };
+            let mut walked = BTreeSet::new();
+
+            for entry in entries {
+                let entry_path = match entry {
+                    Ok(e) => e,
+                    Err(e) => {
+                        if args.strict_walk {
+                            return Err(io::Error::other(format!("{} (--strict-walk)", e)));
+                        }
+                        if args.verbose {
+                            eprintln!("kz: warning: {}", e);
+                        }
+                        walk_errors.push(e);
+                        continue;
+                    }
+                };
+                if args.git_ignore && args.verbose {
+                    walked.insert(entry_path.clone());
+                }
+                let entry_path = entry_path.as_path();
+
+                if !entry_path.is_file() {
+                    continue;
+                }
+
+                if !args.exclude.is_empty() && exclude_set.is_match(entry_path) {
+                    continue;
+                }
+
+                if !args.include.is_empty() && !include_set.is_match(entry_path) {
+                    continue;
+                }
+
+                if !args.extensions.is_empty() && !has_extension(entry_path, &args.extensions) {
+                    continue;
+                }
+
+                if let Some(ref ignore) = kzignore
+                    && let Ok(relative) = entry_path.strip_prefix(path)
+                {
+                    if relative == Path::new(kzignore::FILE_NAME) {
+                        continue;
+                    }
+                    if ignore.is_ignored(relative) {
+                        kzignored += 1;
+                        continue;
+                    }
+                }
+
+                if let Ok(relative) = entry_path.strip_prefix(path)
+                    && relative.components().any(|c| is_hidden_name(c.as_os_str()))
+                {
+                    hidden_included += 1;
+                }
+
+                if let Some(path_str) = entry_path.to_str() {
+                    all_files.push(path_str.to_string());
+                }
+            }
+            report_git_ignored(&walked, filter);
+        }
+    }
+
+    if args.verbose && hidden_included > 0 {
+        eprintln!(
+            "kz: {} hidden file(s) included (use --hidden=false to skip them)",
+            hidden_included
+        );
+    }
+
+    if args.verbose && kzignored > 0 {
+        eprintln!(
+            "kz: {} file(s) ignored by {}",
+            kzignored,
+            kzignore::FILE_NAME
+        );
+    }
+
+    Ok(CollectedFiles {
+        files: all_files,
+        walk_errors,
+    })
+}
+
+/// Prints the single enabled counter for the only input and nothing else,
+/// returning the process exit code.
+fn print_value_only(args: &config::Args) -> i32 {
+    let (name, result) = match args.files.first() {
+        Some(path) => (path.as_str(), process_file(path, args)),
+        None => ("stdin", process_stdin(args)),
+    };
+    match result {
+        Ok(FileResult {
+            skipped: Some(reason),
+            ..
+        }) => {
+            eprintln!("kz: {}: skipped ({})", name, reason);
+            1
+        }
+        Ok(result) => {
+            println!("{}", result.counts.get_values(args)[0]);
+            0
+        }
+        Err(e) => {
+            eprintln!("kz: {}: {}", name, e);
+            1
+        }
+    }
+}
+
+/// Returns the indices of `files` in the order they should be started.
+/// Largest-first keeps one huge file from being picked up last and
+/// leaving the other workers idle at the end of the run.
+/// Builds the progress reporter for `--progress`, `--progress-json` or
+/// `--progress-file`, along with each file's size for byte totals.
+fn progress_reporter(
+    files: &[String],
+    args: &config::Args,
+) -> (Option<progress::Reporter>, Vec<u64>) {
+    let sink: Box<dyn progress::Sink> = if let Some(ref path) = args.progress_file {
+        match progress::JsonLines::to_file(path) {
+            Ok(sink) => Box::new(sink),
+            Err(e) => {
+                eprintln!("kz: {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.progress_json {
+        Box::new(progress::JsonLines::new(Box::new(io::stderr())))
+    } else if args.progress {
+        Box::new(progress::Terminal::new())
+    } else {
+        return (None, Vec::new());
+    };
+    let sizes: Vec<u64> = files
+        .iter()
+        .map(|path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        .collect();
+    let reporter = progress::Reporter::start(sink, files.len(), sizes.iter().sum());
+    (Some(reporter), sizes)
+}
+
+fn write_word_list(out: &mut impl Write, vocabulary: Option<&BTreeSet<String>>) -> io::Result<()> {
+    for word in vocabulary.into_iter().flatten() {
+        writeln!(out, "{}", word)?;
+    }
+    Ok(())
+}
+
+/// Where `--dump-unique-words=DIR` puts the list for `input`: the input's
+/// path under DIR with `.words` appended, keeping only normal components so
+/// absolute or `..` paths cannot escape DIR.
+fn word_list_path(dir: &Path, input: &str) -> std::path::PathBuf {
+    let mut path = dir.to_path_buf();
+    for component in Path::new(input).components() {
+        if let std::path::Component::Normal(part) = component {
+            path.push(part);
+        }
+    }
+    let mut name = path.into_os_string();
+    name.push(".words");
+    name.into()
+}
+
+fn write_word_list_file(path: &Path, vocabulary: Option<&BTreeSet<String>>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut out = io::BufWriter::new(File::create(path)?);
+    write_word_list(&mut out, vocabulary)?;
+    out.flush()
+}
+
+/// Writes `--dump-unique-words` output: the merged list to stdout, or one
+/// file per input (plus `total.words` for several inputs) under a directory.
+fn dump_unique_words(
+    target: &str,
+    file_results: &[(String, io::Result<FileResult>)],
+    total: &Counts,
+) -> io::Result<()> {
+    if target == "-" {
+        return write_word_list(&mut io::stdout().lock(), total.vocabulary.as_ref());
+    }
+    let dir = Path::new(target);
+    for (path, result) in file_results {
+        if let Ok(file_result) = result
+            && file_result.skipped.is_none()
+        {
+            let list = word_list_path(dir, path);
+            write_word_list_file(&list, file_result.counts.vocabulary.as_ref())?;
+        }
+    }
+    if file_results.len() > 1 {
+        write_word_list_file(&dir.join("total.words"), total.vocabulary.as_ref())?;
+    }
+    Ok(())
+}
+
+/// The files that were actually counted, as (path, counts) rows.
+fn counted_rows(results: &[(String, io::Result<FileResult>)]) -> Vec<(&str, &Counts)> {
+    results
+        .iter()
+        .filter_map(|(path, result)| match result {
+            Ok(file_result) if file_result.skipped.is_none() => {
+                Some((path.as_str(), &file_result.counts))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Decides which file rows `--total-of` and `--exclude-from-total` let
+/// into the total.
+struct TotalFilter {
+    of: config::TotalOf,
+    excluded: GlobSet,
+}
 
-                if !entry_path.is_file() {
-                    continue;
-                }
+impl TotalFilter {
+    fn new(args: &config::Args) -> io::Result<Self> {
+        Ok(Self {
+            of: args.total_of,
+            excluded: build_glob_set(&args.exclude_from_total)?,
+        })
+    }
 
-                if !args.exclude.is_empty() && exclude_set.is_match(entry_path) {
-                    continue;
-                }
+    fn includes(&self, path: &str, file_result: &FileResult) -> bool {
+        let selected = match self.of {
+            config::TotalOf::All => true,
+            config::TotalOf::Counted => !file_result.binary,
+            config::TotalOf::Matching => file_result.counts.pattern > 0,
+        };
+        selected && !self.excluded.is_match(path.replace('\\', "/"))
+    }
+}
 
-                if let Some(path_str) = entry_path.to_str() {
-                    all_files.push(path_str.to_string());
-                }
-            }
+/// Orders file rows by `field`, largest first unless `asc`. Skipped and
+/// unreadable files have no counts and keep their relative order at the end.
+fn sort_results(
+    results: &mut [(String, io::Result<FileResult>)],
+    field: config::SortField,
+    asc: bool,
+) {
+    use config::SortField;
+    let value = |result: &io::Result<FileResult>| match result {
+        Ok(file_result) if file_result.skipped.is_none() => {
+            let counts = &file_result.counts;
+            Some(match field {
+                SortField::Lines => counts.lines,
+                SortField::Words => counts.words,
+                SortField::Bytes => counts.bytes,
+                SortField::Chars => counts.chars,
+                SortField::MaxLineLength => counts.max_line_length,
+                SortField::BlankLines => counts.blank_lines,
+                SortField::UniqueWords => counts.unique_words,
+                SortField::Pattern => counts.pattern,
+            })
         }
+        _ => None,
+    };
+    results.sort_by(|(_, a), (_, b)| match (value(a), value(b)) {
+        (Some(a), Some(b)) if asc => a.cmp(&b),
+        (Some(a), Some(b)) => b.cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+}
+
+fn schedule_order(files: &[String], schedule: config::Schedule) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    if schedule == config::Schedule::Size {
+        let sizes: Vec<u64> = files
+            .iter()
+            .map(|path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+            .collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
     }
+    order
+}
 
-    Ok(all_files)
+/// Evaluates each file's `kz:` directives against its counts, printing one
+/// line per violation. Returns the number of violations.
+fn run_check(file_results: &[(String, io::Result<FileResult>)], args: &config::Args) -> usize {
+    let mut violations = 0;
+    for (path, result) in file_results {
+        let Ok(file_result) = result else {
+            continue;
+        };
+        if file_result.skipped.is_some() || is_url(path) {
+            continue;
+        }
+        let scan = file_result.counts.directives.clone().unwrap_or_default();
+        for token in scan.unrecognised() {
+            if args.check_require {
+                println!("{}: unrecognised kz: directive {}", path, token);
+                violations += 1;
+            } else {
+                eprintln!(
+                    "kz: warning: {}: unrecognised kz: directive {}",
+                    path, token
+                );
+            }
+        }
+        let directives = scan.directives();
+        if directives.is_empty() {
+            if args.check_require {
+                println!("{}: no kz: directives", path);
+                violations += 1;
+            }
+            continue;
+        }
+        let counts = &file_result.counts;
+        for directive in directives {
+            let actual = match directive.limit {
+                check::Limit::MaxWords | check::Limit::MinWords => counts.words,
+                check::Limit::MaxLines => counts.lines,
+                check::Limit::MaxLineLength => counts.max_line_length,
+            };
+            if !directive.is_satisfied(actual) {
+                println!(
+                    "{}: {} expected {}, got {}",
+                    path,
+                    directive.limit.name(),
+                    directive.expectation(),
+                    actual
+                );
+                violations += 1;
+            }
+        }
+    }
+    violations
 }
 
 fn main() {
@@ -460,19 +2563,93 @@ fn main() {
         return;
     }
 
+    if let Some(config::Command::Compare(ref compare_args)) = args.command {
+        if let Err(e) = compare::run(compare_args) {
+            eprintln!("kz: compare: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    match args.validate() {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("kz: warning: {}", warning);
+            }
+        }
+        Err(e) => e.exit(),
+    }
+
     args.normalize();
 
+    if let Some(threads) = args.threads
+        && let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+    {
+        eprintln!("kz: warning: --threads: {}", e);
+    }
+
+    if args.value_only {
+        let counters = Counts::new().get_values(&args).len();
+        if counters != 1 {
+            config::Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!(
+                        "--value-only needs exactly one counter, but {} are enabled",
+                        counters
+                    ),
+                )
+                .exit();
+        }
+        std::process::exit(print_value_only(&args));
+    }
+
+    if args.serve || args.serve_socket.is_some() {
+        let result = match &args.serve_socket {
+            Some(path) => serve::run_socket(path, &args),
+            None => serve::run(io::stdin().lock(), io::stdout().lock(), &args),
+        };
+        if let Err(e) = result {
+            eprintln!("kz: serve: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.diff_input {
+        std::process::exit(diff::run(&args));
+    }
+
     if args.files.is_empty() && args.files0_from.is_none() {
         if atty::is(atty::Stream::Stdin) {
             eprintln!("kz: no input provided (use --help for usage)");
             std::process::exit(1);
         }
 
+        if args.interval.is_some() || args.interval_lines.is_some() {
+            if let Err(e) = watch::run(&args) {
+                eprintln!("kz: stdin: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+
         match process_stdin(&args) {
+            Ok(FileResult {
+                skipped: Some(reason),
+                ..
+            }) => {
+                eprintln!("kz: stdin: skipped ({})", reason);
+                std::process::exit(1);
+            }
             Ok(result) => {
-                if args.json {
+                let report = if args.csv {
+                    output::format_csv(&[("stdin", &result.counts)], None, &args)
+                } else if args.json || args.ndjson {
                     let mut json_obj = serde_json::Map::new();
-                    if let Ok(counts_value) = serde_json::to_value(&result.counts)
+                    if let Ok(counts_value) = result.counts.to_json()
                         && let Some(obj) = counts_value.as_object()
                     {
                         for (k, v) in obj {
@@ -486,24 +2663,19 @@ fn main() {
                                 .insert("duration_ms".to_string(), serde_json::Value::Number(num));
                         }
                     }
-                    match serde_json::to_string_pretty(&serde_json::Value::Object(json_obj)) {
-                        Ok(json) => println!("{}", json),
+                    let json_value = serde_json::Value::Object(json_obj);
+                    let json = if args.ndjson {
+                        canonical::to_string_compact(&json_value, args.json_precision())
+                    } else {
+                        canonical::to_string_pretty(&json_value, args.json_precision())
+                    };
+                    match json {
+                        Ok(json) => format!("{}\n", json),
                         Err(e) => {
                             eprintln!("kz: JSON serialization error: {}", e);
                             std::process::exit(1);
                         }
                     }
-                } else if args.stats {
-                    let mut output = result.counts.format_stats();
-                    if let Some(duration) = result.duration {
-                        output.push_str(&format!(
-                            "\n  Duration: {:.3}ms",
-                            duration.as_secs_f64() * 1000.0
-                        ));
-                    }
-                    println!("{}", output);
-                } else if args.histogram {
-                    println!("{}", result.counts.format_histogram());
                 } else {
                     let widths: Vec<usize> = result
                         .counts
@@ -511,11 +2683,48 @@ fn main() {
                         .iter()
                         .map(|v| v.to_string().len().max(1))
                         .collect();
-                    let mut output = result.counts.format(&args, "", &widths);
-                    if let Some(duration) = result.duration {
-                        output.push_str(&format!(" ({:.3}ms)", duration.as_secs_f64() * 1000.0));
+                    let legend = output::pattern_legend(&args)
+                        .map(|legend| format!("{}\n", legend))
+                        .unwrap_or_default();
+                    format!(
+                        "{}{}\n",
+                        legend,
+                        result
+                            .counts
+                            .render_text(&args, "", &widths, 0, result.duration)
+                    )
+                };
+                if let Err(e) = write_stdin_report(&args, &report) {
+                    match &args.tee_report {
+                        Some(path) => eprintln!("kz: {}: {}", path, e),
+                        None => eprintln!("kz: {}", e),
                     }
-                    println!("{}", output);
+                    std::process::exit(1);
+                }
+                if let Some(ref target) = args.dump_unique_words {
+                    let written = if target == "-" {
+                        write_word_list(&mut io::stdout().lock(), result.counts.vocabulary.as_ref())
+                    } else {
+                        write_word_list_file(
+                            &word_list_path(Path::new(target), "stdin"),
+                            result.counts.vocabulary.as_ref(),
+                        )
+                    };
+                    if let Err(e) = written {
+                        eprintln!("kz: --dump-unique-words: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                report_encoding_fallback("stdin", &result.counts);
+                report_feff("stdin", &result.counts, &args);
+                if report_mismatches("stdin", &result.counts) {
+                    std::process::exit(EXIT_VERIFY_MISMATCH);
+                }
+                if result.counts.nonconforming_lines > 0
+                    || (args.fail_if_normalize_needed
+                        && result.counts.normalize.is_some_and(|n| n.needed()))
+                {
+                    std::process::exit(1);
                 }
             }
             Err(e) => {
@@ -526,20 +2735,95 @@ fn main() {
         return;
     }
 
-    let files = match collect_files(&args) {
-        Ok(f) => f,
+    let CollectedFiles {
+        mut files,
+        walk_errors,
+    } = match collect_files(&args) {
+        Ok(collected) => collected,
+        Err(e) => {
+            eprintln!("kz: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let resolved = pathcase::Resolver::default().resolve(files);
+    files = resolved.files;
+    for collision in &resolved.collisions {
+        eprintln!(
+            "kz: warning: {} are the same file on this case-insensitive filesystem; counting it once as {}",
+            collision.names.join(", "),
+            collision.file
+        );
+    }
+
+    let total_filter = match TotalFilter::new(&args) {
+        Ok(filter) => filter,
         Err(e) => {
             eprintln!("kz: {}", e);
             std::process::exit(1);
         }
     };
 
+    let classifier = if args.split_tests || args.tests_only || args.no_tests {
+        match category::TestClassifier::new(&args.test_pattern) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                eprintln!("kz: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(ref classifier) = classifier {
+        if args.tests_only {
+            files.retain(|f| classifier.is_test(f));
+        } else if args.no_tests {
+            files.retain(|f| !classifier.is_test(f));
+        }
+    }
+
+    if let Some(kind) = args.sniff_type {
+        let dropped = sniff::retain_matching(&mut files, kind);
+        if dropped > 0 {
+            eprintln!(
+                "kz: skipped {} file(s) whose content does not look like {}",
+                dropped,
+                kind.name()
+            );
+        }
+    }
+
     if files.is_empty() {
         eprintln!("kz: no files to process");
         std::process::exit(1);
     }
 
-    let show_total = files.len() > 1;
+    if args.detect_encodings {
+        std::process::exit(encodings::run(&files, &args));
+    }
+
+    if args.deterministic {
+        files.sort();
+    }
+
+    let population = files.len();
+    let sample_size = match (args.sample_files, args.sample_files_count) {
+        (Some(rate), _) => Some(sample::size_for_rate(population, rate)),
+        (None, Some(count)) => Some(count.clamp(1, population)),
+        (None, None) => None,
+    };
+    if let Some(size) = sample_size {
+        let chosen = sample::choose(population, size, args.seed);
+        files = chosen
+            .into_iter()
+            .map(|index| std::mem::take(&mut files[index]))
+            .collect();
+    }
+
+    let show_total = files.len() > 1 || args.trend || sample_size.is_some();
+    let run_start = Instant::now();
 
     let total_start = if args.timing {
         Some(Instant::now())
@@ -547,65 +2831,144 @@ fn main() {
         None
     };
 
-    let file_results: Vec<_> = if files.len() == 1 {
-        if args.progress {
-            eprint!("\r\x1b[Kprocessing: 1/1 {}", files[0]);
-            let _ = io::stderr().flush();
+    let (ndjson_sender, ndjson_writer) = if args.ndjson && !args.total_only {
+        let (sender, records) = std::sync::mpsc::channel();
+        let writer =
+            std::thread::spawn(move || output::write_ndjson(records, &mut io::stdout().lock()));
+        (Some(sender), Some(writer))
+    } else {
+        (None, None)
+    };
+
+    if let Err(e) = interrupt::install()
+        && args.verbose
+    {
+        eprintln!("kz: warning: could not install the Ctrl-C handler: {}", e);
+    }
+    let (reporter, sizes) = progress_reporter(&files, &args);
+    let count_file = |index: usize| {
+        if interrupt::requested() {
+            if let Some(ref sender) = ndjson_sender {
+                let _ = sender.send((index, None));
+            }
+            return None;
         }
-        let results: Vec<_> = files
-            .iter()
-            .map(|path| (path.clone(), process_file(path, &args)))
-            .collect();
-        if args.progress {
-            eprint!("\r\x1b[K");
-            let _ = io::stderr().flush();
+        let path = &files[index];
+        let result = process_file(path, &args);
+        if let Some(ref reporter) = reporter {
+            let outcome = match &result {
+                Ok(FileResult {
+                    skipped: Some(reason),
+                    ..
+                }) => progress::Outcome::Skipped(reason),
+                Ok(_) => progress::Outcome::Counted,
+                Err(e) => progress::Outcome::Failed(e),
+            };
+            reporter.file_finished(path, sizes[index], outcome);
+        }
+        let shown = platform::display_path(path, args.path_separator).into_owned();
+        if let Some(ref sender) = ndjson_sender {
+            let record = result.as_ref().ok().map(|file_result| {
+                let excluded = !total_filter.includes(&shown, file_result);
+                let value = file_json(&shown, file_result, classifier.as_ref(), excluded);
+                canonical::to_string_compact(&value, args.json_precision())
+                    .expect("a JSON value always serializes")
+            });
+            // The writer only stops early if stdout is gone; the error
+            // surfaces when it is joined below.
+            let _ = sender.send((index, record));
         }
-        results
+        Some((shown, result))
+    };
+
+    let mut file_results: Vec<_> = if files.len() == 1 {
+        count_file(0).into_iter().collect()
     } else {
-        let total_files = files.len();
-        let processed = AtomicUsize::new(0);
-        let progress_lock = Mutex::new(());
-        let results: Vec<_> = files
-            .par_iter()
-            .map(|path| {
-                let result = (path.clone(), process_file(path, &args));
-                if args.progress {
-                    let count = processed.fetch_add(1, Ordering::Relaxed) + 1;
-                    let display_path = if path.len() > 40 {
-                        format!("...{}", &path[path.len() - 37..])
-                    } else {
-                        path.clone()
-                    };
-                    if let Ok(_guard) = progress_lock.lock() {
-                        eprint!(
-                            "\r\x1b[Kprocessing: {}/{} {}",
-                            count, total_files, display_path
-                        );
-                        let _ = io::stderr().flush();
-                    }
-                }
-                result
-            })
+        let mut results: Vec<_> = schedule_order(&files, args.schedule)
+            .into_par_iter()
+            .with_max_len(1)
+            .filter_map(|index| Some((index, count_file(index)?)))
             .collect();
-        if args.progress {
-            eprint!("\r\x1b[K");
-            let _ = io::stderr().flush();
-        }
-        results
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
     };
+    if let Some(reporter) = reporter {
+        reporter.done();
+    }
+    drop(ndjson_sender);
+    if let Some(field) = args.sort_field {
+        sort_results(&mut file_results, field, args.sort_asc);
+    }
+    if let Some(writer) = ndjson_writer
+        && let Err(e) = writer.join().expect("ndjson writer panicked")
+    {
+        eprintln!("kz: stdout: {}", e);
+        std::process::exit(1);
+    }
 
     let total_duration = total_start.map(|s| s.elapsed());
+    let interrupted = interrupt::requested();
+    let interrupted_marker = interrupt::marker(file_results.len(), files.len());
 
     let mut total = Counts::new();
+    let mut source_total = Counts::new();
+    let mut test_total = Counts::new();
     let mut had_error = false;
-    let mut json_results = Vec::new();
+    let mut failed_files = 0;
+    let mut timed_out = Vec::new();
+    let mut counted_files = 0;
+    let mut warnings = walk_errors.len();
+    let mut largest_file: Option<(&str, usize)> = None;
+    let mut fs_summary = fsmeta::Summary::default();
+    let mut verify_failures = 0;
+    let mut longest_line: Option<(&str, usize, usize)> = None;
+    let mut excluded = summary::Excluded::default();
 
     for (path, result) in &file_results {
         match result {
+            Ok(file_result) if file_result.skipped == Some("timeout") => {
+                timed_out.push(path.as_str());
+                had_error = true;
+                warnings += 1;
+            }
+            Ok(file_result) if !total_filter.includes(path, file_result) => {
+                excluded.files += 1;
+                excluded.lines += file_result.counts.lines;
+                report_encoding_fallback(path, &file_result.counts);
+                report_feff(path, &file_result.counts, &args);
+                if report_mismatches(path, &file_result.counts) {
+                    verify_failures += 1;
+                }
+            }
             Ok(file_result) => {
                 total.add(&file_result.counts);
+                if let Some(ref classifier) = classifier {
+                    if classifier.is_test(path) {
+                        test_total.add(&file_result.counts);
+                    } else {
+                        source_total.add(&file_result.counts);
+                    }
+                }
+                counted_files += 1;
+                report_encoding_fallback(path, &file_result.counts);
+                report_feff(path, &file_result.counts, &args);
+                if report_mismatches(path, &file_result.counts) {
+                    verify_failures += 1;
+                }
+                if let Some(ref meta) = file_result.meta {
+                    fs_summary.add(meta);
+                }
+                let counts = &file_result.counts;
+                if largest_file.is_none_or(|(_, size)| counts.bytes > size) {
+                    largest_file = Some((path, counts.bytes));
+                }
+                if longest_line.is_none_or(|(_, _, len)| counts.max_line_length > len) {
+                    longest_line = Some((path, counts.longest_line, counts.max_line_length));
+                }
             }
             Err(e) => {
+                warnings += 1;
+                failed_files += 1;
                 if e.kind() == io::ErrorKind::NotFound {
                     if args.verbose {
                         eprintln!("kz: {}: {}", path, e);
@@ -618,94 +2981,425 @@ fn main() {
         }
     }
 
-    let widths: Vec<usize> = total
+    let mut trend_store = None;
+    let mut trend_fingerprint = String::new();
+    if args.trend {
+        match trend::cache_dir() {
+            Some(dir) => {
+                let flags: Vec<String> = std::env::args()
+                    .skip(1)
+                    .filter(|a| a != "--trend" && !args.files.contains(a))
+                    .collect();
+                trend_fingerprint = trend::fingerprint(&args.files, &flags);
+                trend_store = Some(trend::TrendStore::open(&dir));
+            }
+            None => eprintln!("kz: warning: no cache directory for --trend (set KZ_CACHE_DIR)"),
+        }
+    }
+    let previous_run = trend_store
+        .as_ref()
+        .and_then(|store| store.previous(&trend_fingerprint))
+        .cloned();
+    let now_secs = trend::now_secs();
+
+    total.rank_top_lines(&args);
+    total.rank_prefix_groups();
+    total.list_dates();
+    total.rank_top_words(&args);
+
+    let sample_report = sample_size.map(|_| {
+        let sampled: Vec<&Counts> = file_results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok())
+            .filter(|file_result| file_result.skipped.is_none())
+            .map(|file_result| &file_result.counts)
+            .collect();
+        let estimate = |value: fn(&Counts) -> usize| {
+            let values: Vec<f64> = sampled.iter().map(|c| value(c) as f64).collect();
+            sample::estimate_total(&values, population)
+        };
+        let report = SampleReport {
+            files: sampled.len(),
+            population,
+            seed: args.seed,
+            lines: args.lines.then(|| estimate(|c| c.lines)),
+            bytes: args.bytes.then(|| estimate(|c| c.bytes)),
+        };
+        if report.files > 0 {
+            total.scale(population as f64 / report.files as f64);
+        }
+        report
+    });
+
+    if args.require_complete && failed_files > 0 {
+        eprintln!(
+            "kz: error: {} file(s) could not be read and --require-complete is set",
+            failed_files
+        );
+        std::process::exit(1);
+    }
+
+    if args.check {
+        let violations = run_check(&file_results, &args);
+        if interrupted {
+            eprintln!("kz: {}", interrupted_marker);
+            std::process::exit(interrupt::EXIT_INTERRUPTED);
+        }
+        if had_error || violations > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut widths: Vec<usize> = total
         .get_values(&args)
         .iter()
         .map(|v| v.to_string().len().max(1))
         .collect();
+    // Rows left out of the total can be wider than it.
+    if excluded.files > 0 {
+        for (_, counts) in counted_rows(&file_results) {
+            for (width, value) in widths.iter_mut().zip(counts.get_values(&args)) {
+                *width = (*width).max(value.to_string().len());
+            }
+        }
+    }
+    let name_width = if args.filename_first {
+        let shown = if args.total_only || args.summary_only {
+            0
+        } else {
+            file_results
+                .iter()
+                .map(|(path, _)| path.chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        shown.max("total".len())
+    } else {
+        0
+    };
 
-    if !args.total_only {
+    if let Some(legend) = output::pattern_legend(&args) {
+        println!("{}", legend);
+    }
+    if !args.total_only && !args.summary_only {
         for (path, result) in &file_results {
             if let Ok(file_result) = result {
-                if args.json {
+                if args.structured_output() || args.tree || file_result.skipped.is_some() {
                     continue;
-                } else if args.stats {
-                    println!("\n{}", path);
-                    let mut output = file_result.counts.format_stats();
-                    if let Some(duration) = file_result.duration {
-                        output.push_str(&format!(
-                            "\n  Duration: {:.3}ms",
-                            duration.as_secs_f64() * 1000.0
-                        ));
-                    }
-                    println!("{}", output);
-                } else if args.histogram {
-                    println!("\n{}", path);
-                    println!("{}", file_result.counts.format_histogram());
-                } else {
-                    let mut output = file_result.counts.format(&args, path, &widths);
-                    if let Some(duration) = file_result.duration {
-                        output.push_str(&format!(" ({:.3}ms)", duration.as_secs_f64() * 1000.0));
-                    }
+                }
+                let mut output = file_result.counts.render_text(
+                    &args,
+                    path,
+                    &widths,
+                    name_width,
+                    file_result.duration,
+                );
+                if !output.is_empty() && !total_filter.includes(path, file_result) {
+                    let row_end = output.find('\n').unwrap_or(output.len());
+                    output.insert_str(row_end, EXCLUDED_MARKER);
+                }
+                if !output.is_empty() {
                     println!("{}", output);
                 }
             }
         }
     }
 
-    if args.json {
-        if !args.total_only {
+    let file_tree = args.tree.then(|| tree::build(&counted_rows(&file_results)));
+    let file_sizes = if args.stats || args.summary {
+        let sizes: Vec<(usize, usize)> = counted_rows(&file_results)
+            .iter()
+            .map(|(_, counts)| (counts.bytes, counts.lines))
+            .collect();
+        summary::FileSizeStats::from_files(&sizes)
+    } else {
+        None
+    };
+
+    let mut json_files = Vec::new();
+    let mut json_total = None;
+    if args.json || args.ndjson || args.html_report.is_some() {
+        if (args.json && !args.total_only) || args.html_report.is_some() {
             for (path, result) in &file_results {
                 if let Ok(file_result) = result {
-                    let mut json_obj = serde_json::Map::new();
-                    json_obj.insert("file".to_string(), serde_json::Value::String(path.clone()));
-                    if let Ok(counts_value) = serde_json::to_value(&file_result.counts) {
-                        json_obj.insert("counts".to_string(), counts_value);
-                    }
-                    if let Some(duration) = file_result.duration {
-                        let ms = duration.as_secs_f64() * 1000.0;
-                        if let Some(num) = serde_json::Number::from_f64(ms) {
-                            json_obj
-                                .insert("duration_ms".to_string(), serde_json::Value::Number(num));
-                        }
-                    }
-                    json_results.push(serde_json::Value::Object(json_obj));
+                    let excluded = !total_filter.includes(path, file_result);
+                    json_files.push(file_json(path, file_result, classifier.as_ref(), excluded));
                 }
             }
         }
-        if show_total || args.total_only {
+        {
             let mut json_obj = serde_json::Map::new();
             json_obj.insert(
                 "file".to_string(),
                 serde_json::Value::String("total".to_string()),
             );
-            if let Ok(total_value) = serde_json::to_value(&total) {
+            if let Ok(total_value) = total.to_json() {
                 json_obj.insert("counts".to_string(), total_value);
             }
+            json_obj.insert(
+                "files_failed".to_string(),
+                serde_json::Value::Number(failed_files.into()),
+            );
+            if !walk_errors.is_empty() {
+                json_obj.insert("warnings".to_string(), serde_json::json!(walk_errors));
+            }
+            if args.fs_meta {
+                json_obj.insert("fs".to_string(), serde_json::json!(fs_summary));
+            }
+            if let Some(ref report) = sample_report {
+                json_obj.insert("estimated".to_string(), serde_json::Value::Bool(true));
+                json_obj.insert("sample".to_string(), report.to_json());
+            }
+            if args.trend {
+                let previous = previous_run.as_ref().map(|p| {
+                    serde_json::json!({
+                        "timestamp": p.timestamp,
+                        "values": p.totals,
+                    })
+                });
+                json_obj.insert("previous".to_string(), serde_json::json!(previous));
+            }
             if let Some(duration) = total_duration {
                 let ms = duration.as_secs_f64() * 1000.0;
                 if let Some(num) = serde_json::Number::from_f64(ms) {
                     json_obj.insert("duration_ms".to_string(), serde_json::Value::Number(num));
                 }
+                let tuning = args.tuning();
+                json_obj.insert(
+                    "tuning".to_string(),
+                    serde_json::json!({
+                        "chunk_size": tuning.chunk_size,
+                        "parallel_threshold": tuning.parallel_threshold,
+                    }),
+                );
+            }
+            if let Some(ref sizes) = file_sizes {
+                json_obj.insert("file_size_stats".to_string(), serde_json::json!(sizes));
+            }
+            if args.lang_detect {
+                json_obj.insert("languages".to_string(), serde_json::json!(total.languages));
             }
-            json_results.push(serde_json::Value::Object(json_obj));
+            if excluded.files > 0 {
+                json_obj.insert(
+                    "excluded_from_total".to_string(),
+                    serde_json::json!(excluded),
+                );
+            }
+            if interrupted {
+                json_obj.insert("interrupted".to_string(), serde_json::Value::Bool(true));
+            }
+            if let Some(ref tree) = file_tree {
+                json_obj.insert(
+                    "tree".to_string(),
+                    tree::to_json(tree, &args, args.tree_depth),
+                );
+            }
+            json_total = Some(serde_json::Value::Object(json_obj));
+        }
+    }
+
+    if let Some(ref report_path) = args.html_report {
+        let options = report::Options {
+            columns: Counts::new()
+                .counters(&args)
+                .into_iter()
+                .map(|(name, _)| name.into_owned())
+                .collect(),
+            max_rows: args.html_report_rows,
+        };
+        let html = report::render(&json_files, json_total.as_ref(), &options);
+        if let Err(e) = std::fs::write(report_path, html) {
+            eprintln!("kz: {}: {}", report_path, e);
+            had_error = true;
+        }
+    }
+
+    if args.json {
+        let mut json_results = if args.total_only {
+            Vec::new()
+        } else {
+            json_files
+        };
+        if show_total || args.total_only || !walk_errors.is_empty() || interrupted {
+            json_results.extend(json_total);
         }
-        match serde_json::to_string_pretty(&serde_json::Value::Array(json_results)) {
+        let json_value = serde_json::Value::Array(json_results);
+        match canonical::to_string_pretty(&json_value, args.json_precision()) {
             Ok(json) => println!("{}", json),
             Err(e) => {
                 eprintln!("kz: JSON serialization error: {}", e);
                 std::process::exit(1);
             }
         }
-    } else if (show_total || args.total_only) && !args.stats && !args.histogram {
-        let mut output = total.format(&args, "total", &widths);
-        if let Some(duration) = total_duration {
-            output.push_str(&format!(" ({:.3}ms)", duration.as_secs_f64() * 1000.0));
+    } else if args.ndjson {
+        if (show_total || args.total_only || !walk_errors.is_empty() || interrupted)
+            && let Some(ref total_value) = json_total
+        {
+            match canonical::to_string_compact(total_value, args.json_precision()) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    eprintln!("kz: JSON serialization error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    } else if args.csv {
+        let rows = if args.total_only {
+            Vec::new()
+        } else {
+            counted_rows(&file_results)
+        };
+        let total_row = (show_total || args.total_only).then_some(&total);
+        print!("{}", output::format_csv(&rows, total_row, &args));
+    } else if let Some(ref tree) = file_tree {
+        print!("{}", tree::render(tree, &args, args.tree_depth));
+    } else if (show_total || args.total_only) && args.only.is_none() && !args.summary_only {
+        if args.split_tests {
+            println!(
+                "{}",
+                source_total.format(&args, "code", &widths, name_width)
+            );
+            println!("{}", test_total.format(&args, "tests", &widths, name_width));
+        }
+        let output = if args.trend {
+            let mut output = total.format_trend(&args, "total", name_width, previous_run.as_ref());
+            if let Some(duration) = total_duration {
+                output.push_str(&format!(" ({:.3}ms)", duration.as_secs_f64() * 1000.0));
+            }
+            output.push_str(&total.format_details(&args));
+            output
+        } else {
+            total.render_text(&args, "total", &widths, name_width, total_duration)
+        };
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        if args.stats
+            && show_total
+            && let Some(ref sizes) = file_sizes
+        {
+            println!("{}", sizes.render(args.precision));
+        }
+    }
+
+    if interrupted {
+        if args.structured_output() {
+            eprintln!("kz: {}", interrupted_marker);
+        } else {
+            println!("{}", interrupted_marker);
+        }
+    }
+
+    if let Some(ref report) = sample_report
+        && !args.structured_output()
+    {
+        println!("  {}", report.render());
+    }
+
+    if excluded.files > 0 && show_total && !args.structured_output() && !args.summary {
+        println!("  not in total: {}", excluded.render());
+    }
+
+    if args.fs_meta && !args.structured_output() && !fs_summary.owners.is_empty() {
+        println!("  {}", fs_summary.render());
+    }
+
+    if let Some(ref previous) = previous_run
+        && !args.structured_output()
+    {
+        println!(
+            "  previous run: {}",
+            trend::format_age(now_secs.saturating_sub(previous.timestamp))
+        );
+    }
+
+    // Partial totals would skew the next run's deltas.
+    if let Some(mut store) = trend_store
+        && !interrupted
+    {
+        store.record(trend_fingerprint, total.get_values(&args));
+        if let Err(e) = store.save() {
+            eprintln!("kz: warning: could not save --trend record: {}", e);
+        }
+    }
+
+    if args.summary && !args.structured_output() {
+        let panel = summary::Summary {
+            files: counted_files,
+            lines: total.lines,
+            words: total.words,
+            bytes: total.bytes,
+            disk_bytes: total.disk_bytes,
+            largest_file: largest_file.map(|(p, size)| (p.to_string(), size)),
+            longest_line: longest_line.map(|(p, line, len)| (format!("{}:{}", p, line), len)),
+            file_sizes,
+            excluded,
+            languages: total.languages.clone(),
+            elapsed: run_start.elapsed(),
+            warnings,
+            chunk_size: args.tuning().chunk_size,
+            parallel_threshold: args.tuning().parallel_threshold,
+        };
+        println!(
+            "{}",
+            summary::render(&panel, summary::terminal_width(), args.ascii)
+        );
+    }
+
+    if let Some(ref target) = args.dump_unique_words
+        && let Err(e) = dump_unique_words(target, &file_results, &total)
+    {
+        eprintln!("kz: --dump-unique-words: {}", e);
+        had_error = true;
+    }
+
+    if !walk_errors.is_empty() {
+        eprintln!(
+            "kz: {} path(s) could not be read while walking directories{}",
+            walk_errors.len(),
+            if args.verbose {
+                ""
+            } else {
+                "; rerun with --verbose for paths"
+            }
+        );
+    }
+
+    if args.total_only && failed_files > 0 {
+        eprintln!(
+            "kz: warning: total excludes {} file(s) that could not be read",
+            failed_files
+        );
+    }
+
+    if !timed_out.is_empty() {
+        eprintln!(
+            "kz: {} file(s) exceeded --file-timeout and were skipped:",
+            timed_out.len()
+        );
+        for path in &timed_out {
+            eprintln!("kz:   {}", path);
         }
-        println!("{}", output);
     }
 
-    if had_error {
+    if interrupted {
+        std::process::exit(interrupt::EXIT_INTERRUPTED);
+    }
+
+    if verify_failures > 0 {
+        eprintln!(
+            "kz: --verify found mismatches in {} file(s)",
+            verify_failures
+        );
+        std::process::exit(EXIT_VERIFY_MISMATCH);
+    }
+
+    if args.fail_if_normalize_needed && total.normalize.is_some_and(|n| n.needed()) {
+        std::process::exit(1);
+    }
+
+    if had_error || total.nonconforming_lines > 0 {
         std::process::exit(1);
     }
 }
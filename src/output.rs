@@ -0,0 +1,156 @@
+use crate::Counts;
+use crate::config::Args;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::mpsc::Receiver;
+
+/// Quotes a CSV field when it holds a comma, quote or line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_row(name: &str, counts: &Counts, args: &Args) -> String {
+    let mut row = csv_field(name);
+    for (_, value) in counts.counters(args) {
+        row.push(',');
+        row.push_str(&value.to_string());
+    }
+    row
+}
+
+/// The line naming each `--pattern` column, printed above text output when
+/// there are several: `pattern[0]="TODO" pattern[1]="FIXME"`. Columns are in
+/// the order the patterns were given.
+pub fn pattern_legend(args: &Args) -> Option<String> {
+    if args.pattern.len() < 2
+        || args.structured_output()
+        || args.summary_only
+        || args.only.is_some()
+    {
+        return None;
+    }
+    let entries: Vec<String> = args
+        .pattern
+        .iter()
+        .enumerate()
+        .map(|(i, pattern)| format!("pattern[{}]={:?}", i, pattern))
+        .collect();
+    Some(entries.join(" "))
+}
+
+/// `--reading-time` as text, rounded to the second: `3 min 12 sec`.
+pub fn reading_time(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{} min {} sec", seconds / 60, seconds % 60)
+}
+
+/// One block character per value, scaled so the largest is a full block.
+pub fn sparkline(values: &[usize]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+        .iter()
+        .map(|&v| BLOCKS[(v * (BLOCKS.len() - 1)).div_ceil(max)])
+        .collect()
+}
+
+/// One CSV line per file under a header naming the requested counters, then
+/// a `total` line when `total` is given.
+pub fn format_csv(results: &[(&str, &Counts)], total: Option<&Counts>, args: &Args) -> String {
+    let mut out = String::from("file");
+    for (name, _) in Counts::new().counters(args) {
+        out.push(',');
+        out.push_str(&csv_field(&name));
+    }
+    out.push('\n');
+    for (name, counts) in results {
+        out.push_str(&csv_row(name, counts, args));
+        out.push('\n');
+    }
+    if let Some(total) = total {
+        out.push_str(&csv_row("total", total, args));
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes `--ndjson` records as workers finish them. Records arrive tagged
+/// with their input index, in whatever order the workers complete, and are
+/// held back until every earlier index has been written, so the output order
+/// matches the input order. `None` marks an input without a record (a file
+/// that could not be read). Each line is flushed as soon as it is written.
+pub fn write_ndjson(
+    records: Receiver<(usize, Option<String>)>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    for (index, record) in records {
+        pending.insert(index, record);
+        while let Some(record) = pending.remove(&next) {
+            if let Some(line) = record {
+                writeln!(out, "{}", line)?;
+                out.flush()?;
+            }
+            next += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_write_ndjson_restores_input_order() {
+        let (tx, rx) = mpsc::channel();
+        for (index, record) in [(2, Some("c")), (0, Some("a")), (3, Some("d")), (1, None)] {
+            tx.send((index, record.map(String::from))).unwrap();
+        }
+        drop(tx);
+        let mut out = Vec::new();
+        write_ndjson(rx, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nc\nd\n");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[0, 0]), "▁▁");
+        assert_eq!(sparkline(&[1, 4, 8, 0]), "▂▅█▁");
+    }
+
+    #[test]
+    fn test_reading_time() {
+        assert_eq!(reading_time(0.0), "0 min 0 sec");
+        assert_eq!(reading_time(59.6), "1 min 0 sec");
+        assert_eq!(reading_time(192.4), "3 min 12 sec");
+    }
+
+    #[test]
+    fn test_format_csv_quotes_and_omits_unrequested() {
+        let args = Args::parse_from(["kz", "-lw", "--csv"]);
+        let mut a = Counts::new();
+        a.lines = 2;
+        a.words = 5;
+        a.bytes = 40;
+        let mut b = Counts::new();
+        b.lines = 1;
+        b.words = 1;
+        let mut total = Counts::new();
+        total.add(&a);
+        total.add(&b);
+
+        let csv = format_csv(&[("a.txt", &a), ("b, \"c\".txt", &b)], Some(&total), &args);
+        assert_eq!(
+            csv,
+            "file,lines,words\na.txt,2,5\n\"b, \"\"c\"\".txt\",1,1\ntotal,3,6\n"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Whether two paths name the same file, by device and inode.
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether two paths name the same file. Canonical paths on Windows carry
+/// the on-disk casing, so equal spellings mean one file.
+#[cfg(not(unix))]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// `name` with the case of every letter flipped, or None when it has no
+/// cased letters to flip.
+fn toggled_case(name: &str) -> Option<String> {
+    let toggled: String = name
+        .chars()
+        .map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().next().unwrap_or(c)
+            } else {
+                c.to_lowercase().next().unwrap_or(c)
+            }
+        })
+        .collect();
+    (toggled != name).then_some(toggled)
+}
+
+/// Probes whether the filesystem holding the existing file `path` ignores
+/// case: the name with its letters' case flipped must open the very same
+/// file. None when the name has no letters to flip.
+fn probe_case_insensitive(path: &Path) -> Option<bool> {
+    let toggled = toggled_case(path.file_name()?.to_str()?)?;
+    Some(same_file(path, &path.with_file_name(toggled)))
+}
+
+/// Rewrites paths to their on-disk casing and drops names that only differ
+/// in case from an earlier one and open the same file. Case sensitivity is
+/// probed once per directory, so case-sensitive trees pay one extra `stat`
+/// per directory and nothing else.
+#[derive(Default)]
+pub struct Resolver {
+    insensitive_dirs: HashMap<PathBuf, bool>,
+    listings: HashMap<PathBuf, Vec<String>>,
+}
+
+/// Several spellings given for one file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Collision {
+    /// The path kept, in on-disk casing.
+    pub file: String,
+    /// Every spelling given, in input order.
+    pub names: Vec<String>,
+}
+
+pub struct Resolved {
+    pub files: Vec<String>,
+    pub collisions: Vec<Collision>,
+}
+
+impl Resolver {
+    fn dir_is_insensitive(&mut self, path: &Path) -> bool {
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if let Some(&insensitive) = self.insensitive_dirs.get(&dir) {
+            return insensitive;
+        }
+        // A name with nothing to flip leaves the question to a later file.
+        let Some(insensitive) = probe_case_insensitive(path) else {
+            return false;
+        };
+        self.insensitive_dirs.insert(dir, insensitive);
+        insensitive
+    }
+
+    fn listing(&mut self, dir: &Path) -> io::Result<&[String]> {
+        if !self.listings.contains_key(dir) {
+            let names = fs::read_dir(if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            })?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+            self.listings.insert(dir.to_path_buf(), names);
+        }
+        Ok(&self.listings[dir])
+    }
+
+    /// `path` with each component spelled as it is on disk. Components
+    /// that cannot be listed or matched are kept as given.
+    fn on_disk(&mut self, path: &str) -> String {
+        let mut resolved = PathBuf::new();
+        for component in Path::new(path).components() {
+            let Component::Normal(name) = component else {
+                resolved.push(component.as_os_str());
+                continue;
+            };
+            let name = name.to_string_lossy();
+            let on_disk = match self.listing(&resolved) {
+                Ok(names) if !names.iter().any(|n| *n == name) => names
+                    .iter()
+                    .find(|n| n.to_lowercase() == name.to_lowercase())
+                    .cloned(),
+                _ => None,
+            };
+            resolved.push(on_disk.as_deref().unwrap_or(&name));
+        }
+        resolved.to_string_lossy().into_owned()
+    }
+
+    pub fn resolve(&mut self, files: Vec<String>) -> Resolved {
+        let mut kept = Vec::with_capacity(files.len());
+        // On-disk path -> the first spelling given for it.
+        let mut first_spelling: HashMap<String, String> = HashMap::new();
+        let mut collision_index: HashMap<String, usize> = HashMap::new();
+        let mut collisions: Vec<Collision> = Vec::new();
+        for file in files {
+            let path = Path::new(&file);
+            if !path.is_file() || !self.dir_is_insensitive(path) {
+                kept.push(file);
+                continue;
+            }
+            let resolved = self.on_disk(&file);
+            match first_spelling.get(&resolved) {
+                Some(first) if *first != file && same_file(Path::new(first), path) => {
+                    let index = *collision_index.entry(resolved.clone()).or_insert_with(|| {
+                        collisions.push(Collision {
+                            file: resolved,
+                            names: vec![first.clone()],
+                        });
+                        collisions.len() - 1
+                    });
+                    if !collisions[index].names.contains(&file) {
+                        collisions[index].names.push(file);
+                    }
+                }
+                _ => {
+                    first_spelling.entry(resolved.clone()).or_insert(file);
+                    kept.push(resolved);
+                }
+            }
+        }
+        Resolved {
+            files: kept,
+            collisions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggled_case() {
+        assert_eq!(toggled_case("ReadMe.md").as_deref(), Some("rEADmE.MD"));
+        assert_eq!(toggled_case("123_-.txt").as_deref(), Some("123_-.TXT"));
+        assert_eq!(toggled_case("42"), None);
+    }
+
+    #[test]
+    fn test_case_sensitive_names_are_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let upper = dir.path().join("Notes.txt");
+        fs::write(&upper, "a\n").unwrap();
+        if probe_case_insensitive(&upper) != Some(false) {
+            return;
+        }
+        let lower = dir.path().join("notes.txt");
+        fs::write(&lower, "b\n").unwrap();
+        let files = vec![
+            upper.to_string_lossy().into_owned(),
+            lower.to_string_lossy().into_owned(),
+        ];
+        let resolved = Resolver::default().resolve(files.clone());
+        assert_eq!(resolved.files, files);
+        assert!(resolved.collisions.is_empty());
+    }
+
+    #[test]
+    fn test_collisions_on_case_insensitive_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let on_disk = dir.path().join("README.md");
+        fs::write(&on_disk, "a\n").unwrap();
+        if probe_case_insensitive(&on_disk) != Some(true) {
+            return;
+        }
+        let lower = dir.path().join("readme.md").to_string_lossy().into_owned();
+        let upper = on_disk.to_string_lossy().into_owned();
+        let resolved = Resolver::default().resolve(vec![lower.clone(), upper.clone()]);
+        assert_eq!(resolved.files, vec![upper.clone()]);
+        assert_eq!(
+            resolved.collisions,
+            vec![Collision {
+                file: upper.clone(),
+                names: vec![lower, upper],
+            }]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::config::PathSeparator;
+use std::borrow::Cow;
+
+/// Longest path the classic Win32 APIs accept without the `\\?\` prefix.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Turns on virtual terminal processing for a console handle. Legacy
+/// Windows consoles print escapes like `\x1b[K` literally without it.
+#[cfg(windows)]
+fn enable_virtual_terminal(handle: std::os::windows::io::RawHandle) -> bool {
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    unsafe extern "system" {
+        fn GetConsoleMode(handle: *mut core::ffi::c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut core::ffi::c_void, mode: u32) -> i32;
+    }
+
+    let mut mode = 0u32;
+    // SAFETY: callers pass a standard stream handle, which lives for the
+    // whole process, and `mode` outlives both calls.
+    unsafe {
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+/// Turns on ANSI escape handling for stderr; returns false when it cannot
+/// be, so callers fall back to plain output.
+#[cfg(windows)]
+pub fn enable_ansi_stderr() -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    enable_virtual_terminal(std::io::stderr().as_raw_handle())
+}
+
+#[cfg(not(windows))]
+pub fn enable_ansi_stderr() -> bool {
+    true
+}
+
+/// Turns on ANSI escape handling for stdout, as for `enable_ansi_stderr`.
+#[cfg(windows)]
+pub fn enable_ansi_stdout() -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    enable_virtual_terminal(std::io::stdout().as_raw_handle())
+}
+
+#[cfg(not(windows))]
+pub fn enable_ansi_stdout() -> bool {
+    true
+}
+
+/// The `\\?\` extended-length form of a path longer than `MAX_PATH`, for raw
+/// Win32 calls (std applies this itself for its own file APIs).
+#[cfg(windows)]
+pub fn extended_length(path: &std::path::Path) -> Cow<'_, std::path::Path> {
+    use std::path::PathBuf;
+
+    let raw = path.as_os_str().to_string_lossy();
+    if raw.len() < MAX_PATH || raw.starts_with(r"\\?\") {
+        return Cow::Borrowed(path);
+    }
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let absolute = absolute.to_string_lossy().replace('/', "\\");
+    let verbatim = match absolute.strip_prefix(r"\\") {
+        Some(unc) => format!(r"\\?\UNC\{}", unc),
+        None => format!(r"\\?\{}", absolute),
+    };
+    Cow::Owned(PathBuf::from(verbatim))
+}
+
+/// A path as printed in reports. Windows accepts both separators, so paths
+/// built from arguments and from recursion can mix them; this rewrites them
+/// to one style (`auto` is the native backslash). Elsewhere `/` is the only
+/// separator and paths are shown as given.
+#[cfg(windows)]
+pub fn display_path(path: &str, style: PathSeparator) -> Cow<'_, str> {
+    let (from, to) = match style {
+        PathSeparator::Auto | PathSeparator::Backslash => ('/', "\\"),
+        PathSeparator::Slash => ('\\', "/"),
+    };
+    if !path.contains(from) || path.contains("://") || path.starts_with(r"\\?\") {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(path.replace(from, to))
+}
+
+#[cfg(not(windows))]
+pub fn display_path(path: &str, _style: PathSeparator) -> Cow<'_, str> {
+    Cow::Borrowed(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn test_display_path_windows() {
+        assert_eq!(
+            display_path(r"src/app\main.rs", PathSeparator::Auto),
+            r"src\app\main.rs"
+        );
+        assert_eq!(
+            display_path(r"src/app\main.rs", PathSeparator::Slash),
+            "src/app/main.rs"
+        );
+        assert_eq!(
+            display_path("https://example.com/a.txt", PathSeparator::Backslash),
+            "https://example.com/a.txt"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_extended_length_only_for_long_paths() {
+        let short = std::path::Path::new(r"C:\short.txt");
+        assert_eq!(extended_length(short), short);
+        let long = format!(r"C:\{}\file.txt", "d".repeat(300));
+        let extended = extended_length(std::path::Path::new(&long));
+        assert!(extended.to_string_lossy().starts_with(r"\\?\C:\"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_display_path_unchanged_off_windows() {
+        assert_eq!(
+            display_path(r"dir/a\b.txt", PathSeparator::Slash),
+            r"dir/a\b.txt"
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Minimum gap between `progress` events in NDJSON output (about 10/s).
+const JSON_THROTTLE: Duration = Duration::from_millis(100);
+const TERMINAL_PATH_WIDTH: usize = 40;
+/// Width the progress line is padded to when it cannot be erased with ANSI.
+const PLAIN_LINE_WIDTH: usize = 79;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<'a> {
+    Start {
+        files: usize,
+        bytes: u64,
+    },
+    Progress {
+        files_done: usize,
+        files: usize,
+        bytes_done: u64,
+        bytes: u64,
+        path: &'a str,
+    },
+    Skip {
+        path: &'a str,
+        reason: &'a str,
+    },
+    Error {
+        path: &'a str,
+        message: String,
+    },
+    Done {
+        files: usize,
+        bytes: u64,
+        skipped: usize,
+        failed: usize,
+        elapsed_ms: u64,
+    },
+}
+
+/// Where progress events go. Implementations must tolerate being called
+/// from the parallel file loop.
+pub trait Sink: Send + Sync {
+    fn emit(&self, event: &Event);
+}
+
+/// The redrawn `processing: n/total path` line for `--progress`. Consoles
+/// without ANSI support get the line padded with spaces instead of erased.
+pub struct Terminal {
+    ansi: bool,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Self {
+            ansi: crate::platform::enable_ansi_stderr(),
+        }
+    }
+
+    fn clear_line(&self) -> &'static str {
+        if self.ansi { "\r\x1b[K" } else { "\r" }
+    }
+}
+
+impl Sink for Terminal {
+    fn emit(&self, event: &Event) {
+        let mut stderr = io::stderr().lock();
+        match event {
+            Event::Progress {
+                files_done,
+                files,
+                path,
+                ..
+            } => {
+                let display_path = if path.len() > TERMINAL_PATH_WIDTH {
+                    let mut start = path.len() - (TERMINAL_PATH_WIDTH - 3);
+                    while !path.is_char_boundary(start) {
+                        start += 1;
+                    }
+                    format!("...{}", &path[start..])
+                } else {
+                    path.to_string()
+                };
+                let line = format!("processing: {}/{} {}", files_done, files, display_path);
+                let _ = write!(
+                    stderr,
+                    "{}{:<width$}",
+                    self.clear_line(),
+                    line,
+                    width = if self.ansi { 0 } else { PLAIN_LINE_WIDTH }
+                );
+            }
+            Event::Done { .. } => {
+                let _ = write!(stderr, "{}", self.clear_line());
+                if !self.ansi {
+                    let _ = write!(stderr, "{:width$}\r", "", width = PLAIN_LINE_WIDTH);
+                }
+            }
+            _ => return,
+        }
+        let _ = stderr.flush();
+    }
+}
+
+/// One JSON object per line for `--progress-json`; `progress` events are
+/// throttled, all others are always written.
+pub struct JsonLines {
+    out: Mutex<Box<dyn Write + Send>>,
+    last_progress: Mutex<Option<Instant>>,
+}
+
+impl JsonLines {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+            last_progress: Mutex::new(None),
+        }
+    }
+
+    pub fn to_file(path: &str) -> io::Result<Self> {
+        Ok(Self::new(Box::new(File::create(path)?)))
+    }
+
+    fn throttled(&self) -> bool {
+        let Ok(mut last) = self.last_progress.lock() else {
+            return false;
+        };
+        let now = Instant::now();
+        if last.is_some_and(|t| now.duration_since(t) < JSON_THROTTLE) {
+            return true;
+        }
+        *last = Some(now);
+        false
+    }
+}
+
+impl Sink for JsonLines {
+    fn emit(&self, event: &Event) {
+        if matches!(event, Event::Progress { .. }) && self.throttled() {
+            return;
+        }
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}", line);
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Tracks how far the file loop has got and reports it to a sink.
+pub struct Reporter {
+    sink: Box<dyn Sink>,
+    files: usize,
+    bytes: u64,
+    files_done: AtomicUsize,
+    bytes_done: AtomicU64,
+    skipped: AtomicUsize,
+    failed: AtomicUsize,
+    start: Instant,
+}
+
+pub enum Outcome<'a> {
+    Counted,
+    Skipped(&'a str),
+    Failed(&'a io::Error),
+}
+
+impl Reporter {
+    pub fn start(sink: Box<dyn Sink>, files: usize, bytes: u64) -> Self {
+        sink.emit(&Event::Start { files, bytes });
+        Self {
+            sink,
+            files,
+            bytes,
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            skipped: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn file_finished(&self, path: &str, bytes: u64, outcome: Outcome) {
+        match outcome {
+            Outcome::Counted => {}
+            Outcome::Skipped(reason) => {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                self.sink.emit(&Event::Skip { path, reason });
+            }
+            Outcome::Failed(e) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                self.sink.emit(&Event::Error {
+                    path,
+                    message: e.to_string(),
+                });
+            }
+        }
+        let files_done = self.files_done.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes_done = self.bytes_done.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.sink.emit(&Event::Progress {
+            files_done,
+            files: self.files,
+            bytes_done,
+            bytes: self.bytes,
+            path,
+        });
+    }
+
+    pub fn done(self) {
+        self.sink.emit(&Event::Done {
+            files: self.files_done.load(Ordering::Relaxed),
+            bytes: self.bytes_done.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            elapsed_ms: self.start.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_events_are_throttled_but_complete() {
+        let buffer = Buffer::default();
+        let reporter = Reporter::start(Box::new(JsonLines::new(Box::new(buffer.clone()))), 3, 30);
+        reporter.file_finished("a", 10, Outcome::Counted);
+        reporter.file_finished("b", 10, Outcome::Skipped("timeout"));
+        let error = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        reporter.file_finished("c", 10, Outcome::Failed(&error));
+        reporter.done();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["start", "progress", "skip", "error", "done"]);
+        let done = events.last().unwrap();
+        assert_eq!(done["files"], 3);
+        assert_eq!(done["bytes"], 30);
+        assert_eq!(done["skipped"], 1);
+        assert_eq!(done["failed"], 1);
+    }
+}
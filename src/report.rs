@@ -0,0 +1,258 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const TEMPLATE: &str = include_str!("report.html");
+const LABEL_WIDTH: usize = 90;
+const BAR_AREA_WIDTH: usize = 420;
+const VALUE_WIDTH: usize = 90;
+const BAR_HEIGHT: usize = 16;
+const ROW_HEIGHT: usize = 22;
+
+pub struct Options {
+    /// Counter columns for the table, named as in the JSON output.
+    pub columns: Vec<String>,
+    /// Per-file rows kept in the table; totals always cover every file.
+    pub max_rows: usize,
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The counts of a `--json` entry, taking the filtered side of `--with-raw`.
+fn counts(entry: &Value) -> Option<&Value> {
+    let counts = entry.get("counts")?;
+    Some(counts.get("filtered").unwrap_or(counts))
+}
+
+/// A counter of a `--json` entry; `pattern:<text>` columns read the
+/// `patterns` object.
+fn number(counts: &Value, column: &str) -> u64 {
+    let value = match column.strip_prefix("pattern:") {
+        Some(pattern) => counts.get("patterns").and_then(|p| p.get(pattern)),
+        None => crate::counter::json_value(counts, column),
+    };
+    value.and_then(Value::as_u64).unwrap_or(0)
+}
+
+fn summary(files: &[Value], total: Option<&Value>, columns: &[String]) -> String {
+    let counted = files.iter().filter(|f| counts(f).is_some()).count();
+    let mut out = String::from("<dl class=\"summary\">\n");
+    out.push_str(&format!("<dt>files</dt><dd>{}</dd>\n", counted));
+    let skipped = files.len() - counted;
+    if skipped > 0 {
+        out.push_str(&format!("<dt>skipped</dt><dd>{}</dd>\n", skipped));
+    }
+    if let Some(total_counts) = total.and_then(counts) {
+        for column in columns {
+            out.push_str(&format!(
+                "<dt>{}</dt><dd>{}</dd>\n",
+                escape(column),
+                number(total_counts, column)
+            ));
+        }
+    }
+    out.push_str("</dl>");
+    out
+}
+
+fn table(files: &[Value], columns: &[String], max_rows: usize) -> String {
+    let rows: Vec<(&str, &Value)> = files
+        .iter()
+        .filter_map(|f| Some((f.get("file")?.as_str()?, counts(f)?)))
+        .collect();
+
+    let mut out = String::from("<table id=\"files\">\n<thead><tr><th>file</th>");
+    for column in columns {
+        out.push_str(&format!("<th class=\"num\">{}</th>", escape(column)));
+    }
+    out.push_str("</tr></thead>\n<tbody>\n");
+    for (path, counts) in rows.iter().take(max_rows) {
+        out.push_str(&format!("<tr><td>{}</td>", escape(path)));
+        for column in columns {
+            out.push_str(&format!(
+                "<td class=\"num\">{}</td>",
+                number(counts, column)
+            ));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</tbody>\n</table>");
+    if rows.len() > max_rows {
+        out.push_str(&format!(
+            "\n<p class=\"note\">Showing the first {} of {} files; the totals cover all of them.</p>",
+            max_rows,
+            rows.len()
+        ));
+    }
+    out
+}
+
+/// A horizontal bar chart as inline SVG, one labelled bar per row.
+fn bar_chart(title: &str, rows: &[(String, u64)]) -> String {
+    let max = rows
+        .iter()
+        .map(|(_, value)| *value)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let width = LABEL_WIDTH + BAR_AREA_WIDTH + VALUE_WIDTH;
+    let height = rows.len() * ROW_HEIGHT;
+    let mut out = format!(
+        "<h2>{}</h2>\n<svg class=\"chart\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" role=\"img\" aria-label=\"{}\">\n",
+        escape(title),
+        width,
+        height,
+        width,
+        height,
+        escape(title)
+    );
+    for (i, (label, value)) in rows.iter().enumerate() {
+        let y = i * ROW_HEIGHT;
+        let bar = (*value as f64 / max as f64 * BAR_AREA_WIDTH as f64).round() as usize;
+        let text_y = y + BAR_HEIGHT - 4;
+        out.push_str(&format!(
+            "<g><text x=\"0\" y=\"{}\">{}</text><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>\n",
+            text_y,
+            escape(label),
+            LABEL_WIDTH,
+            y,
+            bar,
+            BAR_HEIGHT,
+            LABEL_WIDTH + bar + 6,
+            text_y,
+            value
+        ));
+    }
+    out.push_str("</svg>");
+    out
+}
+
+/// Histogram buckets (`"0"`, `"10"`, ...) in numeric order.
+fn histogram_rows(total: &Value) -> Vec<(String, u64)> {
+    let Some(histogram) = total.get("histogram").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    let mut buckets: Vec<(u64, u64)> = histogram
+        .iter()
+        .filter_map(|(bucket, count)| Some((bucket.parse().ok()?, count.as_u64()?)))
+        .collect();
+    buckets.sort_unstable();
+    buckets
+        .into_iter()
+        .map(|(bucket, count)| (format!("{}-{}", bucket, bucket + 9), count))
+        .collect()
+}
+
+/// Sums `column` per file extension, largest first.
+fn extension_rows(files: &[Value], column: &str) -> Vec<(String, u64)> {
+    let mut by_extension: BTreeMap<String, u64> = BTreeMap::new();
+    for file in files {
+        let (Some(path), Some(counts)) = (file.get("file").and_then(Value::as_str), counts(file))
+        else {
+            continue;
+        };
+        let extension = Path::new(path)
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_else(|| "(none)".to_string());
+        *by_extension.entry(extension).or_default() += number(counts, column);
+    }
+    let mut rows: Vec<(String, u64)> = by_extension.into_iter().collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    rows
+}
+
+/// Renders the self-contained HTML page for `--html-report` from the same
+/// per-file and total entries that `--json` prints.
+pub fn render(files: &[Value], total: Option<&Value>, options: &Options) -> String {
+    let mut charts = String::new();
+    if let Some(histogram) = total.and_then(counts).map(histogram_rows)
+        && !histogram.is_empty()
+    {
+        charts.push_str(&bar_chart("Line length histogram", &histogram));
+    }
+    if let Some(column) = options.columns.first() {
+        let extensions = extension_rows(files, column);
+        if !extensions.is_empty() {
+            charts.push('\n');
+            charts.push_str(&bar_chart(&format!("{} by extension", column), &extensions));
+        }
+    }
+
+    let data = serde_json::json!({ "files": files, "total": total });
+    // Keep the blob from closing its <script> element early.
+    let data = data.to_string().replace("</", "<\\/");
+
+    TEMPLATE
+        .replace("{{summary}}", &summary(files, total, &options.columns))
+        .replace("{{charts}}", &charts)
+        .replace(
+            "{{table}}",
+            &table(files, &options.columns, options.max_rows),
+        )
+        .replace("{{data}}", &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, lines: u64) -> Value {
+        serde_json::json!({"file": file, "counts": {"lines": lines, "bytes": lines * 10}})
+    }
+
+    #[test]
+    fn test_table_caps_rows_and_escapes() {
+        let files: Vec<Value> = (0..5).map(|i| entry(&format!("<f{}>.rs", i), i)).collect();
+        let html = table(&files, &["lines".to_string()], 3);
+        assert_eq!(html.matches("<tr><td>").count(), 3);
+        assert!(html.contains("<td>&lt;f0&gt;.rs</td><td class=\"num\">0</td>"));
+        assert!(html.contains("Showing the first 3 of 5 files"));
+    }
+
+    #[test]
+    fn test_extension_rows_sum_and_order() {
+        let files = vec![
+            entry("a.rs", 3),
+            entry("b.md", 10),
+            entry("c.rs", 4),
+            entry("Makefile", 1),
+        ];
+        assert_eq!(
+            extension_rows(&files, "lines"),
+            vec![
+                (".md".to_string(), 10),
+                (".rs".to_string(), 7),
+                ("(none)".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_data_blob_cannot_close_script() {
+        let files = vec![entry("</script>.txt", 1)];
+        let html = render(
+            &files,
+            None,
+            &Options {
+                columns: vec!["lines".to_string()],
+                max_rows: 10,
+            },
+        );
+        assert!(!html.contains("\"</script>.txt"));
+        assert!(html.contains("<\\/script>.txt"));
+    }
+}
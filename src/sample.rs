@@ -0,0 +1,147 @@
+/// z-score for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959964;
+
+/// SplitMix64: tiny, fast and good enough for picking a file subset.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound` (bound > 0), without modulo bias worth caring
+    /// about at file-count scales.
+    fn below(&mut self, bound: usize) -> usize {
+        ((self.next() as u128 * bound as u128) >> 64) as usize
+    }
+}
+
+/// How many of `population` files a rate selects, at least one.
+pub fn size_for_rate(population: usize, rate: f64) -> usize {
+    ((population as f64 * rate).round() as usize).clamp(1, population.max(1))
+}
+
+/// Picks `size` distinct indices out of `0..population` with a partial
+/// Fisher-Yates shuffle, returned in ascending order so the original file
+/// order is kept. The same seed always gives the same subset.
+pub fn choose(population: usize, size: usize, seed: u64) -> Vec<usize> {
+    let size = size.min(population);
+    let mut indices: Vec<usize> = (0..population).collect();
+    let mut rng = SplitMix64(seed);
+    for i in 0..size {
+        let j = i + rng.below(population - i);
+        indices.swap(i, j);
+    }
+    indices.truncate(size);
+    indices.sort_unstable();
+    indices
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub total: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Estimates a population total from per-file sample values: the sample
+/// mean scaled up, with a normal-approximation 95% interval that includes
+/// the finite population correction (so a full census has zero width).
+pub fn estimate_total(sample: &[f64], population: usize) -> Estimate {
+    let n = sample.len();
+    if n == 0 {
+        return Estimate {
+            total: 0.0,
+            low: 0.0,
+            high: 0.0,
+        };
+    }
+    let mean = sample.iter().sum::<f64>() / n as f64;
+    let total = mean * population as f64;
+    if n < 2 || population <= 1 {
+        return Estimate {
+            total,
+            low: total,
+            high: total,
+        };
+    }
+    let variance = sample.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let fpc = (population - n) as f64 / (population - 1) as f64;
+    let margin = Z_95 * population as f64 * (variance / n as f64 * fpc).sqrt();
+    Estimate {
+        total,
+        low: (total - margin).max(0.0),
+        high: total + margin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_is_deterministic_and_distinct() {
+        let a = choose(1000, 50, 7);
+        assert_eq!(a, choose(1000, 50, 7));
+        assert_ne!(a, choose(1000, 50, 8));
+        assert_eq!(a.len(), 50);
+        assert!(a.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(choose(3, 10, 1), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_size_for_rate() {
+        assert_eq!(size_for_rate(1000, 0.1), 100);
+        assert_eq!(size_for_rate(10, 0.01), 1);
+        assert_eq!(size_for_rate(10, 1.0), 10);
+    }
+
+    #[test]
+    fn test_estimate_constant_population_is_exact() {
+        let estimate = estimate_total(&[5.0; 20], 100);
+        assert_eq!(estimate.total, 500.0);
+        assert_eq!(estimate.low, 500.0);
+        assert_eq!(estimate.high, 500.0);
+    }
+
+    #[test]
+    fn test_estimate_known_variance() {
+        // Sample 1..=10: mean 5.5, variance 55/6. Population 1000.
+        let sample: Vec<f64> = (1..=10).map(f64::from).collect();
+        let estimate = estimate_total(&sample, 1000);
+        assert_eq!(estimate.total, 5500.0);
+        let expected_margin = Z_95 * 1000.0 * ((55.0_f64 / 6.0) / 10.0 * (990.0 / 999.0)).sqrt();
+        assert!((estimate.high - 5500.0 - expected_margin).abs() < 1e-6);
+        assert!((5500.0 - estimate.low - expected_margin).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_interval_covers_uniform_population() {
+        // Population 0..10_000 (total 49_995_000); most seeds must cover it.
+        let population: Vec<f64> = (0..10_000).map(f64::from).collect();
+        let truth: f64 = population.iter().sum();
+        let covered = (0..100)
+            .filter(|&seed| {
+                let sample: Vec<f64> = choose(population.len(), 200, seed)
+                    .into_iter()
+                    .map(|i| population[i])
+                    .collect();
+                let estimate = estimate_total(&sample, population.len());
+                estimate.low <= truth && truth <= estimate.high
+            })
+            .count();
+        assert!(covered >= 88, "covered {} of 100", covered);
+    }
+
+    #[test]
+    fn test_census_has_no_margin() {
+        let sample = [1.0, 2.0, 3.0];
+        let estimate = estimate_total(&sample, 3);
+        assert_eq!(estimate.total, 6.0);
+        assert_eq!(estimate.low, estimate.high);
+    }
+}
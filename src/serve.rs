@@ -0,0 +1,178 @@
+use crate::config::Args;
+use crate::count;
+use crate::counter::{self, COUNTERS};
+use crate::{Counts, FileResult, file_deadline, process_data, process_file};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::io::{self, BufRead, Write};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const DEFAULT_COUNTERS: [&str; 3] = ["lines", "words", "bytes"];
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    path: Option<String>,
+    text: Option<String>,
+    counters: Option<Vec<String>>,
+    pattern: Option<String>,
+}
+
+pub fn run<R: BufRead, W: Write>(input: R, mut output: W, base: &Args) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_request(&line, base);
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Serves connections on the Unix socket at `path` until Ctrl-C, then
+/// removes the socket file.
+#[cfg(unix)]
+pub fn run_socket(path: &str, base: &Args) -> io::Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    let socket = path.to_string();
+    ctrlc::set_handler(move || {
+        let _ = std::fs::remove_file(&socket);
+        std::process::exit(crate::interrupt::EXIT_INTERRUPTED);
+    })
+    .map_err(io::Error::other)?;
+
+    let result = listener.incoming().try_for_each(|stream| {
+        let stream = stream?;
+        let reader = io::BufReader::new(stream.try_clone()?);
+        if let Err(e) = run(reader, stream, base) {
+            eprintln!("kz: serve: {}", e);
+        }
+        Ok(())
+    });
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+/// Removes a socket left behind by a server that was killed, so `bind` can
+/// reuse the path. A socket something still listens on is left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        return Ok(());
+    }
+    match UnixStream::connect(path) {
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn run_socket(_path: &str, _base: &Args) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--serve-socket is only supported on Unix",
+    ))
+}
+
+fn error_response(id: Option<Value>, message: String) -> Value {
+    json!({ "version": PROTOCOL_VERSION, "id": id, "error": message })
+}
+
+fn handle_request(line: &str, base: &Args) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(r) => r,
+        Err(e) => return error_response(None, format!("invalid request: {}", e)),
+    };
+
+    let counters: Vec<String> = request
+        .counters
+        .clone()
+        .unwrap_or_else(|| DEFAULT_COUNTERS.iter().map(|c| c.to_string()).collect());
+
+    let mut args = base.clone();
+    for counter in COUNTERS {
+        counter.set(&mut args, false);
+    }
+    args.count_matching_lines = false;
+    args.expect_width = None;
+    args.summary = false;
+    args.timing = false;
+    args.pattern = request.pattern.clone().into_iter().collect();
+    if args.regex {
+        args.pattern_regex = match args
+            .pattern
+            .iter()
+            .map(|pattern| count::compile_regex(pattern, args.ignore_case))
+            .collect()
+        {
+            Ok(regexes) => regexes,
+            Err(e) => {
+                return error_response(request.id, format!("invalid pattern regex: {}", e));
+            }
+        };
+    }
+
+    for counter in &counters {
+        match counter.as_str() {
+            "pattern" if !args.pattern.is_empty() => {}
+            "matching_lines" if !args.pattern.is_empty() => args.count_matching_lines = true,
+            name => match counter::find(name) {
+                Some(counter) if counter.set(&mut args, true) => {}
+                _ => {
+                    return error_response(request.id, format!("unknown counter '{}'", name));
+                }
+            },
+        }
+    }
+
+    let result = match (&request.path, &request.text) {
+        (Some(path), None) => process_file(path, &args),
+        (None, Some(text)) => Ok(FileResult::finish(
+            process_data(text.as_bytes(), &args, file_deadline(&args)),
+            None,
+        )),
+        _ => {
+            return error_response(
+                request.id,
+                "request needs exactly one of 'path' or 'text'".to_string(),
+            );
+        }
+    };
+
+    match result {
+        Ok(FileResult {
+            skipped: Some(reason),
+            ..
+        }) => error_response(request.id, format!("skipped ({})", reason)),
+        Ok(file_result) => json!({
+            "version": PROTOCOL_VERSION,
+            "id": request.id,
+            "file": request.path,
+            "counts": select_counters(&file_result.counts, &counters),
+        }),
+        Err(e) => error_response(request.id, e.to_string()),
+    }
+}
+
+fn select_counters(counts: &Counts, counters: &[String]) -> Value {
+    let all = serde_json::to_value(counts).unwrap_or(Value::Null);
+    let mut selected = Map::new();
+    for counter in counters {
+        if let Some(v) = counter::json_value(&all, counter) {
+            selected.insert(counter.clone(), v.clone());
+        }
+    }
+    Value::Object(selected)
+}
@@ -0,0 +1,123 @@
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes read from the start of each file to recognize its content.
+pub const SNIFF_BYTES: usize = 512;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Content kinds `--sniff-type` can keep.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffType {
+    /// Anything without NUL bytes
+    Text,
+    /// Text starting with `{` or `[`
+    Json,
+    /// Text starting with `<?xml`
+    Xml,
+    /// Text starting with a `#!` line
+    Script,
+    /// Text with a `#` heading, a code fence or a `[link](...)`
+    Markdown,
+}
+
+impl SniffType {
+    pub fn name(self) -> &'static str {
+        match self {
+            SniffType::Text => "text",
+            SniffType::Json => "json",
+            SniffType::Xml => "xml",
+            SniffType::Script => "script",
+            SniffType::Markdown => "markdown",
+        }
+    }
+
+    /// Whether a file starting with `prefix` looks like this kind.
+    pub fn matches(self, prefix: &[u8]) -> bool {
+        if memchr::memchr(0, prefix).is_some() {
+            return false;
+        }
+        let start = prefix.strip_prefix(UTF8_BOM).unwrap_or(prefix);
+        let trimmed = start.trim_ascii_start();
+        match self {
+            SniffType::Text => true,
+            SniffType::Json => matches!(trimmed.first(), Some(b'{' | b'[')),
+            SniffType::Xml => trimmed.starts_with(b"<?xml"),
+            SniffType::Script => start.starts_with(b"#!"),
+            SniffType::Markdown => looks_like_markdown(start),
+        }
+    }
+}
+
+fn looks_like_markdown(text: &[u8]) -> bool {
+    let heading = |line: &[u8]| {
+        let hashes = line.iter().take_while(|&&b| b == b'#').count();
+        (1..=6).contains(&hashes) && line.get(hashes) == Some(&b' ')
+    };
+    text.split(|&b| b == b'\n')
+        .any(|line| heading(line) || line.starts_with(b"```"))
+        || memchr::memmem::find(text, b"](").is_some()
+}
+
+fn read_prefix(path: &Path) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Keeps the files that look like `kind`, reading their first
+/// `SNIFF_BYTES` in parallel, and returns how many were dropped. Files that
+/// cannot be read are kept, so counting reports the error as usual.
+pub fn retain_matching(files: &mut Vec<String>, kind: SniffType) -> usize {
+    let keep: Vec<bool> = files
+        .par_iter()
+        .map(|file| read_prefix(Path::new(file)).map_or(true, |prefix| kind.matches(&prefix)))
+        .collect();
+    let before = files.len();
+    let mut keep = keep.into_iter();
+    files.retain(|_| keep.next().unwrap_or(true));
+    before - files.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        assert!(SniffType::Json.matches(b"  \n{\"a\": 1}"));
+        assert!(SniffType::Json.matches(b"\xEF\xBB\xBF[1, 2]"));
+        assert!(!SniffType::Json.matches(b"key: value"));
+        assert!(!SniffType::Json.matches(b"{\"a\": \0}"));
+    }
+
+    #[test]
+    fn test_xml_and_script() {
+        assert!(SniffType::Xml.matches(b"\n<?xml version=\"1.0\"?><a/>"));
+        assert!(!SniffType::Xml.matches(b"<html></html>"));
+        assert!(SniffType::Script.matches(b"#!/bin/sh\necho hi\n"));
+        assert!(!SniffType::Script.matches(b" #!/bin/sh\n"));
+        assert!(!SniffType::Script.matches(b"# comment\n"));
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert!(SniffType::Markdown.matches(b"intro\n\n## Usage\n"));
+        assert!(SniffType::Markdown.matches(b"```rust\nfn main() {}\n```\n"));
+        assert!(SniffType::Markdown.matches(b"see [docs](https://example.com)"));
+        assert!(!SniffType::Markdown.matches(b"#!/bin/sh\n#comment\n"));
+        assert!(!SniffType::Markdown.matches(b"plain notes\n"));
+    }
+
+    #[test]
+    fn test_text_rejects_binary() {
+        assert!(SniffType::Text.matches(b"caf\xC3\xA9\n"));
+        assert!(SniffType::Text.matches(b""));
+        assert!(!SniffType::Text.matches(b"\x7FELF\0\0"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::count::{Tokenize, Tokenizer};
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SHARDS: usize = 16;
+/// Rough per-entry overhead of a `HashSet<String>` slot beyond the word bytes.
+const ENTRY_OVERHEAD: usize = 48;
+
+/// A scratch directory of shard files, removed when dropped.
+struct SpillDir {
+    path: PathBuf,
+    writers: Vec<BufWriter<File>>,
+}
+
+impl SpillDir {
+    fn create(parent: &Path) -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let path = parent.join(format!("kz-spill-{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&path)?;
+        let mut dir = Self {
+            path,
+            writers: Vec::with_capacity(SHARDS),
+        };
+        for shard in 0..SHARDS {
+            let file = File::create(dir.shard_path(shard))?;
+            dir.writers.push(BufWriter::new(file));
+        }
+        Ok(dir)
+    }
+
+    fn shard_path(&self, shard: usize) -> PathBuf {
+        self.path.join(format!("shard-{:02}", shard))
+    }
+
+    fn spill(&mut self, words: &mut HashSet<String>) -> io::Result<()> {
+        for word in words.drain() {
+            let mut hasher = DefaultHasher::new();
+            word.hash(&mut hasher);
+            let shard = (hasher.finish() % SHARDS as u64) as usize;
+            let writer = &mut self.writers[shard];
+            writer.write_all(word.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Counts distinct words shard by shard; equal words always hash to
+    /// the same shard, so per-shard counts sum to the exact total.
+    fn count_distinct(mut self) -> io::Result<usize> {
+        for writer in &mut self.writers {
+            writer.flush()?;
+        }
+        let mut total = 0;
+        for shard in 0..SHARDS {
+            let reader = BufReader::new(File::open(self.shard_path(shard))?);
+            let mut seen = HashSet::new();
+            for line in reader.lines() {
+                seen.insert(line?);
+            }
+            total += seen.len();
+        }
+        Ok(total)
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        self.writers.clear();
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Exact distinct-word count that keeps at most about `budget` bytes of
+/// words in memory, sharding the overflow into temporary files under `dir`.
+pub fn count_unique_words(
+    data: &[u8],
+    tokenizer: Tokenizer,
+    dir: &Path,
+    budget: usize,
+) -> io::Result<usize> {
+    let text = String::from_utf8_lossy(data);
+    let mut words: HashSet<String> = HashSet::new();
+    let mut used = 0;
+    let mut spill: Option<SpillDir> = None;
+
+    for word in tokenizer.tokens(&text) {
+        if words.contains(word) {
+            continue;
+        }
+        used += word.len() + ENTRY_OVERHEAD;
+        words.insert(word.to_string());
+        if used > budget {
+            if spill.is_none() {
+                spill = Some(SpillDir::create(dir)?);
+            }
+            if let Some(ref mut spill) = spill {
+                spill.spill(&mut words)?;
+            }
+            used = 0;
+        }
+    }
+
+    match spill {
+        None => Ok(words.len()),
+        Some(mut spill) => {
+            spill.spill(&mut words)?;
+            spill.count_distinct()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::{self, TuningParams};
+
+    fn corpus() -> String {
+        (0..20_000)
+            .map(|i| format!("w{} common w{}\n", i % 7_919, i % 13))
+            .collect()
+    }
+
+    #[test]
+    fn test_spill_matches_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = corpus();
+        let expected = count::count_unique_words(
+            data.as_bytes(),
+            TuningParams::default(),
+            Tokenizer::Whitespace,
+        );
+
+        let spilled =
+            count_unique_words(data.as_bytes(), Tokenizer::Whitespace, dir.path(), 4096).unwrap();
+        assert_eq!(spilled, expected);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_large_budget_stays_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let count =
+            count_unique_words(b"a b a c b", Tokenizer::Whitespace, dir.path(), 1 << 20).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::config::Args;
+use crate::{Counts, TimedOut, count, process_data};
+use std::io::{self, Read};
+use std::time::Instant;
+
+/// Files at least this big are mapped rather than read into a buffer.
+pub const MMAP_THRESHOLD: u64 = 128 * 1024;
+
+/// Above this size a file is streamed instead of mapped or buffered. On
+/// 32-bit targets a mapping this big already takes a large share of the
+/// address space.
+pub const STREAM_THRESHOLD: u64 = if cfg!(target_pointer_width = "32") {
+    512 * 1024 * 1024
+} else {
+    u64::MAX
+};
+
+/// Streamed files are read and counted this much at a time.
+pub const BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// How `process_opened_file` gets at a file's bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStrategy {
+    Mmap,
+    Buffered,
+    /// Block by block, for files too large to hold in the address space.
+    Stream,
+}
+
+impl ReadStrategy {
+    /// The first choice for a file; a failed mapping or buffer allocation
+    /// falls back to `Stream` afterwards.
+    pub fn for_file(size: u64, is_file: bool, stream_threshold: u64) -> ReadStrategy {
+        if size > stream_threshold {
+            ReadStrategy::Stream
+        } else if size >= MMAP_THRESHOLD && is_file {
+            ReadStrategy::Mmap
+        } else {
+            ReadStrategy::Buffered
+        }
+    }
+}
+
+/// The options that need the whole file at once: line-aligned blocks
+/// cannot be summed for them with `Counts::add`.
+fn whole_file_options(args: &mut Args) -> Vec<(&'static str, &mut bool)> {
+    vec![
+        ("--unique", &mut args.unique),
+        ("--ttr", &mut args.ttr),
+        ("--repeated-lines", &mut args.repeated_lines),
+        ("--duplicate-lines", &mut args.duplicate_lines),
+        ("--prose-words", &mut args.prose_words),
+        ("--sentences", &mut args.sentences),
+        ("--paragraphs", &mut args.paragraphs),
+        ("--stats", &mut args.stats),
+        ("--lang-detect", &mut args.lang_detect),
+        ("--code", &mut args.code),
+        ("--cloc", &mut args.cloc),
+        ("--markdown", &mut args.markdown),
+        ("--html", &mut args.html),
+        ("--feff-count", &mut args.feff_count),
+        ("--join-continuations", &mut args.join_continuations),
+        ("--debug-internals", &mut args.debug_internals),
+    ]
+}
+
+/// `args` with the options that need the whole file turned off, and the
+/// names of those that were on.
+pub fn streamable(args: &Args) -> (Args, Vec<&'static str>) {
+    let mut args = args.clone();
+    let mut unavailable: Vec<&'static str> = whole_file_options(&mut args)
+        .into_iter()
+        .filter_map(|(name, enabled)| std::mem::take(enabled).then_some(name))
+        .collect();
+    for (name, set) in [
+        ("--expect-width", args.expect_width.take().is_some()),
+        ("--head", args.head.take().is_some()),
+        ("--tail", args.tail.take().is_some()),
+        ("--head-bytes", args.head_bytes.take().is_some()),
+    ] {
+        if set {
+            unavailable.push(name);
+        }
+    }
+    (args, unavailable)
+}
+
+/// Counts `reader` a block of whole lines at a time, summing the blocks
+/// with `Counts::add`. A line longer than the block grows it; if that
+/// allocation fails the line is counted in pieces. The encoding detected
+/// in the first block is used for the rest. None when that block looks
+/// binary.
+pub fn count_blocks(
+    mut reader: impl Read,
+    block_size: usize,
+    args: &Args,
+    deadline: Option<Instant>,
+) -> io::Result<Option<Result<Counts, TimedOut>>> {
+    let mut args = args.clone();
+    let mut total = Counts::new();
+    let mut buffer = vec![0u8; block_size];
+    let mut filled = 0;
+    let mut first = true;
+    loop {
+        let n = match reader.read(&mut buffer[filled..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        filled += n;
+        let at_end = n == 0;
+        if !at_end && filled < buffer.len() {
+            continue;
+        }
+
+        let end = if at_end {
+            filled
+        } else {
+            match memchr::memrchr(b'\n', &buffer[..filled]) {
+                Some(newline) => newline + 1,
+                None if buffer.try_reserve(buffer.len()).is_ok() => {
+                    buffer.resize(buffer.capacity(), 0);
+                    continue;
+                }
+                None => filled,
+            }
+        };
+
+        let block = &buffer[..end];
+        if first && !block.is_empty() {
+            if count::is_binary(block) {
+                return Ok(None);
+            }
+            if args.encoding.is_none() {
+                let (encoding, _) = count::detect_encoding(block, false);
+                args.encoding = Some(encoding.name().to_string());
+            }
+            first = false;
+        }
+        match process_data(block, &args, deadline) {
+            Ok(counts) => total.add(&counts),
+            Err(TimedOut) => return Ok(Some(Err(TimedOut))),
+        }
+
+        if at_end {
+            return Ok(Some(Ok(total)));
+        }
+        buffer.copy_within(end..filled, 0);
+        filled -= end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(flags: &[&str]) -> Args {
+        let mut args = Args::parse_from([&["kz"], flags].concat());
+        args.normalize();
+        args
+    }
+
+    fn counted(data: &[u8], block_size: usize, args: &Args) -> Counts {
+        match count_blocks(data, block_size, args, None).unwrap() {
+            Some(Ok(counts)) => counts,
+            _ => panic!("not counted"),
+        }
+    }
+
+    #[test]
+    fn test_read_strategy() {
+        let limit = 512 * 1024 * 1024;
+        assert_eq!(
+            ReadStrategy::for_file(3 << 30, true, limit),
+            ReadStrategy::Stream
+        );
+        assert_eq!(
+            ReadStrategy::for_file(1 << 20, true, limit),
+            ReadStrategy::Mmap
+        );
+        assert_eq!(
+            ReadStrategy::for_file(1 << 20, false, limit),
+            ReadStrategy::Buffered
+        );
+        assert_eq!(
+            ReadStrategy::for_file(100, true, limit),
+            ReadStrategy::Buffered
+        );
+        assert_eq!(
+            ReadStrategy::for_file(3 << 30, true, STREAM_THRESHOLD),
+            if cfg!(target_pointer_width = "32") {
+                ReadStrategy::Stream
+            } else {
+                ReadStrategy::Mmap
+            }
+        );
+    }
+
+    #[test]
+    fn test_blocks_match_whole_input() {
+        let data = "first line here\n\n  \nsecond café line with the word\n"
+            .repeat(50)
+            .into_bytes();
+        let args = args(&["-lwcmL", "-b", "--pattern", "the", "--unique-lines"]);
+        let whole = process_data(&data, &args, None).ok().unwrap();
+        for block_size in [7, 64, 1000, data.len() * 2] {
+            let streamed = counted(&data, block_size, &args);
+            assert_eq!(streamed.lines, whole.lines);
+            assert_eq!(streamed.words, whole.words);
+            assert_eq!(streamed.bytes, whole.bytes);
+            assert_eq!(streamed.chars, whole.chars);
+            assert_eq!(streamed.blank_lines, whole.blank_lines);
+            assert_eq!(streamed.max_line_length, whole.max_line_length);
+            assert_eq!(streamed.pattern, whole.pattern);
+            assert_eq!(streamed.unique_lines, whole.unique_lines);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_last_line() {
+        let args = args(&["-lwc"]);
+        let streamed = counted(b"one two\nthree", 4, &args);
+        assert_eq!((streamed.lines, streamed.words, streamed.bytes), (1, 3, 13));
+    }
+
+    #[test]
+    fn test_streamable_turns_off_whole_file_options() {
+        let (streamed, unavailable) = streamable(&args(&["-l", "--unique", "--head", "5"]));
+        assert!(streamed.lines && !streamed.unique && streamed.head.is_none());
+        assert_eq!(unavailable, ["--unique", "--head"]);
+        assert!(streamable(&args(&["-lw", "--pattern", "x"])).1.is_empty());
+    }
+
+    #[test]
+    fn test_binary_first_block() {
+        let args = args(&["-l"]);
+        assert!(
+            count_blocks(&b"\0\0\0\n"[..], 64, &args, None)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
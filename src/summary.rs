@@ -0,0 +1,440 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const DEFAULT_WIDTH: usize = 80;
+const MAX_PANEL_WIDTH: usize = 64;
+const MIN_PANEL_WIDTH: usize = 32;
+
+pub struct Summary {
+    pub files: usize,
+    pub lines: usize,
+    pub words: usize,
+    pub bytes: usize,
+    /// Allocated size on disk, with `--disk-usage`.
+    pub disk_bytes: Option<usize>,
+    pub largest_file: Option<(String, usize)>,
+    /// `path:LINE` of the longest line, and its length in bytes.
+    pub longest_line: Option<(String, usize)>,
+    pub file_sizes: Option<FileSizeStats>,
+    pub excluded: Excluded,
+    /// Files per `--lang-detect` language.
+    pub languages: BTreeMap<&'static str, usize>,
+    pub elapsed: Duration,
+    pub warnings: usize,
+    pub chunk_size: usize,
+    pub parallel_threshold: usize,
+}
+
+/// Files shown but left out of the total by `--total-of` or
+/// `--exclude-from-total`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Excluded {
+    pub files: usize,
+    pub lines: usize,
+}
+
+impl Excluded {
+    pub fn render(&self) -> String {
+        format!(
+            "{} file(s), {} line(s)",
+            self.files,
+            human_count(self.lines)
+        )
+    }
+}
+
+/// Min, median, mean and max of one per-file quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Spread {
+    pub min: usize,
+    pub median: f64,
+    pub mean: f64,
+    pub max: usize,
+}
+
+impl Spread {
+    fn of(mut values: Vec<usize>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_unstable();
+        let n = values.len();
+        let median = if n.is_multiple_of(2) {
+            (values[n / 2 - 1] + values[n / 2]) as f64 / 2.0
+        } else {
+            values[n / 2] as f64
+        };
+        Some(Self {
+            min: values[0],
+            median,
+            mean: values.iter().sum::<usize>() as f64 / n as f64,
+            max: values[n - 1],
+        })
+    }
+}
+
+/// How sizes and line counts spread across the counted files, for
+/// `--summary` and `--stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FileSizeStats {
+    pub files: usize,
+    pub total_bytes: usize,
+    pub bytes: Spread,
+    pub lines: Spread,
+}
+
+impl FileSizeStats {
+    /// From each file's (bytes, lines); None when no file was counted.
+    pub fn from_files(files: &[(usize, usize)]) -> Option<Self> {
+        Some(Self {
+            files: files.len(),
+            total_bytes: files.iter().map(|(bytes, _)| bytes).sum(),
+            bytes: Spread::of(files.iter().map(|(bytes, _)| *bytes).collect())?,
+            lines: Spread::of(files.iter().map(|(_, lines)| *lines).collect())?,
+        })
+    }
+
+    /// The text block printed after the total with `--stats`; `precision`
+    /// fixes the decimals of the line median and mean.
+    pub fn render(&self, precision: Option<usize>) -> String {
+        let (median, mean) = match precision {
+            Some(decimals) => (
+                crate::canonical::fixed(self.lines.median, decimals),
+                crate::canonical::fixed(self.lines.mean, decimals),
+            ),
+            None => (
+                self.lines.median.to_string(),
+                format!("{:.1}", self.lines.mean),
+            ),
+        };
+        format!(
+            "File sizes:\n  Files: {}\n  Total: {}\n  Bytes: min {}, median {}, mean {}, max {}\n  Lines: min {}, median {}, mean {}, max {}",
+            self.files,
+            human_bytes(self.total_bytes),
+            human_bytes(self.bytes.min),
+            human_bytes(self.bytes.median.round() as usize),
+            human_bytes(self.bytes.mean.round() as usize),
+            human_bytes(self.bytes.max),
+            self.lines.min,
+            median,
+            mean,
+            self.lines.max
+        )
+    }
+}
+
+struct BoxChars {
+    top_left: &'static str,
+    top_right: &'static str,
+    bottom_left: &'static str,
+    bottom_right: &'static str,
+    horizontal: &'static str,
+    vertical: &'static str,
+}
+
+const UNICODE_BOX: BoxChars = BoxChars {
+    top_left: "┌",
+    top_right: "┐",
+    bottom_left: "└",
+    bottom_right: "┘",
+    horizontal: "─",
+    vertical: "│",
+};
+
+const ASCII_BOX: BoxChars = BoxChars {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    horizontal: "-",
+    vertical: "|",
+};
+
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.trim().parse().ok())
+        .filter(|&w: &usize| w > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+pub fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn human_count(n: usize) -> String {
+    const UNITS: [&str; 4] = ["", "K", "M", "G"];
+    if n < 1000 {
+        return n.to_string();
+    }
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// Languages by file count, most common first: `en 3, de 1`.
+pub fn language_breakdown(languages: &BTreeMap<&'static str, usize>) -> String {
+    let mut ranked: Vec<(&str, usize)> = languages.iter().map(|(l, n)| (*l, *n)).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked
+        .iter()
+        .map(|(language, files)| format!("{} {}", language, files))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn truncate_left(s: &str, max: usize) -> String {
+    let len = s.chars().count();
+    if len <= max {
+        return s.to_string();
+    }
+    if max <= 3 {
+        return s.chars().skip(len - max).collect();
+    }
+    let tail: String = s.chars().skip(len - (max - 3)).collect();
+    format!("...{}", tail)
+}
+
+pub fn render(summary: &Summary, width: usize, ascii: bool) -> String {
+    let chars = if ascii { &ASCII_BOX } else { &UNICODE_BOX };
+    let panel_width = width.clamp(MIN_PANEL_WIDTH, MAX_PANEL_WIDTH);
+    let inner = panel_width - 4;
+    let label_width = 14;
+    let value_width = inner - label_width;
+
+    let secs = summary.elapsed.as_secs_f64();
+    let throughput = if secs > 0.0 {
+        format!("{}/s", human_bytes((summary.bytes as f64 / secs) as usize))
+    } else {
+        "-".to_string()
+    };
+
+    let mut rows: Vec<(&str, String)> = vec![
+        ("Files", summary.files.to_string()),
+        ("Lines", human_count(summary.lines)),
+        ("Words", human_count(summary.words)),
+        ("Bytes", human_bytes(summary.bytes)),
+    ];
+    if let Some(disk_bytes) = summary.disk_bytes {
+        rows.push(("On disk", human_bytes(disk_bytes)));
+    }
+    if let Some((path, size)) = &summary.largest_file {
+        rows.push(("Largest file", path.clone()));
+        rows.push(("", human_bytes(*size)));
+    }
+    if let Some((path, len)) = &summary.longest_line {
+        rows.push(("Longest line", path.clone()));
+        rows.push(("", format!("{} bytes", len)));
+    }
+    if let Some(sizes) = &summary.file_sizes {
+        rows.push((
+            "Size range",
+            format!(
+                "{} - {}",
+                human_bytes(sizes.bytes.min),
+                human_bytes(sizes.bytes.max)
+            ),
+        ));
+        rows.push((
+            "Median size",
+            format!(
+                "{} (mean {})",
+                human_bytes(sizes.bytes.median.round() as usize),
+                human_bytes(sizes.bytes.mean.round() as usize)
+            ),
+        ));
+        rows.push((
+            "Line range",
+            format!("{} - {}", sizes.lines.min, sizes.lines.max),
+        ));
+        rows.push((
+            "Median lines",
+            format!("{} (mean {:.1})", sizes.lines.median, sizes.lines.mean),
+        ));
+    }
+    if !summary.languages.is_empty() {
+        rows.push(("Languages", language_breakdown(&summary.languages)));
+    }
+    if summary.excluded.files > 0 {
+        rows.push(("Not in total", summary.excluded.render()));
+    }
+    rows.push(("Chunk size", human_bytes(summary.chunk_size)));
+    rows.push(("Threshold", human_bytes(summary.parallel_threshold)));
+    rows.push(("Elapsed", format!("{:.3}ms", secs * 1000.0)));
+    rows.push(("Throughput", throughput));
+    rows.push(("Warnings", summary.warnings.to_string()));
+
+    let title = " Summary ";
+    let mut out = String::new();
+    out.push_str(chars.top_left);
+    out.push_str(chars.horizontal);
+    out.push_str(title);
+    out.push_str(&chars.horizontal.repeat(panel_width - 3 - title.len()));
+    out.push_str(chars.top_right);
+    out.push('\n');
+
+    for (label, value) in rows {
+        let value = truncate_left(&value, value_width);
+        out.push_str(&format!(
+            "{} {:<label_width$}{:<value_width$} {}\n",
+            chars.vertical,
+            label,
+            value,
+            chars.vertical,
+            label_width = label_width,
+            value_width = value_width
+        ));
+    }
+
+    out.push_str(chars.bottom_left);
+    out.push_str(&chars.horizontal.repeat(panel_width - 2));
+    out.push_str(chars.bottom_right);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Summary {
+        Summary {
+            files: 3,
+            lines: 12_345,
+            words: 800,
+            bytes: 2048,
+            disk_bytes: None,
+            largest_file: Some(("src/main.rs".to_string(), 1536)),
+            longest_line: Some(("src/lib.rs:42".to_string(), 99)),
+            file_sizes: None,
+            excluded: Excluded::default(),
+            languages: BTreeMap::new(),
+            elapsed: Duration::from_millis(2),
+            warnings: 1,
+            chunk_size: 1024 * 1024,
+            parallel_threshold: 512 * 1024,
+        }
+    }
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_human_count() {
+        assert_eq!(human_count(999), "999");
+        assert_eq!(human_count(12_345), "12.3K");
+        assert_eq!(human_count(2_500_000), "2.5M");
+    }
+
+    #[test]
+    fn test_render_ascii_snapshot() {
+        let expected = "\
++- Summary --------------------+
+| Files         3              |
+| Lines         12.3K          |
+| Words         800            |
+| Bytes         2.0 KiB        |
+| Largest file  src/main.rs    |
+|               1.5 KiB        |
+| Longest line  src/lib.rs:42  |
+|               99 bytes       |
+| Chunk size    1.0 MiB        |
+| Threshold     512.0 KiB      |
+| Elapsed       2.000ms        |
+| Throughput    1000.0 KiB/s   |
+| Warnings      1              |
++------------------------------+";
+        assert_eq!(render(&sample(), 32, true), expected);
+    }
+
+    #[test]
+    fn test_file_size_stats() {
+        let stats =
+            FileSizeStats::from_files(&[(100, 4), (3000, 10), (200, 1), (1_000_000, 7)]).unwrap();
+        assert_eq!(stats.files, 4);
+        assert_eq!(stats.total_bytes, 1_003_300);
+        assert_eq!(
+            stats.bytes,
+            Spread {
+                min: 100,
+                median: 1600.0,
+                mean: 250_825.0,
+                max: 1_000_000
+            }
+        );
+        assert_eq!(
+            (stats.lines.min, stats.lines.median, stats.lines.max),
+            (1, 5.5, 10)
+        );
+        assert!(FileSizeStats::from_files(&[]).is_none());
+
+        let mut summary = sample();
+        summary.file_sizes = Some(stats);
+        let rendered = render(&summary, 48, true);
+        let rows: Vec<&str> = rendered
+            .lines()
+            .map(|line| line.trim_matches('|').trim())
+            .collect();
+        assert!(rows.contains(&"Size range    100 B - 976.6 KiB"));
+        assert!(rows.contains(&"Median size   1.6 KiB (mean 244.9 KiB)"));
+        assert!(rows.contains(&"Line range    1 - 10"));
+        assert!(rows.contains(&"Median lines  5.5 (mean 5.5)"));
+    }
+
+    #[test]
+    fn test_excluded_row() {
+        let mut summary = sample();
+        assert!(!render(&summary, 48, true).contains("Not in total"));
+        summary.excluded = Excluded {
+            files: 2,
+            lines: 1500,
+        };
+        let rendered = render(&summary, 48, true);
+        let rows: Vec<&str> = rendered
+            .lines()
+            .map(|line| line.trim_matches('|').trim())
+            .collect();
+        assert!(rows.contains(&"Not in total  2 file(s), 1.5K line(s)"));
+    }
+
+    #[test]
+    fn test_language_breakdown() {
+        let languages = BTreeMap::from([("de", 1), ("en", 3), ("unknown", 1)]);
+        assert_eq!(language_breakdown(&languages), "en 3, de 1, unknown 1");
+        let mut summary = sample();
+        summary.languages = languages;
+        assert!(render(&summary, 64, true).contains("| Languages     en 3, de 1, unknown 1"));
+    }
+
+    #[test]
+    fn test_render_respects_width() {
+        let rendered = render(&sample(), 40, false);
+        for line in rendered.lines() {
+            assert_eq!(line.chars().count(), 40);
+        }
+    }
+
+    #[test]
+    fn test_truncate_long_paths() {
+        assert_eq!(truncate_left("a/very/long/path.txt", 10), "...ath.txt");
+        assert_eq!(truncate_left("short", 10), "short");
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+
+/// A calendar day read from the start of a log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Date {
+    /// Days since 1970-01-01, for spacing dates by the gaps between them.
+    pub fn days(self) -> i64 {
+        let (month, day) = (i64::from(self.month), i64::from(self.day));
+        let year = i64::from(self.year) - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+        let leap =
+            year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days)
+            .contains(&day)
+            .then_some(Date { year, month, day })
+    }
+}
+
+const MONTH_NAMES: [&[u8; 3]; 12] = [
+    b"jan", b"feb", b"mar", b"apr", b"may", b"jun", b"jul", b"aug", b"sep", b"oct", b"nov", b"dec",
+];
+
+const ISO_DATE: [Field; 5] = [
+    Field::Year,
+    Field::Literal(b'-'),
+    Field::Month,
+    Field::Literal(b'-'),
+    Field::Day,
+];
+
+/// One step of a `DateFormat::Pattern`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `%Y`, four digits
+    Year,
+    /// `%m`, two digits
+    Month,
+    /// `%b`, an English month abbreviation in any case
+    MonthName,
+    /// `%d`, two digits
+    Day,
+    /// `%H`, `%M` or `%S`: two digits below the limit, checked but unused
+    Time(u8),
+    Literal(u8),
+}
+
+/// How `--group-by-timestamp` finds the date at the start of a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `auto`: an ISO 8601 date (`2024-03-01`), optionally after a `[`.
+    Iso8601,
+    /// A strftime-like pattern with `%Y`, `%m` or `%b`, and `%d`.
+    Pattern(Vec<Field>),
+}
+
+impl DateFormat {
+    /// Parses `auto` or a pattern using `%Y %m %b %d %H %M %S %%`.
+    pub fn parse(s: &str) -> Result<DateFormat, String> {
+        if s == "auto" {
+            return Ok(DateFormat::Iso8601);
+        }
+        let mut fields = Vec::new();
+        let mut bytes = s.bytes();
+        while let Some(b) = bytes.next() {
+            if b != b'%' {
+                fields.push(Field::Literal(b));
+                continue;
+            }
+            fields.push(match bytes.next() {
+                Some(b'Y') => Field::Year,
+                Some(b'm') => Field::Month,
+                Some(b'b') => Field::MonthName,
+                Some(b'd') => Field::Day,
+                Some(b'H') => Field::Time(24),
+                Some(b'M') => Field::Time(60),
+                Some(b'S') => Field::Time(61),
+                Some(b'%') => Field::Literal(b'%'),
+                Some(other) => {
+                    return Err(format!(
+                        "unsupported directive '%{}' in timestamp format '{}'",
+                        other as char, s
+                    ));
+                }
+                None => return Err(format!("timestamp format '{}' ends with '%'", s)),
+            });
+        }
+        let has = |wanted: &[Field]| fields.iter().any(|f| wanted.contains(f));
+        if !has(&[Field::Year]) || !has(&[Field::Month, Field::MonthName]) || !has(&[Field::Day]) {
+            return Err(format!(
+                "timestamp format '{}' needs %Y, %m or %b, and %d",
+                s
+            ));
+        }
+        Ok(DateFormat::Pattern(fields))
+    }
+
+    /// The date `line` starts with, if it matches.
+    pub fn date(&self, line: &[u8]) -> Option<Date> {
+        match self {
+            DateFormat::Iso8601 => {
+                let line = line.strip_prefix(b"[").unwrap_or(line);
+                let date = match_pattern(&ISO_DATE, line)?;
+                // 2024-03-011 is not a date.
+                match line.get(10) {
+                    Some(b) if b.is_ascii_digit() => None,
+                    _ => Some(date),
+                }
+            }
+            DateFormat::Pattern(fields) => match_pattern(fields, line),
+        }
+    }
+}
+
+fn digits(input: &[u8], n: usize) -> Option<u16> {
+    let digits = input.get(..n)?;
+    digits.iter().try_fold(0u16, |value, &b| {
+        b.is_ascii_digit().then(|| value * 10 + u16::from(b - b'0'))
+    })
+}
+
+fn match_pattern(fields: &[Field], line: &[u8]) -> Option<Date> {
+    let (mut year, mut month, mut day) = (0, 0, 0);
+    let mut rest = line;
+    for field in fields {
+        let width = match *field {
+            Field::Year => {
+                year = digits(rest, 4)?;
+                4
+            }
+            Field::Month => {
+                month = digits(rest, 2)? as u8;
+                2
+            }
+            Field::MonthName => {
+                let name = rest.get(..3)?.to_ascii_lowercase();
+                month = MONTH_NAMES.iter().position(|m| m[..] == name[..])? as u8 + 1;
+                3
+            }
+            Field::Day => {
+                day = digits(rest, 2)? as u8;
+                2
+            }
+            Field::Time(limit) => {
+                if digits(rest, 2)? >= u16::from(limit) {
+                    return None;
+                }
+                2
+            }
+            Field::Literal(b) => {
+                if rest.first() != Some(&b) {
+                    return None;
+                }
+                1
+            }
+        };
+        rest = &rest[width..];
+    }
+    Date::new(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8) -> Option<Date> {
+        Date::new(year, month, day)
+    }
+
+    #[test]
+    fn test_iso_auto() {
+        let auto = DateFormat::parse("auto").unwrap();
+        assert_eq!(auto.date(b"2024-03-01T12:00:00Z GET /"), date(2024, 3, 1));
+        assert_eq!(auto.date(b"[2024-03-01 12:00:00] INFO"), date(2024, 3, 1));
+        assert_eq!(auto.date(b"2024-03-01"), date(2024, 3, 1));
+        assert_eq!(auto.date(b"2024-02-30 bad day"), None);
+        assert_eq!(auto.date(b"2024-03-011"), None);
+        assert_eq!(auto.date(b"  at Foo.bar(Foo.java:12)"), None);
+        assert_eq!(auto.date(b""), None);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let access_log = DateFormat::parse("%d/%b/%Y:%H:%M:%S").unwrap();
+        assert_eq!(
+            access_log.date(b"07/Mar/2024:23:59:01 +0000"),
+            date(2024, 3, 7)
+        );
+        assert_eq!(access_log.date(b"07/Mar/2024:24:00:00"), None);
+        assert_eq!(access_log.date(b"07/Foo/2024:10:00:00"), None);
+
+        let compact = DateFormat::parse("%Y%m%d").unwrap();
+        assert_eq!(compact.date(b"20240229 leap"), date(2024, 2, 29));
+        assert_eq!(compact.date(b"20230229 not leap"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(DateFormat::parse("%Y-%m").is_err());
+        assert!(DateFormat::parse("%Y-%m-%d %Z").is_err());
+        assert!(DateFormat::parse("%Y-%m-%d%").is_err());
+        assert!(DateFormat::parse("%%%Y %b %d").is_ok());
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(date(1970, 1, 1).unwrap().days(), 0);
+        assert_eq!(date(2000, 3, 1).unwrap().days(), 11_017);
+        let leap_day = date(2024, 2, 29).unwrap().days();
+        assert_eq!(date(2024, 3, 1).unwrap().days() - leap_day, 1);
+        assert_eq!(date(1969, 12, 31).unwrap().days(), -1);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(date(987, 1, 2).unwrap().to_string(), "0987-01-02");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::Counts;
+use crate::config::Args;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// One directory (or file) in the `--tree` rollup, with the counts of
+/// everything below it.
+pub struct Node {
+    pub name: String,
+    pub counts: Counts,
+    pub children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            counts: Counts::new(),
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// Children ordered by the first counter, largest first, then by name.
+    fn sorted_children(&self, args: &Args) -> Vec<&Node> {
+        let primary = |node: &Node| node.counts.get_values(args).first().copied().unwrap_or(0);
+        let mut children: Vec<&Node> = self.children.values().collect();
+        children.sort_by(|a, b| {
+            primary(b)
+                .cmp(&primary(a))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        children
+    }
+}
+
+/// Builds the rollup from per-file counts, keyed by path components. The
+/// root is the directory every path shares (`.` when there is none), so a
+/// scan of `src/` starts at `src` rather than at an empty root.
+pub fn build(files: &[(&str, &Counts)]) -> Node {
+    let mut root = Node::new(".");
+    for (path, counts) in files {
+        root.counts.add(counts);
+        let mut node = &mut root;
+        for component in Path::new(path).components() {
+            let name = match component {
+                Component::CurDir => continue,
+                Component::Normal(name) => name.to_string_lossy().into_owned(),
+                other => other.as_os_str().to_string_lossy().into_owned(),
+            };
+            node = node
+                .children
+                .entry(name.clone())
+                .or_insert_with(|| Node::new(&name));
+            node.counts.add(counts);
+        }
+    }
+    // Fold single-directory chains at the top into the root's name.
+    let mut prefix = Vec::new();
+    while root.children.len() == 1
+        && root
+            .children
+            .values()
+            .next()
+            .is_some_and(|child| !child.children.is_empty())
+    {
+        let (_, child) = root.children.pop_first().expect("one child");
+        prefix.push(child.name);
+        root.children = child.children;
+    }
+    if !prefix.is_empty() {
+        root.name = prefix
+            .iter()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .into_owned();
+    }
+    root
+}
+
+fn branches(ascii: bool) -> [&'static str; 4] {
+    if ascii {
+        ["|-- ", "`-- ", "|   ", "    "]
+    } else {
+        ["├── ", "└── ", "│   ", "    "]
+    }
+}
+
+struct TextView<'a> {
+    args: &'a Args,
+    widths: Vec<usize>,
+    max_depth: Option<usize>,
+    out: String,
+}
+
+impl TextView<'_> {
+    fn node(&mut self, node: &Node, depth: usize, branch: &str, indent: &str) {
+        let values: Vec<String> = node
+            .counts
+            .get_values(self.args)
+            .iter()
+            .zip(&self.widths)
+            .map(|(v, width)| format!("{:>width$}", v))
+            .collect();
+        self.out.push_str(&format!(
+            "{} {}{}{}\n",
+            values.join(" "),
+            indent,
+            branch,
+            node.name
+        ));
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            return;
+        }
+        let [tee, elbow, pipe, blank] = branches(self.args.ascii);
+        let child_indent = if depth == 0 {
+            String::new()
+        } else {
+            format!("{}{}", indent, if branch == tee { pipe } else { blank })
+        };
+        let children = node.sorted_children(self.args);
+        for (i, child) in children.iter().enumerate() {
+            let branch = if i + 1 == children.len() { elbow } else { tee };
+            self.node(child, depth + 1, branch, &child_indent);
+        }
+    }
+}
+
+/// Renders the tree as text: counters on the left, aligned to the root's
+/// widths, then the indented name. Nodes deeper than `max_depth` are left
+/// out, though their counts stay in their ancestors.
+pub fn render(root: &Node, args: &Args, max_depth: Option<usize>) -> String {
+    let mut view = TextView {
+        args,
+        widths: root
+            .counts
+            .get_values(args)
+            .iter()
+            .map(|v| v.to_string().len())
+            .collect(),
+        max_depth,
+        out: String::new(),
+    };
+    view.node(root, 0, "", "");
+    view.out
+}
+
+/// The tree as nested `{name, counts, children}` objects for `--json`,
+/// children in the same order as the text view.
+pub fn to_json(node: &Node, args: &Args, max_depth: Option<usize>) -> serde_json::Value {
+    to_json_at(node, args, max_depth, 0)
+}
+
+fn to_json_at(
+    node: &Node,
+    args: &Args,
+    max_depth: Option<usize>,
+    depth: usize,
+) -> serde_json::Value {
+    let counts: serde_json::Map<String, serde_json::Value> = node
+        .counts
+        .counters(args)
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.into()))
+        .collect();
+    let children: Vec<serde_json::Value> = if max_depth.is_some_and(|max| depth >= max) {
+        Vec::new()
+    } else {
+        node.sorted_children(args)
+            .into_iter()
+            .map(|child| to_json_at(child, args, max_depth, depth + 1))
+            .collect()
+    };
+    serde_json::json!({
+        "name": node.name,
+        "counts": counts,
+        "children": children,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(lines: usize) -> Counts {
+        let mut counts = Counts::new();
+        counts.lines = lines;
+        counts
+    }
+
+    #[test]
+    fn test_rollup_and_render() {
+        let args = Args::parse_from(["kz", "-l", "--ascii"]);
+        let (a, b, c) = (counts(5), counts(30), counts(7));
+        let root = build(&[
+            ("proj/src/a.rs", &a),
+            ("proj/src/util/b.rs", &b),
+            ("proj/README.md", &c),
+        ]);
+        assert_eq!(root.name, "proj");
+        assert_eq!(root.counts.lines, 42);
+        assert_eq!(root.children["src"].counts.lines, 35);
+        assert_eq!(
+            render(&root, &args, None),
+            "42 proj\n35 |-- src\n30 |   |-- util\n30 |   |   `-- b.rs\n 5 |   `-- a.rs\n 7 `-- README.md\n"
+        );
+        assert_eq!(
+            render(&root, &args, Some(1)),
+            "42 proj\n35 |-- src\n 7 `-- README.md\n"
+        );
+    }
+
+    #[test]
+    fn test_unicode_branches_and_json() {
+        let args = Args::parse_from(["kz", "-l"]);
+        let (a, b) = (counts(1), counts(2));
+        let root = build(&[("x/a.txt", &a), ("y/b.txt", &b)]);
+        assert_eq!(root.name, ".");
+        assert_eq!(
+            render(&root, &args, None),
+            "3 .\n2 ├── y\n2 │   └── b.txt\n1 └── x\n1     └── a.txt\n"
+        );
+        let json = to_json(&root, &args, Some(1));
+        assert_eq!(json["counts"]["lines"], 3);
+        assert_eq!(json["children"][0]["name"], "y");
+        assert_eq!(json["children"][0]["children"], serde_json::json!([]));
+    }
+}
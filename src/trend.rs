@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FILE_NAME: &str = "trend.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RunRecord {
+    pub timestamp: u64,
+    pub totals: Vec<usize>,
+}
+
+/// Total rows from earlier `--trend` runs, keyed by a fingerprint of the
+/// inputs and flags so only comparable runs are diffed.
+pub struct TrendStore {
+    path: PathBuf,
+    runs: BTreeMap<String, RunRecord>,
+}
+
+/// `$KZ_CACHE_DIR`, else `$XDG_CACHE_HOME/kazoe`, else `~/.cache/kazoe`.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("KZ_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("kazoe"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("kazoe"))
+}
+
+/// Current time in seconds since the epoch, or `$SOURCE_DATE_EPOCH` when it
+/// is set, so reproducible builds record a fixed time.
+pub fn now_secs() -> u64 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+    {
+        return epoch;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl TrendStore {
+    /// Loads the store, treating a missing or unreadable file as empty.
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(FILE_NAME);
+        let runs = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, runs }
+    }
+
+    pub fn previous(&self, fingerprint: &str) -> Option<&RunRecord> {
+        self.runs.get(fingerprint)
+    }
+
+    pub fn record(&mut self, fingerprint: String, totals: Vec<usize>) {
+        let timestamp = now_secs();
+        self.runs
+            .insert(fingerprint, RunRecord { timestamp, totals });
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&self.runs).map_err(io::Error::other)?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Identifies a run by its canonical input paths and remaining flags,
+/// independent of the order they were given in.
+pub fn fingerprint(paths: &[String], flags: &[String]) -> String {
+    let mut paths: Vec<String> = paths
+        .iter()
+        .map(|p| {
+            fs::canonicalize(p)
+                .map(|c| c.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| p.clone())
+        })
+        .collect();
+    paths.sort();
+    let mut flags = flags.to_vec();
+    flags.sort();
+    format!("{}|{}", paths.join("\0"), flags.join(" "))
+}
+
+pub fn format_delta(current: usize, previous: usize) -> String {
+    let delta = current as i128 - previous as i128;
+    format!("{:+}", delta)
+}
+
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_delta_and_age() {
+        assert_eq!(format_delta(182_311, 181_071), "+1240");
+        assert_eq!(format_delta(5, 8), "-3");
+        assert_eq!(format_delta(7, 7), "+0");
+        assert_eq!(format_age(42), "42s ago");
+        assert_eq!(format_age(7200), "2h ago");
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_order() {
+        let a = fingerprint(
+            &["x".to_string(), "y".to_string()],
+            &["-l".to_string(), "-w".to_string()],
+        );
+        let b = fingerprint(
+            &["y".to_string(), "x".to_string()],
+            &["-w".to_string(), "-l".to_string()],
+        );
+        assert_eq!(a, b);
+        assert_ne!(a, fingerprint(&["x".to_string()], &[]));
+    }
+
+    #[test]
+    fn test_store_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = TrendStore::open(dir.path());
+        assert!(store.previous("run").is_none());
+        store.record("run".to_string(), vec![1, 2, 3]);
+        store.save().unwrap();
+
+        let reopened = TrendStore::open(dir.path());
+        assert_eq!(reopened.previous("run").unwrap().totals, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::count::TuningParams;
+use serde_json::Value;
+
+/// Relative tolerance for floating point statistics, whose summation order
+/// legitimately differs between the chunked and serial paths.
+const FLOAT_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub counter: String,
+    pub parallel: Value,
+    pub serial: Value,
+}
+
+fn floats_match(a: f64, b: f64) -> bool {
+    (a - b).abs() <= FLOAT_TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+fn compare_into(prefix: &str, parallel: &Value, serial: &Value, out: &mut Vec<Mismatch>) {
+    match (parallel, serial) {
+        (Value::Object(p), Value::Object(s)) => {
+            for key in p.keys().chain(s.keys().filter(|k| !p.contains_key(*k))) {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                compare_into(
+                    &name,
+                    p.get(key).unwrap_or(&Value::Null),
+                    s.get(key).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (Value::Number(p), Value::Number(s)) if p.is_f64() || s.is_f64() => {
+            if let (Some(a), Some(b)) = (p.as_f64(), s.as_f64())
+                && !floats_match(a, b)
+            {
+                out.push(Mismatch {
+                    counter: prefix.to_string(),
+                    parallel: parallel.clone(),
+                    serial: serial.clone(),
+                });
+            }
+        }
+        _ if parallel != serial => out.push(Mismatch {
+            counter: prefix.to_string(),
+            parallel: parallel.clone(),
+            serial: serial.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Field-by-field differences between two serialized count sets.
+pub fn compare(parallel: &Value, serial: &Value) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    compare_into("", parallel, serial, &mut mismatches);
+    mismatches
+}
+
+/// Runs `count` with the given tuning and again forced serial, returning
+/// every counter whose results disagree.
+pub fn check<E>(
+    tuning: TuningParams,
+    count: impl Fn(TuningParams) -> Result<Value, E>,
+) -> Result<Vec<Mismatch>, E> {
+    let parallel = count(tuning)?;
+    let serial = count(TuningParams::serial())?;
+    Ok(compare(&parallel, &serial))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count;
+    use std::convert::Infallible;
+
+    /// A line counter with a deliberate off-by-one on the parallel path.
+    fn broken_count_lines(data: &[u8], tuning: TuningParams) -> usize {
+        let lines = count::count_lines(data, tuning);
+        if data.len() >= tuning.parallel_threshold {
+            lines + 1
+        } else {
+            lines
+        }
+    }
+
+    fn tiny_chunks() -> TuningParams {
+        TuningParams {
+            chunk_size: count::MIN_CHUNK_SIZE,
+            parallel_threshold: 0,
+            deadline: None,
+        }
+    }
+
+    #[test]
+    fn test_check_catches_broken_parallel_path() {
+        let data = "a line of text\n".repeat(10_000);
+        let mismatches = check(tiny_chunks(), |tuning| {
+            Ok::<_, Infallible>(serde_json::json!({
+                "lines": broken_count_lines(data.as_bytes(), tuning),
+                "words": count::count_all_words(data.as_bytes(), tuning),
+            }))
+        })
+        .unwrap();
+
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                counter: "lines".to_string(),
+                parallel: serde_json::json!(10_001),
+                serial: serde_json::json!(10_000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_compare_nested_and_float_tolerance() {
+        let parallel = serde_json::json!({"statistics": {"mean": 1.0000000000001, "max": 4}});
+        let serial = serde_json::json!({"statistics": {"mean": 1.0, "max": 5}});
+        let mismatches = compare(&parallel, &serial);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].counter, "statistics.max");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::config::Args;
+use crate::{Counts, process_data};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+struct Interval {
+    buffer: Vec<u8>,
+    lines: usize,
+    started: Instant,
+}
+
+impl Interval {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            lines: 0,
+            started: Instant::now(),
+        }
+    }
+}
+
+pub fn run(args: &Args) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel::<io::Result<Vec<u8>>>();
+
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    if tx.send(Ok(line)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut stdout = io::stdout().lock();
+    let mut cumulative = Counts::new();
+    let mut interval = Interval::new();
+    let mut record = 0;
+
+    loop {
+        let received = match args.interval {
+            Some(period) => {
+                let remaining = period.saturating_sub(interval.started.elapsed());
+                rx.recv_timeout(remaining)
+            }
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(Ok(line)) => {
+                interval.buffer.extend_from_slice(&line);
+                interval.lines += 1;
+                let lines_due = args.interval_lines.is_some_and(|n| interval.lines >= n);
+                let time_due = args
+                    .interval
+                    .is_some_and(|period| interval.started.elapsed() >= period);
+                if lines_due || time_due {
+                    record += 1;
+                    emit(&mut stdout, args, &mut cumulative, &interval, record, false)?;
+                    interval = Interval::new();
+                }
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(RecvTimeoutError::Timeout) => {
+                record += 1;
+                emit(&mut stdout, args, &mut cumulative, &interval, record, false)?;
+                interval = Interval::new();
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                record += 1;
+                emit(&mut stdout, args, &mut cumulative, &interval, record, true)?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn emit<W: Write>(
+    out: &mut W,
+    args: &Args,
+    cumulative: &mut Counts,
+    interval: &Interval,
+    record: usize,
+    last: bool,
+) -> io::Result<()> {
+    let delta = process_data(&interval.buffer, args, None).unwrap_or_else(|_| Counts::new());
+    cumulative.add(&delta);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64();
+
+    if args.json {
+        let mut obj = serde_json::Map::new();
+        obj.insert("record".to_string(), record.into());
+        if let Some(ts) = serde_json::Number::from_f64(timestamp) {
+            obj.insert("timestamp".to_string(), serde_json::Value::Number(ts));
+        }
+        if !args.cumulative {
+            obj.insert(
+                "delta".to_string(),
+                serde_json::to_value(&delta).unwrap_or_default(),
+            );
+        }
+        if !args.delta_only {
+            obj.insert(
+                "cumulative".to_string(),
+                serde_json::to_value(&*cumulative).unwrap_or_default(),
+            );
+        }
+        obj.insert("final".to_string(), last.into());
+        writeln!(out, "{}", serde_json::Value::Object(obj))?;
+    } else {
+        let mut line = format!("[{:.3}]", timestamp);
+        if !args.cumulative {
+            line.push_str(&format!(" +{}", join_values(&delta, args)));
+        }
+        if !args.delta_only {
+            line.push_str(&format!(" = {}", join_values(cumulative, args)));
+        }
+        if last {
+            line.push_str(" (final)");
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+fn join_values(counts: &Counts, args: &Args) -> String {
+    counts
+        .get_values(args)
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,11 +1,29 @@
 use std::fs::{self, File};
 use std::io::Write;
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
 
 fn kz_cmd() -> Command {
     Command::new(env!("CARGO_BIN_EXE_kz"))
 }
 
+/// Runs `kz <args>` with `input` on stdin.
+fn kz_stdin(args: &[&str], input: impl AsRef<[u8]>) -> Output {
+    let mut child = kz_cmd()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_ref())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
 fn create_temp_dir() -> tempfile::TempDir {
     tempfile::tempdir().unwrap()
 }
@@ -122,11 +140,181 @@ mod multi_file {
         assert_eq!(lines.len(), 1);
         assert!(lines[0].contains("total"));
     }
+
+    fn list_with_missing_file(dir: &tempfile::TempDir) -> std::path::PathBuf {
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "aa\n").unwrap();
+        let list = dir.path().join("list");
+        let missing = dir.path().join("missing.txt");
+        fs::write(
+            &list,
+            format!("{}\0{}\0", file.display(), missing.display()),
+        )
+        .unwrap();
+        list
+    }
+
+    #[test]
+    fn total_only_reports_failed_files() {
+        let dir = create_temp_dir();
+        let list = list_with_missing_file(&dir);
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--total-only")
+            .arg("--files0-from")
+            .arg(&list)
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("total excludes 1 file(s) that could not be read"));
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["file"], "total");
+        assert_eq!(json[0]["files_failed"], 1);
+        assert_eq!(json[0]["counts"]["lines"], 1);
+    }
+
+    #[test]
+    fn require_complete_fails_before_totals() {
+        let dir = create_temp_dir();
+        let list = list_with_missing_file(&dir);
+
+        let output = kz_cmd()
+            .arg("--require-complete")
+            .arg("--files0-from")
+            .arg(&list)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--require-complete"));
+    }
 }
 
 mod recursive {
     use super::*;
 
+    fn tree_with_vcs_and_hidden() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::create_dir_all(dir.path().join(".git/objects/pack")).unwrap();
+        let mut pack = vec![0u8; 256 * 1024];
+        pack[..4].copy_from_slice(b"PACK");
+        fs::write(dir.path().join(".git/objects/pack/pack-1.pack"), pack).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::create_dir(dir.path().join(".config")).unwrap();
+        fs::write(dir.path().join(".config/settings"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn skips_vcs_dirs_by_default() {
+        let dir = tree_with_vcs_and_hidden();
+
+        let output = kz_cmd()
+            .arg("-r")
+            .arg("-v")
+            .arg("-l")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stdout.contains(".git"));
+        assert!(!stderr.contains(".git"));
+        assert!(stdout.contains(".config"));
+        assert!(stdout.contains("3 total"));
+        assert!(stderr.contains("1 hidden file(s) included"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("ok.txt"), "one\n").unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("secret.txt"), "two\n").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Privileged users can read the directory anyway; nothing to test.
+        if fs::read_dir(&locked).is_ok() {
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let output = kz_cmd()
+            .arg("-r")
+            .arg("--json")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("1 path(s) could not be read while walking directories"));
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let total = json.as_array().unwrap().last().unwrap();
+        assert_eq!(total["warnings"].as_array().unwrap().len(), 1);
+
+        let strict = kz_cmd()
+            .arg("-r")
+            .arg("--strict-walk")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(!strict.status.success());
+        assert!(strict.stdout.is_empty());
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn include_vcs_dirs_and_hidden_false() {
+        let dir = tree_with_vcs_and_hidden();
+
+        let output = kz_cmd()
+            .arg("-r")
+            .arg("-l")
+            .arg("--include-vcs-dirs")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).contains("HEAD"));
+
+        let output = kz_cmd()
+            .arg("-r")
+            .arg("-l")
+            .arg("--hidden=false")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains(".config"));
+        assert!(stdout.contains("main.rs"));
+    }
+
+    #[test]
+    fn bare_hidden_leaves_the_path_alone() {
+        let dir = tree_with_vcs_and_hidden();
+
+        let output = kz_cmd()
+            .args(["-l", "-r", "--hidden"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(".config"));
+        assert!(stdout.contains("3 total"));
+    }
+
     #[test]
     fn recursive_directory() {
         let dir = create_temp_dir();
@@ -180,6 +368,141 @@ mod recursive {
         assert!(stdout.contains("1"));
         assert!(!stdout.contains("b.log"));
     }
+
+    #[test]
+    fn include_pattern_with_exclude_precedence() {
+        let dir = create_temp_dir();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/main.rs"),
+            "fn main() {}
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("src/gen.rs"),
+            "// generated
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("README.md"),
+            "# readme
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("notes.txt"),
+            "notes
+",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "-r", "--include", "*.rs", "--include", "*.md"])
+            .args(["--exclude", "*gen*", "."])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("main.rs"));
+        assert!(stdout.contains("README.md"));
+        assert!(!stdout.contains("gen.rs"));
+        assert!(!stdout.contains("notes.txt"));
+        assert!(stdout.ends_with("2 total\n"));
+    }
+
+    #[test]
+    fn ext_filters_by_extension_ignoring_case() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("lib.rs"), "a\n").unwrap();
+        fs::write(dir.path().join("Cargo.TOML"), "b\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "c\n").unwrap();
+        fs::write(dir.path().join("rs"), "d\n").unwrap();
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "-r", "--ext", "rs", "--ext", ".toml", "."])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("lib.rs"));
+        assert!(stdout.contains("Cargo.TOML"));
+        assert!(!stdout.contains("notes.txt"));
+        assert!(stdout.ends_with("2 total\n"));
+    }
+}
+
+mod kzignore {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        let nested = dir.path().join("build").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("a.txt"), "1\n").unwrap();
+        fs::write(dir.path().join("skip.log"), "1\n2\n").unwrap();
+        fs::write(dir.path().join("keep.log"), "1\n2\n3\n").unwrap();
+        fs::write(nested.join("out.txt"), "1\n2\n3\n4\n").unwrap();
+        let logs = dir.path().join("logs").join("old");
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(logs.join("x.log"), "1\n").unwrap();
+        fs::write(logs.join("keep.log"), "1\n2\n3\n4\n5\n").unwrap();
+        fs::write(
+            dir.path().join(".kzignore"),
+            "# local exclusions\nbuild/\n*.log\n!keep.log\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn kzignore_applies_with_negation() {
+        let dir = fixture();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("-r")
+            .arg("-v")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("a.txt"));
+        assert!(stdout.contains("keep.log"));
+        assert!(!stdout.contains("skip.log"));
+        assert!(!stdout.contains("out.txt"));
+        assert!(!stdout.contains(".kzignore"));
+        assert!(stdout.contains("old/keep.log"));
+        assert!(!stdout.contains("x.log"));
+        assert!(stdout.contains("9 total"));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("3 file(s) ignored by .kzignore"));
+    }
+
+    #[test]
+    fn no_kzignore_disables_it() {
+        let dir = fixture();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("-r")
+            .arg("--no-kzignore")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("skip.log"));
+        assert!(stdout.contains("out.txt"));
+    }
 }
 
 mod json_output {
@@ -250,6 +573,25 @@ mod json_output {
         let first = &arr[0];
         assert!(first.get("counts").unwrap().get("statistics").is_some());
     }
+
+    #[test]
+    fn json_stats_word_lengths() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("test.txt");
+        fs::write(&file, "naïve\nlonger line here\n").unwrap();
+
+        let output = kz_cmd()
+            .args(["--json", "--stats"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let stats = &json[0]["counts"]["statistics"];
+        assert_eq!(stats["mean_word_length"], 4.75);
+        assert_eq!(stats["median_word_length"], 4);
+        assert_eq!(stats["max_word_length"], 6);
+        assert_eq!(json[0]["counts"]["words"], 4);
+    }
 }
 
 mod special_cases {
@@ -351,6 +693,29 @@ mod pattern_matching {
         assert!(stdout.contains("3"));
     }
 
+    #[test]
+    fn pattern_glob_counts_matching_lines() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("app.log");
+        fs::write(
+            &file,
+            "ERROR db timeout\nINFO ok\nERROR cache timeout\nERROR disk full\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .arg("--pattern")
+            .arg("ERROR*timeout")
+            .arg("--pattern-glob")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.trim_start().starts_with("2 "));
+    }
+
     #[test]
     fn pattern_no_matches() {
         let dir = create_temp_dir();
@@ -470,3 +835,4341 @@ mod filtering {
         assert!(stdout.contains("4"));
     }
 }
+
+mod file_timeout {
+    use super::*;
+
+    #[test]
+    fn timed_out_file_is_skipped() {
+        let dir = create_temp_dir();
+        let large = dir.path().join("large.txt");
+        let small = dir.path().join("small.txt");
+        fs::write(&large, "some words on a line\n".repeat(200_000)).unwrap();
+        fs::write(&small, "hello\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--file-timeout")
+            .arg("0.000000001")
+            .arg(&large)
+            .arg(&small)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        let first = &json.as_array().unwrap()[0];
+        assert_eq!(first.get("skipped").unwrap(), "timeout");
+        assert!(first.get("counts").is_none());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("exceeded --file-timeout"));
+        assert!(stderr.contains("large.txt"));
+    }
+
+    #[test]
+    fn generous_timeout_counts_normally() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("test.txt");
+        fs::write(&file, "line1\nline2\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("--file-timeout")
+            .arg("60")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("2"));
+    }
+}
+
+mod summary_panel {
+    use super::*;
+
+    #[test]
+    fn summary_only_prints_panel() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one two\nthree\n").unwrap();
+        fs::write(
+            dir.path().join("big.txt"),
+            "a much longer line of text here\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .env("COLUMNS", "40")
+            .arg("--ascii")
+            .arg("--summary-only")
+            .arg("-r")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(lines[0].starts_with("+- Summary "));
+        assert!(lines.iter().all(|l| l.chars().count() == 40));
+        assert!(stdout.contains("| Files         2"));
+        assert!(stdout.contains("| Lines         3"));
+        assert!(stdout.contains("big.txt:1 "));
+        assert!(stdout.contains("31 bytes"));
+        assert!(!stdout.contains("total"));
+    }
+
+    #[test]
+    fn summary_ignored_in_json_mode() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("test.txt");
+        fs::write(&file, "hello\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--summary")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_ok());
+    }
+}
+
+mod serve_mode {
+    use super::*;
+
+    #[test]
+    fn serve_answers_requests_until_eof() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("test.txt");
+        fs::write(&file, "hello world\nfoo\n").unwrap();
+
+        let path = serde_json::to_string(file.to_str().unwrap()).unwrap();
+        let requests = format!(
+            "{{\"id\": 1, \"path\": {}, \"counters\": [\"lines\", \"words\"]}}\n\
+             {{\"id\": 2, \"text\": \"one two three\"}}\n\
+             {{\"id\": 3, \"text\": \"x\", \"counters\": [\"bogus\"]}}\n",
+            path
+        );
+        let output = kz_stdin(&["--serve"], requests);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let responses: Vec<serde_json::Value> = stdout
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+
+        assert_eq!(responses[0]["version"], 1);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["counts"]["lines"], 2);
+        assert_eq!(responses[0]["counts"]["words"], 3);
+        assert!(responses[0]["counts"].get("bytes").is_none());
+
+        assert_eq!(responses[1]["counts"]["words"], 3);
+        assert_eq!(responses[1]["counts"]["bytes"], 13);
+
+        assert!(responses[2]["error"].as_str().unwrap().contains("bogus"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_path_is_reused_after_shutdown() {
+        use std::io::Read;
+        use std::os::unix::net::UnixStream;
+        use std::path::Path;
+        use std::time::{Duration, Instant};
+
+        fn start(socket: &Path) -> std::process::Child {
+            let child = kz_cmd()
+                .arg("--serve-socket")
+                .arg(socket)
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            let started = Instant::now();
+            while UnixStream::connect(socket).is_err() {
+                assert!(started.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            child
+        }
+
+        fn words(socket: &Path) -> serde_json::Value {
+            let mut stream = UnixStream::connect(socket).unwrap();
+            stream
+                .write_all(b"{\"id\": 1, \"text\": \"a b\"}\n")
+                .unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            serde_json::from_str::<serde_json::Value>(&response).unwrap()["counts"]["words"].clone()
+        }
+
+        let dir = create_temp_dir();
+        let socket = dir.path().join("kz.sock");
+
+        // A killed server leaves its socket behind; the next one replaces it.
+        let mut first = start(&socket);
+        assert_eq!(words(&socket), 2);
+        first.kill().unwrap();
+        first.wait().unwrap();
+        assert!(socket.exists());
+        let mut second = start(&socket);
+        assert_eq!(words(&socket), 2);
+
+        // A socket with a live server is not taken over.
+        let output = kz_cmd()
+            .arg("--serve-socket")
+            .arg(&socket)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert_eq!(words(&socket), 2);
+
+        // Ctrl-C removes the socket, so a third server starts cleanly.
+        Command::new("kill")
+            .args(["-INT", &second.id().to_string()])
+            .status()
+            .unwrap();
+        second.wait().unwrap();
+        assert!(!socket.exists());
+        let mut third = start(&socket);
+        assert_eq!(words(&socket), 2);
+        third.kill().unwrap();
+        third.wait().unwrap();
+    }
+}
+
+mod repeated_lines {
+    use super::*;
+
+    #[test]
+    fn counts_consecutive_repeats() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("app.log");
+        fs::write(&file, "start\nretry\nretry\nretry\ndone\nretry\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--repeated-lines")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(json[0]["counts"]["repeated_lines"], 2);
+    }
+}
+
+mod url_input {
+    use super::*;
+
+    #[cfg(feature = "http")]
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{}/data.txt", addr)
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn counts_url_content() {
+        let url = serve_once("200 OK", "hello world\nfoo bar baz\n");
+
+        let output = kz_cmd().arg("-l").arg("-w").arg(&url).output().unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("2 5"));
+        assert!(stdout.contains(&url));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn non_200_is_per_file_error() {
+        let url = serve_once("404 Not Found", "");
+
+        let output = kz_cmd().arg("-l").arg(&url).output().unwrap();
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("HTTP 404"));
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn url_requires_http_feature() {
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("https://example.com/data.txt")
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("http` feature"));
+    }
+}
+
+mod interval_mode {
+    use super::*;
+    use std::time::Duration;
+
+    fn records(stdout: &[u8]) -> Vec<serde_json::Value> {
+        String::from_utf8_lossy(stdout)
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn interval_lines_reports_deltas_and_cumulative() {
+        let output = kz_stdin(
+            &["-l", "--json", "--interval-lines", "2"],
+            b"a\nb\nc\nd\ne\n",
+        );
+
+        assert!(output.status.success());
+        let records = records(&output.stdout);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["delta"]["lines"], 2);
+        assert_eq!(records[1]["cumulative"]["lines"], 4);
+        assert_eq!(records[2]["delta"]["lines"], 1);
+        assert_eq!(records[2]["cumulative"]["lines"], 5);
+        assert_eq!(records[2]["final"], true);
+    }
+
+    #[test]
+    fn timed_interval_on_slow_stream() {
+        let mut child = kz_cmd()
+            .arg("-l")
+            .arg("--json")
+            .arg("--delta-only")
+            .arg("--interval")
+            .arg("100ms")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        {
+            let stdin = child.stdin.as_mut().unwrap();
+            for _ in 0..3 {
+                stdin.write_all(b"tick\n").unwrap();
+                stdin.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(250));
+            }
+        }
+        let output = child.wait_with_output().unwrap();
+
+        assert!(output.status.success());
+        let records = records(&output.stdout);
+        assert!(records.len() >= 3);
+        assert!(records[0].get("cumulative").is_none());
+        let total: u64 = records
+            .iter()
+            .map(|r| r["delta"]["lines"].as_u64().unwrap())
+            .sum();
+        assert_eq!(total, 3);
+    }
+}
+
+mod split_tests {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("src/lib.rs"), "1\n2\n3\n").unwrap();
+        fs::write(root.join("src/lib_test.rs"), "1\n").unwrap();
+        fs::write(root.join("tests/it.rs"), "1\n2\n").unwrap();
+        fs::write(root.join("pkg/app.py"), "1\n2\n3\n4\n").unwrap();
+        fs::write(root.join("pkg/test_app.py"), "1\n2\n3\n4\n5\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn split_tests_prints_subtotals() {
+        let dir = fixture();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("-r")
+            .arg("--split-tests")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(" 7 code\n"));
+        assert!(stdout.contains(" 8 tests\n"));
+        assert!(stdout.contains("15 total"));
+    }
+
+    #[test]
+    fn tests_only_and_no_tests_restrict_counting() {
+        let dir = fixture();
+
+        let tests_only = kz_cmd()
+            .arg("-l")
+            .arg("-r")
+            .arg("--total-only")
+            .arg("--tests-only")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&tests_only.stdout).contains("8 total"));
+
+        let no_tests = kz_cmd()
+            .arg("-l")
+            .arg("-r")
+            .arg("--total-only")
+            .arg("--no-tests")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&no_tests.stdout).contains("7 total"));
+    }
+
+    #[test]
+    fn json_entries_carry_category() {
+        let dir = fixture();
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("-r")
+            .arg("--split-tests")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        for entry in json.as_array().unwrap() {
+            let file = entry["file"].as_str().unwrap();
+            if file == "total" {
+                continue;
+            }
+            let expected = if file.contains("test") {
+                "test"
+            } else {
+                "source"
+            };
+            assert_eq!(entry["category"], expected, "{}", file);
+        }
+    }
+}
+
+mod fixed_width {
+    use super::*;
+
+    fn fixture(dir: &tempfile::TempDir) -> std::path::PathBuf {
+        let file = dir.path().join("records.dat");
+        let mut content = String::new();
+        for i in 0..10 {
+            if i == 3 || i == 7 {
+                content.push_str("SHORT\n");
+            } else {
+                content.push_str(&format!("REC{:07}\n", i));
+            }
+        }
+        fs::write(&file, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn cardinality_lists_small_sets() {
+        let dir = create_temp_dir();
+        let file = fixture(&dir);
+
+        let output = kz_cmd()
+            .arg("--line-length-cardinality")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("2 "));
+        assert!(stdout.contains("line lengths: 5 (2 lines), 10 (8 lines)"));
+    }
+
+    #[test]
+    fn expect_width_reports_bad_rows() {
+        let dir = create_temp_dir();
+        let file = fixture(&dir);
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--expect-width")
+            .arg("10")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let counts = &json[0]["counts"];
+        assert_eq!(counts["nonconforming_lines"], 2);
+        assert_eq!(
+            counts["nonconforming_line_numbers"],
+            serde_json::json!([4, 8])
+        );
+    }
+
+    #[test]
+    fn expect_width_passes_on_conforming_file() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("ok.dat");
+        fs::write(&file, "abc\ndef\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("--expect-width")
+            .arg("3")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+    }
+}
+
+mod emoji {
+    use super::*;
+
+    #[test]
+    fn counts_emoji_column_and_json() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("copy.txt");
+        fs::write(
+            &file,
+            "Launch \u{1F680} now \u{00A9} 2024\nTeam \u{1F469}\u{200D}\u{1F4BB}\u{1F3FD} \u{1F1FA}\u{1F1F8}\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd().arg("--emoji").arg(&file).output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("3 "));
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--emoji")
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["counts"]["emoji"], 3);
+    }
+}
+
+mod check {
+    use super::*;
+
+    #[test]
+    fn reports_violations_and_fails() {
+        let dir = create_temp_dir();
+        let ok = dir.path().join("ok.md");
+        let long = dir.path().join("long.md");
+        fs::write(&ok, "<!-- kz: max-words=10 -->\nshort doc\n").unwrap();
+        fs::write(
+            &long,
+            "<!-- kz: max-words=5 max-lines=10 -->\none two three four five six\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .arg("--check")
+            .arg(&ok)
+            .arg(&long)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout.lines().count(), 1);
+        assert!(stdout.contains("long.md: max-words expected <= 5, got 11"));
+    }
+
+    #[test]
+    fn files_without_directives_pass_unless_required() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("plain.txt");
+        fs::write(&file, "nothing to see\n").unwrap();
+
+        let output = kz_cmd().arg("--check").arg(&file).output().unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+
+        let output = kz_cmd()
+            .arg("--check")
+            .arg("--check-require")
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("no kz: directives"));
+    }
+
+    #[test]
+    fn mistyped_directive_is_reported() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("doc.md");
+        fs::write(&file, "<!-- kz: max-word=1 -->\none two three\n").unwrap();
+
+        let output = kz_cmd().arg("--check").arg(&file).output().unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("kz: warning: "));
+        assert!(stderr.contains("doc.md: unrecognised kz: directive max-word=1"));
+
+        let output = kz_cmd()
+            .arg("--check")
+            .arg("--check-require")
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stdout)
+                .contains("doc.md: unrecognised kz: directive max-word=1")
+        );
+    }
+}
+
+mod tuning {
+    use super::*;
+
+    #[test]
+    fn custom_chunking_keeps_counts() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("big.txt");
+        fs::write(&file, "alpha beta gamma\n".repeat(50_000)).unwrap();
+
+        let default = kz_cmd().arg(&file).output().unwrap();
+        let tuned = kz_cmd()
+            .arg("--chunk-size")
+            .arg("64K")
+            .arg("--parallel-threshold")
+            .arg("0")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(tuned.status.success());
+        assert_eq!(default.stdout, tuned.stdout);
+    }
+
+    #[test]
+    fn rejects_tiny_chunk_size() {
+        let output = kz_cmd()
+            .arg("--chunk-size")
+            .arg("4K")
+            .arg("Cargo.toml")
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("at least 64K"));
+    }
+}
+
+mod validation {
+    use super::*;
+
+    fn rejected(args: &[&str]) -> String {
+        let output = kz_cmd().args(args).arg("Cargo.toml").output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    #[test]
+    fn code_and_markdown_conflict() {
+        let stderr = rejected(&["--code", "--markdown"]);
+        assert!(stderr.contains("error: the argument '--code' cannot be used with '--markdown'"));
+    }
+
+    #[test]
+    fn usage_names_the_binary() {
+        for args in [&["--code", "--markdown"][..], &["--sort", "pattern"]] {
+            let stderr = rejected(args);
+            assert!(stderr.contains("Usage: kz "), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn fast_and_encoding_conflict() {
+        let stderr = rejected(&["--fast", "--encoding", "utf-8"]);
+        assert!(
+            stderr.contains(
+                "error: the argument '--fast' cannot be used with '--encoding <ENCODING>'"
+            )
+        );
+    }
+
+    #[test]
+    fn summary_only_and_json_conflict() {
+        let stderr = rejected(&["--summary-only", "--json"]);
+        assert!(stderr.contains(
+            "error: --summary-only prints nothing with --json, --ndjson or --csv; drop one of them"
+        ));
+    }
+
+    #[test]
+    fn fast_without_chars_warns() {
+        let output = kz_cmd()
+            .args(["--fast", "-w", "Cargo.toml"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("kz: warning: --fast only affects character counting (-m)")
+        );
+    }
+
+    #[test]
+    fn progress_to_pipe_warns() {
+        let output = kz_cmd()
+            .args(["--progress", "Cargo.toml"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("stderr is not a terminal"));
+    }
+}
+
+mod schedule {
+    use super::*;
+
+    #[test]
+    fn output_keeps_input_order_for_both_modes() {
+        let dir = create_temp_dir();
+        let small = dir.path().join("a_small.txt");
+        let large = dir.path().join("b_large.txt");
+        fs::write(&small, "one\n").unwrap();
+        fs::write(&large, "line\n".repeat(10_000)).unwrap();
+
+        for mode in ["size", "input"] {
+            let output = kz_cmd()
+                .arg("-l")
+                .arg("--schedule")
+                .arg(mode)
+                .arg(&small)
+                .arg(&large)
+                .output()
+                .unwrap();
+
+            assert!(output.status.success());
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let lines: Vec<&str> = stdout.lines().collect();
+            assert!(lines[0].contains("a_small.txt"));
+            assert!(lines[1].contains("b_large.txt"));
+        }
+    }
+}
+
+mod value_only {
+    use super::*;
+
+    #[test]
+    fn prints_bare_number_for_file() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("--value-only")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    }
+
+    #[test]
+    fn works_with_stdin_and_pattern() {
+        let output = kz_stdin(&["--pattern", "ab", "--value-only"], b"ab ab abc\n");
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    }
+
+    #[test]
+    fn rejects_multiple_counters() {
+        let output = kz_cmd()
+            .arg("--value-only")
+            .arg("Cargo.toml")
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("--value-only needs exactly one counter, but 3 are enabled")
+        );
+    }
+
+    #[test]
+    fn rejects_multiple_inputs() {
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("--value-only")
+            .arg("Cargo.toml")
+            .arg("README.md")
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("--value-only needs a single file or stdin")
+        );
+    }
+}
+
+mod prose_words {
+    use super::*;
+
+    #[test]
+    fn reported_alongside_word_count() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("report.md");
+        fs::write(
+            &file,
+            "Revenue grew 12% in 2024\n\n| Q1 | Q2 |\n|----|----|\n| 10 | 20 |\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("-w")
+            .arg("--prose-words")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["counts"]["words"], 16);
+        assert_eq!(json[0]["counts"]["prose_words"], 5);
+    }
+}
+
+mod with_raw {
+    use super::*;
+
+    fn counts_json(args: &[&str], file: &std::path::Path) -> serde_json::Value {
+        let output = kz_cmd()
+            .args(args)
+            .arg("--json")
+            .arg(file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        json[0]["counts"].clone()
+    }
+
+    #[test]
+    fn raw_and_filtered_match_separate_runs() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("lib.rs");
+        fs::write(
+            &file,
+            "// header comment\nfn main() {\n    /* inline */ run();\n}\n# not a comment in rust\n",
+        )
+        .unwrap();
+
+        let both = counts_json(&["-lw", "--code", "--with-raw"], &file);
+        let raw = counts_json(&["-lw"], &file);
+        let filtered = counts_json(&["-lw", "--code"], &file);
+
+        assert_eq!(both["raw"], raw);
+        assert_eq!(both["filtered"], filtered);
+        assert_ne!(both["raw"]["words"], both["filtered"]["words"]);
+    }
+
+    #[test]
+    fn text_output_pairs_columns() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("notes.md");
+        fs::write(&file, "Intro text\n```\ncode block\n```\nOutro\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("--markdown")
+            .arg("--with-raw")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let fields: Vec<&str> = stdout.split_whitespace().collect();
+        assert_eq!(fields[0], "5");
+        assert_ne!(fields[0], fields[1]);
+    }
+
+    #[test]
+    fn requires_a_filter() {
+        let output = kz_cmd()
+            .arg("--with-raw")
+            .arg("Cargo.toml")
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains(
+                "--with-raw needs a filter (--code, --markdown, --html or --auto-filter)"
+            )
+        );
+    }
+}
+
+mod trend {
+    use super::*;
+
+    fn run_trend(cache: &std::path::Path, dir: &std::path::Path) -> String {
+        let output = kz_cmd()
+            .env("KZ_CACHE_DIR", cache)
+            .arg("-l")
+            .arg("-r")
+            .arg("--trend")
+            .arg(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn reports_delta_against_previous_run() {
+        let cache = create_temp_dir();
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "1\n2\n").unwrap();
+
+        let first = run_trend(cache.path(), dir.path());
+        assert!(first.contains("2 total (no previous run)"));
+
+        fs::write(dir.path().join("b.txt"), "1\n2\n3\n").unwrap();
+        let second = run_trend(cache.path(), dir.path());
+        assert!(second.contains("5 (+3) total"));
+        assert!(second.contains("previous run:"));
+
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+        let third = run_trend(cache.path(), dir.path());
+        assert!(third.contains("2 (-3) total"));
+    }
+}
+
+mod word_boundary {
+    use super::*;
+
+    #[test]
+    fn unicode_mode_splits_on_word_boundaries() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("prose.txt");
+        fs::write(&file, "state-of-the-art -- done\n").unwrap();
+
+        let whitespace = kz_cmd().arg("-w").arg(&file).output().unwrap();
+        assert!(String::from_utf8_lossy(&whitespace.stdout).starts_with("3 "));
+
+        let unicode = kz_cmd()
+            .arg("-w")
+            .arg("--word-boundary")
+            .arg("unicode")
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(unicode.status.success());
+        assert!(String::from_utf8_lossy(&unicode.stdout).starts_with("5 "));
+    }
+
+    #[test]
+    fn kmer_tokenizer_counts_distinct_kmers() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("seq.fa");
+        fs::write(&file, "ACGTACGT\n").unwrap();
+
+        let output = kz_cmd()
+            .args(["-w", "--unique", "--tokenizer", "kmer:3"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("6 4 "));
+
+        let output = kz_cmd()
+            .args(["-w", "--tokenizer", "kmer:0"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod compare {
+    use super::*;
+
+    #[test]
+    fn lists_largest_changes_between_reports() {
+        let dir = create_temp_dir();
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.txt"), "1\n").unwrap();
+        fs::write(src.join("b.txt"), "1\n2\n").unwrap();
+
+        let report = |name: &str| {
+            let output = kz_cmd()
+                .arg("--json")
+                .arg("-l")
+                .arg("-r")
+                .arg(&src)
+                .output()
+                .unwrap();
+            let path = dir.path().join(name);
+            fs::write(&path, output.stdout).unwrap();
+            path
+        };
+
+        let before = report("before.json");
+        fs::write(src.join("b.txt"), "1\n".repeat(50)).unwrap();
+        fs::remove_file(src.join("a.txt")).unwrap();
+        fs::write(src.join("c.txt"), "1\n2\n3\n").unwrap();
+        let after = report("after.json");
+
+        let output = kz_cmd()
+            .arg("compare")
+            .arg(&before)
+            .arg(&after)
+            .arg("--top")
+            .arg("2")
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let rows: Vec<&str> = stdout.lines().skip(2).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("changed") && rows[0].contains("+48"));
+        assert!(rows[1].starts_with("added") && rows[1].ends_with("c.txt"));
+    }
+
+    #[test]
+    fn rejects_unknown_sort_key() {
+        let output = kz_cmd()
+            .arg("compare")
+            .arg("a.json")
+            .arg("b.json")
+            .arg("--sort")
+            .arg("lines")
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid sort key 'lines'"));
+    }
+}
+
+mod unique_spill {
+    use super::*;
+
+    #[test]
+    fn spilled_count_matches_in_memory() {
+        let dir = create_temp_dir();
+        let spill_dir = create_temp_dir();
+        let file = dir.path().join("corpus.txt");
+        let text: String = (0..5000)
+            .map(|i| format!("tok{} shared\n", i % 997))
+            .collect();
+        fs::write(&file, text).unwrap();
+
+        let in_memory = kz_cmd().arg("--unique").arg(&file).output().unwrap();
+        let spilled = kz_cmd()
+            .arg("--unique")
+            .arg("--unique-exact-spill")
+            .arg(spill_dir.path())
+            .arg("--spill-budget")
+            .arg("2K")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(spilled.status.success());
+        assert_eq!(in_memory.stdout, spilled.stdout);
+        assert!(String::from_utf8_lossy(&spilled.stdout).starts_with("998 "));
+        assert_eq!(fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+    }
+}
+
+mod top_lines {
+    use super::*;
+
+    fn log_fixture(dir: &tempfile::TempDir, name: &str, errors: usize) -> std::path::PathBuf {
+        let file = dir.path().join(name);
+        let mut log = String::new();
+        for i in 0..errors {
+            log.push_str(&format!("12:00:{:02} ERROR timeout\n", i % 60));
+        }
+        log.push_str("12:01:00 INFO ok\n12:01:01 INFO ok\n");
+        fs::write(&file, log).unwrap();
+        file
+    }
+
+    #[test]
+    fn lists_most_frequent_lines() {
+        let dir = create_temp_dir();
+        let file = log_fixture(&dir, "app.log", 5);
+
+        let output = kz_cmd()
+            .arg("-l")
+            .arg("--top-lines")
+            .arg("2")
+            .arg("--skip-fields")
+            .arg("1")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "  5  12:00:00 ERROR timeout");
+        assert_eq!(lines[2], "  2  12:01:00 INFO ok");
+    }
+
+    #[test]
+    fn total_only_merges_files_in_json() {
+        let dir = create_temp_dir();
+        let a = log_fixture(&dir, "a.log", 3);
+        let b = log_fixture(&dir, "b.log", 4);
+
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--total-only")
+            .arg("--top-lines")
+            .arg("1")
+            .arg("--skip-fields")
+            .arg("1")
+            .arg("--top-lines-width")
+            .arg("12")
+            .arg(&a)
+            .arg(&b)
+            .output()
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let top = &json[0]["counts"]["top_lines"];
+        assert_eq!(top[0]["count"], 7);
+        assert_eq!(top[0]["line"], "12:00:00 ...");
+    }
+}
+
+mod join_continuations {
+    use super::*;
+
+    #[test]
+    fn counts_logical_lines() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("Makefile");
+        fs::write(
+            &file,
+            "SRCS = a.c \\\n       b.c \\\n       c.c\n\nall:\n\tcc $(SRCS) \\\n",
+        )
+        .unwrap();
+
+        let physical = kz_cmd().arg("-l").arg(&file).output().unwrap();
+        assert!(String::from_utf8_lossy(&physical.stdout).starts_with("6 "));
+
+        let output = kz_cmd()
+            .arg("-lL")
+            .arg("--blank-lines")
+            .arg("--join-continuations")
+            .arg("--json")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let counts = &json[0]["counts"];
+        assert_eq!(counts["lines"], 4);
+        assert_eq!(counts["blank_lines"], 1);
+        assert_eq!(
+            counts["max_line_length"],
+            "SRCS = a.c        b.c        c.c".len()
+        );
+    }
+}
+
+mod detect_encodings {
+    use super::*;
+
+    fn fixture_tree() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("utf8.txt"), "héllo wörld\n").unwrap();
+        fs::write(dir.path().join("ascii.txt"), "plain\n").unwrap();
+        fs::write(
+            dir.path().join("latin1.txt"),
+            b"Le caf\xe9 est tr\xe8s fran\xe7ais, d\xe9j\xe0 la na\xefvet\xe9 du r\xe9sum\xe9.\n",
+        )
+        .unwrap();
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS
+            .encode("日本語のテキストです。これはシフトJISで保存されたファイルです。\n");
+        fs::write(dir.path().join("sjis.txt"), sjis).unwrap();
+        let mut utf16 = vec![0xFF, 0xFE];
+        for unit in "hello\n".encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        fs::write(dir.path().join("utf16.txt"), utf16).unwrap();
+        dir
+    }
+
+    #[test]
+    fn groups_files_by_encoding() {
+        let dir = fixture_tree();
+
+        let output = kz_cmd()
+            .arg("--detect-encodings")
+            .arg("--verbose")
+            .arg("-r")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(lines[0].starts_with("encoding"));
+        assert!(lines[1].starts_with("UTF-8 ") && lines[1].ends_with(" 2"));
+        assert!(stdout.contains("UTF-16LE (BOM)"));
+        assert!(stdout.contains("windows-1252"));
+        assert!(stdout.contains("Shift_JIS"));
+        assert!(stdout.contains("sjis.txt"));
+        assert!(!stdout.contains("ascii.txt"));
+    }
+
+    #[test]
+    fn json_lists_every_file() {
+        let dir = fixture_tree();
+
+        let output = kz_cmd()
+            .arg("--detect-encodings")
+            .arg("--json")
+            .arg("-r")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json["files"].as_array().unwrap().len(), 5);
+        assert_eq!(json["encodings"][0]["encoding"], "UTF-8");
+        assert_eq!(json["encodings"][0]["files"], 2);
+        let utf16 = json["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["file"].as_str().unwrap().ends_with("utf16.txt"))
+            .unwrap();
+        assert_eq!(utf16["bom"], true);
+    }
+
+    #[test]
+    fn requires_paths() {
+        let output = kz_cmd()
+            .arg("--detect-encodings")
+            .stdin(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod head_tail {
+    use super::*;
+
+    fn numbered(dir: &tempfile::TempDir, name: &str, lines: usize) -> std::path::PathBuf {
+        let file = dir.path().join(name);
+        let content: String = (1..=lines).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&file, content).unwrap();
+        file
+    }
+
+    fn counts(args: &[&str], file: &std::path::Path) -> serde_json::Value {
+        let output = kz_cmd()
+            .args(args)
+            .arg("--json")
+            .arg(file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        json[0]["counts"].clone()
+    }
+
+    #[test]
+    fn head_and_tail_record_truncation() {
+        let dir = create_temp_dir();
+        let file = numbered(&dir, "big.log", 100);
+
+        let head = counts(&["-lw", "--head", "10"], &file);
+        assert_eq!(head["lines"], 10);
+        assert_eq!(head["words"], 20);
+        assert_eq!(head["truncated"]["mode"], "head");
+        assert_eq!(head["truncated"]["amount"], 10);
+
+        let tail = counts(&["-L", "--tail", "5"], &file);
+        assert_eq!(tail["max_line_length"], "line 100".len());
+        assert_eq!(tail["truncated"]["mode"], "tail");
+
+        let bytes = counts(&["-c", "--head-bytes", "12"], &file);
+        assert_eq!(bytes["bytes"], 12);
+        assert_eq!(bytes["truncated"]["mode"], "head-bytes");
+    }
+
+    #[test]
+    fn short_files_are_not_marked_truncated() {
+        let dir = create_temp_dir();
+        let file = numbered(&dir, "small.log", 3);
+
+        for args in [
+            ["--head", "1000"],
+            ["--tail", "1000"],
+            ["--head-bytes", "1M"],
+        ] {
+            let counts = counts(&["-lc", args[0], args[1]], &file);
+            assert_eq!(counts["lines"], 3);
+            assert!(counts.get("truncated").is_none());
+        }
+    }
+
+    #[test]
+    fn head_conflicts_with_tail() {
+        let output = kz_cmd()
+            .args(["--head", "1", "--tail", "1", "x"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod diff_input {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sample.diff");
+
+    #[test]
+    fn counts_changes_per_file_from_stdin() {
+        let output = kz_stdin(&["--diff-input"], fs::read(FIXTURE).unwrap());
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("+1 -0 README.md\n"));
+        assert!(stdout.contains(" -  - logo.png (binary)\n"));
+        assert!(stdout.contains("+0 -1 notes.txt (deleted)\n"));
+        assert!(stdout.contains("+1 -1 src/core.rs (renamed from src/lib.rs)\n"));
+        assert!(stdout.contains("+0 -0 src/helpers.rs (renamed from src/util.rs)\n"));
+        assert!(stdout.contains("+2 -0 src/new.sql (new)\n"));
+        assert!(stdout.contains("+3 -2 .rs (3 files)\n"));
+        assert!(stdout.ends_with("+6 -3 total (7 files, 1 binary)\n"));
+    }
+
+    #[test]
+    fn json_report_from_file() {
+        let output = kz_cmd()
+            .arg("--diff-input")
+            .arg("--json")
+            .arg(FIXTURE)
+            .output()
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let files = json["files"].as_array().unwrap();
+        assert_eq!(files.len(), 7);
+        let main = files.iter().find(|f| f["file"] == "src/main.rs").unwrap();
+        assert_eq!(main["added"], 2);
+        assert_eq!(main["removed"], 1);
+        assert_eq!(main["change"], "modified");
+        assert_eq!(json["extensions"]["rs"]["files"], 3);
+        assert_eq!(json["total"]["added"], 6);
+        assert_eq!(json["total"]["binary_files"], 1);
+    }
+}
+
+mod progress_json {
+    use super::*;
+
+    fn events(stream: &[u8]) -> Vec<serde_json::Value> {
+        String::from_utf8_lossy(stream)
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    #[test]
+    fn emits_ordered_events_on_stderr() {
+        let dir = create_temp_dir();
+        let large = dir.path().join("large.txt");
+        let large_content = "some words on a line\n".repeat(200_000);
+        fs::write(&large, &large_content).unwrap();
+        let mut cmd = kz_cmd();
+        cmd.arg("--progress-json")
+            .arg("--file-timeout")
+            .arg("0.000000001")
+            .arg(&large);
+        for i in 0..5 {
+            let file = dir.path().join(format!("f{}.txt", i));
+            fs::write(&file, "one two\n").unwrap();
+            cmd.arg(file);
+        }
+
+        let output = cmd.output().unwrap();
+
+        let events = events(&output.stderr);
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds.first(), Some(&"start"));
+        assert_eq!(kinds.last(), Some(&"done"));
+        assert!(kinds.contains(&"progress"));
+        let skip = events.iter().find(|e| e["event"] == "skip").unwrap();
+        assert_eq!(skip["reason"], "timeout");
+        assert_eq!(skip["path"], large.to_str().unwrap());
+
+        let total_bytes = large_content.len() + 5 * 8;
+        assert_eq!(events[0]["files"], 6);
+        assert_eq!(events[0]["bytes"], total_bytes);
+        let done = events.last().unwrap();
+        assert_eq!(done["files"], 6);
+        assert_eq!(done["bytes"], total_bytes);
+        let skips = kinds.iter().filter(|&&k| k == "skip").count();
+        assert_eq!(done["skipped"], skips);
+        assert_eq!(done["failed"], 0);
+    }
+
+    #[test]
+    fn progress_file_keeps_stderr_clean() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "hello\n").unwrap();
+        let log = dir.path().join("progress.ndjson");
+
+        let output = kz_cmd()
+            .arg("--progress-file")
+            .arg(&log)
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+        let events = events(&fs::read(&log).unwrap());
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["start", "progress", "done"]);
+        assert_eq!(events[1]["path"], file.to_str().unwrap());
+    }
+}
+
+mod dump_unique_words {
+    use super::*;
+
+    #[test]
+    fn single_input_lists_words_on_stdout() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "the cat saw the dog\nthe end\n").unwrap();
+
+        let output = kz_cmd()
+            .arg("--dump-unique-words")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        let counts = lines.next().unwrap();
+        let words: Vec<&str> = lines.collect();
+        assert_eq!(words, vec!["cat", "dog", "end", "saw", "the"]);
+        // The default columns still print, followed by unique words.
+        assert!(counts.starts_with("2 7 28 5 "), "{}", counts);
+    }
+
+    #[test]
+    fn directory_target_matches_reported_counts() {
+        let dir = create_temp_dir();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "alpha beta alpha\n").unwrap();
+        fs::write(&b, "beta gamma delta gamma\n").unwrap();
+        let out = dir.path().join("words");
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .arg("--json")
+            .arg(format!("--dump-unique-words={}", out.display()))
+            .arg("a.txt")
+            .arg("b.txt")
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        for (index, name) in ["a.txt", "b.txt"].iter().enumerate() {
+            let list = fs::read_to_string(out.join(format!("{}.words", name))).unwrap();
+            assert_eq!(
+                list.lines().count(),
+                json[index]["counts"]["unique_words"].as_u64().unwrap() as usize
+            );
+        }
+        let total = fs::read_to_string(out.join("total.words")).unwrap();
+        assert_eq!(total, "alpha\nbeta\ndelta\ngamma\n");
+    }
+
+    #[test]
+    fn stdout_target_conflicts_with_json() {
+        let output = kz_cmd()
+            .arg("--json")
+            .arg("--dump-unique-words")
+            .arg("x")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod filename_layout {
+    use super::*;
+
+    fn run(layout: &str) -> String {
+        let dir = create_temp_dir();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        fs::write(
+            dir.path().join("nested/long-name.txt"),
+            "one two three\nfour five six\n".repeat(5),
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .arg("-lw")
+            .arg(layout)
+            .arg("a.txt")
+            .arg("nested/long-name.txt")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn filename_first_aligns_counts() {
+        assert_eq!(
+            run("--filename-first"),
+            "a.txt                 1  1\n\
+             nested/long-name.txt 10 30\n\
+             total                11 31\n"
+        );
+    }
+
+    #[test]
+    fn no_filename_drops_names_and_total_label() {
+        assert_eq!(run("--no-filename"), " 1  1\n10 30\n11 31\n");
+    }
+
+    #[test]
+    fn layouts_conflict() {
+        let output = kz_cmd()
+            .args(["--no-filename", "--filename-first", "x"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+#[cfg(unix)]
+mod fs_meta {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn reports_owner_mode_and_world_writable() {
+        let dir = create_temp_dir();
+        let private = dir.path().join("private.txt");
+        let shared = dir.path().join("shared.txt");
+        fs::write(&private, "a\n").unwrap();
+        fs::write(&shared, "b\n").unwrap();
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o666)).unwrap();
+
+        let output = kz_cmd()
+            .arg("--fs-meta")
+            .arg("--json")
+            .arg(&private)
+            .arg(&shared)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let first = &json[0]["fs"];
+        assert_eq!(first["mode"], "640");
+        assert_eq!(first["world_writable"], false);
+        assert!(first["uid"].is_u64());
+        assert!(first["mtime"].as_i64().unwrap() > 0);
+        assert_eq!(json[1]["fs"]["mode"], "666");
+        assert_eq!(json[1]["fs"]["world_writable"], true);
+
+        let summary = &json[2]["fs"];
+        assert_eq!(summary["world_writable"], 1);
+        assert_eq!(summary["owners"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn text_summary_line() {
+        let dir = create_temp_dir();
+        let shared = dir.path().join("shared.txt");
+        fs::write(&shared, "b\n").unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o666)).unwrap();
+
+        let output = kz_cmd().arg("--fs-meta").arg(&shared).output().unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let last = stdout.lines().last().unwrap();
+        assert!(last.starts_with("  owners: 1 ("));
+        assert!(last.ends_with("world-writable: 1"));
+    }
+}
+
+mod verify {
+    use super::*;
+
+    #[test]
+    fn parallel_and_serial_counts_agree() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("big.txt");
+        let mut content = String::new();
+        for i in 0..40_000 {
+            content.push_str(&format!("line {} with some wörds 😀\n", i));
+            if i % 7 == 0 {
+                content.push('\n');
+            }
+        }
+        fs::write(&file, content).unwrap();
+
+        let output = kz_cmd()
+            .args(["-lwmcL", "--blank-lines", "--unique", "--stats", "--json"])
+            .args(["--pattern", "wör", "--emoji", "--repeated-lines"])
+            .args(["--chunk-size", "64K", "--parallel-threshold", "0"])
+            .arg("--verify")
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(0));
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("internal error"));
+    }
+}
+
+mod normalize_check {
+    use super::*;
+
+    fn normalized_len(content: &[u8]) -> usize {
+        String::from_utf8_lossy(content)
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .len()
+    }
+
+    #[test]
+    fn reports_conversions_and_bytes_saved() {
+        let dir = create_temp_dir();
+        let mixed: &[u8] = b"one\r\ntwo\nthree\rfour\r\n\r\nfive";
+        let unix: &[u8] = b"already\nfine\n";
+        fs::write(dir.path().join("mixed.txt"), mixed).unwrap();
+        fs::write(dir.path().join("unix.txt"), unix).unwrap();
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--normalize-check", "lf", "--json", "mixed.txt", "unix.txt"])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let counts = &json[0]["counts"];
+        assert_eq!(counts["crlf_to_convert"], 3);
+        assert_eq!(counts["cr_to_convert"], 1);
+        assert_eq!(counts["bytes_saved"], mixed.len() - normalized_len(mixed));
+        assert_eq!(json[1]["counts"]["bytes_saved"], 0);
+        assert_eq!(json[2]["counts"]["crlf_to_convert"], 3);
+    }
+
+    #[test]
+    fn gate_fails_only_when_needed() {
+        let dir = create_temp_dir();
+        let clean = dir.path().join("clean.txt");
+        let dirty = dir.path().join("dirty.txt");
+        fs::write(&clean, "a\nb\n").unwrap();
+        fs::write(&dirty, "a\r\nb\r\n").unwrap();
+
+        let run = |file: &std::path::Path| {
+            kz_cmd()
+                .args(["--normalize-check", "lf", "--fail-if-normalize-needed"])
+                .arg(file)
+                .output()
+                .unwrap()
+        };
+
+        assert_eq!(run(&clean).status.code(), Some(0));
+        let output = run(&dirty);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(
+            String::from_utf8_lossy(&output.stdout)
+                .contains("normalize to LF: 2 CRLF, 0 bare CR, saves 2 bytes")
+        );
+    }
+}
+
+mod sample_files {
+    use super::*;
+
+    fn tree_of_100() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        for i in 0..100 {
+            // Every file has 4 lines, so the estimate must be exact.
+            fs::write(dir.path().join(format!("f{:03}.txt", i)), "a\nb\nc\nd\n").unwrap();
+        }
+        dir
+    }
+
+    fn run(dir: &std::path::Path, seed: &str) -> serde_json::Value {
+        let output = kz_cmd()
+            .current_dir(dir)
+            .args(["-lc", "-r", "--sample-files-count", "10", "--seed", seed])
+            .args(["--json", "."])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap()
+    }
+
+    #[test]
+    fn estimates_totals_from_seeded_subset() {
+        let dir = tree_of_100();
+        let json = run(dir.path(), "7");
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 11);
+
+        let total = rows.last().unwrap();
+        assert_eq!(total["file"], "total");
+        assert_eq!(total["estimated"], true);
+        assert_eq!(total["counts"]["lines"], 400);
+        assert_eq!(total["counts"]["bytes"], 800);
+        assert_eq!(total["sample"]["files"], 10);
+        assert_eq!(total["sample"]["population"], 100);
+        assert_eq!(
+            total["sample"]["confidence_95"]["lines"],
+            serde_json::json!([400, 400])
+        );
+
+        assert_eq!(json, run(dir.path(), "7"));
+        assert_ne!(json, run(dir.path(), "8"));
+    }
+
+    #[test]
+    fn rejects_rate_out_of_range() {
+        let output = kz_cmd()
+            .args(["--sample-files", "1.5", "Cargo.toml"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod deterministic {
+    use super::*;
+
+    #[test]
+    fn identical_bytes_across_thread_counts() {
+        let dir = create_temp_dir();
+        for i in 0..5 {
+            let content: String = (0..5000)
+                .map(|n| format!("{}\n", "word ".repeat((n * (i + 3)) % 17)))
+                .collect();
+            fs::write(dir.path().join(format!("f{}.txt", 4 - i)), content).unwrap();
+        }
+
+        let run = |threads: &str| {
+            let output = kz_cmd()
+                .current_dir(dir.path())
+                .args(["-r", "--stats", "--histogram", "--timing", "--json"])
+                .args(["--deterministic", "--threads", threads])
+                .args(["--chunk-size", "64K", "--parallel-threshold", "64K", "."])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            output.stdout
+        };
+
+        let single = run("1");
+        assert_eq!(single, run("4"));
+
+        let text = String::from_utf8(single).unwrap();
+        assert!(!text.contains("duration_ms"));
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let files: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "./f0.txt", "./f1.txt", "./f2.txt", "./f3.txt", "./f4.txt", "total"
+            ]
+        );
+        assert!(text.contains("\"mean_line_length\": "));
+        assert!(!text.contains("e-"));
+    }
+}
+
+mod blank_line_kinds {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/blank_mix.txt");
+
+    #[test]
+    fn separates_empty_and_whitespace_only() {
+        let output = kz_cmd()
+            .args(["-b", "--empty-lines", "--whitespace-only-lines", FIXTURE])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let values: Vec<&str> = stdout.split_whitespace().take(3).collect();
+        assert_eq!(values, ["6", "3", "3"]);
+    }
+
+    #[test]
+    fn stats_empty_lines_agree_with_counter() {
+        let output = kz_cmd()
+            .args(["--empty-lines", "--stats", "--json", FIXTURE])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let counts = &json[0]["counts"];
+        assert_eq!(counts["empty_lines"], 3);
+        assert_eq!(counts["statistics"]["empty_lines"], 3);
+    }
+}
+
+mod html_report {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    fn report(extra: &[&str]) -> String {
+        let dir = create_temp_dir();
+        let path = dir.path().join("report.html");
+        let output = kz_cmd()
+            .current_dir(FIXTURES)
+            .args(["-lc", "-r", "--html-report"])
+            .arg(&path)
+            .args(extra)
+            .arg("report_tree")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn contains_rows_and_charts() {
+        let html = report(&[]);
+        for row in [
+            "<tr><td>report_tree/main.rs</td><td class=\"num\">3</td><td class=\"num\">34</td></tr>",
+            "<tr><td>report_tree/notes.md</td><td class=\"num\">3</td><td class=\"num\">15</td></tr>",
+            "<tr><td>report_tree/sub/list.txt</td><td class=\"num\">2</td><td class=\"num\">8</td></tr>",
+        ] {
+            assert!(html.contains(row), "missing row {}", row);
+        }
+        assert!(html.contains("<dt>lines</dt><dd>8</dd>"));
+        assert!(html.contains("aria-label=\"Line length histogram\""));
+        assert!(html.contains("<text x=\"0\" y=\"12\">0-9</text>"));
+        assert!(html.contains("aria-label=\"lines by extension\""));
+        assert_eq!(html.matches("<rect ").count(), 5);
+        assert!(html.contains("<script type=\"application/json\" id=\"kz-data\">"));
+        assert!(!html.contains("{{"));
+    }
+
+    #[test]
+    fn caps_table_rows() {
+        let html = report(&["--html-report-rows", "1"]);
+        assert_eq!(html.matches("<tr><td>").count(), 1);
+        assert!(html.contains("Showing the first 1 of 3 files"));
+        assert!(html.contains("<dt>lines</dt><dd>8</dd>"));
+    }
+}
+
+mod disk_usage {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn sparse_file_allocates_less_than_its_length() {
+        let dir = create_temp_dir();
+        let sparse = dir.path().join("sparse.bin");
+        fs::File::create(&sparse)
+            .unwrap()
+            .set_len(32 * 1024 * 1024)
+            .unwrap();
+        let small = dir.path().join("small.txt");
+        fs::write(&small, "hi\n").unwrap();
+
+        let output = kz_cmd()
+            .args(["-c", "--disk-usage", "--json"])
+            .arg(&sparse)
+            .arg(&small)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let sparse_counts = &json[0]["counts"];
+        assert_eq!(sparse_counts["bytes"], 32 * 1024 * 1024);
+        assert!(sparse_counts["disk_bytes"].as_u64().unwrap() < 32 * 1024 * 1024);
+        let total = json[2]["counts"]["disk_bytes"].as_u64().unwrap();
+        let parts = sparse_counts["disk_bytes"].as_u64().unwrap()
+            + json[1]["counts"]["disk_bytes"].as_u64().unwrap();
+        assert_eq!(total, parts);
+    }
+
+    #[test]
+    fn omitted_without_flag() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "hi\n").unwrap();
+        let output = kz_cmd().args(["--json"]).arg(&file).output().unwrap();
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("disk_bytes"));
+    }
+}
+
+mod text_sections {
+    use super::*;
+
+    fn run(args: &[&str]) -> String {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("a.txt"),
+            "foo bar\nfoo\n\nlonger line here foo\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("b.txt"), "foo\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn pattern_row_kept_with_histogram() {
+        let stdout = run(&["--pattern", "foo", "--histogram", "a.txt"]);
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("3 a.txt"));
+        assert_eq!(lines.next(), Some("Line Length Histogram:"));
+    }
+
+    #[test]
+    fn stats_and_histogram_follow_each_row() {
+        let stdout = run(&["-l", "--stats", "--histogram", "a.txt", "b.txt"]);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], "4 a.txt");
+        assert_eq!(lines[1], "Statistics:");
+        assert!(lines.contains(&"Line Length Histogram:"));
+        assert!(lines.contains(&"1 b.txt"));
+        assert_eq!(stdout.matches("Statistics:").count(), 2);
+        // The total row carries the merged histogram.
+        let total = lines.iter().position(|l| *l == "5 total").unwrap();
+        assert_eq!(lines[total + 1], "Line Length Histogram:");
+    }
+
+    #[test]
+    fn only_keeps_exclusive_layout() {
+        let stdout = run(&["-l", "--only", "histogram", "a.txt", "b.txt"]);
+        assert!(!stdout.contains("4 a.txt"));
+        assert!(!stdout.contains("total"));
+        assert_eq!(stdout.matches("Line Length Histogram:").count(), 2);
+        assert!(stdout.contains("\na.txt\nLine Length Histogram:"));
+    }
+}
+
+mod long_paths {
+    use super::*;
+
+    #[test]
+    fn counts_file_beyond_max_path() {
+        let dir = create_temp_dir();
+        let mut deep = dir.path().to_path_buf();
+        while deep.as_os_str().len() < 300 {
+            deep.push("a_fairly_long_directory_name");
+        }
+        fs::create_dir_all(&deep).unwrap();
+        let file = deep.join("deep.txt");
+        fs::write(&file, "one\ntwo\n").unwrap();
+
+        let output = kz_cmd().arg("-l").arg(&file).output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("2 "));
+
+        let output = kz_cmd()
+            .args(["-l", "-r", "--path-separator", "slash"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("a_fairly_long_directory_name/deep.txt"));
+    }
+}
+
+mod csv {
+    use super::*;
+
+    #[test]
+    fn header_rows_and_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one two\nthree\n").unwrap();
+        fs::write(dir.path().join("b, c.txt"), "four\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-lw", "--csv", "a.txt", "b, c.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "file,lines,words\na.txt,2,3\n\"b, c.txt\",1,1\ntotal,3,4\n"
+        );
+    }
+
+    #[test]
+    fn single_file_has_no_total_row() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one two\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--csv", "a.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "file,lines,words,bytes\na.txt,1,2,8\n"
+        );
+    }
+
+    #[test]
+    fn stdin_row() {
+        let output = kz_stdin(&["-l", "--csv"], b"a\nb\n");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "file,lines\nstdin,2\n"
+        );
+    }
+
+    #[test]
+    fn conflicts_with_json() {
+        let output = kz_cmd().args(["--csv", "--json"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn pattern_header_is_quoted() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "hello, world \"o\"\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args([
+                "--csv",
+                "--pattern",
+                "o",
+                "--pattern",
+                "l,l",
+                "--pattern",
+                "\"o",
+            ])
+            .arg("a.txt")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "file,pattern:o,\"pattern:l,l\",\"pattern:\"\"o\"\na.txt,3,0,1\n"
+        );
+    }
+
+    #[test]
+    fn rejects_stats_sections() {
+        for flags in [["--csv", "--stats"], ["--csv", "--only=stats"]] {
+            let output = kz_cmd().args(flags).output().unwrap();
+            assert_eq!(output.status.code(), Some(2));
+        }
+    }
+}
+
+mod graphemes {
+    use super::*;
+
+    #[test]
+    fn family_emoji_is_one_grapheme() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("family.txt");
+        fs::write(
+            &file,
+            "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .args(["--json", "--graphemes", "-m", "-c"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["counts"]["graphemes"], 1);
+        assert_eq!(json[0]["counts"]["chars"], 7);
+        assert_eq!(json[0]["counts"]["bytes"], 25);
+
+        let output = kz_cmd()
+            .args(["--graphemes", "-m", "-c"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("7 1 25 "));
+    }
+}
+
+mod encoding_fallback {
+    use super::*;
+
+    fn mostly_utf8_file(dir: &tempfile::TempDir) -> std::path::PathBuf {
+        let mut data = "Grüße aus Köln, schöne Äpfel und Öl für Straßen.\n"
+            .repeat(4)
+            .into_bytes();
+        data.extend_from_slice(b"caf\xe9\n");
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, data).unwrap();
+        file
+    }
+
+    #[test]
+    fn prefers_utf8_and_warns() {
+        let dir = create_temp_dir();
+        let file = mostly_utf8_file(&dir);
+        let output = kz_cmd().args(["-m", "--json"]).arg(&file).output().unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("notes.txt: detected windows-1252"));
+        assert!(stderr.contains("counting as UTF-8"));
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        // 4 lines of 49 chars plus "caf\u{FFFD}\n".
+        assert_eq!(json[0]["counts"]["chars"], 4 * 49 + 5);
+        assert_eq!(json[0]["counts"]["encoding_fallback"]["used"], "UTF-8");
+    }
+
+    #[test]
+    fn trust_detection_keeps_guess() {
+        let dir = create_temp_dir();
+        let file = mostly_utf8_file(&dir);
+        let output = kz_cmd()
+            .args(["-m", "--trust-detection", "--json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert!(json[0]["counts"]["encoding_fallback"].is_null());
+        assert!(json[0]["counts"]["chars"].as_u64().unwrap() > 4 * 49 + 5);
+    }
+}
+
+mod ndjson {
+    use super::*;
+
+    #[test]
+    fn one_object_per_line_in_input_order_then_total() {
+        let dir = create_temp_dir();
+        // Largest last, so size scheduling finishes it out of input order.
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "one two\nthree\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "x y z\n".repeat(50_000)).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-lw", "--ndjson", "a.txt", "b.txt", "c.txt"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let records: Vec<serde_json::Value> = stdout
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let files: Vec<&str> = records
+            .iter()
+            .map(|r| r["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(files, ["a.txt", "b.txt", "c.txt", "total"]);
+        assert_eq!(records[1]["counts"]["words"], 3);
+        assert_eq!(records[3]["counts"]["lines"], 50_003);
+    }
+
+    #[test]
+    fn matches_json_array_entries() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "alpha beta\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "gamma\n").unwrap();
+        let run = |flag: &str| {
+            let output = kz_cmd()
+                .current_dir(dir.path())
+                .args([flag, "a.txt", "b.txt"])
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        let array: serde_json::Value = serde_json::from_str(&run("--json")).unwrap();
+        let lines: Vec<serde_json::Value> = run("--ndjson")
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(array.as_array().unwrap(), &lines);
+    }
+
+    #[test]
+    fn total_only_prints_just_the_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "alpha beta\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "gamma\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--ndjson", "--total-only", "a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout.lines().count(), 1);
+        assert!(stdout.starts_with("{\"counts\":"));
+    }
+}
+
+mod line_prefix_groups {
+    use super::*;
+
+    fn write_log(dir: &tempfile::TempDir) -> std::path::PathBuf {
+        let mut log = String::new();
+        for i in 0..20 {
+            let level = match i % 4 {
+                0 => "ERROR",
+                1 => "WARN",
+                _ => "INFO",
+            };
+            log.push_str(&format!("{} worker-{} finished job {}\n", level, i % 3, i));
+        }
+        let file = dir.path().join("app.log");
+        fs::write(&file, log).unwrap();
+        file
+    }
+
+    #[test]
+    fn groups_by_first_field() {
+        let dir = create_temp_dir();
+        let file = write_log(&dir);
+        let output = kz_cmd()
+            .args(["-l", "--line-prefix-fields", "1"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[1..], ["  10  INFO", "   5  ERROR", "   5  WARN"]);
+    }
+
+    #[test]
+    fn json_lists_prefix_and_count() {
+        let dir = create_temp_dir();
+        let file = write_log(&dir);
+        let output = kz_cmd()
+            .args(["--json", "--line-prefix-groups", "1"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(
+            json[0]["counts"]["prefix_groups"],
+            serde_json::json!([
+                {"prefix": "I", "count": 10},
+                {"prefix": "E", "count": 5},
+                {"prefix": "W", "count": 5}
+            ])
+        );
+    }
+
+    #[test]
+    fn chars_and_fields_conflict() {
+        let output = kz_cmd()
+            .args(["--line-prefix-groups", "3", "--line-prefix-fields", "1"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod sentences {
+    use super::*;
+
+    #[test]
+    fn column_and_json_field() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("essay.txt");
+        fs::write(
+            &file,
+            "Dr. Jones measured 2.5 litres. It was enough!\nWas it? Yes.\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .args(["-l", "--sentences"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("2 4 "));
+
+        let output = kz_cmd()
+            .args(["--json", "--sentences"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["counts"]["sentences"], 4);
+    }
+}
+
+mod sort {
+    use super::*;
+
+    fn run(args: &[&str]) -> Vec<String> {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "one two three four\nfive\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .args(["a.txt", "b.txt", "c.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn descending_with_total_last() {
+        assert_eq!(
+            run(&["-l", "--sort", "lines"]),
+            ["3 b.txt", "2 c.txt", "1 a.txt", "6 total"]
+        );
+    }
+
+    #[test]
+    fn ascending_and_enables_the_column() {
+        assert_eq!(
+            run(&["-l", "--sort", "words", "--sort-asc"]),
+            ["1 1 a.txt", "3 3 b.txt", "2 5 c.txt", "6 9 total"]
+        );
+    }
+
+    #[test]
+    fn json_rows_follow_the_sort() {
+        let stdout = run(&["--json", "--sort", "bytes"]).join("\n");
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        let files: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(files, ["c.txt", "b.txt", "a.txt", "total"]);
+    }
+
+    #[test]
+    fn pattern_needs_pattern() {
+        let output = kz_cmd().args(["--sort", "pattern"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod case_collisions {
+    use super::*;
+
+    #[test]
+    fn same_file_under_two_casings_counts_once() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("README.md"), "one two\n").unwrap();
+        // Runtime probe: only meaningful where the filesystem ignores case.
+        if !dir.path().join("readme.md").exists() {
+            return;
+        }
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--json", "-l", "readme.md", "README.md"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("readme.md, README.md are the same file"));
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["file"], "README.md");
+    }
+
+    #[test]
+    fn distinct_files_differing_in_case_are_kept() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("Notes.txt"), "a\n").unwrap();
+        if dir.path().join("notes.txt").exists() {
+            return;
+        }
+        fs::write(dir.path().join("notes.txt"), "b\nc\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "Notes.txt", "notes.txt"])
+            .output()
+            .unwrap();
+        assert!(output.stderr.is_empty());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("3 total"));
+    }
+}
+
+mod paragraphs {
+    use super::*;
+
+    #[test]
+    fn column_and_json_field() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("essay.txt");
+        fs::write(
+            &file,
+            "\r\nFirst paragraph,\r\nstill first.\r\n\r\n \r\nSecond.\r\n\r\nThird, no newline",
+        )
+        .unwrap();
+
+        let output = kz_cmd()
+            .args(["-l", "--paragraphs"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("7 3 "));
+
+        let output = kz_cmd()
+            .args(["--json", "--paragraphs"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(json[0]["counts"]["paragraphs"], 3);
+    }
+}
+
+mod color {
+    use super::*;
+
+    fn run(args: &[&str]) -> String {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("small.txt"), "a\n").unwrap();
+        fs::write(dir.path().join("big.txt"), "a\n".repeat(20)).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .env_remove("NO_COLOR")
+            .args(args)
+            .args(["small.txt", "big.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn plain_when_piped() {
+        assert_eq!(run(&["-l"]), " 1 small.txt\n20 big.txt\n21 total\n");
+    }
+
+    #[test]
+    fn forced_color_with_thresholds() {
+        assert_eq!(
+            run(&["-l", "--color", "--color-warn", "10", "--color-error", "15"]),
+            " 1 \x1b[1msmall.txt\x1b[0m\n\x1b[31m20\x1b[0m \x1b[1mbig.txt\x1b[0m\n\x1b[36m21 total\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn no_color_wins_when_last() {
+        assert_eq!(
+            run(&["-l", "--color", "--no-color"]),
+            " 1 small.txt\n20 big.txt\n21 total\n"
+        );
+    }
+
+    #[test]
+    fn never_in_json() {
+        assert!(!run(&["--json", "--color"]).contains('\x1b'));
+    }
+}
+
+mod tree {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        let deep = dir.path().join("proj/src/util");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(dir.path().join("proj/README.md"), "a\n".repeat(7)).unwrap();
+        fs::write(dir.path().join("proj/src/main.rs"), "a\n".repeat(5)).unwrap();
+        fs::write(deep.join("io.rs"), "a\n".repeat(20)).unwrap();
+        fs::write(deep.join("fmt.rs"), "a\n".repeat(10)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rolls_up_and_sorts_by_primary_counter() {
+        let dir = fixture();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--tree", "--ascii", "proj"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "42 proj\n35 |-- src\n30 |   |-- util\n20 |   |   |-- io.rs\n10 |   |   `-- fmt.rs\n 5 |   `-- main.rs\n 7 `-- README.md\n"
+        );
+    }
+
+    #[test]
+    fn depth_limit_and_json() {
+        let dir = fixture();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--tree", "--tree-depth", "1", "proj"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "42 proj\n35 ├── src\n 7 └── README.md\n"
+        );
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--json", "--tree", "proj"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let tree = &json.as_array().unwrap().last().unwrap()["tree"];
+        assert_eq!(tree["name"], "proj");
+        assert_eq!(tree["children"][0]["name"], "src");
+        assert_eq!(tree["children"][0]["children"][0]["counts"]["lines"], 30);
+    }
+}
+
+mod top_words {
+    use super::*;
+
+    #[test]
+    fn lists_per_file_and_merged_total() {
+        let dir = create_temp_dir();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "the cat and the dog\n").unwrap();
+        fs::write(&b, "a dog and a dog\n").unwrap();
+        let output = kz_cmd()
+            .args(["-w", "--top-words", "2"])
+            .arg(&a)
+            .arg(&b)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("\n  2  the\n  1  and\n"), "{stdout}");
+        assert!(stdout.ends_with("total\n  3  dog\n  2  a\n"), "{stdout}");
+    }
+
+    #[test]
+    fn json_array() {
+        let output = kz_stdin(&["--json", "--top-words", "2"], b"b a b a c\n");
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(
+            json["top_words"],
+            serde_json::json!([{"word": "a", "count": 2}, {"word": "b", "count": 2}])
+        );
+    }
+}
+
+mod tee {
+    use super::*;
+
+    fn input() -> Vec<u8> {
+        (0..20_000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// Runs `kz <args>` with `input` on stdin and its stdout piped into
+    /// `consumer`; returns kz's output and the consumer's stdout.
+    fn pipe_through(args: &[&str], consumer: &[&str]) -> (std::process::Output, String) {
+        let mut kz = kz_cmd()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let consumer = Command::new(consumer[0])
+            .args(&consumer[1..])
+            .stdin(kz.stdout.take().unwrap())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = kz.stdin.take().unwrap();
+        let data = input();
+        let writer = std::thread::spawn(move || stdin.write_all(&data));
+        let downstream = consumer.wait_with_output().unwrap();
+        let output = kz.wait_with_output().unwrap();
+        writer.join().unwrap().unwrap();
+        (
+            output,
+            String::from_utf8_lossy(&downstream.stdout).into_owned(),
+        )
+    }
+
+    #[test]
+    fn passes_input_through_and_counts_on_stderr() {
+        let (output, bytes) = pipe_through(&["-l", "--tee"], &["wc", "-c"]);
+        assert!(output.status.success());
+        assert_eq!(bytes.trim(), input().len().to_string());
+        assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "20000");
+    }
+
+    #[test]
+    fn report_file_and_early_close() {
+        let dir = create_temp_dir();
+        let report = dir.path().join("counts.json");
+        let (output, head) = pipe_through(
+            &[
+                "-l",
+                "--json",
+                "--tee",
+                "--tee-report",
+                report.to_str().unwrap(),
+            ],
+            &["head", "-c", "7"],
+        );
+        assert!(output.status.success());
+        assert_eq!(head, "line 0\n");
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["lines"], 20000);
+    }
+
+    #[test]
+    fn rejects_file_arguments() {
+        let output = kz_cmd().args(["--tee", "Cargo.toml"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod regex {
+    use super::*;
+
+    #[test]
+    fn anchors_and_character_classes() {
+        let input = b"ERROR one\nwarn: ERROR two\nERROR three\nid 42, id 7\n";
+        let output = kz_stdin(&["--pattern", "^ERROR", "--regex"], input);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+        let output = kz_stdin(&["--pattern", "[0-9]+", "--regex"], input);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+        // Without --regex the pattern stays a literal.
+        let output = kz_stdin(&["--pattern", "[0-9]+"], b"[0-9]+ and 12\n");
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "1");
+    }
+
+    #[test]
+    fn match_across_chunk_boundary() {
+        // "foo" ends the first 64K chunk, "bar" starts the next one.
+        let mut input = vec![b'x'; 64 * 1024 - 5];
+        input.extend_from_slice(b"foo\n\n  bar\n");
+        input.extend_from_slice(&b"y".repeat(64 * 1024));
+        input.extend_from_slice(b"\nfoo bar\n");
+        let output = kz_stdin(
+            &[
+                "--pattern",
+                r"foo\s+bar",
+                "--regex",
+                "--chunk-size",
+                "64K",
+                "--parallel-threshold",
+                "0",
+            ],
+            &input,
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2");
+    }
+
+    #[test]
+    fn invalid_regex_fails_before_counting() {
+        let output = kz_cmd()
+            .args(["--pattern", "(unclosed", "--regex", "missing.txt"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid --pattern regex"), "{stderr}");
+        assert!(!stderr.contains("missing.txt"), "{stderr}");
+    }
+}
+
+mod ignore_case {
+    use super::*;
+
+    fn count(args: &[&str], input: &[u8]) -> String {
+        let output = kz_stdin(args, input);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn literal_and_regex() {
+        assert_eq!(
+            count(&["--pattern", "FOO", "--ignore-case"], b"foo Foo fOo"),
+            "3"
+        );
+        assert_eq!(count(&["--pattern", "FOO"], b"foo Foo fOo"), "0");
+        assert_eq!(count(&["--pattern", "error", "-i"], b"Error ERROR\n"), "2");
+        assert_eq!(
+            count(
+                &["--pattern", "^warn", "--regex", "--ignore-case"],
+                b"WARN a\nWarn b\nno warn\n"
+            ),
+            "2"
+        );
+    }
+
+    #[test]
+    fn needs_pattern() {
+        let output = kz_cmd()
+            .args(["--ignore-case", "Cargo.toml"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod file_size_stats {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "x\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "xxx\nxxx\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "x\n".repeat(1000)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn json_total_object() {
+        let dir = fixture();
+        let output = kz_cmd()
+            .args(["--stats", "--json", "-r"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let stats = &json.as_array().unwrap().last().unwrap()["file_size_stats"];
+        assert_eq!(stats["files"], 3);
+        assert_eq!(stats["total_bytes"], 2010);
+        assert_eq!(stats["bytes"]["min"], 2);
+        assert_eq!(stats["bytes"]["median"], 8.0);
+        assert_eq!(stats["bytes"]["mean"], 670.0);
+        assert_eq!(stats["bytes"]["max"], 2000);
+        assert_eq!(stats["lines"]["median"], 2.0);
+    }
+
+    #[test]
+    fn text_block_and_summary_panel() {
+        let dir = fixture();
+        let output = kz_cmd()
+            .args(["--stats", "-r"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("File sizes:\n  Files: 3\n  Total: 2.0 KiB\n  Bytes: min 2 B, median 8 B, mean 670 B, max 2.0 KiB\n  Lines: min 1, median 2, mean 334.3, max 1000"),
+            "{stdout}"
+        );
+
+        let output = kz_cmd()
+            .args(["--summary-only", "--ascii", "-r"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("| Median size   8 B (mean 670 B)"),
+            "{stdout}"
+        );
+        assert!(!stdout.contains("File sizes:"), "{stdout}");
+    }
+
+    #[test]
+    fn absent_without_stats_or_summary() {
+        let dir = fixture();
+        let output = kz_cmd()
+            .args(["--json", "-r"])
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("file_size_stats"));
+    }
+}
+
+#[cfg(unix)]
+mod interrupt {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn interrupt_run(extra: &[&str]) -> (std::process::Output, usize) {
+        let dir = create_temp_dir();
+        let content = "some words to count here\n".repeat(2000);
+        let files = 400;
+        for i in 0..files {
+            fs::write(dir.path().join(format!("f{:03}.txt", i)), &content).unwrap();
+        }
+        let mut child = kz_cmd()
+            .args([
+                "-r",
+                "--threads",
+                "1",
+                "--top-words",
+                "5",
+                "--progress-json",
+            ])
+            .args(extra)
+            .arg(dir.path())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The handler is in place once the start event is out.
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+        stderr.read_line(&mut line).unwrap();
+        assert!(line.contains("\"start\""), "{line}");
+        let status = Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut rest = String::new();
+        std::io::Read::read_to_string(&mut stderr, &mut rest).unwrap();
+        let mut output = child.wait_with_output().unwrap();
+        output.stderr = rest.into_bytes();
+        (output, files)
+    }
+
+    #[test]
+    fn prints_partial_results_with_marker() {
+        let (output, files) = interrupt_run(&["-l"]);
+        assert_eq!(output.status.code(), Some(130));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let marker = stdout.lines().last().unwrap();
+        assert!(marker.starts_with("INTERRUPTED \u{2014} "), "{stdout}");
+        assert!(marker.ends_with(&format!(" of {} files processed", files)));
+        let processed: usize = marker.split(' ').nth(2).unwrap().parse().unwrap();
+        assert!(processed < files, "{marker}");
+    }
+
+    #[test]
+    fn json_total_is_flagged() {
+        let (output, _) = interrupt_run(&["-l", "--json"]);
+        assert_eq!(output.status.code(), Some(130));
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert_eq!(
+            json.as_array().unwrap().last().unwrap()["interrupted"],
+            true
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("INTERRUPTED"));
+    }
+}
+
+mod multiple_patterns {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "TODO one\nFIXME two TODO\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "FIXME\n").unwrap();
+        dir
+    }
+
+    fn run(dir: &tempfile::TempDir, args: &[&str]) -> String {
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .args(["a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    #[test]
+    fn column_per_pattern_with_legend_and_totals() {
+        let dir = fixture();
+        assert_eq!(
+            run(&dir, &["-l", "--pattern", "TODO", "--pattern", "FIXME"]),
+            "pattern[0]=\"TODO\" pattern[1]=\"FIXME\"\n2 2 1 a.txt\n1 0 1 b.txt\n3 2 2 total\n"
+        );
+        // A single pattern keeps its one unlabeled column.
+        assert_eq!(
+            run(&dir, &["--pattern", "TODO"]),
+            "2 a.txt\n0 b.txt\n2 total\n"
+        );
+    }
+
+    #[test]
+    fn json_keys_are_stable_when_flags_are_permuted() {
+        let dir = fixture();
+        let patterns = |args: &[&str]| {
+            let json: serde_json::Value = serde_json::from_str(&run(&dir, args)).unwrap();
+            json.as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["counts"]["patterns"].clone())
+                .collect::<Vec<_>>()
+        };
+        let forward = patterns(&["--json", "--pattern", "TODO", "--pattern", "FIXME"]);
+        let reversed = patterns(&["--json", "--pattern", "FIXME", "--pattern", "TODO"]);
+        assert_eq!(forward, reversed);
+        assert_eq!(forward[0], serde_json::json!({"TODO": 2, "FIXME": 1}));
+        assert_eq!(forward[2], serde_json::json!({"TODO": 2, "FIXME": 2}));
+    }
+
+    #[test]
+    fn csv_header_and_case_insensitive() {
+        let dir = fixture();
+        assert_eq!(
+            run(
+                &dir,
+                &[
+                    "--csv",
+                    "--ignore-case",
+                    "--pattern",
+                    "todo",
+                    "--pattern",
+                    "Fixme"
+                ]
+            ),
+            "file,pattern:todo,pattern:Fixme\na.txt,2,1\nb.txt,0,1\ntotal,2,2\n"
+        );
+    }
+}
+
+mod pattern_lines {
+    use super::*;
+
+    fn count(args: &[&str], input: &[u8]) -> String {
+        let output = kz_stdin(args, input);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn counts_lines_once_each() {
+        let input = b"foo foo\nfoo\nbar\n";
+        assert_eq!(count(&["--pattern", "foo", "--pattern-lines"], input), "2");
+        assert_eq!(count(&["--pattern", "foo"], input), "3");
+        assert_eq!(
+            count(&["--pattern", "o+", "--regex", "--pattern-lines"], input),
+            "2"
+        );
+        assert_eq!(
+            count(&["--pattern", "FOO", "-i", "--pattern-lines"], input),
+            "2"
+        );
+    }
+
+    #[test]
+    fn parallel_chunks_do_not_double_count() {
+        let input = "foo and foo\nnothing here\n".repeat(20_000);
+        assert_eq!(
+            count(
+                &[
+                    "--pattern",
+                    "foo",
+                    "--pattern-lines",
+                    "--chunk-size",
+                    "64K",
+                    "--parallel-threshold",
+                    "0"
+                ],
+                input.as_bytes()
+            ),
+            "20000"
+        );
+    }
+}
+
+mod sniff_type {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("data.txt"), "  {\"json\": true}\n").unwrap();
+        fs::write(dir.path().join("notes.json"), "not json at all\n").unwrap();
+        fs::write(dir.path().join("deploy"), "#!/bin/sh\necho deploy\n").unwrap();
+        fs::write(
+            dir.path().join("feed.dat"),
+            "<?xml version=\"1.0\"?>\n<feed/>\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("readme.txt"), "# Title\n\nBody\n").unwrap();
+        fs::write(dir.path().join("image.md"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        dir
+    }
+
+    fn kept(kind: &str) -> (Vec<String>, String) {
+        let dir = fixture();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--json", "--sniff-type", kind, "."])
+            .output()
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+        let mut files: Vec<String> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                entry["file"]
+                    .as_str()
+                    .unwrap()
+                    .trim_start_matches("./")
+                    .to_string()
+            })
+            .filter(|file| file != "total")
+            .collect();
+        files.sort();
+        (files, String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+
+    #[test]
+    fn keeps_files_by_content_not_name() {
+        assert_eq!(kept("json").0, ["data.txt"]);
+        assert_eq!(kept("script").0, ["deploy"]);
+        assert_eq!(kept("xml").0, ["feed.dat"]);
+        assert_eq!(kept("markdown").0, ["readme.txt"]);
+        let (text, stderr) = kept("text");
+        assert_eq!(text.len(), 5);
+        assert!(
+            stderr.contains("skipped 1 file(s) whose content does not look like text"),
+            "{stderr}"
+        );
+    }
+}
+
+mod total_of {
+    use super::*;
+
+    fn vendored_tree() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("vendor/lib")).unwrap();
+        fs::write(dir.path().join("src/a.rs"), "fn a() {}\n// TODO\n").unwrap();
+        fs::write(dir.path().join("src/b.rs"), "fn b() {}\n").unwrap();
+        fs::write(dir.path().join("vendor/lib/big.rs"), "x\n".repeat(50)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn excluded_rows_are_shown_but_not_totaled() {
+        let dir = vendored_tree();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--exclude-from-total", "**/vendor/**", "."])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let vendored = stdout.lines().find(|l| l.contains("big.rs")).unwrap();
+        assert!(vendored.contains("50") && vendored.ends_with("(not in total)"));
+        assert!(stdout.lines().any(|l| l.trim() == "3 total"), "{stdout}");
+        assert!(stdout.contains("not in total: 1 file(s), 50 line(s)"));
+    }
+
+    #[test]
+    fn json_marks_excluded_rows() {
+        let dir = vendored_tree();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args([
+                "-r",
+                "-l",
+                "--json",
+                "--exclude-from-total",
+                "**/vendor/**",
+                ".",
+            ])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let rows = json.as_array().unwrap();
+        let row = |name: &str| {
+            rows.iter()
+                .find(|r| r["file"].as_str().unwrap().ends_with(name))
+                .unwrap()
+        };
+        assert_eq!(row("big.rs")["excluded_from_total"], true);
+        assert_eq!(row("big.rs")["counts"]["lines"], 50);
+        assert!(row("a.rs").get("excluded_from_total").is_none());
+        let total = row("total");
+        assert_eq!(total["counts"]["lines"], 3);
+        assert_eq!(total["excluded_from_total"]["files"], 1);
+        assert_eq!(total["excluded_from_total"]["lines"], 50);
+    }
+
+    #[test]
+    fn matching_totals_only_files_with_a_match() {
+        let dir = vendored_tree();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args([
+                "-r",
+                "-l",
+                "--pattern",
+                "TODO",
+                "--total-of",
+                "matching",
+                "--json",
+                ".",
+            ])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let total = json.as_array().unwrap().last().unwrap();
+        assert_eq!(total["counts"]["lines"], 2);
+        assert_eq!(total["excluded_from_total"]["files"], 2);
+    }
+
+    #[test]
+    fn matching_needs_a_pattern() {
+        let output = kz_cmd().args(["--total-of", "matching"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("needs --pattern"));
+    }
+
+    #[test]
+    fn counted_leaves_out_binary_files() {
+        let dir = vendored_tree();
+        fs::write(dir.path().join("src/blob.bin"), b"\0\x01\x02").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "-l", "--total-of", "counted", "src"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout
+                .lines()
+                .any(|l| l.contains("blob.bin") && l.ends_with("(not in total)"))
+        );
+        assert!(stdout.contains("not in total: 1 file(s), 0 line(s)"));
+    }
+}
+
+mod max_word_length {
+    use super::*;
+
+    #[test]
+    fn reports_the_longest_word_in_characters() {
+        let output = kz_stdin(&["--max-word-length"], "a naïveté\tbc\n");
+        assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "7");
+    }
+
+    #[test]
+    fn total_takes_the_maximum() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one three\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "seventeen\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--max-word-length", "--json", "a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows[0]["counts"]["max_word_length"], 5);
+        assert_eq!(rows[1]["counts"]["max_word_length"], 9);
+        assert_eq!(rows[2]["counts"]["max_word_length"], 9);
+    }
+}
+
+mod lang_detect {
+    use super::*;
+
+    const ENGLISH: &str = "When the weather is good, the people of the village walk down to the river in the evening. \
+        They talk about the work of the day, and the children play on the grass until it is dark. \
+        Then they go back to their houses, where the lights are already on and the dinner is waiting for them.";
+    const GERMAN: &str = "Wenn das Wetter gut ist, gehen die Leute aus dem Dorf am Abend zum Fluss hinunter. \
+        Sie sprechen über die Arbeit des Tages, und die Kinder spielen auf der Wiese, bis es dunkel wird. \
+        Dann gehen sie zurück in ihre Häuser, wo schon die Lichter brennen und das Essen auf sie wartet.";
+
+    #[test]
+    fn reports_language_per_file_and_breakdown_in_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), ENGLISH).unwrap();
+        fs::write(dir.path().join("b.txt"), GERMAN).unwrap();
+        fs::write(dir.path().join("c.txt"), "too short to tell\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--lang-detect", "--json", "a.txt", "b.txt", "c.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows[0]["counts"]["language"], "en");
+        assert_eq!(rows[1]["counts"]["language"], "de");
+        assert_eq!(rows[2]["counts"]["language"], "unknown");
+        assert_eq!(rows[2]["counts"]["language_confidence"], 0.0);
+        let confidence = rows[0]["counts"]["language_confidence"].as_f64().unwrap();
+        assert!(confidence > 0.0 && confidence <= 1.0);
+        assert_eq!(
+            rows[3]["languages"],
+            serde_json::json!({"de": 1, "en": 1, "unknown": 1})
+        );
+    }
+
+    #[test]
+    fn text_rows_show_the_guess() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), ENGLISH).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "--lang-detect", "a.txt"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.trim_end().contains("a.txt [en 0."), "{stdout}");
+    }
+}
+
+mod count_matching_lines {
+    use super::*;
+
+    fn run(args: &[&str], input: &str) -> String {
+        let output = kz_stdin(args, input);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn counts_lines_next_to_occurrences() {
+        let input = "foo foo\nfoo\nbar\n";
+        let stdout = run(&["--pattern", "foo", "--count-matching-lines"], input);
+        let values: Vec<&str> = stdout.split_whitespace().collect();
+        assert_eq!(values, ["3", "2"]);
+    }
+
+    #[test]
+    fn counts_a_line_once_for_several_patterns() {
+        let input = "TODO FIXME\nTODO\nnothing\nfixme\n";
+        let stdout = run(
+            &[
+                "--pattern",
+                "TODO",
+                "--pattern",
+                "FIXME",
+                "-i",
+                "--count-matching-lines",
+                "--json",
+            ],
+            input,
+        );
+        let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(json["matching_lines"], 3);
+        assert_eq!(json["pattern"], 4);
+    }
+
+    #[test]
+    fn follows_regex_mode() {
+        let input = "a1 b2\nc\nd3\n";
+        let stdout = run(
+            &["--pattern", r"\d", "--regex", "--count-matching-lines"],
+            input,
+        );
+        let values: Vec<&str> = stdout.split_whitespace().collect();
+        assert_eq!(values, ["3", "2"]);
+    }
+
+    #[test]
+    fn needs_a_pattern() {
+        let output = kz_cmd().arg("--count-matching-lines").output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+mod precision {
+    use super::*;
+
+    fn run(args: &[&str]) -> String {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "a\nbb\ncc\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .arg("a.txt")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn text_stats_follow_precision() {
+        let default = run(&["--stats"]);
+        assert!(default.contains("Mean line length: 1.67\n"), "{default}");
+        assert!(default.contains("Std deviation: 0.47\n"));
+        let three = run(&["--stats", "--precision", "3"]);
+        assert!(three.contains("Mean line length: 1.667\n"), "{three}");
+        assert!(three.contains("Std deviation: 0.471\n"));
+        assert!(three.contains("Mean word length: 1.667\n"));
+        let zero = run(&["--stats", "--precision", "0"]);
+        assert!(zero.contains("Mean line length: 2\n"), "{zero}");
+    }
+
+    #[test]
+    fn json_floats_are_rounded_only_when_asked() {
+        let full = run(&["--stats", "--json"]);
+        assert!(
+            full.contains("\"mean_line_length\": 1.6666666666666667"),
+            "{full}"
+        );
+        let two = run(&["--stats", "--json", "--precision", "2"]);
+        assert!(two.contains("\"mean_line_length\": 1.67,"), "{two}");
+        assert!(two.contains("\"std_dev\": 0.47\n"));
+    }
+
+    #[test]
+    fn deterministic_uses_six_places_unless_overridden() {
+        let deterministic = run(&["--stats", "--json", "--deterministic"]);
+        assert!(deterministic.contains("\"mean_line_length\": 1.666667,"));
+        let one = run(&["--stats", "--json", "--deterministic", "--precision", "1"]);
+        assert!(one.contains("\"mean_line_length\": 1.7,"), "{one}");
+        assert_eq!(
+            one,
+            run(&["--stats", "--json", "--deterministic", "--precision", "1"])
+        );
+    }
+
+    #[test]
+    fn text_and_json_agree() {
+        let text = run(&["--stats", "--precision", "4"]);
+        let json = run(&["--stats", "--json", "--precision", "4"]);
+        assert!(text.contains("Std deviation: 0.4714\n"), "{text}");
+        assert!(json.contains("\"std_dev\": 0.4714\n"), "{json}");
+    }
+}
+
+mod unique_lines {
+    use super::*;
+
+    #[test]
+    fn total_counts_lines_distinct_across_files() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "x\ny\nx\r\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "y\nz\n").unwrap();
+        fs::write(dir.path().join("empty.txt"), "").unwrap();
+        fs::write(dir.path().join("same.txt"), "dup\ndup\ndup\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--unique-lines", "--json"])
+            .args(["a.txt", "b.txt", "empty.txt", "same.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let values: Vec<u64> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["counts"]["unique_lines"].as_u64().unwrap())
+            .collect();
+        assert_eq!(values, [2, 2, 0, 1, 4]);
+    }
+
+    #[test]
+    fn text_column() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "b\na\nb\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--unique-lines", "a.txt"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "2 a.txt\n");
+    }
+}
+
+mod reading_time {
+    use super::*;
+
+    #[test]
+    fn seconds_in_json_and_summed_in_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "word ".repeat(476)).unwrap();
+        fs::write(dir.path().join("b.txt"), "word ".repeat(119)).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--reading-time", "--json", "a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let seconds: Vec<f64> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["counts"]["reading_time_seconds"].as_f64().unwrap())
+            .collect();
+        assert_eq!(seconds, [120.0, 30.0, 150.0]);
+    }
+
+    #[test]
+    fn text_tag_without_word_column() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "word ".repeat(714)).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "--reading-time", "a.txt"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "0 a.txt [3 min 0 sec read]\n"
+        );
+    }
+}
+
+mod duplicate_lines {
+    use super::*;
+
+    #[test]
+    fn two_columns_per_file_summed_in_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.log"), "a\na\nb\n").unwrap();
+        fs::write(dir.path().join("b.log"), "trace\nok\ntrace\r\ntrace\nok\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--duplicate-lines", "a.log", "b.log"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "1 1 a.log\n2 3 b.log\n3 4 total\n"
+        );
+    }
+
+    #[test]
+    fn json_fields() {
+        let output = kz_stdin(&["--duplicate-lines", "--json"], b"x\ny\nx\n");
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["duplicate_lines"], 1);
+        assert_eq!(json["redundant_lines"], 1);
+    }
+}
+
+mod ttr {
+    use super::*;
+
+    #[test]
+    fn total_recomputes_from_summed_counts() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "the cat the end\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "go\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--ttr", "--json", "a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let ratios: Vec<f64> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["counts"]["type_token_ratio"].as_f64().unwrap())
+            .collect();
+        assert_eq!(ratios, [0.75, 1.0, 0.8]);
+    }
+
+    #[test]
+    fn text_tag_has_four_decimals() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "a b a\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "--ttr", "a.txt"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "3 a.txt [ttr 0.6667]\n"
+        );
+    }
+}
+
+mod group_by_timestamp {
+    use super::*;
+
+    const DAY_ONE: &str =
+        "2024-03-01T09:00:00Z start\n2024-03-01T09:00:01Z panic\n  at main.rs:10\n";
+    const DAY_THREE: &str = "2024-03-03T10:00:00Z restart\n";
+
+    #[test]
+    fn json_buckets_per_file_and_merged() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.log"), DAY_ONE).unwrap();
+        fs::write(
+            dir.path().join("b.log"),
+            format!("{}{}", DAY_ONE, DAY_THREE),
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--group-by-timestamp", "auto", "--json", "a.log", "b.log"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(
+            rows[0]["counts"]["dates"],
+            serde_json::json!([
+                {"date": "2024-03-01", "lines": 2, "bytes": 54},
+                {"date": "unparsed", "lines": 1, "bytes": 16},
+            ])
+        );
+        assert_eq!(
+            rows[2]["counts"]["dates"],
+            serde_json::json!([
+                {"date": "2024-03-01", "lines": 4, "bytes": 108},
+                {"date": "2024-03-03", "lines": 1, "bytes": 29},
+                {"date": "unparsed", "lines": 2, "bytes": 32},
+            ])
+        );
+    }
+
+    #[test]
+    fn text_table_with_sparkline() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("b.log"),
+            format!("{}{}", DAY_ONE, DAY_THREE),
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-l", "--group-by-timestamp", "auto", "b.log"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "4 b.log\n  █▁▅\n  2024-03-01  2 lines  54 bytes\n  2024-03-03  1 lines  29 bytes\n  unparsed    1 lines  16 bytes\n"
+        );
+    }
+
+    #[test]
+    fn custom_pattern_and_invalid_format() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("access.log"),
+            "01/Mar/2024:10:00:00 GET /\n02/Mar/2024:10:00:00 GET /\n",
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--group-by-timestamp", "%d/%b/%Y", "--json", "access.log"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let dates = &json[0]["counts"]["dates"];
+        assert_eq!(dates[0]["date"], "2024-03-01");
+        assert_eq!(dates[1]["date"], "2024-03-02");
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--group-by-timestamp", "%H:%M", "access.log"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+    }
+}
+
+mod non_ascii {
+    use super::*;
+
+    #[test]
+    fn aligned_column_and_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("plain.rs"), "let x = 1;\n").unwrap();
+        fs::write(dir.path().join("cafe.txt"), "café\n").unwrap();
+        fs::write(dir.path().join("quotes.md"), "“smart” quotes…\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--non-ascii", "plain.rs", "cafe.txt", "quotes.md"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "0 plain.rs\n1 cafe.txt\n3 quotes.md\n4 total\n"
+        );
+    }
+
+    #[test]
+    fn json_field() {
+        let output = kz_stdin(&["--non-ascii", "--json"], "pаypal\n");
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["non_ascii"], 1);
+    }
+}
+
+mod html {
+    use super::*;
+
+    #[test]
+    fn counts_text_without_tags() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("doc.html"),
+            "<!DOCTYPE html>\n<html><body class=\"main\">\n<!-- nav bar -->\n<p>Fish &amp; chips</p><p>for two</p>\n</body></html>\n",
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-lw", "--html", "doc.html"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "5 5 doc.html\n");
+    }
+
+    #[test]
+    fn with_raw_and_conflicts() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("a.xml"),
+            "<note to=\"you\">hi there</note>\n",
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "--html", "--with-raw", "--json", "a.xml"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["filtered"]["words"], 2);
+        assert_eq!(json[0]["counts"]["raw"]["words"], 3);
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--html", "--code", "a.xml"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+    }
+}
+
+mod debug_internals {
+    use super::*;
+
+    #[test]
+    fn reports_parallel_path_and_corrections() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("big.txt");
+        fs::write(&file, "abcdef ".repeat(100_000)).unwrap();
+        let output = kz_cmd()
+            .args(["--debug-internals", "-w", "--pattern", "cd"])
+            .args(["--chunk-size", "64K", "--parallel-threshold", "1K"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("100000 100000 "));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("read: mmap"));
+        assert!(stderr.contains("path: parallel (700000 bytes >= threshold 1024)"));
+        assert!(stderr.contains("chunks: 11 UTF-8-aligned"));
+        assert!(stderr.contains("words: 11 chunks, subtracted 7 split at chunk boundaries"));
+        assert!(stderr.contains("pattern \"cd\": 11 chunks, added 1 spanning chunk boundaries"));
+    }
+
+    #[test]
+    fn small_input_is_serial_and_quiet_without_flag() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("small.md");
+        fs::write(&file, "text\n```\ncode\n```\n").unwrap();
+        let output = kz_cmd()
+            .args(["--debug-internals", "--markdown", "-l"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("read: buffered"));
+        assert!(stderr.contains("filter: --markdown kept 5 of 18 bytes (13 removed)"));
+        assert!(stderr.contains("path: serial (5 bytes < threshold"));
+
+        let output = kz_cmd()
+            .args(["--markdown", "-l"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.stderr.is_empty());
+    }
+}
+
+mod indent_stats {
+    use super::*;
+
+    #[test]
+    fn classifies_indentation_after_code_filter() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("a.c"),
+            "int main() {\n\treturn 0;\n    // spaces\n\t  x();\n\n}\n",
+        )
+        .unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--indent-stats", "--code", "--tab-width", "8", "a.c"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(
+            "Indentation:\n  Tab-indented lines: 1\n  Space-indented lines: 0\n  Mixed-indented lines: 1\n  Max depth: 10 columns"
+        ));
+    }
+
+    #[test]
+    fn json_object_and_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.py"), "def f():\n    pass\n").unwrap();
+        fs::write(dir.path().join("b.go"), "func f() {\n\t\treturn\n}\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--indent-stats", "--json", "a.py", "b.go"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["indentation"]["space_lines"], 1);
+        assert_eq!(json[0]["counts"]["indentation"]["max_depth"], 4);
+        let total = &json[2]["counts"]["indentation"];
+        assert_eq!(total["tab_lines"], 1);
+        assert_eq!(total["space_lines"], 1);
+        assert_eq!(total["mixed_lines"], 0);
+        assert_eq!(total["max_depth"], 8);
+    }
+
+    #[test]
+    fn tab_width_requires_indent_stats() {
+        let output = kz_cmd().args(["--tab-width", "2"]).output().unwrap();
+        assert!(!output.status.success());
+    }
+}
+
+mod decompress {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn check_reads_directives_decompressed() {
+        let dir = create_temp_dir();
+        let text = "<!-- kz: max-lines=3 -->\n".to_string() + &"x\n".repeat(5);
+        fs::write(dir.path().join("doc.md.gz"), gzip(text.as_bytes())).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--check", "doc.md.gz"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stdout)
+                .contains("doc.md.gz: max-lines expected <= 3, got 6")
+        );
+    }
+
+    #[test]
+    fn gz_files_count_decompressed() {
+        let dir = create_temp_dir();
+        let compressed = gzip(&b"hello world\n".repeat(1000));
+        fs::write(dir.path().join("log.gz"), &compressed).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--json", "log.gz"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["lines"], 1000);
+        assert_eq!(json[0]["counts"]["words"], 2000);
+        assert_eq!(json[0]["counts"]["bytes"], 12000);
+        assert_eq!(json[0]["counts"]["compressed_bytes"], compressed.len());
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-c", "log.gz"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "12000 log.gz\n");
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-c", "--no-decompress", "log.gz"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("{} log.gz\n", compressed.len())
+        );
+    }
+
+    #[test]
+    fn other_names_and_stdin_need_the_flag() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("archive.bin"), gzip(b"a b c\n")).unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "--decompress", "archive.bin"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "3 archive.bin\n");
+
+        let output = kz_stdin(&["-l", "--decompress"], gzip(b"one\ntwo\n"));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn plain_text_named_gz_is_counted_as_is() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("notes.gz"), "not compressed\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "notes.gz"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "2 notes.gz\n");
+    }
+}
+
+mod feff {
+    use super::*;
+
+    /// Two BOM'd fragments joined with cat.
+    fn concatenated(dir: &tempfile::TempDir) -> std::path::PathBuf {
+        let file = dir.path().join("joined.txt");
+        let mut data = b"\xEF\xBB\xBFone word\n".to_vec();
+        data.extend_from_slice(b"\xEF\xBB\xBFword two\n");
+        fs::write(&file, data).unwrap();
+        file
+    }
+
+    #[test]
+    fn counts_and_warns_with_offset() {
+        let dir = create_temp_dir();
+        let file = concatenated(&dir);
+        let output = kz_cmd()
+            .args(["--feff-count", "-v", "--json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["feff"], 1);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("1 U+FEFF after the start of the input, first at byte 12"));
+    }
+
+    #[test]
+    fn strip_fixes_unique_words_and_chars() {
+        let dir = create_temp_dir();
+        let file = concatenated(&dir);
+        let output = kz_cmd()
+            .args(["--unique", "-m", "--json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        // "word" and "\u{FEFF}word" differ.
+        assert_eq!(json[0]["counts"]["unique_words"], 4);
+        assert_eq!(json[0]["counts"]["chars"], 20);
+
+        let output = kz_cmd()
+            .args(["--unique", "-m", "--strip-feff", "--json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["unique_words"], 3);
+        assert_eq!(json[0]["counts"]["chars"], 18);
+        assert_eq!(json[0]["counts"]["feff"], 1);
+    }
+}
+
+mod trailing_whitespace {
+    use super::*;
+
+    #[test]
+    fn crlf_does_not_hide_trailing_space() {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("win.txt"),
+            "clean\r\nspace \r\ntab\t\r\n\r\nlast ",
+        )
+        .unwrap();
+        fs::write(dir.path().join("unix.txt"), "a \nb\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--trailing-whitespace", "win.txt", "unix.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "3 win.txt\n1 unix.txt\n4 total\n"
+        );
+    }
+
+    #[test]
+    fn json_field() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "x \ny\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--trailing-whitespace", "-l", "--json", "a.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json[0]["counts"]["trailing_whitespace_lines"], 1);
+        assert_eq!(json[0]["counts"]["lines"], 2);
+    }
+}
+
+mod git_ignore {
+    use super::*;
+
+    fn repo() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::create_dir_all(dir.path().join(".git/info")).unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.path().join(".git/info/exclude"), "secret.txt\n").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("target/out.txt"), "built\n").unwrap();
+        fs::write(dir.path().join("run.log"), "log\n").unwrap();
+        fs::write(dir.path().join("secret.txt"), "secret\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes\n").unwrap();
+        dir
+    }
+
+    fn listed(output: &std::process::Output) -> Vec<String> {
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let mut files: Vec<String> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|row| row["file"].as_str())
+            .filter(|file| *file != "total")
+            .map(|file| file.to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn skips_ignored_and_excluded_files() {
+        let dir = repo();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "--git-ignore", "--hidden=false", "-v", "--json", "."])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(listed(&output), ["./notes.txt", "./src/main.rs"]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("./target: ignored by git"));
+        assert!(stderr.contains("./run.log: ignored by git"));
+        assert!(stderr.contains("./secret.txt: ignored by git"));
+        assert!(!stderr.contains("notes.txt"));
+    }
+
+    #[test]
+    fn exclude_applies_on_top_and_default_is_off() {
+        let dir = repo();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "--git-ignore", "--hidden=false", "--json"])
+            .args(["--exclude", "*notes*", "."])
+            .output()
+            .unwrap();
+        assert_eq!(listed(&output), ["./src/main.rs"]);
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-r", "--git-ignore", "--no-git-ignore", "--hidden=false"])
+            .args(["--json", "."])
+            .output()
+            .unwrap();
+        assert_eq!(listed(&output).len(), 5);
+    }
+}
+
+mod stream_fallback {
+    use super::*;
+
+    // The failure is injected only in debug builds of kz.
+    #[cfg(debug_assertions)]
+    #[test]
+    fn mmap_failure_streams_instead_of_failing() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("big.log");
+        fs::write(
+            &file,
+            "alpha beta\n\n  \ngamma alpha delta\n".repeat(20_000),
+        )
+        .unwrap();
+        let flags = ["--json", "-lwcL", "-b", "--pattern", "alpha"];
+
+        let mapped = kz_cmd().args(flags).arg(&file).output().unwrap();
+        let streamed = kz_cmd()
+            .env("KZ_TEST_MMAP_ERROR", "1")
+            .args(flags)
+            .args(["--unique", "--stats"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(streamed.status.success());
+
+        let mapped: serde_json::Value = serde_json::from_slice(&mapped.stdout).unwrap();
+        let streamed_json: serde_json::Value = serde_json::from_slice(&streamed.stdout).unwrap();
+        for counter in [
+            "lines",
+            "words",
+            "bytes",
+            "max_line_length",
+            "blank_lines",
+            "pattern",
+        ] {
+            assert_eq!(
+                streamed_json[0]["counts"][counter], mapped[0]["counts"][counter],
+                "{}",
+                counter
+            );
+        }
+        assert_eq!(streamed_json[0]["counts"]["lines"], 80_000);
+        assert_eq!(streamed_json[0]["counts"]["pattern"], 40_000);
+
+        let stderr = String::from_utf8(streamed.stderr).unwrap();
+        assert!(stderr.contains(
+            "big.log: --unique unavailable for a file that could not be mapped (injected by KZ_TEST_MMAP_ERROR)"
+        ));
+        assert!(stderr.contains("big.log: --stats unavailable"));
+    }
+}
+
+mod line_endings {
+    use super::*;
+
+    #[test]
+    fn per_file_and_total_mixed() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("unix.txt"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("dos.txt"), "a\r\nb\r\nold mac\r").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--line-endings", "-l", "unix.txt", "dos.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "2 unix.txt\n  line endings: 2 LF, 0 CRLF, 0 CR\n\
+             2 dos.txt\n  line endings: 0 LF, 2 CRLF, 1 CR (mixed)\n\
+             4 total\n  line endings: 2 LF, 2 CRLF, 1 CR (mixed)\n"
+        );
+    }
+
+    #[test]
+    fn json_object() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.txt"), "one\r\ntwo\r\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "three\n").unwrap();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--line-endings", "--json", "a.txt", "b.txt"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            json[0]["counts"]["line_endings"],
+            serde_json::json!({"lf": 0, "crlf": 2, "cr": 0, "mixed": false})
+        );
+        assert_eq!(json[2]["counts"]["line_endings"]["lf"], 1);
+        assert_eq!(json[2]["counts"]["line_endings"]["mixed"], true);
+    }
+}
+
+mod max_line_width {
+    use super::*;
+
+    #[test]
+    fn wide_characters_count_two_columns() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("cjk.txt");
+        fs::write(&file, "日本語のテキスト\nshort\tline\n").unwrap();
+
+        let output = kz_cmd()
+            .args(["-L", "--max-line-width", "--json"])
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let counts = &json[0]["counts"];
+        assert_eq!(counts["max_line_length"], 24);
+        assert_eq!(counts["max_line_width"], 16);
+    }
+
+    #[test]
+    fn column_follows_max_line_length() {
+        let output = kz_stdin(&["-L", "--max-line-width"], "éé\n");
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "4 2");
+    }
+}
+
+mod cloc {
+    use super::*;
+
+    #[test]
+    fn code_comment_and_blank_columns() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("main.rs");
+        fs::write(
+            &file,
+            "// entry point\nfn main() {\n\n    /* say\n       hi */\n    run(); // go\n}\n",
+        )
+        .unwrap();
+
+        let output = kz_cmd().arg("--cloc").arg(&file).output().unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let columns: Vec<&str> = stdout.split_whitespace().collect();
+        assert_eq!(columns[..3], ["3", "3", "1"]);
+    }
+
+    #[test]
+    fn json_breakdown_sums_into_total() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join("a.py"), "# note\nx = 1\n").unwrap();
+        fs::write(dir.path().join("b.py"), "y = 2\n\n").unwrap();
+
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["--cloc", "--json", "a.py", "b.py"])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let total = &json.as_array().unwrap().last().unwrap()["counts"]["code_breakdown"];
+        assert_eq!(total["code"], 2);
+        assert_eq!(total["comment"], 1);
+        assert_eq!(total["blank"], 1);
+    }
+
+    #[test]
+    fn conflicts_with_code() {
+        let output = kz_cmd()
+            .args(["--cloc", "--code", "Cargo.toml"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+    }
+}
+
+mod auto_filter {
+    use super::*;
+
+    fn mixed_fixture() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        fs::write(
+            dir.path().join("guide.md"),
+            "# Guide\n\nRun it:\n\n```sh\nkz -r --auto-filter .\n```\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "// helper docs here\nfn add(a: u32) -> u32 {\n    a + 1 /* bump */\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.txt"), "// not a comment here\n").unwrap();
+        dir
+    }
+
+    fn words(dir: &tempfile::TempDir, flags: &[&str], file: &str) -> serde_json::Value {
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "--json"])
+            .args(flags)
+            .arg(file)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    #[test]
+    fn matches_explicit_filter_per_file() {
+        let dir = mixed_fixture();
+        let output = kz_cmd()
+            .current_dir(dir.path())
+            .args(["-w", "-r", "--json", "--auto-filter", "."])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let entry = |name: &str| {
+            json.as_array()
+                .unwrap()
+                .iter()
+                .find(|e| e["file"].as_str().is_some_and(|f| f.ends_with(name)))
+                .unwrap()
+                .clone()
+        };
+
+        for (file, flag, filter) in [
+            ("guide.md", Some("--markdown"), "markdown"),
+            ("lib.rs", Some("--code"), "code"),
+            ("notes.txt", None, "none"),
+        ] {
+            let explicit = words(&dir, flag.as_slice(), file);
+            assert_eq!(
+                entry(file)["counts"]["words"],
+                explicit[0]["counts"]["words"],
+                "{}",
+                file
+            );
+            assert_eq!(entry(file)["filter"], filter);
+        }
+        assert_eq!(entry("notes.txt")["counts"]["words"], 5);
+    }
+
+    #[test]
+    fn explicit_filter_applies_to_every_file() {
+        let dir = mixed_fixture();
+        let json = words(&dir, &["--auto-filter", "--code"], "guide.md");
+        assert!(json[0].get("filter").is_none());
+        assert_eq!(
+            json[0]["counts"]["words"],
+            words(&dir, &["--code"], "guide.md")[0]["counts"]["words"]
+        );
+    }
+}